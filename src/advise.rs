@@ -0,0 +1,89 @@
+use std::fs::File;
+use std::io::Result;
+
+/// Advice about how a range of a file is going to be accessed.
+///
+/// Advice is only a hint. It may be ignored by the operating system,
+/// and is ignored entirely on platforms without `posix_fadvise`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Advice {
+    /// No particular access pattern is expected.
+    Normal,
+    /// The range will be accessed sequentially from lower offsets to higher ones.
+    Sequential,
+    /// The range will be accessed in random order.
+    Random,
+    /// The range will be accessed in the near future.
+    WillNeed,
+    /// The range will not be accessed in the near future.
+    DontNeed,
+}
+
+/// The `Advise` trait allows for telling a target how a range is going to
+/// be accessed, so its caching can be tuned to the access pattern.
+pub trait Advise {
+    /// Declares how `len` bytes at `pos` are going to be accessed. A
+    /// `len` of zero extends the range to the end of the target.
+    ///
+    /// Advice is only a hint, and implementations may ignore it.
+    ///
+    /// # Errors
+    ///
+    /// This method can return any I/O error.
+    fn advise(&mut self, pos: u64, len: u64, advice: Advice) -> Result<()>;
+}
+
+impl<A: Advise + ?Sized> Advise for &mut A {
+    #[inline]
+    fn advise(&mut self, pos: u64, len: u64, advice: Advice) -> Result<()> {
+        (**self).advise(pos, len, advice)
+    }
+}
+
+impl<A: Advise + ?Sized> Advise for Box<A> {
+    #[inline]
+    fn advise(&mut self, pos: u64, len: u64, advice: Advice) -> Result<()> {
+        (**self).advise(pos, len, advice)
+    }
+}
+
+/// Uses `posix_fadvise` on Linux, Android and FreeBSD. Does nothing
+/// elsewhere.
+impl Advise for File {
+    #[inline]
+    fn advise(&mut self, pos: u64, len: u64, advice: Advice) -> Result<()> {
+        advise_file(self, pos, len, advice)
+    }
+}
+
+/// Returns whether `len` bytes at `pos`, with a `len` of zero reaching to
+/// the end, overlap `start..end`.
+pub(crate) fn overlaps(pos: u64, len: u64, start: u64, end: u64) -> bool {
+    pos < end && (len == 0 || pos.saturating_add(len) > start)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+pub(crate) fn advise_file(file: &File, pos: u64, len: u64, advice: Advice) -> Result<()> {
+    use std::io::Error;
+    use std::os::unix::io::AsRawFd;
+    use sys;
+
+    let advice = match advice {
+        Advice::Normal => libc::POSIX_FADV_NORMAL,
+        Advice::Sequential => libc::POSIX_FADV_SEQUENTIAL,
+        Advice::Random => libc::POSIX_FADV_RANDOM,
+        Advice::WillNeed => libc::POSIX_FADV_WILLNEED,
+        Advice::DontNeed => libc::POSIX_FADV_DONTNEED,
+    };
+    let ret = unsafe { libc::posix_fadvise(file.as_raw_fd(), sys::off_t(pos)?, sys::off_t(len)?, advice) };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(Error::from_raw_os_error(ret))
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd")))]
+pub(crate) fn advise_file(_file: &File, _pos: u64, _len: u64, _advice: Advice) -> Result<()> {
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use std::fs::File;
+use std::io::Result;
+
+use Error;
+
+/// The `Allocate` trait allows for reserving storage for a range of a
+/// target up front, so later writes to it neither fragment the target nor
+/// fail for lack of space.
+pub trait Allocate {
+    /// Reserves storage for `len` bytes at `pos`, extending the target to
+    /// `pos + len` bytes if it is shorter.
+    ///
+    /// The target is never shrunk, and existing data is left unchanged.
+    /// Bytes added past the previous end read as zeros.
+    ///
+    /// # Errors
+    ///
+    /// This method can return any I/O error, such as one of kind
+    /// `StorageFull` if there is not enough space for the range.
+    fn allocate(&mut self, pos: u64, len: u64) -> Result<()>;
+}
+
+impl<A: Allocate + ?Sized> Allocate for &mut A {
+    #[inline]
+    fn allocate(&mut self, pos: u64, len: u64) -> Result<()> {
+        (**self).allocate(pos, len)
+    }
+}
+
+impl<A: Allocate + ?Sized> Allocate for Box<A> {
+    #[inline]
+    fn allocate(&mut self, pos: u64, len: u64) -> Result<()> {
+        (**self).allocate(pos, len)
+    }
+}
+
+impl Allocate for Vec<u8> {
+    fn allocate(&mut self, pos: u64, len: u64) -> Result<()> {
+        let end = match pos.checked_add(len) {
+            Some(end) if end <= usize::MAX as u64 => end as usize,
+            _ => return Err(Error::OutOfBounds { offset: pos, len }.into()),
+        };
+        if end > self.len() {
+            self.resize(end, 0);
+        }
+        Ok(())
+    }
+}
+
+/// Uses `posix_fallocate` on Linux, Android and FreeBSD, `F_PREALLOCATE`
+/// on macOS and iOS, and sets the allocation size on Windows. Elsewhere
+/// the file is only extended with `set_len`.
+///
+/// `SetFileValidData` is not used on Windows, since it exposes whatever
+/// data the reserved clusters held before and requires a privilege most
+/// processes lack, so the range is still zeroed when it is first written.
+impl Allocate for File {
+    #[inline]
+    fn allocate(&mut self, pos: u64, len: u64) -> Result<()> {
+        allocate_file(self, pos, len)
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+pub(crate) fn allocate_file(file: &File, pos: u64, len: u64) -> Result<()> {
+    use std::io;
+    use std::os::unix::io::AsRawFd;
+    use sys;
+
+    if len == 0 {
+        return Ok(());
+    }
+    let ret = unsafe { libc::posix_fallocate(file.as_raw_fd(), sys::off_t(pos)?, sys::off_t(len)?) };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::from_raw_os_error(ret))
+    }
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+pub(crate) fn allocate_file(file: &File, pos: u64, len: u64) -> Result<()> {
+    use std::os::unix::io::AsRawFd;
+    use sys;
+
+    let end = end(pos, len)?;
+    let size = file.metadata()?.len();
+    if end <= size {
+        return Ok(());
+    }
+    // `F_PEOFPOSMODE` allocates past the physical end of the file, which
+    // may lie beyond `size` if space was reserved before.
+    let mut store = libc::fstore_t {
+        fst_flags: libc::F_ALLOCATECONTIG | libc::F_ALLOCATEALL,
+        fst_posmode: libc::F_PEOFPOSMODE,
+        fst_offset: 0,
+        fst_length: sys::off_t(end - size)?,
+        fst_bytesalloc: 0,
+    };
+    let ret = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_PREALLOCATE, &mut store) };
+    if ret == -1 {
+        store.fst_flags = libc::F_ALLOCATEALL;
+        sys::cvt(unsafe { libc::fcntl(file.as_raw_fd(), libc::F_PREALLOCATE, &mut store) })?;
+    }
+    file.set_len(end)
+}
+
+#[cfg(windows)]
+pub(crate) fn allocate_file(file: &File, pos: u64, len: u64) -> Result<()> {
+    use std::io;
+    use std::mem;
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::Storage::FileSystem::{FILE_ALLOCATION_INFO, FileAllocationInfo,
+                                                  SetFileInformationByHandle};
+
+    let end = end(pos, len)?;
+    if end <= file.metadata()?.len() {
+        return Ok(());
+    }
+    // An allocation size below the end of the file would truncate it, so
+    // it is only ever raised to the new end.
+    let info = FILE_ALLOCATION_INFO { AllocationSize: end as i64 };
+    let ok = unsafe {
+        SetFileInformationByHandle(file.as_raw_handle(),
+                                   FileAllocationInfo,
+                                   &info as *const FILE_ALLOCATION_INFO as *const _,
+                                   mem::size_of::<FILE_ALLOCATION_INFO>() as u32)
+    };
+    if ok == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    file.set_len(end)
+}
+
+#[cfg(not(any(target_os = "linux",
+              target_os = "android",
+              target_os = "freebsd",
+              target_os = "macos",
+              target_os = "ios",
+              windows)))]
+pub(crate) fn allocate_file(file: &File, pos: u64, len: u64) -> Result<()> {
+    let end = end(pos, len)?;
+    if file.metadata()?.len() < end {
+        file.set_len(end)?;
+    }
+    Ok(())
+}
+
+/// Returns the end of the range, failing if it does not fit in a file
+/// offset.
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd")))]
+fn end(pos: u64, len: u64) -> Result<u64> {
+    use std::io::{self, ErrorKind};
+
+    pos.checked_add(len)
+        .filter(|&end| end <= i64::MAX as u64)
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "range does not fit in a file offset"))
+}
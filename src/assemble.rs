@@ -0,0 +1,224 @@
+use std::cmp;
+use std::error;
+use std::fmt;
+use std::io::{Error, ErrorKind, Result};
+use std::sync::Mutex;
+use std::thread;
+
+use copy::copy_with;
+use {ReadAt, WriteAt};
+
+const BUF_SIZE: usize = 64 * 1024;
+
+/// One range to copy into the destination of an
+/// [`assemble`](fn.assemble.html) operation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ManifestEntry {
+    /// The offset to write to in the destination.
+    pub dst_offset: u64,
+    /// The index of the source, as returned by
+    /// [`Manifest::add_source`](struct.Manifest.html#method.add_source).
+    pub source: usize,
+    /// The offset to read from in the source.
+    pub src_offset: u64,
+    /// The number of bytes to copy.
+    pub len: u64,
+}
+
+/// A list of sources and the ranges to copy from them, describing how to
+/// build an image such as a firmware blob or a virtual disk.
+#[derive(Debug)]
+pub struct Manifest<R> {
+    sources: Vec<R>,
+    entries: Vec<ManifestEntry>,
+}
+
+/// The failure of a single entry of a manifest.
+#[derive(Debug)]
+pub struct EntryError {
+    /// The index of the failed entry in the manifest.
+    pub entry: usize,
+    /// The error the entry failed with.
+    pub error: Error,
+}
+
+impl<R> Manifest<R> {
+    /// Creates an empty manifest.
+    pub fn new() -> Manifest<R> {
+        Manifest {
+            sources: Vec::new(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Adds a source, returning the index entries refer to it by.
+    pub fn add_source(&mut self, source: R) -> usize {
+        self.sources.push(source);
+        self.sources.len() - 1
+    }
+
+    /// Appends an entry copying `len` bytes at `src_offset` in source
+    /// `source` to `dst_offset` in the destination, returning its index.
+    pub fn push(&mut self, dst_offset: u64, source: usize, src_offset: u64, len: u64) -> usize {
+        self.entries.push(ManifestEntry {
+            dst_offset,
+            source,
+            src_offset,
+            len,
+        });
+        self.entries.len() - 1
+    }
+
+    /// Returns the entries of the manifest.
+    pub fn entries(&self) -> &[ManifestEntry] {
+        &self.entries
+    }
+
+    /// Consumes the manifest, returning its sources.
+    pub fn into_sources(self) -> Vec<R> {
+        self.sources
+    }
+
+    /// Returns the indices of the entries of each source, in source
+    /// order, and the indices of entries with an invalid source.
+    fn groups(&self) -> (Vec<Vec<usize>>, Vec<usize>) {
+        let mut groups = vec![Vec::new(); self.sources.len()];
+        let mut invalid = Vec::new();
+        for (i, entry) in self.entries.iter().enumerate() {
+            match groups.get_mut(entry.source) {
+                Some(group) => group.push(i),
+                None => invalid.push(i),
+            }
+        }
+        for group in &mut groups {
+            group.sort_by_key(|&i| self.entries[i].src_offset);
+        }
+        (groups, invalid)
+    }
+}
+
+impl<R> Default for Manifest<R> {
+    fn default() -> Manifest<R> {
+        Manifest::new()
+    }
+}
+
+impl fmt::Display for EntryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "manifest entry {}: {}", self.entry, self.error)
+    }
+}
+
+impl error::Error for EntryError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Copies every entry of `manifest` into `dst`.
+///
+/// Entries are processed source by source, in order of their source
+/// offsets, reusing a single buffer. A failing entry does not stop the
+/// others; the errors of all failed entries are returned, ordered by
+/// entry index. Entries with overlapping destination ranges are written
+/// in an unspecified order.
+///
+/// # Errors
+///
+/// An entry fails with an error of kind `InvalidInput` if its source
+/// index is invalid, `UnexpectedEof` if its source ends before `len`
+/// bytes were read, or any error from its source or the destination.
+pub fn assemble<R, W>(dst: &mut W, manifest: &mut Manifest<R>) -> ::std::result::Result<(), Vec<EntryError>>
+    where R: ReadAt,
+          W: WriteAt + ?Sized
+{
+    let (groups, invalid) = manifest.groups();
+    let mut errors: Vec<EntryError> = invalid.into_iter().map(invalid_source).collect();
+    let mut buf = vec![0; BUF_SIZE];
+    for (source, group) in manifest.sources.iter_mut().zip(groups) {
+        for i in group {
+            let entry = manifest.entries[i];
+            if let Err(error) = copy_entry(source, &entry, &mut buf, |pos, data| dst.write_all_at(pos, data)) {
+                errors.push(EntryError { entry: i, error });
+            }
+        }
+    }
+    finish(errors)
+}
+
+/// Like [`assemble`](fn.assemble.html), but reads from up to `threads`
+/// sources concurrently.
+///
+/// Each source is read by one thread at a time, and writes to `dst` are
+/// serialized by a mutex, so this helps the most when reading from the
+/// sources is slower than writing to the destination.
+///
+/// # Errors
+///
+/// See [`assemble`](fn.assemble.html).
+pub fn assemble_parallel<R, W>(dst: &mut W,
+                               manifest: &mut Manifest<R>,
+                               threads: usize)
+                               -> ::std::result::Result<(), Vec<EntryError>>
+    where R: ReadAt + Send,
+          W: WriteAt + Send + ?Sized
+{
+    let (groups, invalid) = manifest.groups();
+    let entries = &manifest.entries;
+    let queue = Mutex::new(manifest.sources.iter_mut().zip(groups));
+    let dst = Mutex::new(dst);
+    let errors = Mutex::new(invalid.into_iter().map(invalid_source).collect::<Vec<_>>());
+    thread::scope(|scope| {
+        for _ in 0..cmp::max(threads, 1) {
+            scope.spawn(|| {
+                let mut buf = vec![0; BUF_SIZE];
+                loop {
+                    let next = queue.lock().unwrap_or_else(|e| e.into_inner()).next();
+                    let (source, group) = match next {
+                        Some(next) => next,
+                        None => return,
+                    };
+                    for i in group {
+                        let result = copy_entry(source, &entries[i], &mut buf, |pos, data| {
+                            dst.lock().unwrap_or_else(|e| e.into_inner()).write_all_at(pos, data)
+                        });
+                        if let Err(error) = result {
+                            errors.lock().unwrap_or_else(|e| e.into_inner()).push(EntryError { entry: i, error });
+                        }
+                    }
+                }
+            });
+        }
+    });
+    finish(errors.into_inner().unwrap_or_else(|e| e.into_inner()))
+}
+
+fn copy_entry<R, F>(source: &mut R, entry: &ManifestEntry, buf: &mut [u8], mut write: F) -> Result<()>
+    where R: ReadAt,
+          F: FnMut(u64, &[u8]) -> Result<()>
+{
+    if entry.src_offset.checked_add(entry.len).is_none() || entry.dst_offset.checked_add(entry.len).is_none() {
+        return Err(Error::new(ErrorKind::InvalidInput, "entry range overflows u64"));
+    }
+    let copied = copy_with(source, entry.src_offset, entry.len, buf, |done, data| write(entry.dst_offset + done, data))?;
+    if copied < entry.len {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "source ended before the entry was copied"));
+    }
+    Ok(())
+}
+
+fn invalid_source(entry: usize) -> EntryError {
+    EntryError {
+        entry,
+        error: Error::new(ErrorKind::InvalidInput, "invalid source index"),
+    }
+}
+
+fn finish(mut errors: Vec<EntryError>) -> ::std::result::Result<(), Vec<EntryError>> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        errors.sort_by_key(|e| e.entry);
+        Err(errors)
+    }
+}
@@ -0,0 +1,321 @@
+use std::future::Future;
+use std::io::{self, ErrorKind, Result};
+use std::mem;
+use std::ops::DerefMut;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use {ReadAt, WriteAt};
+
+/// The asynchronous counterpart of [`ReadAt`](trait.ReadAt.html).
+///
+/// The trait only depends on `std`, so it can be implemented on top of
+/// any runtime. [`AsyncReadAtExt`](trait.AsyncReadAtExt.html) provides
+/// futures for the usual combinators, and
+/// [`AssumeReady`](struct.AssumeReady.html) wraps synchronous sources.
+pub trait AsyncReadAt {
+    /// Attempts to read some bytes from `pos` bytes into the source.
+    ///
+    /// If no data is available yet, this returns `Poll::Pending` and
+    /// arranges for the current task to be woken once it is.
+    ///
+    /// # Errors
+    ///
+    /// This method can return any I/O error.
+    fn poll_read_at(self: Pin<&mut Self>, cx: &mut Context, pos: u64, buf: &mut [u8]) -> Poll<Result<usize>>;
+}
+
+/// The asynchronous counterpart of [`WriteAt`](trait.WriteAt.html).
+///
+/// [`AsyncWriteAtExt`](trait.AsyncWriteAtExt.html) provides futures for
+/// the usual combinators.
+pub trait AsyncWriteAt {
+    /// Attempts to write some bytes at `pos` bytes into `self`.
+    ///
+    /// If the sink is not ready yet, this returns `Poll::Pending` and
+    /// arranges for the current task to be woken once it is.
+    ///
+    /// # Errors
+    ///
+    /// This method can return any I/O error.
+    fn poll_write_at(self: Pin<&mut Self>, cx: &mut Context, pos: u64, buf: &[u8]) -> Poll<Result<usize>>;
+
+    /// Attempts to flush any pending writes to the underlying sink.
+    ///
+    /// # Errors
+    ///
+    /// This method can return any I/O error.
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>>;
+}
+
+impl<R: AsyncReadAt + Unpin + ?Sized> AsyncReadAt for &mut R {
+    #[inline]
+    fn poll_read_at(mut self: Pin<&mut Self>, cx: &mut Context, pos: u64, buf: &mut [u8]) -> Poll<Result<usize>> {
+        Pin::new(&mut **self).poll_read_at(cx, pos, buf)
+    }
+}
+
+impl<R: AsyncReadAt + Unpin + ?Sized> AsyncReadAt for Box<R> {
+    #[inline]
+    fn poll_read_at(mut self: Pin<&mut Self>, cx: &mut Context, pos: u64, buf: &mut [u8]) -> Poll<Result<usize>> {
+        Pin::new(&mut **self).poll_read_at(cx, pos, buf)
+    }
+}
+
+impl<P> AsyncReadAt for Pin<P>
+    where P: DerefMut + Unpin,
+          P::Target: AsyncReadAt
+{
+    #[inline]
+    fn poll_read_at(self: Pin<&mut Self>, cx: &mut Context, pos: u64, buf: &mut [u8]) -> Poll<Result<usize>> {
+        self.get_mut().as_mut().poll_read_at(cx, pos, buf)
+    }
+}
+
+impl<W: AsyncWriteAt + Unpin + ?Sized> AsyncWriteAt for &mut W {
+    #[inline]
+    fn poll_write_at(mut self: Pin<&mut Self>, cx: &mut Context, pos: u64, buf: &[u8]) -> Poll<Result<usize>> {
+        Pin::new(&mut **self).poll_write_at(cx, pos, buf)
+    }
+
+    #[inline]
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        Pin::new(&mut **self).poll_flush(cx)
+    }
+}
+
+impl<W: AsyncWriteAt + Unpin + ?Sized> AsyncWriteAt for Box<W> {
+    #[inline]
+    fn poll_write_at(mut self: Pin<&mut Self>, cx: &mut Context, pos: u64, buf: &[u8]) -> Poll<Result<usize>> {
+        Pin::new(&mut **self).poll_write_at(cx, pos, buf)
+    }
+
+    #[inline]
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        Pin::new(&mut **self).poll_flush(cx)
+    }
+}
+
+impl<P> AsyncWriteAt for Pin<P>
+    where P: DerefMut + Unpin,
+          P::Target: AsyncWriteAt
+{
+    #[inline]
+    fn poll_write_at(self: Pin<&mut Self>, cx: &mut Context, pos: u64, buf: &[u8]) -> Poll<Result<usize>> {
+        self.get_mut().as_mut().poll_write_at(cx, pos, buf)
+    }
+
+    #[inline]
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        self.get_mut().as_mut().poll_flush(cx)
+    }
+}
+
+/// A struct for using synchronous sources and sinks asynchronously.
+///
+/// Using this struct asserts, that the contained
+/// [`ReadAt`](trait.ReadAt.html) or [`WriteAt`](trait.WriteAt.html)
+/// value completes its operations without blocking for long, such as an
+/// in-memory backend. Every poll performs the operation synchronously and
+/// is immediately ready.
+#[derive(Clone, Debug)]
+pub struct AssumeReady<T>(pub T);
+
+impl<T: ReadAt + Unpin> AsyncReadAt for AssumeReady<T> {
+    #[inline]
+    fn poll_read_at(self: Pin<&mut Self>, _cx: &mut Context, pos: u64, buf: &mut [u8]) -> Poll<Result<usize>> {
+        Poll::Ready(self.get_mut().0.read_at(pos, buf))
+    }
+}
+
+impl<T: WriteAt + Unpin> AsyncWriteAt for AssumeReady<T> {
+    #[inline]
+    fn poll_write_at(self: Pin<&mut Self>, _cx: &mut Context, pos: u64, buf: &[u8]) -> Poll<Result<usize>> {
+        Poll::Ready(self.get_mut().0.write_at(pos, buf))
+    }
+
+    #[inline]
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<()>> {
+        Poll::Ready(self.get_mut().0.flush())
+    }
+}
+
+/// Futures for the combinators of [`AsyncReadAt`](trait.AsyncReadAt.html).
+///
+/// This is implemented for every `AsyncReadAt` type.
+pub trait AsyncReadAtExt: AsyncReadAt {
+    /// Reads some bytes from `pos` bytes into the source, like
+    /// [`ReadAt::read_at`](trait.ReadAt.html#tymethod.read_at).
+    fn read_at<'a>(&'a mut self, pos: u64, buf: &'a mut [u8]) -> ReadAtFuture<'a, Self>
+        where Self: Unpin
+    {
+        ReadAtFuture { inner: self, pos, buf }
+    }
+
+    /// Reads exactly `buf.len()` bytes from `pos` bytes into the source,
+    /// like [`ReadAt::read_exact_at`](trait.ReadAt.html#method.read_exact_at).
+    ///
+    /// The future fails with an error of kind `UnexpectedEof` if the end
+    /// of the source is reached first.
+    fn read_exact_at<'a>(&'a mut self, pos: u64, buf: &'a mut [u8]) -> ReadExactAtFuture<'a, Self>
+        where Self: Unpin
+    {
+        ReadExactAtFuture { inner: self, pos, buf }
+    }
+}
+
+impl<R: AsyncReadAt + ?Sized> AsyncReadAtExt for R {}
+
+/// Futures for the combinators of [`AsyncWriteAt`](trait.AsyncWriteAt.html).
+///
+/// This is implemented for every `AsyncWriteAt` type.
+pub trait AsyncWriteAtExt: AsyncWriteAt {
+    /// Writes some bytes at `pos` bytes into `self`, like
+    /// [`WriteAt::write_at`](trait.WriteAt.html#tymethod.write_at).
+    fn write_at<'a>(&'a mut self, pos: u64, buf: &'a [u8]) -> WriteAtFuture<'a, Self>
+        where Self: Unpin
+    {
+        WriteAtFuture { inner: self, pos, buf }
+    }
+
+    /// Writes exactly `buf.len()` bytes at `pos` bytes into `self`, like
+    /// [`WriteAt::write_all_at`](trait.WriteAt.html#method.write_all_at).
+    ///
+    /// The future fails with an error of kind `WriteZero` if the sink
+    /// stops accepting bytes first.
+    fn write_all_at<'a>(&'a mut self, pos: u64, buf: &'a [u8]) -> WriteAllAtFuture<'a, Self>
+        where Self: Unpin
+    {
+        WriteAllAtFuture { inner: self, pos, buf }
+    }
+
+    /// Flushes any pending writes to the underlying sink.
+    fn flush<'a>(&'a mut self) -> FlushFuture<'a, Self>
+        where Self: Unpin
+    {
+        FlushFuture { inner: self }
+    }
+}
+
+impl<W: AsyncWriteAt + ?Sized> AsyncWriteAtExt for W {}
+
+/// The future returned by
+/// [`AsyncReadAtExt::read_at`](trait.AsyncReadAtExt.html#method.read_at).
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct ReadAtFuture<'a, R: ?Sized> {
+    inner: &'a mut R,
+    pos: u64,
+    buf: &'a mut [u8],
+}
+
+impl<'a, R: AsyncReadAt + Unpin + ?Sized> Future for ReadAtFuture<'a, R> {
+    type Output = Result<usize>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<usize>> {
+        let this = self.get_mut();
+        Pin::new(&mut *this.inner).poll_read_at(cx, this.pos, this.buf)
+    }
+}
+
+/// The future returned by
+/// [`AsyncReadAtExt::read_exact_at`](trait.AsyncReadAtExt.html#method.read_exact_at).
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct ReadExactAtFuture<'a, R: ?Sized> {
+    inner: &'a mut R,
+    pos: u64,
+    buf: &'a mut [u8],
+}
+
+impl<'a, R: AsyncReadAt + Unpin + ?Sized> Future for ReadExactAtFuture<'a, R> {
+    type Output = Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        while !this.buf.is_empty() {
+            match Pin::new(&mut *this.inner).poll_read_at(cx, this.pos, this.buf) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Ok(0)) => {
+                    return Poll::Ready(Err(io::Error::new(ErrorKind::UnexpectedEof, "failed to fill whole buffer")));
+                }
+                Poll::Ready(Ok(n)) => {
+                    let buf = mem::take(&mut this.buf);
+                    this.buf = &mut buf[n..];
+                    this.pos += n as u64;
+                }
+                Poll::Ready(Err(ref e)) if e.kind() == ErrorKind::Interrupted => {}
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// The future returned by
+/// [`AsyncWriteAtExt::write_at`](trait.AsyncWriteAtExt.html#method.write_at).
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct WriteAtFuture<'a, W: ?Sized> {
+    inner: &'a mut W,
+    pos: u64,
+    buf: &'a [u8],
+}
+
+impl<'a, W: AsyncWriteAt + Unpin + ?Sized> Future for WriteAtFuture<'a, W> {
+    type Output = Result<usize>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<usize>> {
+        let this = self.get_mut();
+        Pin::new(&mut *this.inner).poll_write_at(cx, this.pos, this.buf)
+    }
+}
+
+/// The future returned by
+/// [`AsyncWriteAtExt::write_all_at`](trait.AsyncWriteAtExt.html#method.write_all_at).
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct WriteAllAtFuture<'a, W: ?Sized> {
+    inner: &'a mut W,
+    pos: u64,
+    buf: &'a [u8],
+}
+
+impl<'a, W: AsyncWriteAt + Unpin + ?Sized> Future for WriteAllAtFuture<'a, W> {
+    type Output = Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        while !this.buf.is_empty() {
+            match Pin::new(&mut *this.inner).poll_write_at(cx, this.pos, this.buf) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Ok(0)) => {
+                    return Poll::Ready(Err(io::Error::new(ErrorKind::WriteZero, "failed to write whole buffer")));
+                }
+                Poll::Ready(Ok(n)) => {
+                    this.buf = &this.buf[n..];
+                    this.pos += n as u64;
+                }
+                Poll::Ready(Err(ref e)) if e.kind() == ErrorKind::Interrupted => {}
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// The future returned by
+/// [`AsyncWriteAtExt::flush`](trait.AsyncWriteAtExt.html#method.flush).
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct FlushFuture<'a, W: ?Sized> {
+    inner: &'a mut W,
+}
+
+impl<'a, W: AsyncWriteAt + Unpin + ?Sized> Future for FlushFuture<'a, W> {
+    type Output = Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        Pin::new(&mut *self.get_mut().inner).poll_flush(cx)
+    }
+}
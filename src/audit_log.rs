@@ -0,0 +1,260 @@
+use std::fmt::Write as FmtWrite;
+use std::io::{self, ErrorKind, Result};
+use std::str;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sha256::{self, Sha256};
+use {Error, IoOptions, KnownEof, ReadAt, SizeAt, SyncAt, WriteAt};
+
+/// A wrapper appending a tamper-evident record of every write to a
+/// separate journal.
+///
+/// This is meant for media where every modification must be accounted
+/// for, such as evidence images. The journal is a text file with one line
+/// per write:
+///
+/// ```text
+/// <seq> <offset> <length> <seconds>.<nanoseconds> <digest> <chain>
+/// ```
+///
+/// `seq` counts records from zero, the timestamp is the time since the
+/// Unix epoch, and `digest` is the hex SHA-256 digest of the bytes that
+/// were written. `chain` is the hex SHA-256 digest of the previous
+/// record's chain hash followed by the text of this record up to and
+/// including the space before `chain`; the first record starts from 32
+/// zero bytes. Changing, removing or reordering a record therefore breaks
+/// every chain hash after it, which
+/// [`verify_audit_log`](fn.verify_audit_log.html) detects. Keeping the
+/// latest [`head`](#method.head) somewhere out of reach also detects
+/// records being cut off the end.
+///
+/// A record is appended after each successful write, covering the bytes
+/// actually written. If appending fails, the error is returned even though
+/// the data itself was written.
+#[derive(Debug)]
+pub struct AuditLog<T, J> {
+    inner: T,
+    journal: J,
+    journal_pos: u64,
+    records: u64,
+    head: [u8; 32],
+}
+
+impl<T, J> AuditLog<T, J> {
+    /// Wraps `inner`, writing a new journal to `journal` from its start.
+    pub fn new(inner: T, journal: J) -> AuditLog<T, J> {
+        AuditLog {
+            inner,
+            journal,
+            journal_pos: 0,
+            records: 0,
+            head: [0; 32],
+        }
+    }
+
+    /// Returns the number of records in the journal.
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    /// Returns the chain hash of the last record, or zeros if there are no
+    /// records yet.
+    pub fn head(&self) -> [u8; 32] {
+        self.head
+    }
+
+    /// Returns a reference to the inner value.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns a reference to the journal.
+    pub fn journal(&self) -> &J {
+        &self.journal
+    }
+
+    /// Consumes the wrapper, returning the inner value and the journal.
+    pub fn into_inner(self) -> (T, J) {
+        (self.inner, self.journal)
+    }
+}
+
+impl<T, J: ReadAt> AuditLog<T, J> {
+    /// Wraps `inner`, appending to the existing journal in `journal`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidData` if the existing journal fails
+    /// [verification](fn.verify_audit_log.html), or any error from reading
+    /// it.
+    pub fn resume(inner: T, mut journal: J) -> Result<AuditLog<T, J>> {
+        let mut data = Vec::new();
+        journal.read_to_end_at(0, &mut data)?;
+        let (records, head) = scan(&data)?;
+        Ok(AuditLog {
+            inner,
+            journal,
+            journal_pos: data.len() as u64,
+            records,
+            head,
+        })
+    }
+}
+
+impl<T, J: WriteAt> AuditLog<T, J> {
+    /// Appends a record of `data` having been written at `pos`.
+    fn record(&mut self, pos: u64, data: &[u8]) -> Result<()> {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut line = format!("{} {} {} {}.{:09} ", self.records, pos, data.len(),
+                               time.as_secs(), time.subsec_nanos());
+        hex(&mut line, &sha256::digest(data));
+        line.push(' ');
+        let head = chain(&self.head, &line);
+        hex(&mut line, &head);
+        line.push('\n');
+
+        self.journal.write_all_at(self.journal_pos, line.as_bytes())?;
+        self.journal_pos += line.len() as u64;
+        self.records += 1;
+        self.head = head;
+        Ok(())
+    }
+}
+
+/// Checks the hash chain of the [`AuditLog`](struct.AuditLog.html)
+/// journal in `journal`, returning the number of records and the chain
+/// hash of the last one.
+///
+/// # Errors
+///
+/// Returns an error of kind `InvalidData` if a record is malformed, out of
+/// sequence or does not match its chain hash, or any error from reading
+/// the journal. A record not matching its chain hash is reported as
+/// [`Error::ChecksumMismatch`](enum.Error.html), and a partial record at
+/// the end as [`Error::Torn`](enum.Error.html), with offsets into the
+/// journal.
+pub fn verify_audit_log<J: ReadAt>(mut journal: J) -> Result<(u64, [u8; 32])> {
+    let mut data = Vec::new();
+    journal.read_to_end_at(0, &mut data)?;
+    scan(&data)
+}
+
+fn chain(prev: &[u8; 32], text: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(prev);
+    hasher.update(text.as_bytes());
+    hasher.finish()
+}
+
+fn hex(out: &mut String, bytes: &[u8]) {
+    for b in bytes {
+        let _ = write!(out, "{:02x}", b);
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// Checks a whole journal, returning the number of records and the last
+/// chain hash.
+fn scan(data: &[u8]) -> Result<(u64, [u8; 32])> {
+    let text = str::from_utf8(data).map_err(|_| invalid("audit journal is not valid UTF-8"))?;
+    let complete = text.rfind('\n').map_or(0, |i| i + 1);
+    if complete < text.len() {
+        return Err(Error::Torn {
+            offset: complete as u64,
+            len: (text.len() - complete) as u64,
+        }.into());
+    }
+    let mut records = 0;
+    let mut head = [0; 32];
+    let mut offset = 0;
+    for line in text.lines() {
+        let split = line.rfind(' ').ok_or_else(|| invalid("malformed audit record"))?;
+        let (body, hash) = line.split_at(split + 1);
+        let fields: Vec<&str> = body.split_whitespace().collect();
+        if fields.len() != 5 || fields[0].parse::<u64>().ok() != Some(records) {
+            return Err(invalid("malformed or out-of-sequence audit record"));
+        }
+        let expected = chain(&head, body);
+        let mut expected_hex = String::with_capacity(64);
+        hex(&mut expected_hex, &expected);
+        if hash != expected_hex {
+            return Err(Error::ChecksumMismatch { offset }.into());
+        }
+        offset += line.len() as u64 + 1;
+        records += 1;
+        head = expected;
+    }
+    Ok((records, head))
+}
+
+impl<T: ReadAt, J> ReadAt for AuditLog<T, J> {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        self.inner.read_at(pos, buf)
+    }
+
+    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
+        self.inner.read_exact_at(pos, buf)
+    }
+
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        self.inner.read_at_with(pos, buf, options)
+    }
+}
+
+impl<T: WriteAt, J: WriteAt> WriteAt for AuditLog<T, J> {
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        let n = self.inner.write_at(pos, buf)?;
+        if n > 0 {
+            self.record(pos, &buf[..n])?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()?;
+        self.journal.flush()
+    }
+
+    fn write_at_with(&mut self, pos: u64, buf: &[u8], options: &IoOptions) -> Result<usize> {
+        let n = self.inner.write_at_with(pos, buf, options)?;
+        if n > 0 {
+            self.record(pos, &buf[..n])?;
+        }
+        Ok(n)
+    }
+}
+
+impl<T: KnownEof, J> KnownEof for AuditLog<T, J> {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        self.inner.remaining_at(pos)
+    }
+
+    #[inline]
+    fn is_eof_at(&mut self, pos: u64) -> Result<bool> {
+        self.inner.is_eof_at(pos)
+    }
+}
+
+impl<T: SizeAt, J> SizeAt for AuditLog<T, J> {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        self.inner.size()
+    }
+}
+
+/// Syncs the inner value, then the journal.
+impl<T: SyncAt, J: SyncAt> SyncAt for AuditLog<T, J> {
+    fn sync_all(&mut self) -> Result<()> {
+        self.inner.sync_all()?;
+        self.journal.sync_all()
+    }
+
+    fn sync_data(&mut self) -> Result<()> {
+        self.inner.sync_data()?;
+        self.journal.sync_data()
+    }
+}
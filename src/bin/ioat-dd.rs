@@ -0,0 +1,206 @@
+//! A small `dd`-like tool built on `ioat`.
+//!
+//! ```text
+//! ioat-dd copy if=URI of=URI [bs=N] [skip=N] [seek=N] [count=N] [progress]
+//! ioat-dd verify if=URI of=URI [bs=N] [skip=N] [seek=N] [count=N]
+//! ioat-dd hexdump if=URI [skip=N] [count=N]
+//! ioat-dd probe if=URI
+//! ```
+//!
+//! URIs are opened with `ioat::open` and `ioat::open_write`, and inputs
+//! are opened through `ioat::open_image`. Offsets and lengths are in
+//! bytes, and accept a `K`, `M` or `G` suffix.
+
+extern crate ioat;
+
+use std::cmp;
+use std::env;
+use std::io::{self, Error, ErrorKind, Result, Write};
+use std::process;
+use std::time::Instant;
+
+use ioat::{BoxReadAt, ReadAt};
+
+struct Args {
+    input: Option<String>,
+    output: Option<String>,
+    bs: usize,
+    skip: u64,
+    seek: u64,
+    count: Option<u64>,
+    progress: bool,
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let command = args.next().unwrap_or_default();
+    let result = parse(args).and_then(|args| match &command[..] {
+        "copy" => copy(&args),
+        "verify" => verify(&args),
+        "hexdump" => hexdump(&args),
+        "probe" => probe(&args),
+        _ => Err(usage()),
+    });
+    if let Err(e) = result {
+        eprintln!("ioat-dd: {}", e);
+        process::exit(1);
+    }
+}
+
+fn usage() -> Error {
+    Error::new(ErrorKind::InvalidInput,
+               "usage: ioat-dd copy|verify|hexdump|probe if=URI [of=URI] [bs=N] [skip=N] [seek=N] [count=N] \
+                [progress]")
+}
+
+fn parse<I: Iterator<Item = String>>(args: I) -> Result<Args> {
+    let mut parsed = Args {
+        input: None,
+        output: None,
+        bs: 1024 * 1024,
+        skip: 0,
+        seek: 0,
+        count: None,
+        progress: false,
+    };
+    for arg in args {
+        let (key, value) = match arg.find('=') {
+            Some(i) => (&arg[..i], &arg[i + 1..]),
+            None if arg == "progress" => {
+                parsed.progress = true;
+                continue;
+            }
+            None => return Err(usage()),
+        };
+        match key {
+            "if" => parsed.input = Some(value.to_string()),
+            "of" => parsed.output = Some(value.to_string()),
+            "bs" => parsed.bs = cmp::max(size(value)?, 1) as usize,
+            "skip" => parsed.skip = size(value)?,
+            "seek" => parsed.seek = size(value)?,
+            "count" => parsed.count = Some(size(value)?),
+            _ => return Err(usage()),
+        }
+    }
+    Ok(parsed)
+}
+
+fn size(s: &str) -> Result<u64> {
+    let (digits, unit) = match s.chars().last() {
+        Some('K') | Some('k') => (&s[..s.len() - 1], 1 << 10),
+        Some('M') | Some('m') => (&s[..s.len() - 1], 1 << 20),
+        Some('G') | Some('g') => (&s[..s.len() - 1], 1 << 30),
+        _ => (s, 1),
+    };
+    digits.parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(unit))
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("invalid size: {}", s)))
+}
+
+fn input(args: &Args) -> Result<BoxReadAt> {
+    let uri = args.input.as_ref().ok_or_else(usage)?;
+    ioat::open_image(ioat::open(uri)?)
+}
+
+/// Reads up to `buf.len()` bytes at `pos`, stopping early only at the end
+/// of the source.
+fn fill(src: &mut BoxReadAt, pos: u64, buf: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match src.read_at(pos + filled as u64, &mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+fn want(args: &Args, done: u64) -> usize {
+    match args.count {
+        Some(count) => cmp::min(count - done, args.bs as u64) as usize,
+        None => args.bs,
+    }
+}
+
+fn copy(args: &Args) -> Result<()> {
+    let mut src = input(args)?;
+    let mut dst = ioat::open_write(args.output.as_ref().ok_or_else(usage)?)?;
+    let mut buf = vec![0; args.bs];
+    let start = Instant::now();
+    let mut done = 0;
+    while args.count.is_none_or(|count| done < count) {
+        let want = want(args, done);
+        let n = fill(&mut src, args.skip + done, &mut buf[..want])?;
+        if n == 0 {
+            break;
+        }
+        dst.write_all_at(args.seek + done, &buf[..n])?;
+        done += n as u64;
+        if args.progress {
+            let secs = start.elapsed().as_secs_f64().max(1e-3);
+            eprint!("\r{} bytes copied, {:.1} MiB/s", done, done as f64 / secs / (1 << 20) as f64);
+        }
+    }
+    dst.flush()?;
+    if args.progress {
+        eprintln!();
+    }
+    eprintln!("{} bytes copied", done);
+    Ok(())
+}
+
+fn verify(args: &Args) -> Result<()> {
+    let mut a = input(args)?;
+    let mut b = ioat::open_image(ioat::open(args.output.as_ref().ok_or_else(usage)?)?)?;
+    let mut buf_a = vec![0; args.bs];
+    let mut buf_b = vec![0; args.bs];
+    let mut done = 0;
+    while args.count.is_none_or(|count| done < count) {
+        let want = want(args, done);
+        let n = fill(&mut a, args.skip + done, &mut buf_a[..want])?;
+        let m = fill(&mut b, args.seek + done, &mut buf_b[..want])?;
+        if let Some(i) = buf_a[..n].iter().zip(&buf_b[..m]).position(|(x, y)| x != y) {
+            return Err(Error::new(ErrorKind::InvalidData,
+                                  format!("contents differ at offset {}", done + i as u64)));
+        }
+        if n != m {
+            return Err(Error::new(ErrorKind::InvalidData,
+                                  format!("lengths differ after offset {}", done + cmp::min(n, m) as u64)));
+        }
+        if n == 0 {
+            break;
+        }
+        done += n as u64;
+    }
+    eprintln!("{} bytes verified", done);
+    Ok(())
+}
+
+fn hexdump(args: &Args) -> Result<()> {
+    let mut src = input(args)?;
+    let mut buf = vec![0; cmp::min(args.count.unwrap_or(256), 1 << 20) as usize];
+    let n = fill(&mut src, args.skip, &mut buf)?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for (i, line) in buf[..n].chunks(16).enumerate() {
+        write!(out, "{:08x} ", args.skip + i as u64 * 16)?;
+        for b in line {
+            write!(out, " {:02x}", b)?;
+        }
+        for _ in line.len()..16 {
+            write!(out, "   ")?;
+        }
+        let text: String = line.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }).collect();
+        writeln!(out, "  |{}|", text)?;
+    }
+    Ok(())
+}
+
+fn probe(args: &Args) -> Result<()> {
+    let mut src = ioat::open(args.input.as_ref().ok_or_else(usage)?)?;
+    println!("{:?}", ioat::probe(&mut src)?);
+    Ok(())
+}
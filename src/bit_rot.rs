@@ -0,0 +1,258 @@
+use std::cmp;
+use std::io::Result;
+use std::mem;
+use std::ops::Range;
+
+use {IoOptions, KnownEof, RangeSet, ReadAt, ResizeAt, SizeAt, SyncAt, WriteAt};
+
+/// A test wrapper that flips random bits in the data read or written
+/// through it, simulating a medium that rots.
+///
+/// Each byte is corrupted with the configured probability by flipping one
+/// of its bits, so checksum, Merkle tree and mirroring layers can be
+/// checked to actually detect and repair corruption. The random number
+/// generator is seeded, so a failing run can be reproduced with the same
+/// seed and sequence of operations. Corruption can be limited to target
+/// ranges, and the offsets of every corrupted byte are recorded.
+///
+/// Corruption on the write path is persistent, since the corrupted bytes
+/// are stored by the inner value, while corruption on the read path only
+/// affects the data returned by that read.
+#[derive(Clone, Debug)]
+pub struct BitRot<T> {
+    inner: T,
+    state: u64,
+    read_rate: f64,
+    write_rate: f64,
+    targets: Option<RangeSet>,
+    corrupted: RangeSet,
+    scratch: Vec<u8>,
+}
+
+impl<T> BitRot<T> {
+    /// Wraps `inner`, using `seed` for the random number generator.
+    ///
+    /// No bytes are corrupted until a rate is set.
+    pub fn new(inner: T, seed: u64) -> BitRot<T> {
+        BitRot {
+            inner,
+            state: seed,
+            read_rate: 0.0,
+            write_rate: 0.0,
+            targets: None,
+            corrupted: RangeSet::new(),
+            scratch: Vec::new(),
+        }
+    }
+
+    /// Sets the fraction of bytes corrupted when they are read, between
+    /// zero and one. The default is zero.
+    pub fn set_read_rate(&mut self, rate: f64) {
+        self.read_rate = rate;
+    }
+
+    /// Sets the fraction of bytes corrupted when they are written, between
+    /// zero and one. The default is zero.
+    pub fn set_write_rate(&mut self, rate: f64) {
+        self.write_rate = rate;
+    }
+
+    /// Limits corruption to `range`, in addition to any ranges targeted
+    /// before. By default every byte can be corrupted.
+    pub fn target(&mut self, range: Range<u64>) {
+        self.targets.get_or_insert_with(RangeSet::new).insert(range);
+    }
+
+    /// Returns the offsets of every byte corrupted so far, on either path.
+    pub fn corrupted(&self) -> &RangeSet {
+        &self.corrupted
+    }
+
+    /// Returns a reference to the inner value.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner value.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Consumes the wrapper, returning the inner value.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Returns the next number of the splitmix64 sequence.
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns the number of bytes to leave intact before the next
+    /// corrupted one, drawn from a geometric distribution so the cost does
+    /// not depend on the number of bytes skipped.
+    fn skip(&mut self, rate: f64) -> u64 {
+        if rate >= 1.0 {
+            return 0;
+        }
+        let uniform = ((self.next() >> 11) + 1) as f64 / (1u64 << 53) as f64;
+        (uniform.ln() / (-rate).ln_1p()) as u64
+    }
+
+    /// Corrupts bytes of `buf`, which is at `pos`, returning the offsets
+    /// of those corrupted.
+    ///
+    /// A byte at `u64::MAX` cannot be recorded, so it is left intact.
+    fn corrupt(&mut self, pos: u64, buf: &mut [u8], rate: f64) -> RangeSet {
+        let mut corrupted = RangeSet::new();
+        if rate.is_nan() || rate <= 0.0 {
+            return corrupted;
+        }
+        let mut i = self.skip(rate);
+        while i < buf.len() as u64 {
+            let at = match pos.checked_add(i) {
+                Some(at) if at < u64::MAX => at,
+                _ => break,
+            };
+            if self.targets.as_ref().is_none_or(|targets| targets.contains(at)) {
+                buf[i as usize] ^= 1 << (self.next() % 8);
+                corrupted.insert(at..at + 1);
+            }
+            i = i.saturating_add(1).saturating_add(self.skip(rate));
+        }
+        corrupted
+    }
+
+    /// Corrupts the bytes just read into `buf`, which is at `pos`.
+    fn rot_read(&mut self, pos: u64, buf: &mut [u8]) {
+        let rate = self.read_rate;
+        let corrupted = self.corrupt(pos, buf, rate);
+        self.record(&corrupted, u64::MAX);
+    }
+
+    /// Writes a corrupted copy of `buf` with `f`.
+    fn write_with<F>(&mut self, pos: u64, buf: &[u8], f: F) -> Result<usize>
+        where F: FnOnce(&mut T, &[u8]) -> Result<usize>
+    {
+        let rate = self.write_rate;
+        if rate.is_nan() || rate <= 0.0 {
+            return f(&mut self.inner, buf);
+        }
+        let mut scratch = mem::take(&mut self.scratch);
+        scratch.clear();
+        scratch.extend_from_slice(buf);
+        let corrupted = self.corrupt(pos, &mut scratch, rate);
+        let result = f(&mut self.inner, &scratch);
+        self.scratch = scratch;
+        let n = result?;
+        self.record(&corrupted, pos.saturating_add(n as u64));
+        Ok(n)
+    }
+
+    /// Records the offsets in `corrupted` before `end` as corrupted.
+    fn record(&mut self, corrupted: &RangeSet, end: u64) {
+        for range in corrupted.iter() {
+            if range.start < end {
+                self.corrupted.insert(range.start..cmp::min(range.end, end));
+            }
+        }
+    }
+}
+
+impl<T: ReadAt> ReadAt for BitRot<T> {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        let n = self.inner.read_at(pos, buf)?;
+        self.rot_read(pos, &mut buf[..n]);
+        Ok(n)
+    }
+
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        let n = self.inner.read_at_with(pos, buf, options)?;
+        self.rot_read(pos, &mut buf[..n]);
+        Ok(n)
+    }
+}
+
+impl<T: WriteAt> WriteAt for BitRot<T> {
+    /// Only the bytes the inner value reports as written are recorded as
+    /// corrupted.
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        self.write_with(pos, buf, |inner, buf| inner.write_at(pos, buf))
+    }
+
+    fn write_at_with(&mut self, pos: u64, buf: &[u8], options: &IoOptions) -> Result<usize> {
+        self.write_with(pos, buf, |inner, buf| inner.write_at_with(pos, buf, options))
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<T: KnownEof> KnownEof for BitRot<T> {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        self.inner.remaining_at(pos)
+    }
+}
+
+impl<T: SizeAt> SizeAt for BitRot<T> {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        self.inner.size()
+    }
+}
+
+impl<T: ResizeAt> ResizeAt for BitRot<T> {
+    #[inline]
+    fn set_len(&mut self, len: u64) -> Result<()> {
+        self.inner.set_len(len)
+    }
+}
+
+impl<T: SyncAt> SyncAt for BitRot<T> {
+    #[inline]
+    fn sync_all(&mut self) -> Result<()> {
+        self.inner.sync_all()
+    }
+
+    #[inline]
+    fn sync_data(&mut self) -> Result<()> {
+        self.inner.sync_data()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io;
+
+    #[test]
+    fn short_write_records_only_written_bytes() {
+        let mut data = [0; 8];
+        let mut rot = BitRot::new(&mut data[..], 1);
+        rot.set_write_rate(1.0);
+        assert_eq!(rot.write_at(4, &[0; 8]).unwrap(), 4);
+        assert_eq!(rot.corrupted().len(), 4);
+        assert!(rot.corrupted().contains_range(4..8));
+        assert!(data[4..].iter().all(|&b| b != 0));
+    }
+
+    #[test]
+    fn corruption_stops_before_max_offset() {
+        let mut rot = BitRot::new(io::repeat(0), 1);
+        rot.set_read_rate(1.0);
+        let mut buf = [0; 8];
+        assert_eq!(rot.read_at(u64::MAX - 4, &mut buf).unwrap(), 8);
+        assert!(buf[..4].iter().all(|&b| b != 0));
+        assert_eq!(buf[4..], [0; 4]);
+        assert_eq!(rot.corrupted().len(), 4);
+        assert!(rot.corrupted().contains_range(u64::MAX - 4..u64::MAX));
+    }
+}
@@ -0,0 +1,210 @@
+use std::io::{self, ErrorKind, Result};
+
+use {Allocate, Error, IoOptions, KnownEof, PunchHole, ReadAt, ResizeAt, SizeAt, SyncAt, WriteAt};
+
+/// A wrapper addressing the inner value in blocks of `N` bytes.
+///
+/// [`read_blocks`](#method.read_blocks) and
+/// [`write_blocks`](#method.write_blocks) take a block index and a slice
+/// of whole blocks, so every operation they issue is aligned by
+/// construction. This suits backends that reject misaligned requests,
+/// such as files opened with `O_DIRECT` or raw flash devices. Note that
+/// `O_DIRECT` may also require the buffer itself to be aligned in memory,
+/// which this wrapper does not ensure.
+///
+/// `BlockAligned` also implements [`ReadAt`](trait.ReadAt.html) and
+/// [`WriteAt`](trait.WriteAt.html) for byte offsets, checking alignment
+/// at runtime: an offset or length that is not a multiple of `N` fails
+/// with an error of kind `InvalidInput`.
+#[derive(Clone, Debug)]
+pub struct BlockAligned<const N: usize, T> {
+    inner: T,
+}
+
+impl<const N: usize, T> BlockAligned<N, T> {
+    /// Fails to evaluate, and so to compile, when `N` is zero.
+    const NON_ZERO: () = assert!(N > 0, "block size must be non-zero");
+
+    /// Wraps `inner`, addressing it in blocks of `N` bytes.
+    ///
+    /// A block size of zero is rejected at compile time.
+    pub fn new(inner: T) -> BlockAligned<N, T> {
+        let () = Self::NON_ZERO;
+        BlockAligned { inner }
+    }
+
+    /// Returns the block size in bytes.
+    pub fn block_size(&self) -> usize {
+        N
+    }
+
+    /// Returns a reference to the inner value.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner value.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Consumes the wrapper, returning the inner value.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn byte_offset(block: u64) -> Result<u64> {
+        block.checked_mul(N as u64)
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "block index out of range"))
+    }
+
+    fn check_aligned(pos: u64, len: u64) -> Result<()> {
+        if !pos.is_multiple_of(N as u64) || !len.is_multiple_of(N as u64) {
+            return Err(Error::Misaligned {
+                offset: pos,
+                len,
+                align: N as u64,
+            }.into());
+        }
+        Ok(())
+    }
+}
+
+impl<const N: usize, T: ReadAt> BlockAligned<N, T> {
+    /// Reads whole blocks starting at block index `block` into `buf`,
+    /// returning the number of blocks read.
+    ///
+    /// Fewer blocks than requested are only returned at the end of the
+    /// inner value.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `UnexpectedEof` if the inner value ends
+    /// in the middle of a block, and any error from the inner value.
+    pub fn read_blocks(&mut self, block: u64, buf: &mut [[u8; N]]) -> Result<usize> {
+        let pos = Self::byte_offset(block)?;
+        let buf = buf.as_flattened_mut();
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.inner.read_at(pos + filled as u64, &mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if !filled.is_multiple_of(N) {
+            return Err(io::Error::new(ErrorKind::UnexpectedEof, "backend ends in the middle of a block"));
+        }
+        Ok(filled / N)
+    }
+}
+
+impl<const N: usize, T: WriteAt> BlockAligned<N, T> {
+    /// Writes all of `buf` starting at block index `block`.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from the inner value.
+    pub fn write_blocks(&mut self, block: u64, buf: &[[u8; N]]) -> Result<()> {
+        let pos = Self::byte_offset(block)?;
+        self.inner.write_all_at(pos, buf.as_flattened())
+    }
+}
+
+impl<const N: usize, T: ReadAt> ReadAt for BlockAligned<N, T> {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        Self::check_aligned(pos, buf.len() as u64)?;
+        self.inner.read_at(pos, buf)
+    }
+
+    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
+        Self::check_aligned(pos, buf.len() as u64)?;
+        self.inner.read_exact_at(pos, buf)
+    }
+
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        Self::check_aligned(pos, buf.len() as u64)?;
+        self.inner.read_at_with(pos, buf, options)
+    }
+}
+
+impl<const N: usize, T: KnownEof> KnownEof for BlockAligned<N, T> {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        self.inner.remaining_at(pos)
+    }
+
+    #[inline]
+    fn is_eof_at(&mut self, pos: u64) -> Result<bool> {
+        self.inner.is_eof_at(pos)
+    }
+}
+
+/// Fails with an error of kind `InvalidInput` unless `len` is a
+/// multiple of the block size.
+impl<const N: usize, T: ResizeAt> ResizeAt for BlockAligned<N, T> {
+    fn set_len(&mut self, len: u64) -> Result<()> {
+        Self::check_aligned(len, 0)?;
+        self.inner.set_len(len)
+    }
+}
+
+impl<const N: usize, T: SizeAt> SizeAt for BlockAligned<N, T> {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        self.inner.size()
+    }
+}
+
+impl<const N: usize, T: WriteAt> WriteAt for BlockAligned<N, T> {
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        Self::check_aligned(pos, buf.len() as u64)?;
+        self.inner.write_at(pos, buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    fn write_all_at(&mut self, pos: u64, buf: &[u8]) -> Result<()> {
+        Self::check_aligned(pos, buf.len() as u64)?;
+        self.inner.write_all_at(pos, buf)
+    }
+
+    fn write_at_with(&mut self, pos: u64, buf: &[u8], options: &IoOptions) -> Result<usize> {
+        Self::check_aligned(pos, buf.len() as u64)?;
+        self.inner.write_at_with(pos, buf, options)
+    }
+
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        Self::check_aligned(pos, len)?;
+        self.inner.write_zeroes_at(pos, len)
+    }
+}
+
+impl<const N: usize, T: SyncAt> SyncAt for BlockAligned<N, T> {
+    #[inline]
+    fn sync_all(&mut self) -> Result<()> {
+        self.inner.sync_all()
+    }
+
+    #[inline]
+    fn sync_data(&mut self) -> Result<()> {
+        self.inner.sync_data()
+    }
+}
+
+impl<const N: usize, T: PunchHole> PunchHole for BlockAligned<N, T> {
+    fn punch_hole(&mut self, pos: u64, len: u64) -> Result<()> {
+        Self::check_aligned(pos, len)?;
+        self.inner.punch_hole(pos, len)
+    }
+}
+
+impl<const N: usize, T: Allocate> Allocate for BlockAligned<N, T> {
+    fn allocate(&mut self, pos: u64, len: u64) -> Result<()> {
+        Self::check_aligned(pos, len)?;
+        self.inner.allocate(pos, len)
+    }
+}
@@ -0,0 +1,170 @@
+use std::cmp;
+use std::io::{ErrorKind, Result};
+
+use {IoOptions, KnownEof, ReadAt, SizeAt, WriteAt};
+
+const DEFAULT_BLOCK_SIZE: usize = 4096;
+
+/// A wrapper caching the most recently read block of a source, so that
+/// many small reads close together are served from memory.
+///
+/// Blocks are aligned to the block size. Reads at least as large as a
+/// block that miss the cached block go straight to the source. Writes
+/// through the wrapper are passed on and drop the cached block if they
+/// overlap it, but writes made to the source in other ways are not seen
+/// until [`invalidate`](#method.invalidate) is called.
+#[derive(Clone, Debug)]
+pub struct BufReaderAt<T> {
+    inner: T,
+    buf: Box<[u8]>,
+    block: Option<u64>,
+    filled: usize,
+}
+
+impl<T> BufReaderAt<T> {
+    /// Wraps `inner`, caching blocks of 4 KiB.
+    pub fn new(inner: T) -> BufReaderAt<T> {
+        BufReaderAt::with_block_size(DEFAULT_BLOCK_SIZE, inner)
+    }
+
+    /// Wraps `inner`, caching blocks of `block_size` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block_size` is zero.
+    pub fn with_block_size(block_size: usize, inner: T) -> BufReaderAt<T> {
+        assert!(block_size > 0, "block size must be non-zero");
+        BufReaderAt {
+            inner,
+            buf: vec![0; block_size].into_boxed_slice(),
+            block: None,
+            filled: 0,
+        }
+    }
+
+    /// Returns the block size in bytes.
+    pub fn block_size(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Drops the cached block, so that the next read goes to the source.
+    pub fn invalidate(&mut self) {
+        self.block = None;
+    }
+
+    /// Returns a reference to the source.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the source, dropping the cached
+    /// block in case the source is written to through it.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.block = None;
+        &mut self.inner
+    }
+
+    /// Consumes the wrapper, returning the source.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Drops the cached block if it overlaps `len` bytes at `pos`.
+    fn invalidate_range(&mut self, pos: u64, len: u64) {
+        if let Some(start) = self.block {
+            if pos < start.saturating_add(self.buf.len() as u64) && pos.saturating_add(len) > start {
+                self.block = None;
+            }
+        }
+    }
+}
+
+impl<T: ReadAt> BufReaderAt<T> {
+    /// Reads the block starting at `start` into the cache with `f`.
+    fn fill<F>(&mut self, start: u64, f: &mut F) -> Result<()>
+        where F: FnMut(&mut T, u64, &mut [u8]) -> Result<usize>
+    {
+        self.block = None;
+        let mut filled = 0;
+        while filled < self.buf.len() {
+            match f(&mut self.inner, start + filled as u64, &mut self.buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        self.block = Some(start);
+        self.filled = filled;
+        Ok(())
+    }
+
+    /// Reads at `pos`, going to the source with `f` on a miss.
+    fn read_with<F>(&mut self, pos: u64, buf: &mut [u8], mut f: F) -> Result<usize>
+        where F: FnMut(&mut T, u64, &mut [u8]) -> Result<usize>
+    {
+        let size = self.buf.len() as u64;
+        let start = pos - pos % size;
+        if self.block != Some(start) {
+            if buf.len() as u64 >= size {
+                return f(&mut self.inner, pos, buf);
+            }
+            self.fill(start, &mut f)?;
+        }
+        let offset = (pos - start) as usize;
+        if offset >= self.filled {
+            return Ok(0);
+        }
+        let n = cmp::min(buf.len(), self.filled - offset);
+        buf[..n].copy_from_slice(&self.buf[offset..offset + n]);
+        Ok(n)
+    }
+}
+
+impl<T: ReadAt> ReadAt for BufReaderAt<T> {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        self.read_with(pos, buf, |inner, pos, buf| inner.read_at(pos, buf))
+    }
+
+    /// The options are used for reads that go to the source, including
+    /// those filling the cache.
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        self.read_with(pos, buf, |inner, pos, buf| inner.read_at_with(pos, buf, options))
+    }
+}
+
+impl<T: WriteAt> WriteAt for BufReaderAt<T> {
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        self.invalidate_range(pos, buf.len() as u64);
+        self.inner.write_at(pos, buf)
+    }
+
+    fn write_at_with(&mut self, pos: u64, buf: &[u8], options: &IoOptions) -> Result<usize> {
+        self.invalidate_range(pos, buf.len() as u64);
+        self.inner.write_at_with(pos, buf, options)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        self.invalidate_range(pos, len);
+        self.inner.write_zeroes_at(pos, len)
+    }
+}
+
+impl<T: KnownEof> KnownEof for BufReaderAt<T> {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        self.inner.remaining_at(pos)
+    }
+}
+
+impl<T: SizeAt> SizeAt for BufReaderAt<T> {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        self.inner.size()
+    }
+}
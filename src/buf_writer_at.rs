@@ -0,0 +1,402 @@
+use std::collections::BTreeMap;
+use std::io::{self, Result};
+use std::mem::ManuallyDrop;
+use std::{cmp, ptr, thread};
+
+use {Error, IoOptions, KnownEof, ReadAt, ResizeAt, SizeAt, SyncAt, WriteAt};
+
+const DEFAULT_CAPACITY: usize = 64 * 1024;
+
+/// A wrapper buffering writes to a target, so that many small writes are
+/// issued as a few large ones.
+///
+/// Buffered writes that overlap or touch are merged into one range, later
+/// writes taking precedence, and the ranges are written out in ascending
+/// order on [`flush`](trait.WriteAt.html#tymethod.flush), when a write
+/// does not fit in the buffer anymore, and when the wrapper is dropped.
+/// Errors when dropping are ignored, so call `flush` to see them. Writes
+/// at least as large as the buffer go straight to the target once it has
+/// been written out.
+///
+/// Reads, zeroing and syncs first write out the buffered ranges they
+/// depend on, so they see the buffered data. Writes made to the target in
+/// other ways may be overwritten by buffered data later on.
+#[derive(Debug)]
+pub struct BufWriterAt<T: WriteAt> {
+    inner: T,
+    ranges: BTreeMap<u64, Vec<u8>>,
+    buffered: usize,
+    capacity: usize,
+}
+
+impl<T: WriteAt> BufWriterAt<T> {
+    /// Wraps `inner`, buffering up to 64 KiB.
+    pub fn new(inner: T) -> BufWriterAt<T> {
+        BufWriterAt::with_capacity(DEFAULT_CAPACITY, inner)
+    }
+
+    /// Wraps `inner`, buffering up to `capacity` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn with_capacity(capacity: usize, inner: T) -> BufWriterAt<T> {
+        assert!(capacity > 0, "capacity must be non-zero");
+        BufWriterAt {
+            inner,
+            ranges: BTreeMap::new(),
+            buffered: 0,
+            capacity,
+        }
+    }
+
+    /// Returns the number of bytes that can be buffered.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of bytes buffered.
+    pub fn buffered(&self) -> usize {
+        self.buffered
+    }
+
+    /// Returns the number of separate ranges buffered, which is the number
+    /// of writes the next flush issues.
+    pub fn buffered_ranges(&self) -> usize {
+        self.ranges.len()
+    }
+
+    /// Returns a reference to the target.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the target.
+    ///
+    /// Data written through it may be overwritten by buffered data later
+    /// on.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Writes out the buffered data and returns the target.
+    ///
+    /// If writing fails, the error is returned along with the wrapper,
+    /// which still holds the data not written out yet.
+    pub fn into_inner(mut self) -> ::std::result::Result<T, (io::Error, BufWriterAt<T>)> {
+        if let Err(e) = self.write_ranges(0, u64::MAX) {
+            return Err((e, self));
+        }
+        let this = ManuallyDrop::new(self);
+        // The fields are read out once and `this` is never dropped, and
+        // the buffer is empty, so nothing is lost or dropped twice.
+        unsafe {
+            drop(ptr::read(&this.ranges));
+            Ok(ptr::read(&this.inner))
+        }
+    }
+
+    /// Writes out the buffered ranges overlapping or after `pos`, up to
+    /// `end`, in ascending order.
+    fn write_ranges(&mut self, pos: u64, end: u64) -> Result<()> {
+        let first = match self.ranges.range(..pos).next_back() {
+            Some((&start, data)) if start + data.len() as u64 > pos => start,
+            _ => pos,
+        };
+        let starts: Vec<u64> = self.ranges.range(first..end).map(|(&start, _)| start).collect();
+        for start in starts {
+            self.inner.write_all_at(start, &self.ranges[&start])?;
+            let data = self.ranges.remove(&start).unwrap();
+            self.buffered -= data.len();
+        }
+        Ok(())
+    }
+
+    /// Buffers `buf` at `pos`, merging it with the ranges it overlaps or
+    /// touches.
+    fn insert(&mut self, pos: u64, buf: &[u8]) {
+        let end = pos + buf.len() as u64;
+        let (start, mut data) = match self.ranges.range(..pos).next_back() {
+            Some((&start, data)) if start + data.len() as u64 >= pos => {
+                (start, self.ranges.remove(&start).unwrap())
+            }
+            _ => (pos, Vec::new()),
+        };
+        self.buffered -= data.len();
+        let offset = (pos - start) as usize;
+        if data.len() <= offset + buf.len() {
+            data.truncate(offset);
+            data.extend_from_slice(buf);
+        } else {
+            data[offset..offset + buf.len()].copy_from_slice(buf);
+        }
+        // Only the last range starting within the write can reach past it,
+        // and then the range before it cannot.
+        let later: Vec<u64> = self.ranges.range(pos..=end).map(|(&start, _)| start).collect();
+        for other_start in later {
+            let other = self.ranges.remove(&other_start).unwrap();
+            self.buffered -= other.len();
+            if other_start + other.len() as u64 > end {
+                data.extend_from_slice(&other[(end - other_start) as usize..]);
+            }
+        }
+        self.buffered += data.len();
+        self.ranges.insert(start, data);
+    }
+
+    /// Buffers `buf` at `pos`, or writes it to the target with `f` if it
+    /// is too large or `direct` is set.
+    fn write_with<F>(&mut self, pos: u64, buf: &[u8], direct: bool, f: F) -> Result<usize>
+        where F: FnOnce(&mut T, u64, &[u8]) -> Result<usize>
+    {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let end = match pos.checked_add(buf.len() as u64) {
+            Some(end) => end,
+            None => return Err(Error::OutOfBounds { offset: pos, len: buf.len() as u64 }.into()),
+        };
+        if direct {
+            self.write_ranges(pos, end)?;
+            return f(&mut self.inner, pos, buf);
+        }
+        if self.buffered + buf.len() > self.capacity {
+            self.write_ranges(0, u64::MAX)?;
+        }
+        if buf.len() >= self.capacity {
+            return f(&mut self.inner, pos, buf);
+        }
+        self.insert(pos, buf);
+        Ok(buf.len())
+    }
+
+    /// Returns the position just past the last buffered byte.
+    fn buffered_end(&self) -> u64 {
+        self.ranges.iter().next_back().map_or(0, |(&start, data)| start + data.len() as u64)
+    }
+}
+
+impl<T: WriteAt> Drop for BufWriterAt<T> {
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            let _ = self.write_ranges(0, u64::MAX);
+        }
+    }
+}
+
+impl<T: WriteAt> WriteAt for BufWriterAt<T> {
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        self.write_with(pos, buf, false, |inner, pos, buf| inner.write_at(pos, buf))
+    }
+
+    /// The options are used for writes that go straight to the target.
+    /// Writes with `dsync` set are never buffered, so they are on the
+    /// device when this returns.
+    fn write_at_with(&mut self, pos: u64, buf: &[u8], options: &IoOptions) -> Result<usize> {
+        self.write_with(pos, buf, options.dsync, |inner, pos, buf| inner.write_at_with(pos, buf, options))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.write_ranges(0, u64::MAX)?;
+        self.inner.flush()
+    }
+
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        let end = pos.checked_add(len).ok_or(Error::OutOfBounds { offset: pos, len })?;
+        self.write_ranges(pos, end)?;
+        self.inner.write_zeroes_at(pos, len)
+    }
+}
+
+impl<T: ReadAt + WriteAt> ReadAt for BufWriterAt<T> {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        self.write_ranges(pos, pos.saturating_add(buf.len() as u64))?;
+        self.inner.read_at(pos, buf)
+    }
+
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        self.write_ranges(pos, pos.saturating_add(buf.len() as u64))?;
+        self.inner.read_at_with(pos, buf, options)
+    }
+}
+
+/// The end is that of the target or of the buffered data, whichever comes
+/// last.
+impl<T: KnownEof + WriteAt> KnownEof for BufWriterAt<T> {
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        let buffered = self.buffered_end().saturating_sub(pos);
+        Ok(cmp::max(buffered, self.inner.remaining_at(pos)?))
+    }
+}
+
+/// The size is that of the target or the end of the buffered data,
+/// whichever is larger.
+impl<T: SizeAt + WriteAt> SizeAt for BufWriterAt<T> {
+    fn size(&self) -> Result<u64> {
+        Ok(cmp::max(self.buffered_end(), self.inner.size()?))
+    }
+}
+
+/// Drops the buffered data past the new length before resizing the
+/// target.
+impl<T: ResizeAt + WriteAt> ResizeAt for BufWriterAt<T> {
+    fn set_len(&mut self, len: u64) -> Result<()> {
+        for (_, data) in self.ranges.split_off(&len) {
+            self.buffered -= data.len();
+        }
+        if let Some((&start, data)) = self.ranges.iter_mut().next_back() {
+            if start + data.len() as u64 > len {
+                let keep = (len - start) as usize;
+                self.buffered -= data.len() - keep;
+                data.truncate(keep);
+            }
+        }
+        self.inner.set_len(len)
+    }
+}
+
+impl<T: SyncAt + WriteAt> SyncAt for BufWriterAt<T> {
+    fn sync_all(&mut self) -> Result<()> {
+        self.write_ranges(0, u64::MAX)?;
+        self.inner.sync_all()
+    }
+
+    fn sync_data(&mut self) -> Result<()> {
+        self.write_ranges(0, u64::MAX)?;
+        self.inner.sync_data()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A target recording the writes issued to it.
+    #[derive(Debug, Default)]
+    struct Recorder {
+        data: Vec<u8>,
+        writes: Vec<(u64, usize)>,
+    }
+
+    impl WriteAt for Recorder {
+        fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+            self.writes.push((pos, buf.len()));
+            self.data.write_at(pos, buf)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    impl ReadAt for Recorder {
+        fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+            self.data.read_at(pos, buf)
+        }
+    }
+
+    impl SizeAt for Recorder {
+        fn size(&self) -> Result<u64> {
+            Ok(self.data.len() as u64)
+        }
+    }
+
+    fn writer() -> BufWriterAt<Recorder> {
+        BufWriterAt::with_capacity(64, Recorder::default())
+    }
+
+    #[test]
+    fn merges_overlapping_and_touching_writes() {
+        let mut w = writer();
+        w.write_all_at(0, b"aaaa").unwrap();
+        w.write_all_at(2, b"bbbb").unwrap();
+        w.write_all_at(6, b"cc").unwrap();
+        w.write_all_at(20, b"dd").unwrap();
+        assert_eq!(w.buffered_ranges(), 2);
+        assert_eq!(w.buffered(), 10);
+        assert!(w.get_ref().writes.is_empty());
+        w.flush().unwrap();
+        assert_eq!(w.get_ref().writes, [(0, 8), (20, 2)]);
+        assert_eq!(&w.get_ref().data[..8], b"aabbbbcc");
+        assert_eq!(w.buffered(), 0);
+    }
+
+    #[test]
+    fn write_spanning_ranges() {
+        let mut w = writer();
+        w.write_all_at(4, b"11").unwrap();
+        w.write_all_at(10, b"22").unwrap();
+        w.write_all_at(16, b"333333").unwrap();
+        // Covers the first two ranges and the start of the third.
+        w.write_all_at(3, b"xxxxxxxxxxxxxxx").unwrap();
+        assert_eq!(w.buffered_ranges(), 1);
+        assert_eq!(w.buffered(), 19);
+        // Lands inside the merged range.
+        w.write_all_at(5, b"y").unwrap();
+        assert_eq!(w.buffered(), 19);
+        w.flush().unwrap();
+        assert_eq!(w.get_ref().writes, [(3, 19)]);
+        assert_eq!(&w.get_ref().data[3..], b"xxyxxxxxxxxxxxx3333");
+    }
+
+    #[test]
+    fn reads_see_buffered_data() {
+        let mut w = writer();
+        w.write_all_at(0, b"abcd").unwrap();
+        w.write_all_at(10, b"efgh").unwrap();
+        w.write_all_at(30, b"ijkl").unwrap();
+        let mut buf = [0; 2];
+        w.read_exact_at(12, &mut buf).unwrap();
+        assert_eq!(&buf, b"gh");
+        // Only the range the read overlaps was written out.
+        assert_eq!(w.get_ref().writes, [(10, 4)]);
+        assert_eq!(w.buffered_ranges(), 2);
+        assert_eq!(w.size().unwrap(), 34);
+    }
+
+    #[test]
+    fn dsync_writes_are_not_buffered() {
+        let mut w = writer();
+        w.write_all_at(0, b"aaaa").unwrap();
+        w.write_all_at(10, b"bb").unwrap();
+        let options = IoOptions { dsync: true, ..IoOptions::default() };
+        assert_eq!(w.write_at_with(2, b"cc", &options).unwrap(), 2);
+        assert_eq!(w.get_ref().writes, [(0, 4), (2, 2)]);
+        assert_eq!(w.buffered_ranges(), 1);
+        w.flush().unwrap();
+        assert_eq!(&w.get_ref().data[..4], b"aacc");
+    }
+
+    #[test]
+    fn capacity() {
+        let mut w = writer();
+        w.write_all_at(0, &[1; 40]).unwrap();
+        w.write_all_at(100, &[2; 30]).unwrap();
+        // The buffer was written out to make room.
+        assert_eq!(w.get_ref().writes, [(0, 40)]);
+        assert_eq!(w.buffered(), 30);
+        w.write_all_at(200, &[3; 64]).unwrap();
+        assert_eq!(w.get_ref().writes, [(0, 40), (100, 30), (200, 64)]);
+        assert_eq!(w.buffered(), 0);
+    }
+
+    #[test]
+    fn set_len_drops_buffered_data() {
+        let mut w = BufWriterAt::with_capacity(64, Vec::new());
+        w.write_all_at(0, b"abcdef").unwrap();
+        w.write_all_at(10, b"gh").unwrap();
+        w.set_len(4).unwrap();
+        assert_eq!(w.buffered(), 4);
+        assert_eq!(w.into_inner().unwrap(), b"abcd");
+    }
+
+    #[test]
+    fn drop_writes_out() {
+        let mut data = vec![0; 8];
+        {
+            let mut w = BufWriterAt::new(&mut data[..]);
+            w.write_all_at(2, b"xy").unwrap();
+        }
+        assert_eq!(data, b"\0\0xy\0\0\0\0");
+    }
+}
@@ -0,0 +1,137 @@
+use std::cmp;
+use std::io::{self, ErrorKind, Result};
+
+use bytes::{Bytes, BytesMut};
+
+use {Error, KnownEof, ReadAt, ReadBuf, ResizeAt, SizeAt, WriteAt};
+
+impl ReadAt for Bytes {
+    #[inline]
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        (&self[..]).read_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
+        (&self[..]).read_exact_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_to_end_at(&mut self, pos: u64, buf: &mut Vec<u8>) -> Result<usize> {
+        (&self[..]).read_to_end_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_buf_at(&mut self, pos: u64, buf: &mut ReadBuf) -> Result<()> {
+        (&self[..]).read_buf_at(pos, buf)
+    }
+
+    /// Returns a slice sharing the buffer, without copying.
+    fn read_bytes_at(&mut self, pos: u64, len: usize) -> Result<Bytes> {
+        match pos.checked_add(len as u64) {
+            Some(end) if end <= self.len() as u64 => Ok(self.slice(pos as usize..end as usize)),
+            _ => Err(io::Error::new(ErrorKind::UnexpectedEof, "failed to fill whole buffer")),
+        }
+    }
+}
+
+impl ReadAt for BytesMut {
+    #[inline]
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        (&self[..]).read_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
+        (&self[..]).read_exact_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_to_end_at(&mut self, pos: u64, buf: &mut Vec<u8>) -> Result<usize> {
+        (&self[..]).read_to_end_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_buf_at(&mut self, pos: u64, buf: &mut ReadBuf) -> Result<()> {
+        (&self[..]).read_buf_at(pos, buf)
+    }
+}
+
+/// Writes past the end grow the buffer, like for `Vec<u8>`.
+impl WriteAt for BytesMut {
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        if pos >= usize::MAX as u64 {
+            return Ok(0);
+        }
+        let i = pos as usize;
+        let n = cmp::min(usize::MAX - i, buf.len());
+        if i + n > self.len() {
+            self.resize(i + n, 0);
+        }
+        self[i..i + n].copy_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_all_at(&mut self, pos: u64, buf: &[u8]) -> Result<()> {
+        if self.write_at(pos, buf)? < buf.len() {
+            Err(io::Error::new(ErrorKind::WriteZero, "failed to write whole buffer"))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        let end = match pos.checked_add(len) {
+            Some(end) if end <= usize::MAX as u64 => end as usize,
+            _ => return Err(Error::OutOfBounds { offset: pos, len }.into()),
+        };
+        if end > self.len() {
+            self.resize(end, 0);
+        }
+        self[pos as usize..end].fill(0);
+        Ok(())
+    }
+}
+
+impl KnownEof for Bytes {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        Ok((self.len() as u64).saturating_sub(pos))
+    }
+}
+
+impl KnownEof for BytesMut {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        Ok((self.len() as u64).saturating_sub(pos))
+    }
+}
+
+impl SizeAt for Bytes {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        Ok(self.len() as u64)
+    }
+}
+
+impl SizeAt for BytesMut {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        Ok(self.len() as u64)
+    }
+}
+
+impl ResizeAt for BytesMut {
+    fn set_len(&mut self, len: u64) -> Result<()> {
+        if len > usize::MAX as u64 {
+            return Err(Error::OutOfBounds { offset: 0, len }.into());
+        }
+        self.resize(len as usize, 0);
+        Ok(())
+    }
+}
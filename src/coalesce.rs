@@ -0,0 +1,327 @@
+use std::cmp;
+use std::io::{Error, ErrorKind, Result};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+use {IoOptions, ReadAt, read_opt};
+
+type Outcome = ::std::result::Result<Arc<Vec<u8>>, (ErrorKind, String)>;
+
+#[derive(Debug)]
+struct Flight {
+    pos: u64,
+    len: usize,
+    outcome: Mutex<Option<Outcome>>,
+    done: Condvar,
+}
+
+/// A wrapper that coalesces concurrent reads of the same range.
+///
+/// `Coalesce` is meant to be shared between threads, e.g. in an `Arc`,
+/// and implements [`ReadAt`](trait.ReadAt.html) for `&Coalesce<T>`.
+/// When a read starts inside the range of a read that is already in
+/// flight, it waits for that read to complete and is served from its
+/// result instead of issuing another read to the inner source. If the
+/// requested range extends beyond the range in flight, only the
+/// overlapping prefix is returned, as allowed for `read_at`.
+///
+/// Reads that do not overlap a read in flight are issued to the inner
+/// source one at a time, since it is guarded by a mutex.
+#[derive(Debug)]
+pub struct Coalesce<T> {
+    inner: Mutex<T>,
+    in_flight: Mutex<Vec<Arc<Flight>>>,
+}
+
+impl<T: ReadAt> Coalesce<T> {
+    /// Creates a new coalescing wrapper around `inner`.
+    pub fn new(inner: T) -> Coalesce<T> {
+        Coalesce {
+            inner: Mutex::new(inner),
+            in_flight: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Consumes the wrapper, returning the inner source.
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Reads some bytes from `pos` bytes into the source, sharing the
+    /// result with any concurrent read of an overlapping range.
+    ///
+    /// # Errors
+    ///
+    /// If the shared read fails, every read waiting on it fails with an
+    /// error of the same kind.
+    pub fn read_at(&self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        self.read_with(pos, buf, None)
+    }
+
+    /// Like [`read_at`](#method.read_at), passing `options` along to the
+    /// inner source.
+    ///
+    /// A read served from a shared read gets the result of a read issued
+    /// with the options of whichever read started it.
+    pub fn read_at_with(&self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        self.read_with(pos, buf, Some(options))
+    }
+
+    fn read_with(&self, pos: u64, buf: &mut [u8], options: Option<&IoOptions>) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let (flight, leader) = {
+            let mut in_flight = lock(&self.in_flight);
+            let existing = in_flight.iter()
+                .find(|f| f.pos <= pos && pos - f.pos < f.len as u64)
+                .cloned();
+            match existing {
+                Some(flight) => (flight, false),
+                None => {
+                    let flight = Arc::new(Flight {
+                        pos,
+                        len: buf.len(),
+                        outcome: Mutex::new(None),
+                        done: Condvar::new(),
+                    });
+                    in_flight.push(flight.clone());
+                    (flight, true)
+                }
+            }
+        };
+
+        if leader {
+            let mut landing = Landing { owner: self, flight: &flight, outcome: None };
+            let mut data = vec![0; buf.len()];
+            let result = read_opt(&mut *lock(&self.inner), pos, &mut data, options);
+            landing.outcome = Some(match result {
+                Ok(n) => {
+                    data.truncate(n);
+                    Ok(Arc::new(data))
+                }
+                Err(e) => Err((e.kind(), e.to_string())),
+            });
+        }
+
+        let outcome = {
+            let mut outcome = lock(&flight.outcome);
+            while outcome.is_none() {
+                outcome = flight.done.wait(outcome).unwrap_or_else(|e| e.into_inner());
+            }
+            outcome.clone().unwrap()
+        };
+        match outcome {
+            Ok(data) => {
+                let offset = (pos - flight.pos) as usize;
+                if offset >= data.len() && !leader {
+                    // The shared read came up short of this range, so it
+                    // does not tell us anything about `pos`.
+                    return read_opt(&mut *lock(&self.inner), pos, buf, options);
+                }
+                let n = cmp::min(data.len().saturating_sub(offset), buf.len());
+                buf[..n].copy_from_slice(&data[offset..offset + n]);
+                Ok(n)
+            }
+            Err((kind, msg)) => Err(Error::new(kind, msg)),
+        }
+    }
+}
+
+impl<T: ReadAt> ReadAt for Coalesce<T> {
+    #[inline]
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        Coalesce::read_at(self, pos, buf)
+    }
+
+    #[inline]
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        Coalesce::read_at_with(self, pos, buf, options)
+    }
+}
+
+impl<T: ReadAt> ReadAt for &Coalesce<T> {
+    #[inline]
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        Coalesce::read_at(self, pos, buf)
+    }
+
+    #[inline]
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        Coalesce::read_at_with(self, pos, buf, options)
+    }
+}
+
+/// Publishes the outcome of a read in flight and wakes its waiters, even
+/// if the leading read panics.
+struct Landing<'a, T: 'a> {
+    owner: &'a Coalesce<T>,
+    flight: &'a Arc<Flight>,
+    outcome: Option<Outcome>,
+}
+
+impl<'a, T> Drop for Landing<'a, T> {
+    fn drop(&mut self) {
+        lock(&self.owner.in_flight).retain(|f| !Arc::ptr_eq(f, self.flight));
+        let outcome = self.outcome
+            .take()
+            .unwrap_or_else(|| Err((ErrorKind::Other, "coalesced read panicked".to_owned())));
+        *lock(&self.flight.outcome) = Some(outcome);
+        self.flight.done.notify_all();
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::mpsc::{self, Receiver, Sender};
+    use std::thread::{self, JoinHandle};
+
+    /// How a gated read completes.
+    enum Step {
+        Full,
+        Short(usize),
+        Fail,
+        Panic,
+    }
+
+    /// A source whose reads announce themselves and wait to be told how
+    /// to complete.
+    struct Gated {
+        data: Vec<u8>,
+        reads: usize,
+        entered: Sender<()>,
+        steps: Receiver<Step>,
+    }
+
+    impl ReadAt for Gated {
+        fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+            self.reads += 1;
+            self.entered.send(()).unwrap();
+            let n = match self.steps.recv().unwrap() {
+                Step::Full => buf.len(),
+                Step::Short(n) => n,
+                Step::Fail => return Err(Error::new(ErrorKind::TimedOut, "gated read failed")),
+                Step::Panic => panic!("gated read panicked"),
+            };
+            let pos = pos as usize;
+            buf[..n].copy_from_slice(&self.data[pos..pos + n]);
+            Ok(n)
+        }
+    }
+
+    struct Harness {
+        coalesce: Arc<Coalesce<Gated>>,
+        entered: Receiver<()>,
+        steps: Sender<Step>,
+    }
+
+    impl Harness {
+        fn new() -> Harness {
+            let (entered_tx, entered) = mpsc::channel();
+            let (steps, steps_rx) = mpsc::channel();
+            let gated = Gated { data: (0..64).collect(), reads: 0, entered: entered_tx, steps: steps_rx };
+            Harness { coalesce: Arc::new(Coalesce::new(gated)), entered, steps }
+        }
+
+        fn spawn_read(&self, pos: u64, len: usize) -> JoinHandle<Result<Vec<u8>>> {
+            let coalesce = self.coalesce.clone();
+            thread::spawn(move || {
+                let mut buf = vec![0; len];
+                let n = coalesce.read_at(pos, &mut buf)?;
+                buf.truncate(n);
+                Ok(buf)
+            })
+        }
+
+        /// Starts a leading read and waits until it reaches the source.
+        fn spawn_leader(&self, pos: u64, len: usize) -> JoinHandle<Result<Vec<u8>>> {
+            let leader = self.spawn_read(pos, len);
+            self.entered.recv().unwrap();
+            leader
+        }
+
+        /// Starts reads and waits until they all wait on the only read in
+        /// flight.
+        fn spawn_followers(&self, reads: &[(u64, usize)]) -> Vec<JoinHandle<Result<Vec<u8>>>> {
+            let followers = reads.iter().map(|&(pos, len)| self.spawn_read(pos, len)).collect();
+            // The list of reads in flight and the leader hold the flight
+            // too.
+            while lock(&self.coalesce.in_flight).first().map_or(0, Arc::strong_count) < 2 + reads.len() {
+                thread::yield_now();
+            }
+            followers
+        }
+
+        fn reads(self) -> usize {
+            match Arc::try_unwrap(self.coalesce) {
+                Ok(coalesce) => coalesce.into_inner().reads,
+                Err(_) => panic!("coalesce still shared"),
+            }
+        }
+    }
+
+    fn join(handle: JoinHandle<Result<Vec<u8>>>) -> Result<Vec<u8>> {
+        handle.join().unwrap()
+    }
+
+    #[test]
+    fn followers_share_the_leading_read() {
+        let harness = Harness::new();
+        let leader = harness.spawn_leader(0, 16);
+        let followers = harness.spawn_followers(&[(4, 8), (8, 16), (15, 1)]);
+        harness.steps.send(Step::Full).unwrap();
+        assert_eq!(join(leader).unwrap(), (0..16).collect::<Vec<u8>>());
+        let results: Vec<_> = followers.into_iter().map(|f| join(f).unwrap()).collect();
+        assert_eq!(results, [(4..12).collect::<Vec<u8>>(), (8..16).collect(), vec![15]]);
+        assert_eq!(harness.reads(), 1);
+    }
+
+    #[test]
+    fn leader_error_reaches_followers() {
+        let harness = Harness::new();
+        let leader = harness.spawn_leader(0, 16);
+        let followers = harness.spawn_followers(&[(2, 4), (10, 8)]);
+        harness.steps.send(Step::Fail).unwrap();
+        assert_eq!(join(leader).unwrap_err().kind(), ErrorKind::TimedOut);
+        for follower in followers {
+            assert_eq!(join(follower).unwrap_err().kind(), ErrorKind::TimedOut);
+        }
+        assert_eq!(harness.reads(), 1);
+    }
+
+    #[test]
+    fn short_leading_read() {
+        let harness = Harness::new();
+        let leader = harness.spawn_leader(0, 16);
+        let followers = harness.spawn_followers(&[(2, 4), (8, 4)]);
+        harness.steps.send(Step::Short(4)).unwrap();
+        assert_eq!(join(leader).unwrap(), [0, 1, 2, 3]);
+        // The read at 8 is past the short result, so it reads on its own.
+        harness.entered.recv().unwrap();
+        harness.steps.send(Step::Full).unwrap();
+        let results: Vec<_> = followers.into_iter().map(|f| join(f).unwrap()).collect();
+        assert_eq!(results, [vec![2, 3], vec![8, 9, 10, 11]]);
+        assert_eq!(harness.reads(), 2);
+    }
+
+    #[test]
+    fn leader_panic_releases_followers() {
+        let harness = Harness::new();
+        let leader = harness.spawn_leader(0, 16);
+        let followers = harness.spawn_followers(&[(4, 4)]);
+        harness.steps.send(Step::Panic).unwrap();
+        assert!(leader.join().is_err());
+        for follower in followers {
+            assert_eq!(join(follower).unwrap_err().kind(), ErrorKind::Other);
+        }
+        assert!(lock(&harness.coalesce.in_flight).is_empty());
+        let leader = harness.spawn_leader(0, 4);
+        harness.steps.send(Step::Full).unwrap();
+        assert_eq!(join(leader).unwrap(), [0, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,82 @@
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Publishes a new version of the file at `path` atomically, so readers
+/// and crashes only ever see the old or the new version in full.
+///
+/// The current contents of the file, if it exists, are copied to a
+/// temporary file in the same directory, and `write` is called to update
+/// any number of regions of that copy, e.g. through
+/// [`WriteAt`](trait.WriteAt.html). The copy is then synced to disk,
+/// renamed over `path`, and on Unix the directory is synced so the rename
+/// itself is durable. The new file keeps the permissions of the old one.
+///
+/// # Errors
+///
+/// Returns any error from `write` or the file system. If an error occurs
+/// before the rename, the temporary file is removed and `path` is left
+/// unchanged.
+pub fn commit_via_tempfile<P, F>(path: P, write: F) -> Result<()>
+    where P: AsRef<Path>,
+          F: FnOnce(&mut File) -> Result<()>
+{
+    let path = path.as_ref();
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let (mut file, temp) = temp_file(dir, path)?;
+    let result = (|| {
+        match File::open(path) {
+            Ok(mut old) => {
+                io::copy(&mut old, &mut file)?;
+                file.set_permissions(old.metadata()?.permissions())?;
+            }
+            Err(ref e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        write(&mut file)?;
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&temp, path)
+    })();
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+    sync_dir(dir)
+}
+
+/// Creates a new file next to `path`, with a unique name.
+fn temp_file(dir: &Path, path: &Path) -> Result<(File, PathBuf)> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let base = path.file_name().ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "path has no file name"))?;
+    loop {
+        let mut name = OsString::from(".");
+        name.push(base);
+        name.push(format!(".ioat-commit-{}-{}", process::id(), COUNTER.fetch_add(1, Ordering::Relaxed)));
+        let temp = dir.join(name);
+        match OpenOptions::new().read(true).write(true).create_new(true).open(&temp) {
+            Ok(file) => return Ok((file, temp)),
+            Err(ref e) if e.kind() == ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+/// Directories cannot be opened for syncing on other platforms, so the
+/// durability of the rename is left to the file system's metadata journal.
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> Result<()> {
+    Ok(())
+}
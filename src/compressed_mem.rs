@@ -0,0 +1,305 @@
+use std::cmp;
+use std::io::{self, ErrorKind, Result};
+use std::mem;
+
+use advise;
+use cow_mem::{is_zero, reserve_chunk};
+use extents;
+use lz4;
+use {Advice, Advise, Extent, Extents, KnownEof, ReadAt, ResizeAt, SizeAt, WriteAt};
+
+const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// A growable in-memory backend storing its data as LZ4-compressed chunks.
+///
+/// This keeps many large, mostly compressible images in memory at once,
+/// such as disk images compared by tests. The chunk last read or written
+/// is kept decompressed, and written chunks are only compressed again when
+/// another chunk is accessed or on [`flush`](trait.WriteAt.html), so
+/// sequential access only compresses each chunk once. Chunks that are all
+/// zeros take up no memory.
+#[derive(Clone, Debug)]
+pub struct CompressedMem {
+    chunk_size: usize,
+    len: u64,
+    chunks: Vec<Option<Box<[u8]>>>,
+    cached: Option<usize>,
+    dirty: bool,
+    cache: Vec<u8>,
+}
+
+impl CompressedMem {
+    /// Creates an empty backend with chunks of 64 KiB.
+    pub fn new() -> CompressedMem {
+        CompressedMem::with_chunk_size(DEFAULT_CHUNK_SIZE)
+    }
+
+    /// Creates an empty backend with chunks of `chunk_size` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero.
+    pub fn with_chunk_size(chunk_size: usize) -> CompressedMem {
+        assert!(chunk_size > 0, "chunk size must be non-zero");
+        CompressedMem {
+            chunk_size,
+            len: 0,
+            chunks: Vec::new(),
+            cached: None,
+            dirty: false,
+            cache: Vec::new(),
+        }
+    }
+
+    /// Returns the length of the backend in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns whether the backend is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of bytes taken up by compressed chunks.
+    ///
+    /// Writes to the decompressed chunk are only counted once it has been
+    /// compressed again, such as after a flush.
+    pub fn compressed_size(&self) -> u64 {
+        self.chunks.iter().flatten().map(|c| c.len() as u64).sum()
+    }
+
+    fn locate(&self, pos: u64, len: usize) -> (usize, usize, usize) {
+        let chunk = (pos / self.chunk_size as u64) as usize;
+        let offset = (pos % self.chunk_size as u64) as usize;
+        (chunk, offset, cmp::min(len, self.chunk_size - offset))
+    }
+
+    /// Compresses the decompressed chunk back into place, if it was
+    /// written.
+    fn store(&mut self) {
+        if let (Some(chunk), true) = (self.cached, self.dirty) {
+            self.chunks[chunk] = if is_zero(&self.cache) {
+                None
+            } else {
+                Some(lz4::compress(&self.cache).into_boxed_slice())
+            };
+            self.dirty = false;
+        }
+    }
+
+    /// Makes `chunk` the decompressed chunk.
+    fn load(&mut self, chunk: usize) -> Result<()> {
+        if self.cached == Some(chunk) {
+            return Ok(());
+        }
+        self.store();
+        self.cached = None;
+        match self.chunks.get(chunk) {
+            Some(Some(data)) => {
+                let mut cache = mem::take(&mut self.cache);
+                let ok = lz4::decompress(data, &mut cache, self.chunk_size).is_some();
+                self.cache = cache;
+                if !ok || self.cache.len() != self.chunk_size {
+                    return Err(io::Error::new(ErrorKind::InvalidData, "corrupt compressed chunk"));
+                }
+            }
+            _ => {
+                self.cache.clear();
+                self.cache.resize(self.chunk_size, 0);
+            }
+        }
+        self.cached = Some(chunk);
+        Ok(())
+    }
+}
+
+impl Default for CompressedMem {
+    fn default() -> CompressedMem {
+        CompressedMem::new()
+    }
+}
+
+impl ReadAt for CompressedMem {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        if pos >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let remaining = cmp::min(self.len - pos, buf.len() as u64) as usize;
+        let (chunk, offset, n) = self.locate(pos, remaining);
+        if self.cached != Some(chunk) && !matches!(self.chunks.get(chunk), Some(Some(_))) {
+            for b in &mut buf[..n] {
+                *b = 0;
+            }
+        } else {
+            self.load(chunk)?;
+            buf[..n].copy_from_slice(&self.cache[offset..offset + n]);
+        }
+        Ok(n)
+    }
+}
+
+impl KnownEof for CompressedMem {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        Ok(self.len.saturating_sub(pos))
+    }
+}
+
+impl SizeAt for CompressedMem {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        Ok(self.len)
+    }
+}
+
+/// Shrinking releases the chunks past the new end, and extending adds
+/// chunks that take up no memory until written.
+impl ResizeAt for CompressedMem {
+    fn set_len(&mut self, len: u64) -> Result<()> {
+        if len < self.len {
+            let (chunk, offset, _) = self.locate(len, 0);
+            if offset != 0 && chunk < self.chunks.len() {
+                self.load(chunk)?;
+                for b in &mut self.cache[offset..] {
+                    *b = 0;
+                }
+                self.dirty = true;
+            }
+            let kept = if offset == 0 { chunk } else { chunk + 1 };
+            self.chunks.truncate(kept);
+            if self.cached.is_some_and(|c| c >= kept) {
+                self.cached = None;
+                self.dirty = false;
+            }
+        }
+        self.len = len;
+        Ok(())
+    }
+}
+
+impl WriteAt for CompressedMem {
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let (_, offset, n) = self.locate(pos, buf.len());
+        let chunk = reserve_chunk(&mut self.chunks, self.chunk_size, pos, n as u64)?;
+        self.load(chunk)?;
+        self.cache[offset..offset + n].copy_from_slice(&buf[..n]);
+        self.dirty = true;
+        self.len = cmp::max(self.len, pos + n as u64);
+        Ok(n)
+    }
+
+    /// Compresses the chunk written last.
+    fn flush(&mut self) -> Result<()> {
+        self.store();
+        Ok(())
+    }
+}
+
+/// `DontNeed` compresses the decompressed chunk back into place and frees
+/// it if it overlaps the range. Other advice is ignored.
+impl Advise for CompressedMem {
+    fn advise(&mut self, pos: u64, len: u64, advice: Advice) -> Result<()> {
+        if let (Advice::DontNeed, Some(chunk)) = (advice, self.cached) {
+            let start = chunk as u64 * self.chunk_size as u64;
+            if advise::overlaps(pos, len, start, start + self.chunk_size as u64) {
+                self.store();
+                self.cached = None;
+                self.cache = Vec::new();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Chunks that read as zeros and are not the decompressed chunk are
+/// reported as holes.
+impl Extents for CompressedMem {
+    fn extent_at(&mut self, pos: u64) -> Result<Option<Extent>> {
+        let (chunks, cached) = (&self.chunks, self.cached);
+        Ok(extents::chunked_extent(pos, self.len, self.chunk_size, |i| {
+            cached == Some(i) || matches!(chunks.get(i), Some(Some(_)))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 7) as u8 + 1).collect()
+    }
+
+    fn contents(mem: &mut CompressedMem) -> Vec<u8> {
+        let mut buf = Vec::new();
+        mem.read_to_end_at(0, &mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn round_trip_across_chunks() {
+        let mut mem = CompressedMem::with_chunk_size(16);
+        let data = pattern(60);
+        mem.write_all_at(5, &data).unwrap();
+        let mut buf = vec![0; 60];
+        mem.read_exact_at(5, &mut buf).unwrap();
+        assert_eq!(buf, data);
+        mem.flush().unwrap();
+        mem.advise(0, 80, Advice::DontNeed).unwrap();
+        assert!(mem.compressed_size() > 0);
+        let mut expected = vec![0; 5];
+        expected.extend_from_slice(&data);
+        assert_eq!(contents(&mut mem), expected);
+    }
+
+    #[test]
+    fn set_len_zeroes_truncated_tail() {
+        let mut mem = CompressedMem::with_chunk_size(16);
+        mem.write_all_at(0, &[0xff; 40]).unwrap();
+        // Leave the chunk being cut compressed, so it is loaded to zero it.
+        mem.read_exact_at(0, &mut [0; 1]).unwrap();
+        mem.set_len(20).unwrap();
+        mem.set_len(40).unwrap();
+        let mut expected = vec![0xff; 20];
+        expected.resize(40, 0);
+        assert_eq!(contents(&mut mem), expected);
+        mem.flush().unwrap();
+        mem.advise(0, 40, Advice::DontNeed).unwrap();
+        assert_eq!(contents(&mut mem), expected);
+    }
+
+    #[test]
+    fn zero_chunks_take_no_memory() {
+        let mut mem = CompressedMem::with_chunk_size(16);
+        mem.write_all_at(0, &pattern(16)).unwrap();
+        mem.write_all_at(16, &[0; 16]).unwrap();
+        mem.write_all_at(32, &pattern(16)).unwrap();
+        mem.write_all_at(32, &[0; 16]).unwrap();
+        mem.flush().unwrap();
+        assert!(mem.chunks[0].is_some());
+        assert!(mem.chunks[1].is_none());
+        assert!(mem.chunks[2].is_none());
+        assert_eq!(mem.len(), 48);
+        assert_eq!(contents(&mut mem)[16..], [0; 32]);
+    }
+
+    #[test]
+    fn corrupt_chunk() {
+        let mut mem = CompressedMem::with_chunk_size(16);
+        mem.write_all_at(0, &pattern(32)).unwrap();
+        mem.flush().unwrap();
+        mem.advise(0, 32, Advice::DontNeed).unwrap();
+        mem.chunks[0] = Some(vec![0xff; 8].into_boxed_slice());
+        mem.chunks[1] = Some(lz4::compress(&pattern(8)).into_boxed_slice());
+        for pos in [0, 16] {
+            let err = mem.read_at(pos, &mut [0; 4]).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+            let err = mem.write_at(pos, &[1]).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+        }
+    }
+}
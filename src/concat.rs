@@ -0,0 +1,226 @@
+use std::cmp;
+use std::io::Result;
+
+use {Error, IoOptions, KnownEof, ReadAt, SizeAt, SyncAt, WriteAt};
+
+/// A backend made of fixed-size segments laid end to end, such as the
+/// volumes of a split archive or the chunks of a stored object.
+///
+/// Each segment covers as many bytes as it was added with, whatever the
+/// actual size of its inner value, and requests are routed to the
+/// segments they fall in. A request crossing from one segment into the
+/// next is a [split request](trait.ReadAt.html#split-requests), so a
+/// segment shorter than its declared length cuts reads short at its
+/// actual end. Writes cannot grow the backend, and are cut short at its
+/// end.
+#[derive(Clone, Debug, Default)]
+pub struct Concat<T> {
+    segments: Vec<(T, u64)>,
+    starts: Vec<u64>,
+    len: u64,
+}
+
+impl<T> Concat<T> {
+    /// Creates a backend from segments and their lengths, in order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the total length does not fit in a `u64`.
+    pub fn new(segments: Vec<(T, u64)>) -> Concat<T> {
+        let mut concat = Concat {
+            segments: Vec::with_capacity(segments.len()),
+            starts: Vec::with_capacity(segments.len()),
+            len: 0,
+        };
+        for (segment, len) in segments {
+            concat.push(segment, len);
+        }
+        concat
+    }
+
+    /// Appends a segment of `len` bytes at the end.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the total length does not fit in a `u64`.
+    pub fn push(&mut self, segment: T, len: u64) {
+        self.starts.push(self.len);
+        self.len = self.len.checked_add(len).expect("total length overflows");
+        self.segments.push((segment, len));
+    }
+
+    /// Returns the total length in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns whether the total length is zero.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the segments and their lengths.
+    pub fn segments(&self) -> &[(T, u64)] {
+        &self.segments
+    }
+
+    /// Returns a mutable reference to the segment at `index`.
+    pub fn segment_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.segments.get_mut(index).map(|s| &mut s.0)
+    }
+
+    /// Consumes the backend, returning the segments and their lengths.
+    pub fn into_inner(self) -> Vec<(T, u64)> {
+        self.segments
+    }
+
+    /// Returns the index of the segment holding `pos`, which must be
+    /// before the end.
+    fn locate(&self, pos: u64) -> usize {
+        // Empty segments share their start with the next one, so the last
+        // segment starting at or before `pos` is the one holding it.
+        self.starts.partition_point(|&start| start <= pos) - 1
+    }
+
+    fn each<F>(&mut self, pos: u64, len: usize, mut f: F) -> Result<usize>
+        where F: FnMut(&mut T, u64, usize, usize) -> Result<usize>
+    {
+        let len = cmp::min(len as u64, self.len.saturating_sub(pos)) as usize;
+        let mut done = 0;
+        while done < len {
+            let cur = pos + done as u64;
+            let i = self.locate(cur);
+            let offset = cur - self.starts[i];
+            let (ref mut segment, seg_len) = self.segments[i];
+            let n = cmp::min((len - done) as u64, seg_len - offset) as usize;
+            match f(segment, offset, done, n) {
+                Ok(m) => {
+                    done += m;
+                    if m < n {
+                        break;
+                    }
+                }
+                Err(_) if done > 0 => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(done)
+    }
+}
+
+impl<T: ReadAt> ReadAt for Concat<T> {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        self.each(pos, buf.len(), |segment, pos, i, n| segment.read_at(pos, &mut buf[i..i + n]))
+    }
+
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        self.each(pos, buf.len(), |segment, pos, i, n| segment.read_at_with(pos, &mut buf[i..i + n], options))
+    }
+}
+
+impl<T: WriteAt> WriteAt for Concat<T> {
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        self.each(pos, buf.len(), |segment, pos, i, n| segment.write_at(pos, &buf[i..i + n]))
+    }
+
+    fn write_at_with(&mut self, pos: u64, buf: &[u8], options: &IoOptions) -> Result<usize> {
+        self.each(pos, buf.len(), |segment, pos, i, n| segment.write_at_with(pos, &buf[i..i + n], options))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        for &mut (ref mut segment, _) in &mut self.segments {
+            segment.flush()?;
+        }
+        Ok(())
+    }
+
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        if pos.checked_add(len).is_none_or(|end| end > self.len) {
+            return Err(Error::OutOfBounds { offset: pos, len }.into());
+        }
+        let mut done = 0;
+        while done < len {
+            let cur = pos + done;
+            let i = self.locate(cur);
+            let offset = cur - self.starts[i];
+            let (ref mut segment, seg_len) = self.segments[i];
+            let n = cmp::min(len - done, seg_len - offset);
+            segment.write_zeroes_at(offset, n)?;
+            done += n;
+        }
+        Ok(())
+    }
+}
+
+/// The end is where reads stop: the end of the first segment from `pos`
+/// on that is shorter than its declared length, or the total length.
+impl<T: KnownEof> KnownEof for Concat<T> {
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        if pos >= self.len {
+            return Ok(0);
+        }
+        let mut remaining = 0;
+        let mut cur = pos;
+        for i in self.locate(pos)..self.segments.len() {
+            let offset = cur - self.starts[i];
+            let (ref mut segment, seg_len) = self.segments[i];
+            let n = cmp::min(segment.remaining_at(offset)?, seg_len - offset);
+            remaining += n;
+            if n < seg_len - offset {
+                break;
+            }
+            cur += n;
+        }
+        Ok(remaining)
+    }
+}
+
+/// The size is the total declared length, even if a segment is shorter.
+impl<T> SizeAt for Concat<T> {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        Ok(self.len)
+    }
+}
+
+impl<T: SyncAt> SyncAt for Concat<T> {
+    fn sync_all(&mut self) -> Result<()> {
+        for &mut (ref mut segment, _) in &mut self.segments {
+            segment.sync_all()?;
+        }
+        Ok(())
+    }
+
+    fn sync_data(&mut self) -> Result<()> {
+        for &mut (ref mut segment, _) in &mut self.segments {
+            segment.sync_data()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remaining_stops_at_short_segment() {
+        let mut concat = Concat::new(vec![(&b"abcd"[..], 4), (&b"ef"[..], 4), (&b"ghij"[..], 4)]);
+        assert_eq!(concat.remaining_at(0).unwrap(), 6);
+        assert_eq!(concat.remaining_at(5).unwrap(), 1);
+        assert_eq!(concat.remaining_at(6).unwrap(), 0);
+        assert_eq!(concat.remaining_at(8).unwrap(), 4);
+        assert_eq!(concat.remaining_at(12).unwrap(), 0);
+        let mut buf = [0; 12];
+        assert_eq!(concat.read_at(0, &mut buf).unwrap(), 6);
+        assert_eq!(concat.read_at(8, &mut buf).unwrap(), 4);
+        assert_eq!(concat.size().unwrap(), 12);
+    }
+
+    #[test]
+    fn remaining_skips_empty_segments() {
+        let mut concat = Concat::new(vec![(&b"ab"[..], 2), (&b""[..], 0), (&b"cdef"[..], 3)]);
+        assert_eq!(concat.remaining_at(0).unwrap(), 5);
+        assert_eq!(concat.remaining_at(2).unwrap(), 3);
+    }
+}
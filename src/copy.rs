@@ -0,0 +1,55 @@
+use std::cmp;
+use std::io::{ErrorKind, Result};
+
+use {Error, ReadAt, WriteAt};
+
+const BUF_SIZE: usize = 64 * 1024;
+
+/// Copies `len` bytes at `src_pos` in `src` to `dst_pos` in `dst`, and
+/// returns the number of bytes copied.
+///
+/// The data is copied through a single buffer of at most 64 KiB. Short
+/// reads and writes are continued where they left off, and interrupted
+/// calls are retried. If `src` ends before `len` bytes have been read, the
+/// bytes up to its end are copied, and their number is returned.
+///
+/// # Errors
+///
+/// Returns [`Error::OutOfBounds`](enum.Error.html) if either range
+/// overflows a `u64`, an error of kind `WriteZero` if `dst` stops
+/// accepting data, and any error from `src` or `dst`. Data may have been
+/// partially copied when an error is returned.
+pub fn copy_at<R, W>(src: &mut R, src_pos: u64, dst: &mut W, dst_pos: u64, len: u64) -> Result<u64>
+    where R: ReadAt + ?Sized,
+          W: WriteAt + ?Sized
+{
+    if dst_pos.checked_add(len).is_none() {
+        return Err(Error::OutOfBounds { offset: dst_pos, len }.into());
+    }
+    let mut buf = vec![0; cmp::min(len, BUF_SIZE as u64) as usize];
+    copy_with(src, src_pos, len, &mut buf, |done, data| dst.write_all_at(dst_pos + done, data))
+}
+
+/// Copies `len` bytes at `src_pos` in `src` through `buf`, passing each
+/// chunk to `write` along with its position relative to `src_pos`.
+pub(crate) fn copy_with<R, F>(src: &mut R, src_pos: u64, len: u64, buf: &mut [u8], mut write: F) -> Result<u64>
+    where R: ReadAt + ?Sized,
+          F: FnMut(u64, &[u8]) -> Result<()>
+{
+    if src_pos.checked_add(len).is_none() {
+        return Err(Error::OutOfBounds { offset: src_pos, len }.into());
+    }
+    let mut done = 0;
+    while done < len {
+        let want = cmp::min(len - done, buf.len() as u64) as usize;
+        let n = match src.read_at(src_pos + done, &mut buf[..want]) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        write(done, &buf[..n])?;
+        done += n as u64;
+    }
+    Ok(done)
+}
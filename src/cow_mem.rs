@@ -0,0 +1,434 @@
+use std::cmp;
+use std::convert::TryFrom;
+use std::io::{self, Result};
+use std::sync::Arc;
+
+use extents;
+use {Error, Extent, Extents, KnownEof, PunchHole, RangeSet, ReadAt, ResizeAt, SizeAt, WriteAt};
+
+const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// A growable in-memory backend made of reference-counted chunks, which
+/// can be frozen into cheap, read-only snapshots.
+///
+/// [`freeze`](#method.freeze) only copies the list of chunks. Chunks are
+/// shared between the backend and its snapshots until the backend writes
+/// to them, at which point the written chunk is copied. Chunks that have
+/// never been written take up no memory and read as zeros.
+#[derive(Clone, Debug)]
+pub struct CowMem {
+    chunk_size: usize,
+    detect_zeroes: bool,
+    len: u64,
+    chunks: Vec<Option<Arc<Vec<u8>>>>,
+}
+
+/// A read-only snapshot of a [`CowMem`](struct.CowMem.html).
+///
+/// Snapshots can be cloned and sent between threads, and do not change
+/// when the backend they were frozen from is written to.
+#[derive(Clone, Debug)]
+pub struct MemSnapshot {
+    inner: CowMem,
+}
+
+impl CowMem {
+    /// Creates an empty backend with chunks of 64 KiB.
+    pub fn new() -> CowMem {
+        CowMem::with_chunk_size(DEFAULT_CHUNK_SIZE)
+    }
+
+    /// Creates an empty backend with chunks of `chunk_size` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero.
+    pub fn with_chunk_size(chunk_size: usize) -> CowMem {
+        assert!(chunk_size > 0, "chunk size must be non-zero");
+        CowMem {
+            chunk_size,
+            detect_zeroes: false,
+            len: 0,
+            chunks: Vec::new(),
+        }
+    }
+
+    /// Returns the length of the backend in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns whether the backend is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Sets whether writes leaving a chunk all zeros release the chunk.
+    ///
+    /// Released chunks take up no memory and read as zeros, as if they had
+    /// never been written, which keeps the backend compact when restoring
+    /// sparse images. Checking for zeros costs a scan of the written bytes,
+    /// and of the rest of the chunk if they are all zeros. This is off by
+    /// default.
+    pub fn set_detect_zeroes(&mut self, detect_zeroes: bool) {
+        self.detect_zeroes = detect_zeroes;
+    }
+
+    /// Returns a snapshot of the current contents.
+    pub fn freeze(&self) -> MemSnapshot {
+        MemSnapshot { inner: self.clone() }
+    }
+
+    /// Returns whether any chunk overlapping `len` bytes at `pos` has been
+    /// written.
+    ///
+    /// Chunks that were never written read as zeros, so tools copying the
+    /// backend can skip ranges for which this returns `false`.
+    pub fn is_written(&self, pos: u64, len: u64) -> bool {
+        let end = cmp::min(pos.saturating_add(len), self.len);
+        if pos >= end {
+            return false;
+        }
+        let first = (pos / self.chunk_size as u64) as usize;
+        let last = ((end - 1) / self.chunk_size as u64) as usize;
+        self.chunks.iter().take(last + 1).skip(first).any(Option::is_some)
+    }
+
+    /// Returns the ranges covered by written chunks, clamped to the length
+    /// of the backend.
+    pub fn written_extents(&self) -> RangeSet {
+        self.extents_where(|_| true)
+    }
+
+    /// Returns the ranges covered by chunks shared with snapshots, or with
+    /// other parts of the backend through
+    /// [`clone_range`](#method.clone_range), clamped to the length of the
+    /// backend.
+    ///
+    /// Shared chunks take up memory only once, and are copied when the
+    /// backend writes to them.
+    pub fn shared_extents(&self) -> RangeSet {
+        self.extents_where(|data| Arc::strong_count(data) > 1)
+    }
+
+    /// Copies `len` bytes at `src_pos` of `src` to `dst_pos`, sharing whole
+    /// chunks instead of copying their bytes.
+    ///
+    /// Like a reflink copy on XFS or Btrfs, shared chunks take up no more
+    /// memory until either side writes to them. Chunks are only shared
+    /// where both ranges line up with chunk boundaries and `src` has the
+    /// same chunk size; the rest of the range is copied. To clone a range
+    /// within the backend, clone it from a snapshot frozen from the
+    /// backend.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::OutOfBounds`](enum.Error.html) if the range reaches
+    /// past the end of `src`, or past the largest offset at `dst_pos`.
+    pub fn clone_range(&mut self, src: &MemSnapshot, src_pos: u64, dst_pos: u64, len: u64) -> Result<()> {
+        let src = &src.inner;
+        if src_pos.checked_add(len).is_none_or(|end| end > src.len) {
+            return Err(Error::OutOfBounds { offset: src_pos, len }.into());
+        }
+        if dst_pos.checked_add(len).is_none() {
+            return Err(Error::OutOfBounds { offset: dst_pos, len }.into());
+        }
+        let size = self.chunk_size as u64;
+        let mut done = 0;
+        while done < len {
+            let remaining = cmp::min(len - done, src.chunk_size as u64) as usize;
+            let (chunk, offset, n) = src.locate(src_pos + done, remaining);
+            let pos = dst_pos + done;
+            let data = src.chunks.get(chunk).and_then(Option::as_ref);
+            if src.chunk_size == self.chunk_size && n == self.chunk_size && offset == 0 && pos.is_multiple_of(size) {
+                let target = reserve_chunk(&mut self.chunks, self.chunk_size, pos, size)?;
+                self.chunks[target] = data.cloned();
+                self.len = cmp::max(self.len, pos + size);
+                done += size;
+                continue;
+            }
+            let n = cmp::min(n as u64, size - pos % size);
+            match data {
+                Some(data) => self.write_all_at(pos, &data[offset..offset + n as usize])?,
+                // Chunks that were never written already read as zeros.
+                None if self.is_written(pos, n) => self.write_zeroes_at(pos, n)?,
+                None => self.len = cmp::max(self.len, pos + n),
+            }
+            done += n;
+        }
+        Ok(())
+    }
+
+    fn extents_where<F: Fn(&Arc<Vec<u8>>) -> bool>(&self, f: F) -> RangeSet {
+        let mut extents = RangeSet::new();
+        let size = self.chunk_size as u64;
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            if chunk.as_ref().is_some_and(&f) {
+                let start = i as u64 * size;
+                extents.insert(start..cmp::min(start + size, self.len));
+            }
+        }
+        extents
+    }
+
+    fn locate(&self, pos: u64, len: usize) -> (usize, usize, usize) {
+        let chunk = (pos / self.chunk_size as u64) as usize;
+        let offset = (pos % self.chunk_size as u64) as usize;
+        (chunk, offset, cmp::min(len, self.chunk_size - offset))
+    }
+}
+
+impl Default for CowMem {
+    fn default() -> CowMem {
+        CowMem::new()
+    }
+}
+
+impl From<Vec<u8>> for CowMem {
+    fn from(data: Vec<u8>) -> CowMem {
+        let mut mem = CowMem::new();
+        mem.len = data.len() as u64;
+        mem.chunks = data.chunks(mem.chunk_size)
+            .map(|c| {
+                let mut chunk = c.to_vec();
+                chunk.resize(mem.chunk_size, 0);
+                Some(Arc::new(chunk))
+            })
+            .collect();
+        mem
+    }
+}
+
+impl MemSnapshot {
+    /// Returns the length of the snapshot in bytes.
+    pub fn len(&self) -> u64 {
+        self.inner.len
+    }
+
+    /// Returns whether the snapshot is empty.
+    pub fn is_empty(&self) -> bool {
+        self.inner.len == 0
+    }
+}
+
+impl ReadAt for CowMem {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        if pos >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let remaining = cmp::min(self.len - pos, buf.len() as u64) as usize;
+        let (chunk, offset, n) = self.locate(pos, remaining);
+        match self.chunks.get(chunk) {
+            Some(Some(data)) => buf[..n].copy_from_slice(&data[offset..offset + n]),
+            _ => {
+                for b in &mut buf[..n] {
+                    *b = 0;
+                }
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl ReadAt for MemSnapshot {
+    #[inline]
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        self.inner.read_at(pos, buf)
+    }
+}
+
+impl KnownEof for CowMem {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        Ok(self.len.saturating_sub(pos))
+    }
+}
+
+impl KnownEof for MemSnapshot {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        Ok(self.inner.len.saturating_sub(pos))
+    }
+}
+
+impl SizeAt for CowMem {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        Ok(self.len)
+    }
+}
+
+impl SizeAt for MemSnapshot {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        Ok(self.inner.len)
+    }
+}
+
+/// Shrinking releases the chunks past the new end, and extending adds
+/// chunks that take up no memory until written.
+impl ResizeAt for CowMem {
+    fn set_len(&mut self, len: u64) -> Result<()> {
+        if len < self.len {
+            let (chunk, offset, _) = self.locate(len, 0);
+            let kept = if offset == 0 { chunk } else { chunk + 1 };
+            self.chunks.truncate(kept);
+            if let Some(Some(ref mut data)) = self.chunks.get_mut(chunk) {
+                for b in &mut Arc::make_mut(data)[offset..] {
+                    *b = 0;
+                }
+            }
+        }
+        self.len = len;
+        Ok(())
+    }
+}
+
+/// Chunks covered by the hole are released, and the covered parts of the
+/// chunks at either end are zeroed.
+impl PunchHole for CowMem {
+    fn punch_hole(&mut self, pos: u64, len: u64) -> Result<()> {
+        let end = cmp::min(pos.saturating_add(len), self.len);
+        let mut pos = pos;
+        while pos < end {
+            let (chunk, offset, n) = self.locate(pos, cmp::min(end - pos, self.chunk_size as u64) as usize);
+            if let Some(slot) = self.chunks.get_mut(chunk) {
+                if n == self.chunk_size {
+                    *slot = None;
+                } else if let Some(ref mut data) = *slot {
+                    Arc::make_mut(data)[offset..offset + n].fill(0);
+                }
+            }
+            pos += n as u64;
+        }
+        Ok(())
+    }
+}
+
+/// Chunks that have never been written are reported as holes.
+impl Extents for CowMem {
+    fn extent_at(&mut self, pos: u64) -> Result<Option<Extent>> {
+        let chunks = &self.chunks;
+        Ok(extents::chunked_extent(pos, self.len, self.chunk_size, |i| matches!(chunks.get(i), Some(Some(_)))))
+    }
+}
+
+impl WriteAt for CowMem {
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let (_, offset, n) = self.locate(pos, buf.len());
+        let chunk = reserve_chunk(&mut self.chunks, self.chunk_size, pos, n as u64)?;
+        self.len = cmp::max(self.len, pos + n as u64);
+        if self.detect_zeroes && is_zero(&buf[..n]) {
+            let released = match self.chunks[chunk] {
+                None => true,
+                Some(ref data) => {
+                    is_zero(&data[..offset]) && is_zero(&data[offset + n..])
+                }
+            };
+            if released {
+                self.chunks[chunk] = None;
+                return Ok(n);
+            }
+        }
+        let chunk_size = self.chunk_size;
+        let data = self.chunks[chunk].get_or_insert_with(|| Arc::new(vec![0; chunk_size]));
+        Arc::make_mut(data)[offset..offset + n].copy_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Adds empty slots to `chunks` up to the chunk holding `pos`, returning
+/// its index.
+///
+/// Fails with [`Error::OutOfBounds`](enum.Error.html) if `len` bytes at
+/// `pos` cannot be addressed, or the chunk list cannot grow that far.
+pub(crate) fn reserve_chunk<T: Clone>(chunks: &mut Vec<Option<T>>, chunk_size: usize, pos: u64, len: u64) -> Result<usize> {
+    let out_of_bounds = || io::Error::from(Error::OutOfBounds { offset: pos, len });
+    pos.checked_add(len).ok_or_else(out_of_bounds)?;
+    let chunk = usize::try_from(pos / chunk_size as u64).map_err(|_| out_of_bounds())?;
+    if chunk >= chunks.len() {
+        chunks.try_reserve(chunk + 1 - chunks.len()).map_err(|_| out_of_bounds())?;
+        chunks.resize(chunk + 1, None);
+    }
+    Ok(chunk)
+}
+
+/// Returns whether `buf` only contains zeros.
+///
+/// Blocks are combined without branching so the check vectorizes, and
+/// the scan stops at the first block with a non-zero byte.
+pub(crate) fn is_zero(buf: &[u8]) -> bool {
+    let mut blocks = buf.chunks_exact(64);
+    blocks.all(|block| block.iter().fold(0, |acc, &b| acc | b) == 0) &&
+    blocks.remainder().iter().all(|&b| b == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mem() -> CowMem {
+        let mut mem = CowMem::with_chunk_size(4);
+        mem.write_all_at(0, b"abcdefghij").unwrap();
+        mem
+    }
+
+    fn contents<R: ReadAt + KnownEof>(src: &mut R) -> Vec<u8> {
+        let mut buf = Vec::new();
+        src.read_to_end_at(0, &mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn snapshot_ignores_later_changes() {
+        let mut mem = mem();
+        let mut snapshot = mem.freeze();
+        mem.write_all_at(2, b"XY").unwrap();
+        assert_eq!(contents(&mut snapshot), b"abcdefghij");
+        mem.punch_hole(4, 4).unwrap();
+        assert_eq!(contents(&mut snapshot), b"abcdefghij");
+        mem.set_len(5).unwrap();
+        mem.set_len(12).unwrap();
+        assert_eq!(contents(&mut snapshot), b"abcdefghij");
+        assert_eq!(contents(&mut mem), b"abXY\0\0\0\0\0\0\0\0");
+        assert_eq!(snapshot.len(), 10);
+    }
+
+    #[test]
+    fn snapshots_share_chunks() {
+        let mut mem = mem();
+        let first = mem.freeze();
+        let second = mem.freeze();
+        assert!(mem.shared_extents().contains_range(0..10));
+        for (a, b) in first.inner.chunks.iter().zip(&second.inner.chunks) {
+            assert!(Arc::ptr_eq(a.as_ref().unwrap(), b.as_ref().unwrap()));
+        }
+        mem.write_all_at(4, b"X").unwrap();
+        let shared = mem.shared_extents();
+        assert!(shared.contains_range(0..4) && shared.contains_range(8..10) && !shared.contains(4));
+        // The snapshots still share the chunk the backend copied.
+        assert!(Arc::ptr_eq(first.inner.chunks[1].as_ref().unwrap(), second.inner.chunks[1].as_ref().unwrap()));
+    }
+
+    #[test]
+    fn unaddressable_chunk() {
+        let mut mem = CowMem::with_chunk_size(1);
+        let err = mem.write_at(u64::MAX / 2, b"a").unwrap_err();
+        assert!(matches!(Error::downcast_ref(&err), Some(&Error::OutOfBounds { .. })));
+        let err = mem.write_at(u64::MAX, b"ab").unwrap_err();
+        assert!(matches!(Error::downcast_ref(&err), Some(&Error::OutOfBounds { .. })));
+        assert!(mem.is_empty());
+        let mut chunks: Vec<Option<u8>> = Vec::new();
+        assert!(reserve_chunk(&mut chunks, 1, u64::MAX - 1, 2).is_err());
+        assert_eq!(reserve_chunk(&mut chunks, 2, 5, 1).unwrap(), 2);
+        assert_eq!(chunks.len(), 3);
+    }
+}
@@ -0,0 +1,141 @@
+use std::cmp;
+use std::io::{self, BufRead, ErrorKind, Read, Result, Seek, SeekFrom};
+
+use {ReadAt, SizeAt};
+
+const DEFAULT_CAPACITY: usize = 8 * 1024;
+
+/// An adapter which reads a source as a stream, starting at a chosen
+/// offset.
+///
+/// It implements `Read` and `BufRead` by keeping track of a position of
+/// its own, so the source can be handed to stream-based parsers and
+/// decoders. Reads are buffered, except those at least as large as the
+/// buffer while it is empty. `Seek` is implemented for sources that know
+/// their size.
+#[derive(Debug)]
+pub struct CursorAt<T> {
+    inner: T,
+    pos: u64,
+    buf: Box<[u8]>,
+    start: usize,
+    end: usize,
+}
+
+impl<T> CursorAt<T> {
+    /// Creates a cursor at `pos` of `inner`, with a buffer of 8 KiB.
+    pub fn new(inner: T, pos: u64) -> CursorAt<T> {
+        CursorAt::with_capacity(DEFAULT_CAPACITY, inner, pos)
+    }
+
+    /// Creates a cursor at `pos` of `inner`, with a buffer of `capacity`
+    /// bytes.
+    pub fn with_capacity(capacity: usize, inner: T, pos: u64) -> CursorAt<T> {
+        CursorAt {
+            inner,
+            pos,
+            buf: vec![0; capacity].into_boxed_slice(),
+            start: 0,
+            end: 0,
+        }
+    }
+
+    /// Returns the position of the cursor in the source.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Returns the bytes buffered at the position of the cursor.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf[self.start..self.end]
+    }
+
+    /// Returns a reference to the source.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the source.
+    ///
+    /// Bytes already buffered are not read again, so writing to the
+    /// source through this reference may not be seen by the cursor.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Consumes the cursor, returning the source and discarding any
+    /// buffered bytes.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn move_to(&mut self, pos: u64) {
+        match pos.checked_sub(self.pos) {
+            Some(n) if n <= (self.end - self.start) as u64 => self.start += n as usize,
+            _ => {
+                self.start = 0;
+                self.end = 0;
+            }
+        }
+        self.pos = pos;
+    }
+}
+
+impl<T: ReadAt> Read for CursorAt<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if self.start == self.end && buf.len() >= self.buf.len() {
+            let n = self.inner.read_at(self.pos, buf)?;
+            self.pos += n as u64;
+            return Ok(n);
+        }
+        let n = {
+            let data = self.fill_buf()?;
+            let n = cmp::min(data.len(), buf.len());
+            buf[..n].copy_from_slice(&data[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<T: ReadAt> BufRead for CursorAt<T> {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        if self.start == self.end {
+            self.end = self.inner.read_at(self.pos, &mut self.buf)?;
+            self.start = 0;
+        }
+        Ok(&self.buf[self.start..self.end])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let amt = cmp::min(amt, self.end - self.start);
+        self.start += amt;
+        self.pos += amt as u64;
+    }
+}
+
+/// Seeking within the buffered bytes keeps them.
+impl<T: SizeAt> Seek for CursorAt<T> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(pos) => {
+                self.move_to(pos);
+                return Ok(pos);
+            }
+            SeekFrom::Current(offset) => (self.pos, offset),
+            SeekFrom::End(offset) => (self.inner.size()?, offset),
+        };
+        match base.checked_add_signed(offset) {
+            Some(pos) => {
+                self.move_to(pos);
+                Ok(pos)
+            }
+            None => Err(io::Error::new(ErrorKind::InvalidInput, "invalid seek to a negative or overflowing position")),
+        }
+    }
+
+    fn stream_position(&mut self) -> Result<u64> {
+        Ok(self.pos)
+    }
+}
@@ -0,0 +1,200 @@
+use std::cmp;
+use std::io::Result;
+
+use {Allocate, Error, IoOptions, KnownEof, PunchHole, ReadAt, ResizeAt, SizeAt, SyncAt, WriteAt};
+
+/// A modification that a [`DryRun`](struct.DryRun.html) recorded instead
+/// of performing it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IntendedWrite {
+    /// `len` bytes of data written at `pos`.
+    Write {
+        /// The start of the range.
+        pos: u64,
+        /// The length of the range.
+        len: u64,
+    },
+    /// `len` bytes at `pos` set to zero.
+    WriteZeroes {
+        /// The start of the range.
+        pos: u64,
+        /// The length of the range.
+        len: u64,
+    },
+    /// A hole of `len` bytes punched at `pos`.
+    PunchHole {
+        /// The start of the range.
+        pos: u64,
+        /// The length of the range.
+        len: u64,
+    },
+    /// The target resized to `len` bytes.
+    Resize {
+        /// The new length.
+        len: u64,
+    },
+}
+
+/// A wrapper which records the writes made through it instead of passing
+/// them on, so that a destructive operation can be rehearsed.
+///
+/// Placed at the bottom of a stack of layers, it lets every layer above
+/// validate the writes as it would for real, rejecting misaligned,
+/// fenced-off or out-of-bounds ones, while the target is left untouched.
+/// Reads are passed on, so they return the original contents rather than
+/// the intended writes. The size reported grows and shrinks with the
+/// intended writes and resizes, however, so that layers checking bounds
+/// against it see the same sizes as in a real run. Syncs succeed
+/// without doing anything, and preallocations only grow the size.
+#[derive(Clone, Debug)]
+pub struct DryRun<T> {
+    inner: T,
+    log: Vec<IntendedWrite>,
+    resized: Option<u64>,
+    end: u64,
+}
+
+impl<T> DryRun<T> {
+    /// Creates a wrapper around `inner`.
+    pub fn new(inner: T) -> DryRun<T> {
+        DryRun {
+            inner,
+            log: Vec::new(),
+            resized: None,
+            end: 0,
+        }
+    }
+
+    /// Returns the writes recorded so far, in the order they were made.
+    pub fn intended_writes(&self) -> &[IntendedWrite] {
+        &self.log
+    }
+
+    /// Returns the number of bytes that would have been written, including
+    /// zeros.
+    pub fn bytes_intended(&self) -> u64 {
+        self.log
+            .iter()
+            .map(|write| match *write {
+                IntendedWrite::Write { len, .. } | IntendedWrite::WriteZeroes { len, .. } => len,
+                IntendedWrite::PunchHole { .. } | IntendedWrite::Resize { .. } => 0,
+            })
+            .sum()
+    }
+
+    /// Forgets the writes recorded so far, as if the target were back in
+    /// its original state.
+    pub fn clear(&mut self) {
+        self.log.clear();
+        self.resized = None;
+        self.end = 0;
+    }
+
+    /// Returns a reference to the target.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the target.
+    ///
+    /// Writing to the target through this reference modifies it for real.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Consumes the wrapper, returning the target and the recorded writes.
+    pub fn into_inner(self) -> (T, Vec<IntendedWrite>) {
+        (self.inner, self.log)
+    }
+
+    fn extend(&mut self, pos: u64, len: u64) -> Result<()> {
+        let end = pos.checked_add(len).ok_or(Error::OutOfBounds { offset: pos, len })?;
+        self.end = cmp::max(self.end, end);
+        Ok(())
+    }
+}
+
+impl<T: ReadAt> ReadAt for DryRun<T> {
+    #[inline]
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        self.inner.read_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        self.inner.read_at_with(pos, buf, options)
+    }
+}
+
+impl<T: KnownEof> KnownEof for DryRun<T> {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        self.inner.remaining_at(pos)
+    }
+}
+
+impl<T: SizeAt> SizeAt for DryRun<T> {
+    fn size(&self) -> Result<u64> {
+        let size = match self.resized {
+            Some(len) => len,
+            None => self.inner.size()?,
+        };
+        Ok(cmp::max(size, self.end))
+    }
+}
+
+impl<T> WriteAt for DryRun<T> {
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        let len = cmp::min(buf.len() as u64, u64::MAX - pos);
+        if len > 0 {
+            self.extend(pos, len)?;
+            self.log.push(IntendedWrite::Write { pos, len });
+        }
+        Ok(len as usize)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        self.extend(pos, len)?;
+        if len > 0 {
+            self.log.push(IntendedWrite::WriteZeroes { pos, len });
+        }
+        Ok(())
+    }
+}
+
+impl<T> ResizeAt for DryRun<T> {
+    fn set_len(&mut self, len: u64) -> Result<()> {
+        self.resized = Some(len);
+        self.end = 0;
+        self.log.push(IntendedWrite::Resize { len });
+        Ok(())
+    }
+}
+
+impl<T> PunchHole for DryRun<T> {
+    fn punch_hole(&mut self, pos: u64, len: u64) -> Result<()> {
+        pos.checked_add(len).ok_or(Error::OutOfBounds { offset: pos, len })?;
+        if len > 0 {
+            self.log.push(IntendedWrite::PunchHole { pos, len });
+        }
+        Ok(())
+    }
+}
+
+impl<T> SyncAt for DryRun<T> {
+    #[inline]
+    fn sync_all(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<T> Allocate for DryRun<T> {
+    fn allocate(&mut self, pos: u64, len: u64) -> Result<()> {
+        self.extend(pos, len)
+    }
+}
@@ -0,0 +1,244 @@
+use std::convert::TryFrom;
+use std::error;
+use std::fmt;
+use std::io;
+
+/// A failure detected by this crate's adapters, as opposed to one passed
+/// through from the underlying source or sink.
+///
+/// Adapters return these wrapped in an `io::Error`, so they fit the
+/// signatures of [`ReadAt`](trait.ReadAt.html) and
+/// [`WriteAt`](trait.WriteAt.html). Use `Error::try_from` or
+/// [`downcast_ref`](#method.downcast_ref) to get them back out and match
+/// on the cause.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Error {
+    /// A range of `len` bytes at `offset` falls outside what the source or
+    /// sink can address.
+    OutOfBounds {
+        /// The start of the range.
+        offset: u64,
+        /// The length of the range.
+        len: u64,
+    },
+    /// A range of `len` bytes at `offset` is not aligned to `align` bytes.
+    Misaligned {
+        /// The start of the range.
+        offset: u64,
+        /// The length of the range.
+        len: u64,
+        /// The required alignment.
+        align: u64,
+    },
+    /// The data at `offset` does not match its checksum.
+    ChecksumMismatch {
+        /// The start of the data that failed verification.
+        offset: u64,
+    },
+    /// A write at `offset` would grow the sink beyond `limit` bytes.
+    QuotaExceeded {
+        /// The start of the rejected write.
+        offset: u64,
+        /// The maximum size of the sink.
+        limit: u64,
+    },
+    /// The `len` bytes at `offset` were only partially written.
+    Torn {
+        /// The start of the torn range.
+        offset: u64,
+        /// The length of the torn range.
+        len: u64,
+    },
+    /// A writer holding `epoch` was fenced off by a newer writer.
+    StaleEpoch {
+        /// The epoch held by the rejected writer.
+        epoch: u64,
+        /// The epoch currently stored.
+        current: u64,
+    },
+}
+
+impl Error {
+    /// Returns the `io::ErrorKind` this error is reported with.
+    pub fn io_kind(&self) -> io::ErrorKind {
+        match *self {
+            Error::OutOfBounds { .. } | Error::Misaligned { .. } => io::ErrorKind::InvalidInput,
+            Error::ChecksumMismatch { .. } | Error::Torn { .. } => io::ErrorKind::InvalidData,
+            Error::QuotaExceeded { .. } => io::ErrorKind::QuotaExceeded,
+            Error::StaleEpoch { .. } => io::ErrorKind::PermissionDenied,
+        }
+    }
+
+    /// Returns the class of this error. Checksum mismatches and torn
+    /// writes are corruption, and everything else is permanent.
+    pub fn class(&self) -> ErrorClass {
+        match *self {
+            Error::ChecksumMismatch { .. } | Error::Torn { .. } => ErrorClass::Corruption,
+            _ => ErrorClass::Permanent,
+        }
+    }
+
+    /// Returns the error wrapped in `err`, if it was created from one.
+    pub fn downcast_ref(err: &io::Error) -> Option<&Error> {
+        err.get_ref().and_then(|inner| inner.downcast_ref())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::OutOfBounds { offset, len } => {
+                write!(f, "{} bytes at offset {} are out of bounds", len, offset)
+            }
+            Error::Misaligned { offset, len, align } => {
+                write!(f, "{} bytes at offset {} are not aligned to {} bytes", len, offset, align)
+            }
+            Error::ChecksumMismatch { offset } => write!(f, "checksum mismatch at offset {}", offset),
+            Error::QuotaExceeded { offset, limit } => {
+                write!(f, "write at offset {} exceeds the limit of {} bytes", offset, limit)
+            }
+            Error::Torn { offset, len } => write!(f, "torn write of {} bytes at offset {}", len, offset),
+            Error::StaleEpoch { epoch, current } => {
+                write!(f, "writer epoch {} was fenced off by epoch {}", epoch, current)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        io::Error::new(err.io_kind(), err)
+    }
+}
+
+/// Fails with the original error if it does not wrap an `Error`.
+impl TryFrom<io::Error> for Error {
+    type Error = io::Error;
+
+    fn try_from(err: io::Error) -> Result<Error, io::Error> {
+        match Error::downcast_ref(&err) {
+            Some(&inner) => Ok(inner),
+            None => Err(err),
+        }
+    }
+}
+
+/// The broad cause of a failed operation, which decides whether it is
+/// worth retrying.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorClass {
+    /// The operation may succeed if it is simply tried again, such as
+    /// after a timeout or a dropped connection.
+    Transient,
+    /// The data was read back wrong, or the medium reported that it could
+    /// not be read. Retrying may help, and so may another replica.
+    Corruption,
+    /// The operation will keep failing, such as for invalid arguments or
+    /// missing permissions.
+    Permanent,
+}
+
+/// Classification of I/O errors, as used by the default
+/// [`ErrorPolicy`](trait.ErrorPolicy.html).
+pub trait ErrorExt {
+    /// Returns the class of the error.
+    fn class(&self) -> ErrorClass;
+
+    /// Returns whether the error is [`Transient`](enum.ErrorClass.html).
+    fn is_transient(&self) -> bool {
+        self.class() == ErrorClass::Transient
+    }
+
+    /// Returns whether the error is [`Corruption`](enum.ErrorClass.html).
+    fn is_corruption(&self) -> bool {
+        self.class() == ErrorClass::Corruption
+    }
+
+    /// Returns whether the error is [`Permanent`](enum.ErrorClass.html).
+    fn is_permanent(&self) -> bool {
+        self.class() == ErrorClass::Permanent
+    }
+}
+
+/// Errors wrapping an [`Error`](enum.Error.html) use its class. Otherwise
+/// timeouts, interruptions, busy resources and network failures are
+/// transient, `InvalidData` and device I/O errors are corruption, and
+/// everything else is permanent.
+impl ErrorExt for io::Error {
+    fn class(&self) -> ErrorClass {
+        if let Some(err) = Error::downcast_ref(self) {
+            return err.class();
+        }
+        if is_media_error(self) {
+            return ErrorClass::Corruption;
+        }
+        match self.kind() {
+            io::ErrorKind::Interrupted |
+            io::ErrorKind::WouldBlock |
+            io::ErrorKind::TimedOut |
+            io::ErrorKind::ResourceBusy |
+            io::ErrorKind::ConnectionReset |
+            io::ErrorKind::ConnectionAborted |
+            io::ErrorKind::NotConnected |
+            io::ErrorKind::BrokenPipe |
+            io::ErrorKind::NetworkDown |
+            io::ErrorKind::NetworkUnreachable |
+            io::ErrorKind::HostUnreachable => ErrorClass::Transient,
+            io::ErrorKind::InvalidData => ErrorClass::Corruption,
+            _ => ErrorClass::Permanent,
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_media_error(err: &io::Error) -> bool {
+    err.raw_os_error() == Some(libc::EIO)
+}
+
+#[cfg(windows)]
+fn is_media_error(err: &io::Error) -> bool {
+    const ERROR_CRC: i32 = 23;
+    const ERROR_SECTOR_NOT_FOUND: i32 = 27;
+    const ERROR_READ_FAULT: i32 = 30;
+    const ERROR_IO_DEVICE: i32 = 1117;
+
+    matches!(err.raw_os_error(), Some(ERROR_CRC | ERROR_SECTOR_NOT_FOUND | ERROR_READ_FAULT | ERROR_IO_DEVICE))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn is_media_error(_err: &io::Error) -> bool {
+    false
+}
+
+/// Decides how the layers that retry or fail over treat errors.
+///
+/// [`Salvage`](struct.Salvage.html) only retries sectors failing with
+/// transient or corruption errors. Applications with different needs,
+/// such as treating a vendor-specific error code as transient, can pass
+/// their own policy. Closures taking an `&io::Error` implement this trait.
+pub trait ErrorPolicy {
+    /// Returns the class of `err`.
+    fn classify(&self, err: &io::Error) -> ErrorClass;
+}
+
+impl<F: Fn(&io::Error) -> ErrorClass> ErrorPolicy for F {
+    #[inline]
+    fn classify(&self, err: &io::Error) -> ErrorClass {
+        self(err)
+    }
+}
+
+/// The default [`ErrorPolicy`](trait.ErrorPolicy.html), classifying
+/// errors as described on [`ErrorExt`](trait.ErrorExt.html).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct DefaultErrorPolicy;
+
+impl ErrorPolicy for DefaultErrorPolicy {
+    #[inline]
+    fn classify(&self, err: &io::Error) -> ErrorClass {
+        err.class()
+    }
+}
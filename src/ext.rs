@@ -0,0 +1,326 @@
+use std::cmp;
+use std::io::{self, ErrorKind, Result};
+
+use {Endian, Error, ReadAt, WriteAt};
+
+/// A byte order chosen at compile time, for the numeric methods of
+/// [`ReadAtExt`](trait.ReadAtExt.html) and
+/// [`WriteAtExt`](trait.WriteAtExt.html).
+pub trait ByteOrder {
+    /// The byte order this type stands for.
+    const ENDIAN: Endian;
+}
+
+/// Least significant byte first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LittleEndian {}
+
+/// Most significant byte first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BigEndian {}
+
+/// A short name for [`LittleEndian`](enum.LittleEndian.html).
+pub type LE = LittleEndian;
+
+/// A short name for [`BigEndian`](enum.BigEndian.html).
+pub type BE = BigEndian;
+
+/// The byte order of the target.
+#[cfg(target_endian = "little")]
+pub type NativeEndian = LittleEndian;
+
+/// The byte order of the target.
+#[cfg(target_endian = "big")]
+pub type NativeEndian = BigEndian;
+
+impl ByteOrder for LittleEndian {
+    const ENDIAN: Endian = Endian::Little;
+}
+
+impl ByteOrder for BigEndian {
+    const ENDIAN: Endian = Endian::Big;
+}
+
+/// An unsigned integer type used as the length prefix of a string, for
+/// [`read_prefixed_string_at`](trait.ReadAtExt.html#method.read_prefixed_string_at).
+pub trait LengthPrefix {
+    /// The size of the prefix in bytes.
+    const SIZE: u64;
+
+    /// Reads a prefix in byte order `B` at `pos`.
+    fn read_len_at<R: ReadAt + ?Sized, B: ByteOrder>(src: &mut R, pos: u64) -> Result<u64>;
+}
+
+impl LengthPrefix for u8 {
+    const SIZE: u64 = 1;
+
+    fn read_len_at<R: ReadAt + ?Sized, B: ByteOrder>(src: &mut R, pos: u64) -> Result<u64> {
+        src.read_u8_at(pos).map(u64::from)
+    }
+}
+
+impl LengthPrefix for u16 {
+    const SIZE: u64 = 2;
+
+    fn read_len_at<R: ReadAt + ?Sized, B: ByteOrder>(src: &mut R, pos: u64) -> Result<u64> {
+        src.read_u16_at::<B>(pos).map(u64::from)
+    }
+}
+
+impl LengthPrefix for u32 {
+    const SIZE: u64 = 4;
+
+    fn read_len_at<R: ReadAt + ?Sized, B: ByteOrder>(src: &mut R, pos: u64) -> Result<u64> {
+        src.read_u32_at::<B>(pos).map(u64::from)
+    }
+}
+
+impl LengthPrefix for u64 {
+    const SIZE: u64 = 8;
+
+    fn read_len_at<R: ReadAt + ?Sized, B: ByteOrder>(src: &mut R, pos: u64) -> Result<u64> {
+        src.read_u64_at::<B>(pos)
+    }
+}
+
+/// Reads of fixed-size values at fixed offsets, such as the fields of a
+/// binary header.
+///
+/// This is implemented for every [`ReadAt`](trait.ReadAt.html) type. Each
+/// method reads the whole value with `read_exact_at`, so it fails with an
+/// error of kind `UnexpectedEof` if the source ends first. The byte order
+/// is given as a type parameter, as in `read_u32_at::<LE>(pos)`.
+pub trait ReadAtExt: ReadAt {
+    /// Reads `N` bytes at `pos` into an array.
+    fn read_array_at<const N: usize>(&mut self, pos: u64) -> Result<[u8; N]> {
+        let mut buf = [0; N];
+        self.read_exact_at(pos, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// Reads a `u8` at `pos`.
+    fn read_u8_at(&mut self, pos: u64) -> Result<u8> {
+        Ok(self.read_array_at::<1>(pos)?[0])
+    }
+
+    /// Reads an `i8` at `pos`.
+    fn read_i8_at(&mut self, pos: u64) -> Result<i8> {
+        Ok(self.read_array_at::<1>(pos)?[0] as i8)
+    }
+
+    /// Reads a `u16` in byte order `B` at `pos`.
+    fn read_u16_at<B: ByteOrder>(&mut self, pos: u64) -> Result<u16> {
+        let buf = self.read_array_at(pos)?;
+        Ok(match B::ENDIAN {
+            Endian::Little => u16::from_le_bytes(buf),
+            Endian::Big => u16::from_be_bytes(buf),
+        })
+    }
+
+    /// Reads a `u32` in byte order `B` at `pos`.
+    fn read_u32_at<B: ByteOrder>(&mut self, pos: u64) -> Result<u32> {
+        let buf = self.read_array_at(pos)?;
+        Ok(match B::ENDIAN {
+            Endian::Little => u32::from_le_bytes(buf),
+            Endian::Big => u32::from_be_bytes(buf),
+        })
+    }
+
+    /// Reads a `u64` in byte order `B` at `pos`.
+    fn read_u64_at<B: ByteOrder>(&mut self, pos: u64) -> Result<u64> {
+        let buf = self.read_array_at(pos)?;
+        Ok(match B::ENDIAN {
+            Endian::Little => u64::from_le_bytes(buf),
+            Endian::Big => u64::from_be_bytes(buf),
+        })
+    }
+
+    /// Reads a `u128` in byte order `B` at `pos`.
+    fn read_u128_at<B: ByteOrder>(&mut self, pos: u64) -> Result<u128> {
+        let buf = self.read_array_at(pos)?;
+        Ok(match B::ENDIAN {
+            Endian::Little => u128::from_le_bytes(buf),
+            Endian::Big => u128::from_be_bytes(buf),
+        })
+    }
+
+    /// Reads an `i16` in byte order `B` at `pos`.
+    fn read_i16_at<B: ByteOrder>(&mut self, pos: u64) -> Result<i16> {
+        Ok(self.read_u16_at::<B>(pos)? as i16)
+    }
+
+    /// Reads an `i32` in byte order `B` at `pos`.
+    fn read_i32_at<B: ByteOrder>(&mut self, pos: u64) -> Result<i32> {
+        Ok(self.read_u32_at::<B>(pos)? as i32)
+    }
+
+    /// Reads an `i64` in byte order `B` at `pos`.
+    fn read_i64_at<B: ByteOrder>(&mut self, pos: u64) -> Result<i64> {
+        Ok(self.read_u64_at::<B>(pos)? as i64)
+    }
+
+    /// Reads an `i128` in byte order `B` at `pos`.
+    fn read_i128_at<B: ByteOrder>(&mut self, pos: u64) -> Result<i128> {
+        Ok(self.read_u128_at::<B>(pos)? as i128)
+    }
+
+    /// Reads an IEEE 754 `f32` in byte order `B` at `pos`.
+    fn read_f32_at<B: ByteOrder>(&mut self, pos: u64) -> Result<f32> {
+        Ok(f32::from_bits(self.read_u32_at::<B>(pos)?))
+    }
+
+    /// Reads an IEEE 754 `f64` in byte order `B` at `pos`.
+    fn read_f64_at<B: ByteOrder>(&mut self, pos: u64) -> Result<f64> {
+        Ok(f64::from_bits(self.read_u64_at::<B>(pos)?))
+    }
+
+    /// Reads a NUL-terminated string at `pos`, such as an entry of an ELF
+    /// string table, and returns it without the terminator.
+    ///
+    /// The source is read in small chunks until the terminator is found,
+    /// so the string can end anywhere before the end of the source.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidData` if no terminator is found
+    /// within `max_len` bytes, and of kind `UnexpectedEof` if the source
+    /// ends first.
+    fn read_cstr_at(&mut self, pos: u64, max_len: usize) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        let mut chunk = [0; 256];
+        loop {
+            let at = pos.checked_add(out.len() as u64)
+                .ok_or(Error::OutOfBounds { offset: pos, len: out.len() as u64 })?;
+            let want = cmp::min(chunk.len(), max_len.saturating_add(1) - out.len());
+            let n = match self.read_at(at, &mut chunk[..want]) {
+                Ok(0) => return Err(io::Error::new(ErrorKind::UnexpectedEof, "unterminated string")),
+                Ok(n) => n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            if let Some(end) = chunk[..n].iter().position(|&b| b == 0) {
+                out.extend_from_slice(&chunk[..end]);
+                return Ok(out);
+            }
+            out.extend_from_slice(&chunk[..n]);
+            if out.len() > max_len {
+                return Err(io::Error::new(ErrorKind::InvalidData, "string exceeds the maximum length"));
+            }
+        }
+    }
+
+    /// Reads a UTF-8 string at `pos`, preceded by its length in bytes as
+    /// an `L` in byte order `B`, as in
+    /// `read_prefixed_string_at::<u32, LE>(pos)`.
+    ///
+    /// Memory for the string is allocated as it is read, so a corrupt
+    /// length fails at the end of the source rather than allocating all
+    /// of it up front.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidData` if the string is not valid
+    /// UTF-8, and of kind `UnexpectedEof` if the source ends first.
+    fn read_prefixed_string_at<L: LengthPrefix, B: ByteOrder>(&mut self, pos: u64) -> Result<String> {
+        let len = L::read_len_at::<Self, B>(self, pos)?;
+        let start = pos.checked_add(L::SIZE)
+            .filter(|start| start.checked_add(len).is_some())
+            .ok_or(Error::OutOfBounds { offset: pos, len })?;
+        let mut out = Vec::new();
+        while (out.len() as u64) < len {
+            let n = cmp::min(len - out.len() as u64, 64 * 1024) as usize;
+            let filled = out.len();
+            out.resize(filled + n, 0);
+            self.read_exact_at(start + filled as u64, &mut out[filled..])?;
+        }
+        String::from_utf8(out).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+    }
+}
+
+impl<R: ReadAt + ?Sized> ReadAtExt for R {}
+
+/// Writes of fixed-size values at fixed offsets, such as the fields of a
+/// superblock, mirroring [`ReadAtExt`](trait.ReadAtExt.html).
+///
+/// This is implemented for every [`WriteAt`](trait.WriteAt.html) type.
+/// Each method writes the whole value with `write_all_at`.
+pub trait WriteAtExt: WriteAt {
+    /// Writes `N` bytes from an array at `pos`.
+    fn write_array_at<const N: usize>(&mut self, pos: u64, buf: [u8; N]) -> Result<()> {
+        self.write_all_at(pos, &buf)
+    }
+
+    /// Writes a `u8` at `pos`.
+    fn write_u8_at(&mut self, pos: u64, n: u8) -> Result<()> {
+        self.write_array_at(pos, [n])
+    }
+
+    /// Writes an `i8` at `pos`.
+    fn write_i8_at(&mut self, pos: u64, n: i8) -> Result<()> {
+        self.write_array_at(pos, [n as u8])
+    }
+
+    /// Writes a `u16` in byte order `B` at `pos`.
+    fn write_u16_at<B: ByteOrder>(&mut self, pos: u64, n: u16) -> Result<()> {
+        self.write_array_at(pos, match B::ENDIAN {
+            Endian::Little => n.to_le_bytes(),
+            Endian::Big => n.to_be_bytes(),
+        })
+    }
+
+    /// Writes a `u32` in byte order `B` at `pos`.
+    fn write_u32_at<B: ByteOrder>(&mut self, pos: u64, n: u32) -> Result<()> {
+        self.write_array_at(pos, match B::ENDIAN {
+            Endian::Little => n.to_le_bytes(),
+            Endian::Big => n.to_be_bytes(),
+        })
+    }
+
+    /// Writes a `u64` in byte order `B` at `pos`.
+    fn write_u64_at<B: ByteOrder>(&mut self, pos: u64, n: u64) -> Result<()> {
+        self.write_array_at(pos, match B::ENDIAN {
+            Endian::Little => n.to_le_bytes(),
+            Endian::Big => n.to_be_bytes(),
+        })
+    }
+
+    /// Writes a `u128` in byte order `B` at `pos`.
+    fn write_u128_at<B: ByteOrder>(&mut self, pos: u64, n: u128) -> Result<()> {
+        self.write_array_at(pos, match B::ENDIAN {
+            Endian::Little => n.to_le_bytes(),
+            Endian::Big => n.to_be_bytes(),
+        })
+    }
+
+    /// Writes an `i16` in byte order `B` at `pos`.
+    fn write_i16_at<B: ByteOrder>(&mut self, pos: u64, n: i16) -> Result<()> {
+        self.write_u16_at::<B>(pos, n as u16)
+    }
+
+    /// Writes an `i32` in byte order `B` at `pos`.
+    fn write_i32_at<B: ByteOrder>(&mut self, pos: u64, n: i32) -> Result<()> {
+        self.write_u32_at::<B>(pos, n as u32)
+    }
+
+    /// Writes an `i64` in byte order `B` at `pos`.
+    fn write_i64_at<B: ByteOrder>(&mut self, pos: u64, n: i64) -> Result<()> {
+        self.write_u64_at::<B>(pos, n as u64)
+    }
+
+    /// Writes an `i128` in byte order `B` at `pos`.
+    fn write_i128_at<B: ByteOrder>(&mut self, pos: u64, n: i128) -> Result<()> {
+        self.write_u128_at::<B>(pos, n as u128)
+    }
+
+    /// Writes an IEEE 754 `f32` in byte order `B` at `pos`.
+    fn write_f32_at<B: ByteOrder>(&mut self, pos: u64, n: f32) -> Result<()> {
+        self.write_u32_at::<B>(pos, n.to_bits())
+    }
+
+    /// Writes an IEEE 754 `f64` in byte order `B` at `pos`.
+    fn write_f64_at<B: ByteOrder>(&mut self, pos: u64, n: f64) -> Result<()> {
+        self.write_u64_at::<B>(pos, n.to_bits())
+    }
+}
+
+impl<W: WriteAt + ?Sized> WriteAtExt for W {}
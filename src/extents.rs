@@ -0,0 +1,228 @@
+use std::fs::File;
+use std::io::Result;
+
+use SizeAt;
+
+/// A range of a target that either holds data or is a hole.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Extent {
+    /// The start of the extent.
+    pub offset: u64,
+    /// The length of the extent in bytes.
+    pub len: u64,
+    /// Whether the extent is a hole, which reads as zeros and takes up
+    /// no storage.
+    pub is_hole: bool,
+}
+
+/// The `Extents` trait allows for finding the holes of a sparse target, so
+/// that tools copying it can skip them instead of reading their zeros.
+pub trait Extents {
+    /// Returns the extent starting at `pos` and running up to the next
+    /// change between data and hole, or `None` if `pos` is at or past the
+    /// end of the target.
+    ///
+    /// Targets that cannot detect holes report everything as data. Data
+    /// extents may still read as zeros, and consecutive extents may be of
+    /// the same kind.
+    ///
+    /// # Errors
+    ///
+    /// This method can return any I/O error.
+    fn extent_at(&mut self, pos: u64) -> Result<Option<Extent>>;
+
+    /// Returns an iterator over the extents from `pos` to the end of the
+    /// target.
+    ///
+    /// The iterator ends after the first error.
+    fn extents(&mut self, pos: u64) -> ExtentIter<'_, Self> {
+        ExtentIter { inner: self, pos, done: false }
+    }
+}
+
+/// An iterator over the extents of a target.
+///
+/// This struct is created by [`Extents::extents`](trait.Extents.html#method.extents).
+#[derive(Debug)]
+pub struct ExtentIter<'a, E: ?Sized + 'a> {
+    inner: &'a mut E,
+    pos: u64,
+    done: bool,
+}
+
+impl<'a, E: Extents + ?Sized> Iterator for ExtentIter<'a, E> {
+    type Item = Result<Extent>;
+
+    fn next(&mut self) -> Option<Result<Extent>> {
+        if self.done {
+            return None;
+        }
+        match self.inner.extent_at(self.pos) {
+            Ok(Some(extent)) if extent.len > 0 => {
+                self.pos = extent.offset + extent.len;
+                Some(Ok(extent))
+            }
+            Ok(_) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<E: Extents + ?Sized> Extents for &mut E {
+    #[inline]
+    fn extent_at(&mut self, pos: u64) -> Result<Option<Extent>> {
+        (**self).extent_at(pos)
+    }
+}
+
+impl<E: Extents + ?Sized> Extents for Box<E> {
+    #[inline]
+    fn extent_at(&mut self, pos: u64) -> Result<Option<Extent>> {
+        (**self).extent_at(pos)
+    }
+}
+
+impl Extents for [u8] {
+    fn extent_at(&mut self, pos: u64) -> Result<Option<Extent>> {
+        Ok(data_to_end(pos, self.len() as u64))
+    }
+}
+
+impl Extents for Vec<u8> {
+    #[inline]
+    fn extent_at(&mut self, pos: u64) -> Result<Option<Extent>> {
+        self[..].extent_at(pos)
+    }
+}
+
+/// Uses `lseek` with `SEEK_DATA` and `SEEK_HOLE` on Linux, Android,
+/// FreeBSD and macOS, restoring the file position afterwards, and
+/// `FSCTL_QUERY_ALLOCATED_RANGES` on Windows. Files are reported as data
+/// on other platforms, and on file systems without support for holes.
+impl Extents for File {
+    fn extent_at(&mut self, pos: u64) -> Result<Option<Extent>> {
+        let size = self.size()?;
+        if pos >= size {
+            return Ok(None);
+        }
+        match file_extent(self, pos, size)? {
+            Some((end, is_hole)) => Ok(Some(Extent { offset: pos, len: end - pos, is_hole })),
+            None => Ok(data_to_end(pos, size)),
+        }
+    }
+}
+
+/// Returns a data extent from `pos` to `len`.
+fn data_to_end(pos: u64, len: u64) -> Option<Extent> {
+    if pos < len {
+        Some(Extent { offset: pos, len: len - pos, is_hole: false })
+    } else {
+        None
+    }
+}
+
+/// Returns the extent at `pos` of a target made of chunks of `chunk_size`
+/// bytes, where `is_data` tells whether a chunk holds data.
+pub(crate) fn chunked_extent<F>(pos: u64, len: u64, chunk_size: usize, is_data: F) -> Option<Extent>
+    where F: Fn(usize) -> bool
+{
+    if pos >= len {
+        return None;
+    }
+    let chunk_size = chunk_size as u64;
+    let first = (pos / chunk_size) as usize;
+    let last = ((len - 1) / chunk_size) as usize;
+    let kind = is_data(first);
+    let differs = (first + 1..=last).find(|&i| is_data(i) != kind);
+    let end = differs.map_or(len, |i| i as u64 * chunk_size);
+    Some(Extent { offset: pos, len: end - pos, is_hole: !kind })
+}
+
+/// Returns the end of the extent at `pos` and whether it is a hole, or
+/// `None` if the file system cannot tell.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd", target_os = "macos", target_os = "ios"))]
+fn file_extent(file: &File, pos: u64, size: u64) -> Result<Option<(u64, bool)>> {
+    use std::io::Error;
+    use std::os::unix::io::AsRawFd;
+    use sys;
+
+    let fd = file.as_raw_fd();
+    let saved = unsafe { libc::lseek(fd, 0, libc::SEEK_CUR) };
+    if saved == -1 {
+        return Err(Error::last_os_error());
+    }
+    let seek = |whence| {
+        let ret = unsafe { libc::lseek(fd, sys::off_t(pos)?, whence) };
+        if ret == -1 { Err(Error::last_os_error()) } else { Ok(ret as u64) }
+    };
+    let result = match seek(libc::SEEK_DATA) {
+        Ok(data) if data > pos => Ok(Some((data.min(size), true))),
+        Ok(_) => seek(libc::SEEK_HOLE).map(|hole| Some((hole.clamp(pos + 1, size), false))),
+        Err(ref e) if e.raw_os_error() == Some(libc::ENXIO) => Ok(Some((size, true))),
+        Err(ref e) if e.raw_os_error() == Some(libc::EINVAL) => Ok(None),
+        Err(e) => Err(e),
+    };
+    unsafe { libc::lseek(fd, saved, libc::SEEK_SET) };
+    result
+}
+
+#[cfg(windows)]
+fn file_extent(file: &File, pos: u64, size: u64) -> Result<Option<(u64, bool)>> {
+    use std::io::Error;
+    use std::mem;
+    use std::os::windows::io::AsRawHandle;
+    use std::ptr;
+    use windows_sys::Win32::Foundation::{ERROR_INVALID_FUNCTION, ERROR_MORE_DATA};
+    use windows_sys::Win32::System::IO::DeviceIoControl;
+    use windows_sys::Win32::System::Ioctl::{FILE_ALLOCATED_RANGE_BUFFER, FSCTL_QUERY_ALLOCATED_RANGES};
+
+    let query = FILE_ALLOCATED_RANGE_BUFFER {
+        FileOffset: pos as i64,
+        Length: (size - pos) as i64,
+    };
+    let mut range = FILE_ALLOCATED_RANGE_BUFFER { FileOffset: 0, Length: 0 };
+    let mut returned = 0;
+    let ok = unsafe {
+        DeviceIoControl(file.as_raw_handle(),
+                        FSCTL_QUERY_ALLOCATED_RANGES,
+                        &query as *const FILE_ALLOCATED_RANGE_BUFFER as *const _,
+                        mem::size_of::<FILE_ALLOCATED_RANGE_BUFFER>() as u32,
+                        &mut range as *mut FILE_ALLOCATED_RANGE_BUFFER as *mut _,
+                        mem::size_of::<FILE_ALLOCATED_RANGE_BUFFER>() as u32,
+                        &mut returned,
+                        ptr::null_mut())
+    };
+    if ok == 0 {
+        let err = Error::last_os_error();
+        match err.raw_os_error() {
+            Some(code) if code == ERROR_MORE_DATA as i32 => {}
+            Some(code) if code == ERROR_INVALID_FUNCTION as i32 => return Ok(None),
+            _ => return Err(err),
+        }
+    }
+    if returned == 0 {
+        return Ok(Some((size, true)));
+    }
+    let start = range.FileOffset as u64;
+    if start > pos {
+        Ok(Some((start.min(size), true)))
+    } else {
+        Ok(Some(((start + range.Length as u64).clamp(pos + 1, size), false)))
+    }
+}
+
+#[cfg(not(any(target_os = "linux",
+              target_os = "android",
+              target_os = "freebsd",
+              target_os = "macos",
+              target_os = "ios",
+              windows)))]
+fn file_extent(_file: &File, _pos: u64, _size: u64) -> Result<Option<(u64, bool)>> {
+    Ok(None)
+}
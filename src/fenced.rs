@@ -0,0 +1,230 @@
+use std::io::{self, ErrorKind, Result};
+
+use {Allocate, Error, IoOptions, KnownEof, LE, PunchHole, ReadAt, ReadAtExt, ReadBuf, ResizeAt, SizeAt, SyncAt, WriteAt};
+
+const MAGIC: &[u8; 8] = b"ioatfnce";
+
+/// The number of bytes reserved at the start of the inner value for the
+/// epoch of a [`Fenced`](struct.Fenced.html) backend.
+pub const FENCE_HEADER_LEN: u64 = 16;
+
+/// A wrapper rejecting writes from writers that have been superseded,
+/// for backends shared between several hosts.
+///
+/// Every writer holds an epoch, and the highest epoch handed out so far is
+/// stored in a header at the start of the inner value. Before each write,
+/// the header is read back, and if another writer has since taken a newer
+/// epoch, the write fails with
+/// [`Error::StaleEpoch`](enum.Error.html). This keeps a writer that lost
+/// its lease, such as one on the wrong side of a network partition, from
+/// corrupting data that has already moved on.
+///
+/// The header takes up the first
+/// [`FENCE_HEADER_LEN`](constant.FENCE_HEADER_LEN.html) bytes, and offsets
+/// of reads and writes are relative to the end of it. Checking the epoch
+/// and writing are separate operations, so this does not replace fencing
+/// enforced by the storage itself, but it does stop writers that were
+/// superseded before they started a write.
+#[derive(Debug)]
+pub struct Fenced<T> {
+    inner: T,
+    epoch: u64,
+}
+
+impl<T: ReadAt + WriteAt> Fenced<T> {
+    /// Takes the next epoch of `inner`, fencing off all earlier writers.
+    ///
+    /// An inner value starting with zeros has no writers yet, and gets a
+    /// header with epoch one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidData` if `inner` starts with
+    /// neither a header nor zeros, and any error from reading or writing
+    /// the header.
+    pub fn acquire(mut inner: T) -> Result<Fenced<T>> {
+        let current = read_epoch(&mut inner)?;
+        let epoch = current.checked_add(1)
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "fencing epoch overflow"))?;
+        write_epoch(&mut inner, epoch)?;
+        Ok(Fenced { inner, epoch })
+    }
+
+    /// Writes to `inner` in `epoch`, as handed out by an external
+    /// coordinator such as a lock service.
+    ///
+    /// If `epoch` is newer than the one stored in the header, it is
+    /// stored, fencing off all earlier writers.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::StaleEpoch`](enum.Error.html) if a newer epoch is
+    /// already stored, and the same errors as
+    /// [`acquire`](#method.acquire) otherwise.
+    pub fn with_epoch(mut inner: T, epoch: u64) -> Result<Fenced<T>> {
+        let current = read_epoch(&mut inner)?;
+        if current > epoch {
+            return Err(Error::StaleEpoch { epoch, current }.into());
+        }
+        if current < epoch {
+            write_epoch(&mut inner, epoch)?;
+        }
+        Ok(Fenced { inner, epoch })
+    }
+
+    fn check(&mut self) -> Result<()> {
+        let current = read_epoch(&mut self.inner)?;
+        if current != self.epoch {
+            return Err(Error::StaleEpoch { epoch: self.epoch, current }.into());
+        }
+        Ok(())
+    }
+}
+
+impl<T> Fenced<T> {
+    /// Returns the epoch this writer holds.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Returns a reference to the inner value.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Consumes the wrapper, returning the inner value.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+/// Reads the stored epoch, or zero if there is no header yet.
+fn read_epoch<T: ReadAt>(inner: &mut T) -> Result<u64> {
+    let mut header = [0; FENCE_HEADER_LEN as usize];
+    let mut filled = 0;
+    while filled < header.len() {
+        match inner.read_at(filled as u64, &mut header[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    if header[..8] == MAGIC[..] {
+        (&header[..]).read_u64_at::<LE>(8)
+    } else if header.iter().all(|&b| b == 0) {
+        Ok(0)
+    } else {
+        Err(io::Error::new(ErrorKind::InvalidData, "missing fencing header"))
+    }
+}
+
+fn write_epoch<T: WriteAt>(inner: &mut T, epoch: u64) -> Result<()> {
+    let mut header = [0; FENCE_HEADER_LEN as usize];
+    header[..8].copy_from_slice(MAGIC);
+    header[8..].copy_from_slice(&epoch.to_le_bytes());
+    inner.write_all_at(0, &header)?;
+    inner.flush()
+}
+
+fn offset(pos: u64) -> Result<u64> {
+    pos.checked_add(FENCE_HEADER_LEN).ok_or(Error::OutOfBounds { offset: pos, len: 0 }.into())
+}
+
+impl<T: ReadAt> ReadAt for Fenced<T> {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        self.inner.read_at(offset(pos)?, buf)
+    }
+
+    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
+        self.inner.read_exact_at(offset(pos)?, buf)
+    }
+
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        self.inner.read_at_with(offset(pos)?, buf, options)
+    }
+
+    fn read_buf_at(&mut self, pos: u64, buf: &mut ReadBuf) -> Result<()> {
+        self.inner.read_buf_at(offset(pos)?, buf)
+    }
+}
+
+impl<T: ReadAt + WriteAt> WriteAt for Fenced<T> {
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        let pos = offset(pos)?;
+        self.check()?;
+        self.inner.write_at(pos, buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    fn write_all_at(&mut self, pos: u64, buf: &[u8]) -> Result<()> {
+        let pos = offset(pos)?;
+        self.check()?;
+        self.inner.write_all_at(pos, buf)
+    }
+
+    fn write_at_with(&mut self, pos: u64, buf: &[u8], options: &IoOptions) -> Result<usize> {
+        let pos = offset(pos)?;
+        self.check()?;
+        self.inner.write_at_with(pos, buf, options)
+    }
+
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        let pos = offset(pos)?;
+        self.check()?;
+        self.inner.write_zeroes_at(pos, len)
+    }
+}
+
+impl<T: ReadAt + WriteAt + ResizeAt> ResizeAt for Fenced<T> {
+    fn set_len(&mut self, len: u64) -> Result<()> {
+        let len = offset(len)?;
+        self.check()?;
+        self.inner.set_len(len)
+    }
+}
+
+impl<T: KnownEof> KnownEof for Fenced<T> {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        self.inner.remaining_at(offset(pos)?)
+    }
+}
+
+impl<T: SizeAt> SizeAt for Fenced<T> {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        Ok(self.inner.size()?.saturating_sub(FENCE_HEADER_LEN))
+    }
+}
+
+impl<T: SyncAt> SyncAt for Fenced<T> {
+    #[inline]
+    fn sync_all(&mut self) -> Result<()> {
+        self.inner.sync_all()
+    }
+
+    #[inline]
+    fn sync_data(&mut self) -> Result<()> {
+        self.inner.sync_data()
+    }
+}
+
+impl<T: ReadAt + WriteAt + PunchHole> PunchHole for Fenced<T> {
+    fn punch_hole(&mut self, pos: u64, len: u64) -> Result<()> {
+        let pos = offset(pos)?;
+        self.check()?;
+        self.inner.punch_hole(pos, len)
+    }
+}
+
+impl<T: ReadAt + WriteAt + Allocate> Allocate for Fenced<T> {
+    fn allocate(&mut self, pos: u64, len: u64) -> Result<()> {
+        let pos = offset(pos)?;
+        self.check()?;
+        self.inner.allocate(pos, len)
+    }
+}
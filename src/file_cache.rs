@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{IoSlice, IoSliceMut, Result};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use {Advice, Advise, Allocate, Extent, Extents, IoOptions, KnownEof, Prefetch, PunchHole, ReadAt, ReadBuf, ResizeAt, SizeAt, SyncAt, WriteAt};
+
+#[derive(Debug)]
+struct State {
+    capacity: usize,
+    next_id: u64,
+    tick: u64,
+    open: HashMap<u64, (File, u64)>,
+}
+
+/// A cache keeping at most a fixed number of files open.
+///
+/// Files are opened through the cache with [`open`](#method.open) or
+/// [`open_write`](#method.open_write), which return a
+/// [`CachedFile`](struct.CachedFile.html) handle. When more files are
+/// open than the capacity allows, the least recently used one is closed,
+/// and reopened the next time its handle is accessed. This lets stores
+/// made of many files, such as the segments of a log, stay within the
+/// descriptor limit of the process.
+///
+/// Files are taken out of the cache while an operation on them is in
+/// progress, so the number of open files can exceed the capacity by the
+/// number of concurrent operations. The cache can be cloned, and clones
+/// share the same files.
+#[derive(Clone, Debug)]
+pub struct FileCache {
+    state: Arc<Mutex<State>>,
+}
+
+/// A file opened through a [`FileCache`](struct.FileCache.html).
+///
+/// Dropping the handle closes the file if it is open.
+#[derive(Debug)]
+pub struct CachedFile {
+    state: Arc<Mutex<State>>,
+    id: u64,
+    path: PathBuf,
+    writable: bool,
+}
+
+impl FileCache {
+    /// Creates a cache keeping at most `capacity` files open.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn new(capacity: usize) -> FileCache {
+        assert!(capacity > 0, "capacity must be non-zero");
+        FileCache {
+            state: Arc::new(Mutex::new(State {
+                capacity,
+                next_id: 0,
+                tick: 0,
+                open: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Returns the number of files currently open in the cache.
+    pub fn open_files(&self) -> usize {
+        lock(&self.state).open.len()
+    }
+
+    /// Opens the file at `path` for reading.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from opening the file.
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> Result<CachedFile> {
+        self.insert(path.as_ref(), false, File::open(path.as_ref())?)
+    }
+
+    /// Opens the file at `path` for reading and writing, creating it if
+    /// it does not exist. Existing files are never truncated.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from opening the file.
+    pub fn open_write<P: AsRef<Path>>(&self, path: P) -> Result<CachedFile> {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path.as_ref())?;
+        self.insert(path.as_ref(), true, file)
+    }
+
+    fn insert(&self, path: &Path, writable: bool, file: File) -> Result<CachedFile> {
+        let id = {
+            let mut state = lock(&self.state);
+            let id = state.next_id;
+            state.next_id += 1;
+            state.check_in(id, file);
+            id
+        };
+        Ok(CachedFile {
+            state: self.state.clone(),
+            id,
+            path: path.to_path_buf(),
+            writable,
+        })
+    }
+}
+
+impl State {
+    fn check_in(&mut self, id: u64, file: File) {
+        self.tick += 1;
+        self.open.insert(id, (file, self.tick));
+        while self.open.len() > self.capacity {
+            let oldest = self.open.iter().min_by_key(|&(_, &(_, used))| used).map(|(&id, _)| id);
+            match oldest {
+                Some(oldest) => self.open.remove(&oldest),
+                None => break,
+            };
+        }
+    }
+}
+
+impl CachedFile {
+    /// Returns the path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns whether the file is currently open.
+    pub fn is_open(&self) -> bool {
+        lock(&self.state).open.contains_key(&self.id)
+    }
+
+    fn with_file<F, R>(&mut self, f: F) -> Result<R>
+        where F: FnOnce(&mut File) -> Result<R>
+    {
+        let cached = lock(&self.state).open.remove(&self.id);
+        let mut file = match cached {
+            Some((file, _)) => file,
+            None => OpenOptions::new().read(true).write(self.writable).open(&self.path)?,
+        };
+        let result = f(&mut file);
+        lock(&self.state).check_in(self.id, file);
+        result
+    }
+}
+
+impl Drop for CachedFile {
+    fn drop(&mut self) {
+        lock(&self.state).open.remove(&self.id);
+    }
+}
+
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl ReadAt for CachedFile {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        self.with_file(|file| file.read_at(pos, buf))
+    }
+
+    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
+        self.with_file(|file| file.read_exact_at(pos, buf))
+    }
+
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        self.with_file(|file| file.read_at_with(pos, buf, options))
+    }
+
+    fn read_vectored_at(&mut self, pos: u64, bufs: &mut [IoSliceMut]) -> Result<usize> {
+        self.with_file(|file| file.read_vectored_at(pos, bufs))
+    }
+
+    fn read_buf_at(&mut self, pos: u64, buf: &mut ReadBuf) -> Result<()> {
+        self.with_file(|file| file.read_buf_at(pos, buf))
+    }
+}
+
+impl WriteAt for CachedFile {
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        self.with_file(|file| file.write_at(pos, buf))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let mut state = lock(&self.state);
+        match state.open.get_mut(&self.id) {
+            Some(&mut (ref mut file, _)) => WriteAt::flush(file),
+            None => Ok(()),
+        }
+    }
+
+    fn write_all_at(&mut self, pos: u64, buf: &[u8]) -> Result<()> {
+        self.with_file(|file| file.write_all_at(pos, buf))
+    }
+
+    fn write_at_with(&mut self, pos: u64, buf: &[u8], options: &IoOptions) -> Result<usize> {
+        self.with_file(|file| file.write_at_with(pos, buf, options))
+    }
+
+    fn write_vectored_at(&mut self, pos: u64, bufs: &[IoSlice]) -> Result<usize> {
+        self.with_file(|file| file.write_vectored_at(pos, bufs))
+    }
+
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        self.with_file(|file| file.write_zeroes_at(pos, len))
+    }
+}
+
+impl KnownEof for CachedFile {
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        self.with_file(|file| file.remaining_at(pos))
+    }
+}
+
+impl ResizeAt for CachedFile {
+    fn set_len(&mut self, len: u64) -> Result<()> {
+        self.with_file(|file| file.set_len(len))
+    }
+}
+
+/// Uses the metadata of the open file, or of the path if the file is not
+/// open.
+impl SizeAt for CachedFile {
+    fn size(&self) -> Result<u64> {
+        let state = lock(&self.state);
+        match state.open.get(&self.id) {
+            Some((file, _)) => file.size(),
+            None => Ok(fs::metadata(&self.path)?.len()),
+        }
+    }
+}
+
+impl Prefetch for CachedFile {
+    fn kernel_prefetch(&mut self, pos: u64, len: u64) -> Result<()> {
+        self.with_file(|file| file.kernel_prefetch(pos, len))
+    }
+}
+
+impl Advise for CachedFile {
+    fn advise(&mut self, pos: u64, len: u64, advice: Advice) -> Result<()> {
+        self.with_file(|file| file.advise(pos, len, advice))
+    }
+}
+
+/// Reopens the file if it was closed, since data written through an
+/// earlier handle may not have reached the disk yet.
+impl SyncAt for CachedFile {
+    fn sync_all(&mut self) -> Result<()> {
+        self.with_file(|file| file.sync_all())
+    }
+
+    fn sync_data(&mut self) -> Result<()> {
+        self.with_file(|file| file.sync_data())
+    }
+}
+
+impl PunchHole for CachedFile {
+    fn punch_hole(&mut self, pos: u64, len: u64) -> Result<()> {
+        self.with_file(|file| file.punch_hole(pos, len))
+    }
+}
+
+impl Extents for CachedFile {
+    fn extent_at(&mut self, pos: u64) -> Result<Option<Extent>> {
+        self.with_file(|file| file.extent_at(pos))
+    }
+}
+
+impl Allocate for CachedFile {
+    fn allocate(&mut self, pos: u64, len: u64) -> Result<()> {
+        self.with_file(|file| file.allocate(pos, len))
+    }
+}
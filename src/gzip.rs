@@ -0,0 +1,564 @@
+use std::io::Result;
+
+use advise;
+use inflate::{self, Bits, WINDOW};
+use sha256;
+use {Advice, Advise, Error, KnownEof, MemoryBudget, MemoryReservation, ReadAt, SizeAt, WriteAt};
+
+const DEFAULT_SPACING: u64 = 1024 * 1024;
+const INDEX_MAGIC: &[u8; 8] = b"IOATGZI1";
+const INDEX_HEADER_LEN: usize = 40;
+const POINT_HEADER_LEN: usize = 20;
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            crc = if crc & 1 != 0 { 0xedb8_8320 ^ (crc >> 1) } else { crc >> 1 };
+            k += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+fn crc32(crc: u32, data: &[u8]) -> u32 {
+    !data.iter().fold(!crc, |crc, &b| CRC_TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+/// A position at a deflate block boundary where decoding can resume.
+#[derive(Clone, Debug)]
+struct Point {
+    out: u64,
+    bit: u64,
+    window: Vec<u8>,
+}
+
+/// A read-only view of the uncompressed contents of a gzip image.
+///
+/// gzip cannot be decoded from the middle, so opening an image without an
+/// index decodes it once in full, verifying the checksum of every member
+/// and recording an access point about every megabyte of output. Each
+/// access point holds the last 32 KiB of data before it, so the index for
+/// an image takes about 3% of its uncompressed size. The index can be
+/// saved with [`save_index`](#method.save_index) and loaded in later
+/// sessions with [`load_index`](#method.load_index), skipping the full
+/// decode.
+///
+/// Reads decode the span between two access points and keep it cached,
+/// so sequential reads decode each span once. Images made of several
+/// concatenated members are read as their concatenation, as by `gzip`.
+#[derive(Debug)]
+pub struct Gzip<R> {
+    inner: R,
+    len: u64,
+    compressed_len: u64,
+    trailer: [u8; 8],
+    points: Vec<Point>,
+    cached: Option<usize>,
+    cache: Vec<u8>,
+    reservation: Option<MemoryReservation>,
+}
+
+/// Skips a gzip member header.
+fn read_header<R: ReadAt + ?Sized>(bits: &mut Bits<R>) -> Result<()> {
+    let mut header = [0; 10];
+    bits.read_bytes(&mut header)?;
+    if header[..2] != [0x1f, 0x8b] {
+        return Err(inflate::invalid("not a gzip stream"));
+    } else if header[2] != 8 {
+        return Err(inflate::invalid("unsupported gzip compression method"));
+    }
+    let flags = header[3];
+    if flags & 0xe0 != 0 {
+        return Err(inflate::invalid("reserved gzip flags set"));
+    }
+    if flags & 0x04 != 0 {
+        let mut len = [0; 2];
+        bits.read_bytes(&mut len)?;
+        let mut extra = vec![0; u16::from_le_bytes(len) as usize];
+        bits.read_bytes(&mut extra)?;
+    }
+    for flag in [0x08, 0x10] {
+        if flags & flag != 0 {
+            let mut byte = [1];
+            while byte[0] != 0 {
+                bits.read_bytes(&mut byte)?;
+            }
+        }
+    }
+    if flags & 0x02 != 0 {
+        bits.read_bytes(&mut [0; 2])?;
+    }
+    Ok(())
+}
+
+impl<R: ReadAt> Gzip<R> {
+    /// Indexes the gzip image in `inner` with a full decode.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ChecksumMismatch`](enum.Error.html) if the data
+    /// of a member does not match its trailer, an error of kind
+    /// `InvalidData` if the image is not a valid gzip stream, one of kind
+    /// `UnexpectedEof` if it is truncated, and any I/O error from
+    /// `inner`.
+    pub fn new(inner: R) -> Result<Gzip<R>> {
+        Gzip::with_spacing(inner, DEFAULT_SPACING)
+    }
+
+    /// Indexes the gzip image in `inner` with a full decode, recording an
+    /// access point about every `spacing` bytes of output.
+    ///
+    /// Access points can only be placed between deflate blocks, so they
+    /// may be further apart than `spacing`.
+    ///
+    /// # Errors
+    ///
+    /// As for [`new`](#method.new).
+    pub fn with_spacing(mut inner: R, spacing: u64) -> Result<Gzip<R>> {
+        let mut points: Vec<Point> = Vec::new();
+        let mut trailer = [0; 8];
+        let mut out = Vec::new();
+        let mut drained = 0;
+        let compressed_len = {
+            let mut bits = Bits::new(&mut inner, 0)?;
+            read_header(&mut bits)?;
+            let mut crc = 0;
+            let mut member_start = 0;
+            loop {
+                let total = drained + out.len() as u64;
+                if points.last().is_none_or(|point| total - point.out >= spacing) {
+                    points.push(Point {
+                        out: total,
+                        bit: bits.bit_pos(),
+                        window: out[out.len().saturating_sub(WINDOW)..].to_vec(),
+                    });
+                }
+                let start = out.len();
+                let last = inflate::inflate_block(&mut bits, &mut out)?;
+                crc = crc32(crc, &out[start..]);
+                if last {
+                    let offset = bits.bit_pos().div_ceil(8);
+                    bits.read_bytes(&mut trailer)?;
+                    let size = drained + out.len() as u64 - member_start;
+                    if trailer[..4] != crc.to_le_bytes() || trailer[4..] != (size as u32).to_le_bytes() {
+                        return Err(Error::ChecksumMismatch { offset }.into());
+                    }
+                    if bits.at_end()? {
+                        break bits.bit_pos() / 8;
+                    }
+                    read_header(&mut bits)?;
+                    crc = 0;
+                    member_start = drained + out.len() as u64;
+                }
+                if out.len() > 2 * WINDOW {
+                    let n = out.len() - WINDOW;
+                    out.drain(..n);
+                    drained += n as u64;
+                }
+            }
+        };
+        let len = drained + out.len() as u64;
+        // Empty blocks at the end can leave a point past all the data.
+        if points.len() > 1 && points.last().is_some_and(|point| point.out == len) {
+            points.pop();
+        }
+        Ok(Gzip {
+            inner,
+            len,
+            compressed_len,
+            trailer,
+            points,
+            cached: None,
+            cache: Vec::new(),
+            reservation: None,
+        })
+    }
+
+    /// Opens the gzip image in `inner` with an index read from `src` at
+    /// `pos`, as written by [`save_index`](#method.save_index).
+    ///
+    /// The index is checked against the length of the image and its last
+    /// trailer, so an index for a different or modified image is
+    /// rejected in most cases. Data is not verified against the member
+    /// checksums when reading through a loaded index.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ChecksumMismatch`](enum.Error.html) if the index
+    /// is corrupt, an error of kind `InvalidData` if it is malformed or
+    /// does not match the image, and any I/O error from `src` or `inner`.
+    pub fn load_index<S: ReadAt + ?Sized>(mut inner: R, src: &mut S, pos: u64) -> Result<Gzip<R>> {
+        let mut buf = vec![0; INDEX_HEADER_LEN];
+        src.read_exact_at(pos, &mut buf)?;
+        if &buf[..8] != INDEX_MAGIC {
+            return Err(inflate::invalid("invalid gzip index magic"));
+        }
+        let len = le_u64(&buf[8..16]);
+        let compressed_len = le_u64(&buf[16..24]);
+        let count = le_u64(&buf[24..32]);
+        let mut trailer = [0; 8];
+        trailer.copy_from_slice(&buf[32..40]);
+        if count == 0 || compressed_len < 18 {
+            return Err(inflate::invalid("invalid gzip index header"));
+        }
+
+        let mut points: Vec<Point> = Vec::new();
+        let mut at = pos + INDEX_HEADER_LEN as u64;
+        for _ in 0..count {
+            let start = buf.len();
+            buf.resize(start + POINT_HEADER_LEN, 0);
+            src.read_exact_at(at, &mut buf[start..])?;
+            let out = le_u64(&buf[start..start + 8]);
+            let bit = le_u64(&buf[start + 8..start + 16]);
+            let window_len = u32::from_le_bytes([buf[start + 16], buf[start + 17], buf[start + 18], buf[start + 19]]);
+            let valid = match points.last() {
+                Some(last) => out > last.out && out < len && bit > last.bit,
+                None => out == 0,
+            };
+            if !valid || bit >= compressed_len * 8 || window_len as usize > WINDOW || window_len as u64 > out {
+                return Err(inflate::invalid("invalid gzip index entry"));
+            }
+            let mut window = vec![0; window_len as usize];
+            src.read_exact_at(at + POINT_HEADER_LEN as u64, &mut window)?;
+            buf.extend_from_slice(&window);
+            at += (POINT_HEADER_LEN + window.len()) as u64;
+            points.push(Point { out, bit, window });
+        }
+        let mut digest = [0; 32];
+        src.read_exact_at(at, &mut digest)?;
+        if sha256::digest(&buf) != digest {
+            return Err(Error::ChecksumMismatch { offset: pos }.into());
+        }
+
+        let mut stored = [0; 8];
+        inner.read_exact_at(compressed_len - 8, &mut stored)?;
+        if stored != trailer || inner.read_at(compressed_len, &mut [0])? != 0 {
+            return Err(inflate::invalid("gzip index does not match the image"));
+        }
+        Ok(Gzip {
+            inner,
+            len,
+            compressed_len,
+            trailer,
+            points,
+            cached: None,
+            cache: Vec::new(),
+            reservation: None,
+        })
+    }
+
+    /// Writes the index to `dst` at `pos`, returning its length in bytes.
+    ///
+    /// The index consists of a header, the access points with their
+    /// windows, and a checksum of the whole.
+    ///
+    /// # Errors
+    ///
+    /// This method can return any I/O error from `dst`.
+    pub fn save_index<W: WriteAt + ?Sized>(&self, dst: &mut W, pos: u64) -> Result<u64> {
+        let windows: usize = self.points.iter().map(|point| point.window.len()).sum();
+        let mut buf = Vec::with_capacity(INDEX_HEADER_LEN + self.points.len() * POINT_HEADER_LEN + windows + 32);
+        buf.extend_from_slice(INDEX_MAGIC);
+        buf.extend_from_slice(&self.len.to_le_bytes());
+        buf.extend_from_slice(&self.compressed_len.to_le_bytes());
+        buf.extend_from_slice(&(self.points.len() as u64).to_le_bytes());
+        buf.extend_from_slice(&self.trailer);
+        for point in &self.points {
+            buf.extend_from_slice(&point.out.to_le_bytes());
+            buf.extend_from_slice(&point.bit.to_le_bytes());
+            buf.extend_from_slice(&(point.window.len() as u32).to_le_bytes());
+            buf.extend_from_slice(&point.window);
+        }
+        let digest = sha256::digest(&buf);
+        buf.extend_from_slice(&digest);
+        dst.write_all_at(pos, &buf)?;
+        Ok(buf.len() as u64)
+    }
+
+    /// Returns the length of the uncompressed data.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns whether the uncompressed data is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Draws the memory of the cached span from `budget`.
+    ///
+    /// Spans that do not fit in `budget` are decoded for each read
+    /// instead of cached, and the cache is dropped whenever the budget
+    /// comes under pressure.
+    pub fn set_budget(&mut self, budget: &MemoryBudget) {
+        let mut reservation = budget.reservation();
+        if self.cached.is_some() && !reservation.try_resize(self.cache.len() as u64) {
+            self.drop_cache();
+        }
+        self.reservation = Some(reservation);
+    }
+
+    /// Returns a reference to the compressed image.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Consumes the adapter, returning the compressed image.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Decodes the span starting at access point `i` into the cache.
+    fn load(&mut self, i: usize) -> Result<()> {
+        if self.cached == Some(i) {
+            return Ok(());
+        }
+        self.cached = None;
+        let point = &self.points[i];
+        let len = self.points.get(i + 1).map_or(self.len, |next| next.out) - point.out;
+        let mut out = point.window.clone();
+        let base = out.len();
+        let mut bits = Bits::new(&mut self.inner, point.bit)?;
+        let mut last = false;
+        while ((out.len() - base) as u64) < len {
+            if last {
+                bits.read_bytes(&mut [0; 8])?;
+                if bits.at_end()? {
+                    break;
+                }
+                read_header(&mut bits)?;
+            }
+            last = inflate::inflate_block(&mut bits, &mut out)?;
+        }
+        if (out.len() - base) as u64 != len {
+            return Err(inflate::invalid("gzip index does not match the image"));
+        }
+        out.drain(..base);
+        self.cache = out;
+        self.cached = Some(i);
+        Ok(())
+    }
+
+    fn drop_cache(&mut self) {
+        self.cached = None;
+        self.cache = Vec::new();
+        if let Some(ref mut reservation) = self.reservation {
+            reservation.try_resize(0);
+        }
+    }
+}
+
+impl<R: ReadAt> ReadAt for Gzip<R> {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        if pos >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        if self.reservation.as_mut().is_some_and(|reservation| reservation.take_pressure()) {
+            self.drop_cache();
+        }
+        let i = self.points.partition_point(|point| point.out <= pos) - 1;
+        self.load(i)?;
+        let data = &self.cache[(pos - self.points[i].out) as usize..];
+        let n = data.len().min(buf.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        let len = self.cache.len() as u64;
+        if !self.reservation.as_mut().is_none_or(|reservation| reservation.try_resize(len)) {
+            self.drop_cache();
+        }
+        Ok(n)
+    }
+}
+
+impl<R: ReadAt> KnownEof for Gzip<R> {
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        Ok(self.len.saturating_sub(pos))
+    }
+}
+
+impl<R: ReadAt> SizeAt for Gzip<R> {
+    fn size(&self) -> Result<u64> {
+        Ok(self.len)
+    }
+}
+
+/// `DontNeed` drops the cached span if it overlaps the range. Other advice
+/// is ignored.
+impl<R: ReadAt> Advise for Gzip<R> {
+    fn advise(&mut self, pos: u64, len: u64, advice: Advice) -> Result<()> {
+        if let (Advice::DontNeed, Some(i)) = (advice, self.cached) {
+            let end = self.points.get(i + 1).map_or(self.len, |next| next.out);
+            if advise::overlaps(pos, len, self.points[i].out, end) {
+                self.drop_cache();
+            }
+        }
+        Ok(())
+    }
+}
+
+fn le_u64(bytes: &[u8]) -> u64 {
+    let mut buf = [0; 8];
+    buf.copy_from_slice(bytes);
+    u64::from_le_bytes(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+
+    const HELLO: &[u8] = b"\
+        \x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\xcb\x48\xcd\xc9\xc9\xd7\x51\x48\xaf\xca\x2c\xe0\x02\x00\
+        \x86\x1f\x82\xa4\x0c\x00\x00\x00";
+    const TWO_MEMBERS: &[u8] = b"\
+        \x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\x4b\xcb\x2c\x2a\x2e\x51\xc8\x4d\xcd\x4d\x4a\x2d\xe2\x02\
+        \x00\xa7\xf4\x85\x0a\x0d\x00\x00\x00\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\x2b\x4e\x4d\xce\xcf\
+        \x4b\x51\xc8\x4d\xcd\x4d\x4a\x2d\xe2\x02\x00\x36\x18\x4b\x0e\x0e\x00\x00\x00";
+    const BLOCKS: &[u8] = b"\
+        \x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\x24\x8f\xc1\x6d\x43\x31\x0c\x43\xef\x9d\x82\x03\x04\x41\
+        \x06\xe9\xb5\x03\x28\xb6\x10\x10\xb0\xac\x1f\x5b\x0a\x3a\x7e\xf5\xfb\x6f\x12\x44\x3e\x52\xdf\xbe\
+        \xd4\xc0\x63\xa7\xa1\xfb\xf0\x85\xcd\x80\x98\xc6\x0d\xcd\xe7\xd6\x16\x1a\xb9\x20\x9d\x07\x77\xe3\
+        \x7c\x41\x07\xeb\xb8\xb5\x97\x01\xca\xdc\xe6\x1d\xa1\x76\x94\x99\xb3\xb1\xb3\xe7\x0c\x64\x60\xc8\
+        \xb3\xf0\xd0\xb8\xd0\x0a\x93\xd7\x14\xc8\xe0\x3b\xe5\x8e\x9f\x80\x4e\x5a\xb1\x61\x3c\x87\x4f\xad\
+        \x62\x37\xbc\x93\x1b\xd3\x77\xac\xec\xd0\x5f\x5d\x8d\x21\x41\x9f\xc8\x31\xc4\x9a\x5f\xe4\x53\xc4\
+        \xcd\x33\xe9\x1f\xc9\xa3\xc4\x50\xa9\xe2\x56\x9d\xfc\x7a\xa0\xa2\xe2\x8e\xc7\xd7\x1f\x00\x00\x00\
+        \xff\xff\x1a\xea\x3e\x30\xe4\x02\x00\x00\x00\xff\xff\x1a\xac\x2e\x33\xe2\x02\x00\x00\x00\xff\xff\
+        \xa2\xb7\x8d\xc6\x5c\x00\x00\x00\x00\xff\xff\x03\x00\xd0\x41\x1a\xdd\xb8\x02\x00\x00";
+    const LOREM: &[u8] = b"Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor incididunt \
+        ut labore et dolore magna aliqua. Ut enim ad minim veniam, quis nostrud exercitation ullamco laboris nisi ut \
+        aliquip ex ea commodo consequat.";
+
+    /// The data of `BLOCKS`, which holds one sync-flushed block per line.
+    fn blocks_data() -> Vec<u8> {
+        let mut data = Vec::new();
+        for i in 0..4 {
+            data.extend_from_slice(&LOREM[i * 40..]);
+            data.extend_from_slice(format!(" {}\n", i).as_bytes());
+        }
+        data
+    }
+
+    fn contents<R: ReadAt>(gzip: &mut Gzip<R>) -> Vec<u8> {
+        let mut buf = vec![0; gzip.len() as usize];
+        gzip.read_exact_at(0, &mut buf).unwrap();
+        assert_eq!(gzip.read_at(gzip.len(), &mut [0]).unwrap(), 0);
+        buf
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc32(0, b""), 0);
+        assert_eq!(crc32(0, b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(crc32(0, b"1234"), b"56789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn single_member() {
+        let mut gzip = Gzip::new(HELLO).unwrap();
+        assert_eq!(gzip.len(), 12);
+        assert_eq!(contents(&mut gzip), b"hello, gzip\n");
+    }
+
+    #[test]
+    fn multiple_members() {
+        let mut gzip = Gzip::new(TWO_MEMBERS).unwrap();
+        assert_eq!(contents(&mut gzip), b"first member\nsecond member\n");
+        let mut buf = [0; 10];
+        gzip.read_exact_at(9, &mut buf).unwrap();
+        assert_eq!(&buf, b"ber\nsecond");
+    }
+
+    #[test]
+    fn access_points() {
+        let data = blocks_data();
+        let mut gzip = Gzip::with_spacing(BLOCKS, 1).unwrap();
+        assert!(gzip.points.len() > 2);
+        assert_eq!(contents(&mut gzip), data);
+        // Read backwards, so each read starts at a different access point
+        // and decodes back-references into the window stored with it.
+        for pos in (0..data.len()).rev().step_by(37) {
+            let mut buf = vec![0; 50];
+            let n = gzip.read_at(pos as u64, &mut buf).unwrap();
+            assert!(n > 0);
+            assert_eq!(buf[..n], data[pos..pos + n]);
+        }
+    }
+
+    #[test]
+    fn bad_crc() {
+        let mut image = TWO_MEMBERS.to_vec();
+        let trailer = image.len() - 8;
+        image[trailer] ^= 1;
+        let err = Gzip::new(&image[..]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        match Error::downcast_ref(&err) {
+            Some(&Error::ChecksumMismatch { offset }) => assert_eq!(offset, trailer as u64),
+            other => panic!("unexpected error {:?}", other),
+        }
+    }
+
+    #[test]
+    fn truncated_image() {
+        for len in [0, 5, 12, HELLO.len() - 4, TWO_MEMBERS.len() - 1] {
+            let image = if len < HELLO.len() { &HELLO[..len] } else { &TWO_MEMBERS[..len] };
+            assert_eq!(Gzip::new(image).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        }
+    }
+
+    #[test]
+    fn bad_header() {
+        let mut image = HELLO.to_vec();
+        image[2] = 7;
+        assert_eq!(Gzip::new(&image[..]).unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn index_round_trip() {
+        let gzip = Gzip::with_spacing(BLOCKS, 1).unwrap();
+        let mut index = vec![0; 4096];
+        let len = gzip.save_index(&mut index[..], 10).unwrap() as usize;
+        let mut src = &index[..10 + len];
+        let mut loaded = Gzip::load_index(BLOCKS, &mut src, 10).unwrap();
+        assert_eq!(loaded.points.len(), gzip.points.len());
+        assert_eq!(contents(&mut loaded), blocks_data());
+    }
+
+    #[test]
+    fn corrupt_index() {
+        let gzip = Gzip::with_spacing(BLOCKS, 1).unwrap();
+        let mut index = vec![0; 4096];
+        let len = gzip.save_index(&mut index[..], 0).unwrap() as usize;
+        index.truncate(len);
+
+        let mut corrupt = index.clone();
+        // The last byte of the last window.
+        corrupt[len - 33] ^= 1;
+        let err = Gzip::load_index(BLOCKS, &mut &corrupt[..], 0).unwrap_err();
+        assert!(matches!(Error::downcast_ref(&err), Some(&Error::ChecksumMismatch { offset: 0 })));
+
+        let mut corrupt = index.clone();
+        corrupt[0] ^= 1;
+        let err = Gzip::load_index(BLOCKS, &mut &corrupt[..], 0).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        let err = Gzip::load_index(BLOCKS, &mut &index[..len - 1], 0).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn index_for_another_image() {
+        let gzip = Gzip::new(HELLO).unwrap();
+        let mut index = vec![0; 4096];
+        gzip.save_index(&mut index[..], 0).unwrap();
+        let err = Gzip::load_index(TWO_MEMBERS, &mut &index[..], 0).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(Error::downcast_ref(&err).is_none());
+    }
+}
@@ -0,0 +1,417 @@
+//! A DEFLATE decoder that works one block at a time, so decoding can
+//! resume at any block boundary given the preceding window.
+
+use std::io::{self, ErrorKind, Result};
+use std::sync::OnceLock;
+
+use ReadAt;
+
+/// The size of the window that back-references may reach into.
+pub const WINDOW: usize = 32 * 1024;
+
+const BUF_SIZE: usize = 64 * 1024;
+const FAST_BITS: u32 = 10;
+const MAX_BITS: usize = 15;
+
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115,
+                                131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DIST_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+                              2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+                              13];
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+pub fn invalid(msg: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(ErrorKind::UnexpectedEof, "truncated compressed stream")
+}
+
+/// Reads a compressed stream from a `ReadAt` bit by bit, least
+/// significant bit first.
+pub struct Bits<'a, R: ?Sized + 'a> {
+    src: &'a mut R,
+    buf: Vec<u8>,
+    start: u64,
+    len: usize,
+    i: usize,
+    bitbuf: u64,
+    bitcnt: u32,
+}
+
+impl<'a, R: ReadAt + ?Sized> Bits<'a, R> {
+    /// Starts reading `src` at bit offset `bit`.
+    pub fn new(src: &'a mut R, bit: u64) -> Result<Bits<'a, R>> {
+        let mut bits = Bits {
+            src,
+            buf: vec![0; BUF_SIZE],
+            start: bit / 8,
+            len: 0,
+            i: 0,
+            bitbuf: 0,
+            bitcnt: 0,
+        };
+        bits.bits((bit % 8) as u32)?;
+        Ok(bits)
+    }
+
+    /// Returns the bit offset of the next bit to be read.
+    pub fn bit_pos(&self) -> u64 {
+        (self.start + self.i as u64) * 8 - self.bitcnt as u64
+    }
+
+    /// Makes sure the buffer holds an unread byte, returning `false` at
+    /// the end of the stream.
+    fn fill(&mut self) -> Result<bool> {
+        if self.i < self.len {
+            return Ok(true);
+        }
+        self.start += self.len as u64;
+        self.i = 0;
+        self.len = 0;
+        loop {
+            match self.src.read_at(self.start, &mut self.buf) {
+                Ok(n) => {
+                    self.len = n;
+                    return Ok(n > 0);
+                }
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Returns the next `n` bits without consuming them, padded with
+    /// zeros at the end of the stream, and the number of bits available.
+    fn peek(&mut self, n: u32) -> Result<(u32, u32)> {
+        while self.bitcnt < n {
+            if !self.fill()? {
+                break;
+            }
+            self.bitbuf |= (self.buf[self.i] as u64) << self.bitcnt;
+            self.i += 1;
+            self.bitcnt += 8;
+        }
+        Ok(((self.bitbuf & ((1 << n) - 1)) as u32, self.bitcnt))
+    }
+
+    fn consume(&mut self, n: u32) {
+        self.bitbuf >>= n;
+        self.bitcnt -= n;
+    }
+
+    /// Reads `n` bits, at most 16.
+    pub fn bits(&mut self, n: u32) -> Result<u32> {
+        let (value, available) = self.peek(n)?;
+        if available < n {
+            return Err(truncated());
+        }
+        self.consume(n);
+        Ok(value)
+    }
+
+    /// Skips to the next byte boundary.
+    pub fn align(&mut self) {
+        let n = self.bitcnt % 8;
+        self.consume(n);
+    }
+
+    /// Returns whether the end of the stream has been reached, after
+    /// skipping to the next byte boundary.
+    pub fn at_end(&mut self) -> Result<bool> {
+        self.align();
+        Ok(self.bitcnt == 0 && !self.fill()?)
+    }
+
+    /// Reads whole bytes into `buf`, after skipping to the next byte
+    /// boundary.
+    pub fn read_bytes(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.align();
+        let mut done = 0;
+        while done < buf.len() && self.bitcnt > 0 {
+            buf[done] = self.bitbuf as u8;
+            self.consume(8);
+            done += 1;
+        }
+        while done < buf.len() {
+            if !self.fill()? {
+                return Err(truncated());
+            }
+            let n = (buf.len() - done).min(self.len - self.i);
+            buf[done..done + n].copy_from_slice(&self.buf[self.i..self.i + n]);
+            self.i += n;
+            done += n;
+        }
+        Ok(())
+    }
+}
+
+/// A canonical Huffman code, decoded through a table for short codes and
+/// bit by bit for long ones.
+struct Huffman {
+    fast: Vec<u16>,
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                return Err(invalid("over-subscribed huffman code"));
+            }
+        }
+        let mut offsets = [0usize; MAX_BITS + 2];
+        for len in 1..=MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len] as usize;
+        }
+        let mut symbols = vec![0; offsets[MAX_BITS + 1]];
+        let mut next = offsets;
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[next[len as usize]] = symbol as u16;
+                next[len as usize] += 1;
+            }
+        }
+
+        let mut fast = vec![0; 1 << FAST_BITS];
+        let mut code = 0u32;
+        for len in 1..=MAX_BITS {
+            for &symbol in &symbols[offsets[len]..offsets[len + 1]] {
+                if len as u32 <= FAST_BITS {
+                    let reversed = code.reverse_bits() >> (32 - len);
+                    for i in (reversed as usize..fast.len()).step_by(1 << len) {
+                        fast[i] = symbol << 4 | len as u16;
+                    }
+                }
+                code += 1;
+            }
+            code <<= 1;
+        }
+        Ok(Huffman { fast, counts, symbols })
+    }
+
+    fn decode<R: ReadAt + ?Sized>(&self, bits: &mut Bits<R>) -> Result<u16> {
+        let (peek, available) = bits.peek(FAST_BITS)?;
+        let entry = self.fast[peek as usize];
+        let len = (entry & 15) as u32;
+        if len != 0 && len <= available {
+            bits.consume(len);
+            return Ok(entry >> 4);
+        }
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &self.counts[1..] {
+            code |= bits.bits(1)? as i32;
+            let count = count as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("invalid huffman code"))
+    }
+}
+
+fn fixed() -> &'static (Huffman, Huffman) {
+    static FIXED: OnceLock<(Huffman, Huffman)> = OnceLock::new();
+    FIXED.get_or_init(|| {
+        let mut lengths = [0; 288];
+        lengths[..144].fill(8);
+        lengths[144..256].fill(9);
+        lengths[256..280].fill(7);
+        lengths[280..].fill(8);
+        let lit = Huffman::new(&lengths).expect("fixed literal code is valid");
+        let dist = Huffman::new(&[5; 30]).expect("fixed distance code is valid");
+        (lit, dist)
+    })
+}
+
+fn dynamic<R: ReadAt + ?Sized>(bits: &mut Bits<R>) -> Result<(Huffman, Huffman)> {
+    let nlen = bits.bits(5)? as usize + 257;
+    let ndist = bits.bits(5)? as usize + 1;
+    let ncode = bits.bits(4)? as usize + 4;
+    if nlen > 286 || ndist > 30 {
+        return Err(invalid("too many length or distance codes"));
+    }
+    let mut lengths = [0u8; 19];
+    for &i in &CODE_LENGTH_ORDER[..ncode] {
+        lengths[i] = bits.bits(3)? as u8;
+    }
+    let code = Huffman::new(&lengths)?;
+
+    let mut lengths = [0u8; 286 + 30];
+    let mut i = 0;
+    while i < nlen + ndist {
+        let symbol = code.decode(bits)?;
+        let (len, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 if i == 0 => return Err(invalid("repeated code length without a previous one")),
+            16 => (lengths[i - 1], 3 + bits.bits(2)? as usize),
+            17 => (0, 3 + bits.bits(3)? as usize),
+            _ => (0, 11 + bits.bits(7)? as usize),
+        };
+        if i + repeat > nlen + ndist {
+            return Err(invalid("too many code lengths"));
+        }
+        lengths[i..i + repeat].fill(len);
+        i += repeat;
+    }
+    if lengths[256] == 0 {
+        return Err(invalid("missing end-of-block code"));
+    }
+    Ok((Huffman::new(&lengths[..nlen])?, Huffman::new(&lengths[nlen..nlen + ndist])?))
+}
+
+fn codes<R: ReadAt + ?Sized>(bits: &mut Bits<R>, out: &mut Vec<u8>, lit: &Huffman, dist: &Huffman) -> Result<()> {
+    loop {
+        let symbol = lit.decode(bits)? as usize;
+        if symbol < 256 {
+            out.push(symbol as u8);
+            continue;
+        } else if symbol == 256 {
+            return Ok(());
+        }
+        let symbol = symbol - 257;
+        if symbol >= LENGTH_BASE.len() {
+            return Err(invalid("invalid length code"));
+        }
+        let len = LENGTH_BASE[symbol] as usize + bits.bits(LENGTH_EXTRA[symbol] as u32)? as usize;
+        let symbol = dist.decode(bits)? as usize;
+        if symbol >= DIST_BASE.len() {
+            return Err(invalid("invalid distance code"));
+        }
+        let distance = DIST_BASE[symbol] as usize + bits.bits(DIST_EXTRA[symbol] as u32)? as usize;
+        if distance > out.len() {
+            return Err(invalid("distance too far back"));
+        }
+        let start = out.len() - distance;
+        if distance >= len {
+            out.extend_from_within(start..start + len);
+        } else {
+            for i in start..start + len {
+                let byte = out[i];
+                out.push(byte);
+            }
+        }
+    }
+}
+
+/// Decodes the next block of the stream, appending its data to `out`,
+/// and returns whether it was the final block.
+///
+/// Back-references may reach into any data already in `out`, which must
+/// hold at least the last [`WINDOW`] bytes of preceding output.
+pub fn inflate_block<R: ReadAt + ?Sized>(bits: &mut Bits<R>, out: &mut Vec<u8>) -> Result<bool> {
+    let last = bits.bits(1)? == 1;
+    match bits.bits(2)? {
+        0 => {
+            let mut header = [0; 4];
+            bits.read_bytes(&mut header)?;
+            let len = u16::from_le_bytes([header[0], header[1]]);
+            if len != !u16::from_le_bytes([header[2], header[3]]) {
+                return Err(invalid("stored block length mismatch"));
+            }
+            let start = out.len();
+            out.resize(start + len as usize, 0);
+            bits.read_bytes(&mut out[start..])?;
+        }
+        1 => {
+            let (lit, dist) = fixed();
+            codes(bits, out, lit, dist)?;
+        }
+        2 => {
+            let (lit, dist) = dynamic(bits)?;
+            codes(bits, out, &lit, &dist)?;
+        }
+        _ => return Err(invalid("invalid deflate block type")),
+    }
+    Ok(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STORED: &[u8] = b"\x01\x0c\x00\xf3\xff\x73\x74\x6f\x72\x65\x64\x20\x62\x6c\x6f\x63\x6b";
+    const FIXED: &[u8] = b"\x4b\x4c\x4a\x4e\x44\x42\x0a\x89\x68\x00\x00";
+    const DYNAMIC: &[u8] = b"\
+        \x25\x8f\x51\x8e\x03\x31\x08\x43\xaf\xe2\x03\x54\x3d\x49\x7f\xf7\x00\x34\x41\x95\xa5\x10\x66\x02\
+        \x59\xed\xf1\x97\xe9\xfc\x81\xb0\x9f\xcd\xcb\x97\x1a\x78\xc4\x36\x74\x1f\xbe\x10\x4c\x88\x69\x3e\
+        \xd0\x7c\x86\xb6\xd4\xdc\x0b\xd2\x79\x30\x1a\xe7\x07\x3a\x58\xc7\xd0\x5e\x06\x28\x77\x98\x77\xa4\
+        \xda\x51\x66\xce\xc6\xce\xbe\x67\x62\x27\x86\xbc\x0b\x0f\xcd\x1b\xad\x30\xf9\x4c\x81\x0c\x9e\x5b\
+        \x9e\xf8\x49\xe8\xa4\x15\x1b\xc6\x6b\xf8\xad\x55\xec\x81\x73\x33\x30\x3d\x72\xed\x0e\xfd\xd3\xd5\
+        \x98\x92\xf4\x89\x3d\x86\x58\xf3\x9b\x7c\x89\x18\xbc\x92\xbe\x48\x1e\x25\x86\x4a\x15\xb7\xea\xe4\
+        \xf7\x03\x15\x95\xcf\x7f";
+    const LOREM: &[u8] = b"Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor incididunt \
+        ut labore et dolore magna aliqua. Ut enim ad minim veniam, quis nostrud exercitation ullamco laboris nisi ut \
+        aliquip ex ea commodo consequat.";
+
+    fn inflate(mut data: &[u8]) -> Result<Vec<u8>> {
+        let mut bits = Bits::new(&mut data, 0)?;
+        let mut out = Vec::new();
+        while !inflate_block(&mut bits, &mut out)? {}
+        Ok(out)
+    }
+
+    #[test]
+    fn stored_block() {
+        assert_eq!(inflate(STORED).unwrap(), b"stored block");
+    }
+
+    #[test]
+    fn fixed_block_with_overlapping_matches() {
+        assert_eq!(FIXED[0] >> 1 & 3, 1);
+        assert_eq!(inflate(FIXED).unwrap(), b"abcabcabcabcabc aaaaaaaaaaaaaaaa");
+    }
+
+    #[test]
+    fn dynamic_block() {
+        assert_eq!(DYNAMIC[0] >> 1 & 3, 2);
+        assert_eq!(inflate(DYNAMIC).unwrap(), LOREM);
+    }
+
+    #[test]
+    fn bit_offset() {
+        // The fixed block again, shifted up by three bits.
+        let mut shifted = vec![0b101];
+        for (i, &byte) in FIXED.iter().enumerate() {
+            shifted[i] |= byte << 3;
+            shifted.push(byte >> 5);
+        }
+        let mut src = &shifted[..];
+        let mut bits = Bits::new(&mut src, 3).unwrap();
+        let mut out = Vec::new();
+        assert!(inflate_block(&mut bits, &mut out).unwrap());
+        assert_eq!(out, b"abcabcabcabcabc aaaaaaaaaaaaaaaa");
+    }
+
+    #[test]
+    fn truncated_input() {
+        for len in [0, 1, STORED.len() - 1] {
+            assert_eq!(inflate(&STORED[..len]).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        }
+        for len in [1, 40, DYNAMIC.len() - 1] {
+            assert_eq!(inflate(&DYNAMIC[..len]).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        }
+    }
+
+    #[test]
+    fn invalid_blocks() {
+        assert_eq!(inflate(&[0x07]).unwrap_err().kind(), ErrorKind::InvalidData);
+        let mut stored = STORED.to_vec();
+        stored[3] ^= 1;
+        assert_eq!(inflate(&stored).unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,33 @@
+/// The relative priority of an operation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Priority {
+    /// Background work, such as scrubbing or prefetching.
+    Low,
+    /// Regular foreground work.
+    #[default]
+    Normal,
+    /// Latency-sensitive work.
+    High,
+}
+
+/// Options for a single read or write, passed to
+/// [`read_at_with`](trait.ReadAt.html#method.read_at_with) and
+/// [`write_at_with`](trait.WriteAt.html#method.write_at_with).
+///
+/// Sources and sinks are free to ignore any option they do not support,
+/// so options should be treated as hints unless documented otherwise by
+/// the implementation. New options may be added in the future, so this
+/// struct can only be created through `IoOptions::default()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub struct IoOptions {
+    /// The priority of the operation.
+    pub priority: Priority,
+    /// Return an error of kind `WouldBlock` instead of waiting for the
+    /// operation to complete, if it cannot complete immediately.
+    pub nowait: bool,
+    /// Synchronize the written data to the device before returning.
+    pub dsync: bool,
+    /// Avoid keeping the data in caches after the operation completes.
+    pub no_cache: bool,
+}
@@ -0,0 +1,286 @@
+use std::io::{IoSlice, IoSliceMut, Result};
+
+use {Advice, Advise, Allocate, Extent, Extents, IoOptions, KnownEof, LockAt, LockKind, Prefetch, PunchHole, ReadAt, ReadBuf, ResizeAt, SizeAt, SyncAt, WriteAt};
+
+/// A kernel I/O scheduling class and priority.
+///
+/// Levels range from 0, the highest priority, to 7, the lowest, and are
+/// clamped to that range.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IoPriority {
+    /// Only served when no other process needs the device.
+    Idle,
+    /// The default class, with the given level.
+    BestEffort(u8),
+    /// Served before all other classes, with the given level. This
+    /// usually requires elevated privileges.
+    Realtime(u8),
+}
+
+/// A wrapper running every operation on the inner value at a given
+/// kernel I/O priority.
+///
+/// On Linux, the priority of the calling thread is changed with
+/// `ioprio_set` for the duration of each operation, and restored
+/// afterwards. This costs two extra system calls per operation, so
+/// it is best suited for bulk background traffic such as scrubbing,
+/// prefetching and backups. On other platforms the priority is ignored.
+///
+/// Note that I/O schedulers only honor priorities for I/O they actually
+/// submit; writes absorbed by the page cache are written back later at
+/// the priority of the kernel flusher threads.
+#[derive(Clone, Debug)]
+pub struct Prioritized<T> {
+    inner: T,
+    priority: IoPriority,
+}
+
+impl<T> Prioritized<T> {
+    /// Wraps `inner`, running its operations at `priority`.
+    pub fn new(inner: T, priority: IoPriority) -> Prioritized<T> {
+        Prioritized { inner, priority }
+    }
+
+    /// Returns the priority operations are run at.
+    pub fn priority(&self) -> IoPriority {
+        self.priority
+    }
+
+    /// Sets the priority operations are run at.
+    pub fn set_priority(&mut self, priority: IoPriority) {
+        self.priority = priority;
+    }
+
+    /// Returns a reference to the inner value.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner value.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Consumes the wrapper, returning the inner value.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn with_priority<F, R>(&mut self, f: F) -> Result<R>
+        where F: FnOnce(&mut T) -> Result<R>
+    {
+        let _guard = imp::Guard::set(self.priority)?;
+        f(&mut self.inner)
+    }
+}
+
+impl<T: ReadAt> ReadAt for Prioritized<T> {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        self.with_priority(|inner| inner.read_at(pos, buf))
+    }
+
+    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
+        self.with_priority(|inner| inner.read_exact_at(pos, buf))
+    }
+
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        self.with_priority(|inner| inner.read_at_with(pos, buf, options))
+    }
+
+    fn read_vectored_at(&mut self, pos: u64, bufs: &mut [IoSliceMut]) -> Result<usize> {
+        self.with_priority(|inner| inner.read_vectored_at(pos, bufs))
+    }
+
+    fn read_buf_at(&mut self, pos: u64, buf: &mut ReadBuf) -> Result<()> {
+        self.with_priority(|inner| inner.read_buf_at(pos, buf))
+    }
+}
+
+impl<T: WriteAt> WriteAt for Prioritized<T> {
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        self.with_priority(|inner| inner.write_at(pos, buf))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.with_priority(|inner| inner.flush())
+    }
+
+    fn write_all_at(&mut self, pos: u64, buf: &[u8]) -> Result<()> {
+        self.with_priority(|inner| inner.write_all_at(pos, buf))
+    }
+
+    fn write_at_with(&mut self, pos: u64, buf: &[u8], options: &IoOptions) -> Result<usize> {
+        self.with_priority(|inner| inner.write_at_with(pos, buf, options))
+    }
+
+    fn write_vectored_at(&mut self, pos: u64, bufs: &[IoSlice]) -> Result<usize> {
+        self.with_priority(|inner| inner.write_vectored_at(pos, bufs))
+    }
+
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        self.with_priority(|inner| inner.write_zeroes_at(pos, len))
+    }
+}
+
+impl<T: SyncAt> SyncAt for Prioritized<T> {
+    fn sync_all(&mut self) -> Result<()> {
+        self.with_priority(|inner| inner.sync_all())
+    }
+
+    fn sync_data(&mut self) -> Result<()> {
+        self.with_priority(|inner| inner.sync_data())
+    }
+}
+
+impl<T: PunchHole> PunchHole for Prioritized<T> {
+    fn punch_hole(&mut self, pos: u64, len: u64) -> Result<()> {
+        self.with_priority(|inner| inner.punch_hole(pos, len))
+    }
+}
+
+impl<T: Extents> Extents for Prioritized<T> {
+    fn extent_at(&mut self, pos: u64) -> Result<Option<Extent>> {
+        self.with_priority(|inner| inner.extent_at(pos))
+    }
+}
+
+impl<T: Allocate> Allocate for Prioritized<T> {
+    fn allocate(&mut self, pos: u64, len: u64) -> Result<()> {
+        self.with_priority(|inner| inner.allocate(pos, len))
+    }
+}
+
+impl<T: KnownEof> KnownEof for Prioritized<T> {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        self.inner.remaining_at(pos)
+    }
+
+    #[inline]
+    fn is_eof_at(&mut self, pos: u64) -> Result<bool> {
+        self.inner.is_eof_at(pos)
+    }
+}
+
+impl<T: SizeAt> SizeAt for Prioritized<T> {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        self.inner.size()
+    }
+}
+
+impl<T: LockAt> LockAt for Prioritized<T> {
+    #[inline]
+    fn lock_range(&mut self, pos: u64, len: u64, kind: LockKind) -> Result<()> {
+        self.inner.lock_range(pos, len, kind)
+    }
+
+    #[inline]
+    fn try_lock_range(&mut self, pos: u64, len: u64, kind: LockKind) -> Result<bool> {
+        self.inner.try_lock_range(pos, len, kind)
+    }
+
+    #[inline]
+    fn unlock_range(&mut self, pos: u64, len: u64) -> Result<()> {
+        self.inner.unlock_range(pos, len)
+    }
+}
+
+impl<T: ResizeAt> ResizeAt for Prioritized<T> {
+    #[inline]
+    fn set_len(&mut self, len: u64) -> Result<()> {
+        self.inner.set_len(len)
+    }
+}
+
+impl<T: Prefetch> Prefetch for Prioritized<T> {
+    fn kernel_prefetch(&mut self, pos: u64, len: u64) -> Result<()> {
+        self.with_priority(|inner| inner.kernel_prefetch(pos, len))
+    }
+}
+
+impl<T: Advise> Advise for Prioritized<T> {
+    fn advise(&mut self, pos: u64, len: u64, advice: Advice) -> Result<()> {
+        self.with_priority(|inner| inner.advise(pos, len, advice))
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::cmp;
+    use std::io::{Error, Result};
+
+    use super::IoPriority;
+
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+    const IOPRIO_CLASS_RT: libc::c_int = 1;
+    const IOPRIO_CLASS_BE: libc::c_int = 2;
+    const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+
+    /// Restores the previous I/O priority of the calling thread on drop.
+    pub struct Guard {
+        previous: Option<libc::c_int>,
+    }
+
+    impl Guard {
+        pub fn set(priority: IoPriority) -> Result<Guard> {
+            let value = match priority {
+                IoPriority::Idle => IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT,
+                IoPriority::BestEffort(level) => {
+                    IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT | cmp::min(level, 7) as libc::c_int
+                }
+                IoPriority::Realtime(level) => {
+                    IOPRIO_CLASS_RT << IOPRIO_CLASS_SHIFT | cmp::min(level, 7) as libc::c_int
+                }
+            };
+            let previous = get()?;
+            if previous == value {
+                return Ok(Guard { previous: None });
+            }
+            set(value)?;
+            Ok(Guard { previous: Some(previous) })
+        }
+    }
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            if let Some(previous) = self.previous {
+                let _ = set(previous);
+            }
+        }
+    }
+
+    fn get() -> Result<libc::c_int> {
+        let ret = unsafe { libc::syscall(libc::SYS_ioprio_get, IOPRIO_WHO_PROCESS, 0) };
+        if ret == -1 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(ret as libc::c_int)
+        }
+    }
+
+    fn set(value: libc::c_int) -> Result<()> {
+        let ret = unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, value) };
+        if ret == -1 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use std::io::Result;
+
+    use super::IoPriority;
+
+    pub struct Guard;
+
+    impl Guard {
+        pub fn set(_priority: IoPriority) -> Result<Guard> {
+            Ok(Guard)
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{Empty, Repeat, Result};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use SliceAt;
+
+/// The `KnownEof` trait is implemented by sources that know where they
+/// end.
+///
+/// A short read from [`ReadAt`](trait.ReadAt.html) does not mean that the
+/// end was reached; a source such as a pipe wrapped in
+/// [`AssertThreadSafe`](struct.AssertThreadSafe.html) may return fewer
+/// bytes while more are still to come. Generic code that needs to tell
+/// the two apart, such as a retry layer deciding whether to keep reading,
+/// can require this trait.
+pub trait KnownEof {
+    /// Returns the number of bytes available from `pos` to the end of the
+    /// source, or zero if `pos` is at or past the end.
+    ///
+    /// Sources without an end, such as `io::Repeat`, return `u64::MAX`.
+    ///
+    /// # Errors
+    ///
+    /// This method can return any I/O error.
+    fn remaining_at(&mut self, pos: u64) -> Result<u64>;
+
+    /// Returns whether `pos` is at or past the end of the source.
+    ///
+    /// # Errors
+    ///
+    /// This method can return any I/O error.
+    fn is_eof_at(&mut self, pos: u64) -> Result<bool> {
+        Ok(self.remaining_at(pos)? == 0)
+    }
+}
+
+impl<E: KnownEof + ?Sized> KnownEof for &mut E {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        (**self).remaining_at(pos)
+    }
+
+    #[inline]
+    fn is_eof_at(&mut self, pos: u64) -> Result<bool> {
+        (**self).is_eof_at(pos)
+    }
+}
+
+impl<E: KnownEof + ?Sized> KnownEof for Box<E> {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        (**self).remaining_at(pos)
+    }
+
+    #[inline]
+    fn is_eof_at(&mut self, pos: u64) -> Result<bool> {
+        (**self).is_eof_at(pos)
+    }
+}
+
+impl KnownEof for &[u8] {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        Ok((self.len() as u64).saturating_sub(pos))
+    }
+}
+
+impl KnownEof for Vec<u8> {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        Ok((self.len() as u64).saturating_sub(pos))
+    }
+}
+
+impl KnownEof for VecDeque<u8> {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        Ok((self.len() as u64).saturating_sub(pos))
+    }
+}
+
+impl KnownEof for Arc<[u8]> {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        Ok((self.len() as u64).saturating_sub(pos))
+    }
+}
+
+impl KnownEof for Rc<[u8]> {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        Ok((self.len() as u64).saturating_sub(pos))
+    }
+}
+
+impl KnownEof for Cow<'_, [u8]> {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        Ok((self.len() as u64).saturating_sub(pos))
+    }
+}
+
+impl KnownEof for Box<[u8]> {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        Ok((self.len() as u64).saturating_sub(pos))
+    }
+}
+
+/// Uses the current length of the file from its metadata. The file may
+/// still be extended or truncated by others afterwards.
+impl KnownEof for File {
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        Ok(self.metadata()?.len().saturating_sub(pos))
+    }
+}
+
+impl KnownEof for &File {
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        Ok(self.metadata()?.len().saturating_sub(pos))
+    }
+}
+
+impl<T: AsRef<[u8]>> KnownEof for SliceAt<T> {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        Ok((self.0.as_ref().len() as u64).saturating_sub(pos))
+    }
+}
+
+impl KnownEof for Empty {
+    #[inline]
+    fn remaining_at(&mut self, _pos: u64) -> Result<u64> {
+        Ok(0)
+    }
+}
+
+impl KnownEof for Repeat {
+    #[inline]
+    fn remaining_at(&mut self, _pos: u64) -> Result<u64> {
+        Ok(u64::MAX)
+    }
+}
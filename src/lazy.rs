@@ -0,0 +1,215 @@
+use std::fmt;
+use std::io::{Error, ErrorKind, IoSlice, IoSliceMut, Result};
+use std::mem;
+
+use {Advice, Advise, Allocate, Extent, Extents, IoOptions, KnownEof, LockAt, LockKind, Prefetch, PunchHole, ReadAt, ReadBuf, SyncAt, WriteAt};
+
+enum State<T, F> {
+    Pending(F),
+    Open(T),
+    Failed(ErrorKind, String),
+    Opening,
+}
+
+/// A backend that is only opened when first accessed.
+///
+/// The function passed to [`new`](#method.new) is called on the first
+/// read or write, or on [`ensure_open`](#method.ensure_open). This lets
+/// applications keep handles to thousands of files, such as the segments
+/// of a log-structured store, without holding a descriptor for each.
+///
+/// If opening fails, the error is returned, and every later access fails
+/// with an error of the same kind. Flushing a backend that was never
+/// opened does nothing.
+pub struct Lazy<T, F = fn() -> Result<T>> {
+    state: State<T, F>,
+}
+
+impl<T, F: FnOnce() -> Result<T>> Lazy<T, F> {
+    /// Creates a backend opened by calling `open` on first access.
+    pub fn new(open: F) -> Lazy<T, F> {
+        Lazy { state: State::Pending(open) }
+    }
+
+    /// Opens the backend if it is not open yet, and returns it.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from opening the backend, now or on an earlier
+    /// attempt.
+    pub fn ensure_open(&mut self) -> Result<&mut T> {
+        if let State::Pending(_) = self.state {
+            let open = match mem::replace(&mut self.state, State::Opening) {
+                State::Pending(open) => open,
+                _ => unreachable!(),
+            };
+            self.state = match open() {
+                Ok(inner) => State::Open(inner),
+                Err(e) => {
+                    self.state = State::Failed(e.kind(), e.to_string());
+                    return Err(e);
+                }
+            };
+        }
+        match self.state {
+            State::Open(ref mut inner) => Ok(inner),
+            State::Failed(kind, ref msg) => Err(Error::new(kind, msg.clone())),
+            _ => Err(Error::other("backend panicked while opening")),
+        }
+    }
+}
+
+impl<T, F> Lazy<T, F> {
+    /// Returns whether the backend has been opened.
+    pub fn is_open(&self) -> bool {
+        matches!(self.state, State::Open(_))
+    }
+
+    /// Returns a mutable reference to the backend, if it is open.
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        match self.state {
+            State::Open(ref mut inner) => Some(inner),
+            _ => None,
+        }
+    }
+
+    /// Consumes the wrapper, returning the backend, if it is open.
+    pub fn into_inner(self) -> Option<T> {
+        match self.state {
+            State::Open(inner) => Some(inner),
+            _ => None,
+        }
+    }
+}
+
+impl<T: fmt::Debug, F> fmt::Debug for Lazy<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.state {
+            State::Pending(_) | State::Opening => f.write_str("Lazy(<unopened>)"),
+            State::Open(ref inner) => f.debug_tuple("Lazy").field(inner).finish(),
+            State::Failed(kind, ref msg) => write!(f, "Lazy(<failed: {:?}: {}>)", kind, msg),
+        }
+    }
+}
+
+impl<T: ReadAt, F: FnOnce() -> Result<T>> ReadAt for Lazy<T, F> {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        self.ensure_open()?.read_at(pos, buf)
+    }
+
+    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
+        self.ensure_open()?.read_exact_at(pos, buf)
+    }
+
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        self.ensure_open()?.read_at_with(pos, buf, options)
+    }
+
+    fn read_vectored_at(&mut self, pos: u64, bufs: &mut [IoSliceMut]) -> Result<usize> {
+        self.ensure_open()?.read_vectored_at(pos, bufs)
+    }
+
+    fn read_buf_at(&mut self, pos: u64, buf: &mut ReadBuf) -> Result<()> {
+        self.ensure_open()?.read_buf_at(pos, buf)
+    }
+}
+
+impl<T: WriteAt, F: FnOnce() -> Result<T>> WriteAt for Lazy<T, F> {
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        self.ensure_open()?.write_at(pos, buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match self.get_mut() {
+            Some(inner) => inner.flush(),
+            None => Ok(()),
+        }
+    }
+
+    fn write_all_at(&mut self, pos: u64, buf: &[u8]) -> Result<()> {
+        self.ensure_open()?.write_all_at(pos, buf)
+    }
+
+    fn write_at_with(&mut self, pos: u64, buf: &[u8], options: &IoOptions) -> Result<usize> {
+        self.ensure_open()?.write_at_with(pos, buf, options)
+    }
+
+    fn write_vectored_at(&mut self, pos: u64, bufs: &[IoSlice]) -> Result<usize> {
+        self.ensure_open()?.write_vectored_at(pos, bufs)
+    }
+
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        self.ensure_open()?.write_zeroes_at(pos, len)
+    }
+}
+
+impl<T: KnownEof, F: FnOnce() -> Result<T>> KnownEof for Lazy<T, F> {
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        self.ensure_open()?.remaining_at(pos)
+    }
+
+    fn is_eof_at(&mut self, pos: u64) -> Result<bool> {
+        self.ensure_open()?.is_eof_at(pos)
+    }
+}
+
+impl<T: Prefetch, F: FnOnce() -> Result<T>> Prefetch for Lazy<T, F> {
+    fn kernel_prefetch(&mut self, pos: u64, len: u64) -> Result<()> {
+        self.ensure_open()?.kernel_prefetch(pos, len)
+    }
+}
+
+impl<T: LockAt, F: FnOnce() -> Result<T>> LockAt for Lazy<T, F> {
+    fn lock_range(&mut self, pos: u64, len: u64, kind: LockKind) -> Result<()> {
+        self.ensure_open()?.lock_range(pos, len, kind)
+    }
+
+    fn try_lock_range(&mut self, pos: u64, len: u64, kind: LockKind) -> Result<bool> {
+        self.ensure_open()?.try_lock_range(pos, len, kind)
+    }
+
+    fn unlock_range(&mut self, pos: u64, len: u64) -> Result<()> {
+        self.ensure_open()?.unlock_range(pos, len)
+    }
+}
+
+impl<T: Advise, F: FnOnce() -> Result<T>> Advise for Lazy<T, F> {
+    fn advise(&mut self, pos: u64, len: u64, advice: Advice) -> Result<()> {
+        self.ensure_open()?.advise(pos, len, advice)
+    }
+}
+
+/// Syncing a backend that was never opened does nothing.
+impl<T: SyncAt, F> SyncAt for Lazy<T, F> {
+    fn sync_all(&mut self) -> Result<()> {
+        match self.get_mut() {
+            Some(inner) => inner.sync_all(),
+            None => Ok(()),
+        }
+    }
+
+    fn sync_data(&mut self) -> Result<()> {
+        match self.get_mut() {
+            Some(inner) => inner.sync_data(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<T: PunchHole, F: FnOnce() -> Result<T>> PunchHole for Lazy<T, F> {
+    fn punch_hole(&mut self, pos: u64, len: u64) -> Result<()> {
+        self.ensure_open()?.punch_hole(pos, len)
+    }
+}
+
+impl<T: Extents, F: FnOnce() -> Result<T>> Extents for Lazy<T, F> {
+    fn extent_at(&mut self, pos: u64) -> Result<Option<Extent>> {
+        self.ensure_open()?.extent_at(pos)
+    }
+}
+
+impl<T: Allocate, F: FnOnce() -> Result<T>> Allocate for Lazy<T, F> {
+    fn allocate(&mut self, pos: u64, len: u64) -> Result<()> {
+        self.ensure_open()?.allocate(pos, len)
+    }
+}
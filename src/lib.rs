@@ -1,21 +1,182 @@
 //! The `ioat` crate provides traits for atomic, random-access I/O access.
 
+#[cfg(feature = "bytes")]
+extern crate bytes;
+#[cfg(unix)]
+extern crate libc;
+#[cfg(windows)]
+extern crate windows_sys;
+
 use std::fs::File;
+use std::borrow::Cow;
 use std::cmp;
-use std::io::{Empty, Error, ErrorKind, Read, Repeat, Result, Seek, SeekFrom, Sink, Write};
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::mem::MaybeUninit;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockWriteGuard};
+use std::io::{self, Cursor, Empty, ErrorKind, IoSlice, IoSliceMut, Read, Repeat, Result, Seek, SeekFrom, Sink, Write};
+
+#[cfg(feature = "bytes")]
+use bytes::{Bytes, BytesMut};
+
+mod advise;
+mod allocate;
+mod assemble;
+#[cfg(feature = "async")]
+mod async_at;
+mod audit_log;
+mod bit_rot;
+mod block_aligned;
+mod buf_reader_at;
+mod buf_writer_at;
+#[cfg(feature = "bytes")]
+mod bytes_at;
+mod coalesce;
+mod commit;
+mod compressed_mem;
+mod concat;
+mod copy;
+mod cow_mem;
+mod cursor_at;
+mod dry_run;
+mod error;
+mod ext;
+mod extents;
+mod fenced;
+mod file_cache;
+mod gzip;
+mod inflate;
+mod io_options;
+mod io_priority;
+mod known_eof;
+mod lazy;
+mod lock_at;
+mod lz4;
+mod memory_budget;
+mod mirrored;
+#[cfg(all(feature = "mmio", unix))]
+mod mmio;
+mod null;
+mod offset;
+mod open_options;
+mod prefetch;
+mod punch_hole;
+mod probe;
+#[cfg(all(feature = "process", any(target_os = "linux", windows)))]
+mod process;
+mod range_set;
+mod read_buf;
+mod record_view;
+mod registry;
+mod rescue;
+mod resize_at;
+mod salvage;
+mod seqlock;
+mod sha256;
+#[cfg(any(unix, windows))]
+mod shared_file;
+mod size_at;
+mod slice;
+mod slice_at;
+mod spill;
+mod split;
+mod spooled;
+mod striped;
+mod sync_at;
+mod sys;
+mod take_at;
+mod tiered;
+mod undo_log;
+mod writer_at;
+
+pub use advise::{Advice, Advise};
+pub use allocate::Allocate;
+pub use assemble::{EntryError, Manifest, ManifestEntry, assemble, assemble_parallel};
+#[cfg(feature = "async")]
+pub use async_at::{AssumeReady, AsyncReadAt, AsyncReadAtExt, AsyncWriteAt, AsyncWriteAtExt, FlushFuture, ReadAtFuture,
+                   ReadExactAtFuture, WriteAllAtFuture, WriteAtFuture};
+pub use audit_log::{AuditLog, verify_audit_log};
+pub use bit_rot::BitRot;
+pub use block_aligned::BlockAligned;
+pub use buf_reader_at::BufReaderAt;
+pub use buf_writer_at::BufWriterAt;
+pub use coalesce::Coalesce;
+pub use commit::commit_via_tempfile;
+pub use compressed_mem::CompressedMem;
+pub use concat::Concat;
+pub use copy::copy_at;
+pub use cow_mem::{CowMem, MemSnapshot};
+pub use cursor_at::CursorAt;
+pub use dry_run::{DryRun, IntendedWrite};
+pub use error::{DefaultErrorPolicy, Error, ErrorClass, ErrorExt, ErrorPolicy};
+pub use ext::{BE, BigEndian, ByteOrder, LE, LengthPrefix, LittleEndian, NativeEndian, ReadAtExt, WriteAtExt};
+pub use extents::{Extent, ExtentIter, Extents};
+pub use fenced::{FENCE_HEADER_LEN, Fenced};
+pub use file_cache::{CachedFile, FileCache};
+pub use gzip::Gzip;
+pub use io_options::{IoOptions, Priority};
+pub use io_priority::{IoPriority, Prioritized};
+pub use known_eof::KnownEof;
+pub use lazy::Lazy;
+pub use lock_at::{LockAt, LockKind};
+pub use memory_budget::{MemoryBudget, MemoryReservation};
+pub use mirrored::{FirstHealthy, Mirrored, ReadPolicy, RoundRobin};
+#[cfg(all(feature = "mmio", unix))]
+pub use mmio::{AccessWidth, MmioWindow};
+pub use null::Null;
+pub use offset::{Length, Offset};
+pub use open_options::{CacheControl, OpenOptions};
+pub use prefetch::Prefetch;
+pub use punch_hole::PunchHole;
+pub use probe::{FormatKind, open_image, probe};
+#[cfg(all(feature = "process", any(target_os = "linux", windows)))]
+pub use process::ProcessMemory;
+pub use range_set::RangeSet;
+pub use read_buf::ReadBuf;
+pub use record_view::{Endian, RecordSchema, RecordView};
+pub use registry::{BoxReadAt, BoxWriteAt, Registry, open, open_write, register_reader, register_writer};
+pub use rescue::{BlockStatus, MapBlock, Rescue, RescueMap, RescuePhase};
+pub use resize_at::ResizeAt;
+pub use salvage::Salvage;
+pub use seqlock::{SeqLockMem, SeqLockReader};
+#[cfg(any(unix, windows))]
+pub use shared_file::SharedFile;
+pub use size_at::SizeAt;
+pub use slice::{Slice, SliceMut};
+pub use slice_at::SliceAt;
+pub use spill::SpillBuf;
+pub use split::Split;
+pub use spooled::Spooled;
+pub use striped::Striped;
+pub use sync_at::SyncAt;
+pub use take_at::TakeAt;
+pub use tiered::{Rebalancer, Tiered};
+pub use undo_log::UndoLog;
+pub use writer_at::WriterAt;
 
 /// The `ReadAt` trait allows for atomically reading bytes from a source at specific offsets.
 ///
-/// As an example, this trait is implemented by `File`. Unlike `Read`,
-/// however, it is not implemented for `&File`, since the implementation
-/// is not thread-safe. If two threads where to call `read_at` at once,
-/// a race condition could occur, where one thread seeks and the other
-/// thread reads.
+/// As an example, this trait is implemented by `File`. On Unix and
+/// Windows it is also implemented for `&File` with the operating
+/// system's positional reads, which need no seek, so one file can be read
+/// from several threads at once without a lock.
 ///
-/// For this reason, `ReadAt` is not automatically implemented for all
-/// `Read + Seek` types. If a `Read + Seek` type is guaranteed to not
-/// seek in parallel with a call to `read_at`, it can be wrapped in
+/// `ReadAt` is not automatically implemented for all `Read + Seek`
+/// types, however, since a thread could seek while another one reads.
+/// If a `Read + Seek` type is guaranteed to not seek in parallel with a
+/// call to `read_at`, it can be wrapped in
 /// [`AssertThreadSafe`](struct.AssertThreadSafe.html).
+///
+/// # Split requests
+///
+/// Some adapters, such as [`Split`](struct.Split.html),
+/// [`Concat`](struct.Concat.html) and [`Striped`](struct.Striped.html),
+/// serve a request with several requests to their inner values, issued
+/// in order. If one of these comes back short, or fails after earlier
+/// ones succeeded, the bytes transferred so far are returned like a short
+/// read, and the error, if any, is left to the next call to report.
+/// Writes through these adapters behave the same way.
 pub trait ReadAt {
     /// Reads some bytes from `pos` bytes into the source.
     ///
@@ -57,19 +218,112 @@ pub trait ReadAt {
             }
         }
         if !buf.is_empty() {
-            Err(Error::new(ErrorKind::UnexpectedEof, "failed to fill whole buffer"))
+            Err(io::Error::new(ErrorKind::UnexpectedEof, "failed to fill whole buffer"))
         } else {
             Ok(())
         }
     }
+
+    /// Reads some bytes from `pos` bytes into the source, passing along
+    /// [`IoOptions`](struct.IoOptions.html) for this read.
+    ///
+    /// By default the options are ignored and this calls `read_at`.
+    /// Adapters should forward the options to the sources they wrap.
+    ///
+    /// # Errors
+    ///
+    /// This method can return any I/O error.
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], _options: &IoOptions) -> Result<usize> {
+        self.read_at(pos, buf)
+    }
+
+    /// Like `read_at`, except that it reads into a slice of buffers.
+    ///
+    /// The buffers are filled in order, and the last buffer written to
+    /// may be only partially filled. By default this calls `read_at` with
+    /// the first non-empty buffer.
+    ///
+    /// # Errors
+    ///
+    /// This method can return any I/O error.
+    fn read_vectored_at(&mut self, pos: u64, bufs: &mut [IoSliceMut]) -> Result<usize> {
+        match bufs.iter_mut().find(|b| !b.is_empty()) {
+            Some(buf) => self.read_at(pos, buf),
+            None => Ok(0),
+        }
+    }
+
+    /// Reads some bytes from `pos` bytes into the source, appending them
+    /// to the filled part of `buf`.
+    ///
+    /// This allows reading into memory that has not been initialized. By
+    /// default the uninitialized part of `buf` is zeroed, and `read_at` is
+    /// called with its unfilled part. Sources that can write directly into
+    /// uninitialized memory override this.
+    ///
+    /// # Errors
+    ///
+    /// This method can return any I/O error.
+    fn read_buf_at(&mut self, pos: u64, buf: &mut ReadBuf) -> Result<()> {
+        let n = self.read_at(pos, buf.initialize_unfilled())?;
+        buf.add_filled(n);
+        Ok(())
+    }
+
+    /// Reads all bytes from `pos` bytes into the source until the end,
+    /// appending them to `buf`.
+    ///
+    /// This returns the number of bytes read. The vector is grown as
+    /// needed, doubling its capacity whenever it is full, and only newly
+    /// reserved space is zeroed. Sources that know how many bytes remain,
+    /// such as files and in-memory buffers, reserve that much up front.
+    /// This method is an analogue to `read_to_end` for `Read`.
+    ///
+    /// # Errors
+    ///
+    /// If `read_at()` returns an error other than one of kind
+    /// `Interrupted`, then this method immediately propagates it. An error
+    /// of kind `InvalidInput` is returned if the source reaches past
+    /// offset `u64::MAX`. Any bytes read until then are kept in `buf`.
+    fn read_to_end_at(&mut self, pos: u64, buf: &mut Vec<u8>) -> Result<usize> {
+        read_to_end(self, pos, buf, None)
+    }
+
+    /// Reads exactly `len` bytes from `pos` bytes into the source into a
+    /// reference-counted buffer.
+    ///
+    /// By default the bytes are read into a new buffer. Sources that are
+    /// themselves a `Bytes` return a slice of it instead, without copying.
+    ///
+    /// # Errors
+    ///
+    /// This method returns the same errors as
+    /// [`read_exact_at`](#method.read_exact_at).
+    #[cfg(feature = "bytes")]
+    fn read_bytes_at(&mut self, pos: u64, len: usize) -> Result<Bytes> {
+        let mut buf = BytesMut::zeroed(len);
+        self.read_exact_at(pos, &mut buf)?;
+        Ok(buf.freeze())
+    }
+
+    /// Creates an adapter which only exposes `len` bytes at `offset` of
+    /// this source, as a source of its own starting at zero.
+    ///
+    /// This method is an analogue to `take` for `Read`. A `&mut`
+    /// reference can be passed to keep using the source afterwards.
+    fn take_at(self, offset: u64, len: u64) -> TakeAt<Self>
+        where Self: Sized
+    {
+        TakeAt::new(self, offset, len)
+    }
 }
 
 /// The `WriteAt` trait allows for atomically writing bytes to a sink at specific offsets.
 ///
-/// As an example, this trait is implemented by `File`. Similarly to
-/// [`ReadAt`](trait.ReadAt.html) it is not implemented for `&File`,
-/// or other `Write + Seek` types in general, as this would not be
-/// thread-safe.
+/// As an example, this trait is implemented by `File`, and like
+/// [`ReadAt`](trait.ReadAt.html) for `&File` on Unix and Windows. It is
+/// not implemented for other `Write + Seek` types in general, as this
+/// would not be thread-safe.
 ///
 /// If it can be guaranteed, that a `Write + Seek` value will not seek
 /// in parallel to a call to `write_at`, it can be wrapped in
@@ -111,7 +365,7 @@ pub trait WriteAt {
         while !buf.is_empty() {
             match self.write_at(pos, buf) {
                 Ok(0) => {
-                    return Err(Error::new(ErrorKind::WriteZero, "failed to write whole buffer"));
+                    return Err(io::Error::new(ErrorKind::WriteZero, "failed to write whole buffer"));
                 }
                 Ok(n) => {
                     buf = &buf[n..];
@@ -123,9 +377,66 @@ pub trait WriteAt {
         }
         Ok(())
     }
+
+    /// Writes some bytes at `pos` bytes into `self`, passing along
+    /// [`IoOptions`](struct.IoOptions.html) for this write.
+    ///
+    /// By default the options are ignored and this calls `write_at`.
+    /// Adapters should forward the options to the sinks they wrap.
+    ///
+    /// # Errors
+    ///
+    /// This method can return any I/O error.
+    fn write_at_with(&mut self, pos: u64, buf: &[u8], _options: &IoOptions) -> Result<usize> {
+        self.write_at(pos, buf)
+    }
+
+    /// Like `write_at`, except that it writes the contents of a slice of
+    /// buffers, in order.
+    ///
+    /// By default this calls `write_at` with the first non-empty buffer.
+    ///
+    /// # Errors
+    ///
+    /// This method can return any I/O error.
+    fn write_vectored_at(&mut self, pos: u64, bufs: &[IoSlice]) -> Result<usize> {
+        match bufs.iter().find(|b| !b.is_empty()) {
+            Some(buf) => self.write_at(pos, buf),
+            None => Ok(0),
+        }
+    }
+
+    /// Writes `len` zero bytes at `pos` bytes into `self`.
+    ///
+    /// By default this calls `write_all_at` with a zeroed buffer of at most
+    /// 64 KiB. Sinks that can zero a range without transferring the data,
+    /// such as files on Linux, override it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::OutOfBounds`](enum.Error.html) if the range
+    /// overflows a `u64`, and any error `write_all_at` would return.
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        write_zeroes(self, pos, len)
+    }
+}
+
+/// Writes `len` zero bytes at `pos` to `dst` through `write_all_at`.
+fn write_zeroes<W: WriteAt + ?Sized>(dst: &mut W, pos: u64, len: u64) -> Result<()> {
+    if pos.checked_add(len).is_none() {
+        return Err(Error::OutOfBounds { offset: pos, len }.into());
+    }
+    let zeroes = vec![0; cmp::min(len, 64 * 1024) as usize];
+    let mut done = 0;
+    while done < len {
+        let n = cmp::min(len - done, zeroes.len() as u64) as usize;
+        dst.write_all_at(pos + done, &zeroes[..n])?;
+        done += n as u64;
+    }
+    Ok(())
 }
 
-impl<'a, R: ReadAt> ReadAt for &'a mut R {
+impl<R: ReadAt + ?Sized> ReadAt for &mut R {
     #[inline]
     fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
         (**self).read_at(pos, buf)
@@ -135,9 +446,35 @@ impl<'a, R: ReadAt> ReadAt for &'a mut R {
     fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
         (**self).read_exact_at(pos, buf)
     }
+
+    #[inline]
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        (**self).read_at_with(pos, buf, options)
+    }
+
+    #[inline]
+    fn read_vectored_at(&mut self, pos: u64, bufs: &mut [IoSliceMut]) -> Result<usize> {
+        (**self).read_vectored_at(pos, bufs)
+    }
+
+    #[inline]
+    fn read_buf_at(&mut self, pos: u64, buf: &mut ReadBuf) -> Result<()> {
+        (**self).read_buf_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_to_end_at(&mut self, pos: u64, buf: &mut Vec<u8>) -> Result<usize> {
+        (**self).read_to_end_at(pos, buf)
+    }
+
+    #[cfg(feature = "bytes")]
+    #[inline]
+    fn read_bytes_at(&mut self, pos: u64, len: usize) -> Result<Bytes> {
+        (**self).read_bytes_at(pos, len)
+    }
 }
 
-impl<'a, W: WriteAt> WriteAt for &'a mut W {
+impl<W: WriteAt + ?Sized> WriteAt for &mut W {
     #[inline]
     fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
         (**self).write_at(pos, buf)
@@ -148,231 +485,1185 @@ impl<'a, W: WriteAt> WriteAt for &'a mut W {
         (**self).write_all_at(pos, buf)
     }
 
+    #[inline]
+    fn write_at_with(&mut self, pos: u64, buf: &[u8], options: &IoOptions) -> Result<usize> {
+        (**self).write_at_with(pos, buf, options)
+    }
+
+    #[inline]
+    fn write_vectored_at(&mut self, pos: u64, bufs: &[IoSlice]) -> Result<usize> {
+        (**self).write_vectored_at(pos, bufs)
+    }
+
+    #[inline]
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        (**self).write_zeroes_at(pos, len)
+    }
+
     #[inline]
     fn flush(&mut self) -> Result<()> {
         (**self).flush()
     }
 }
 
-// For now this only supports `[u8]`, `Vec<u8>` and `Box<[u8]>`, which
-// is the same as `Cursor` supports for `Write`. A wrapper struct with
-// a blanket implementation would be possible for any `AsRef<[u8]>`, but
-// that can also be handled downstream. A blanket directly on
-// `AsRef<[u8]>` is not possible, since that would conflict with the
-// concrete implementations.
-
-impl<'a> ReadAt for &'a [u8] {
+impl<R: ReadAt + ?Sized> ReadAt for Box<R> {
+    #[inline]
     fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
-        if pos >= self.len() as u64 {
-            return Ok(0);
-        }
-        let i = pos as usize;
-        buf.copy_from_slice(&self[i..]);
-        Ok(cmp::min(self.len() - i, buf.len()))
+        (**self).read_at(pos, buf)
     }
 
+    #[inline]
     fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
-        if try!(self.read_at(pos, buf)) < buf.len() {
-            Err(Error::new(ErrorKind::UnexpectedEof, "failed to write whole buffer"))
-        } else {
-            Ok(())
-        }
+        (**self).read_exact_at(pos, buf)
     }
-}
 
-impl ReadAt for Vec<u8> {
     #[inline]
-    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
-        (&self[..]).read_at(pos, buf)
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        (**self).read_at_with(pos, buf, options)
     }
 
     #[inline]
-    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
-        (&self[..]).read_exact_at(pos, buf)
+    fn read_vectored_at(&mut self, pos: u64, bufs: &mut [IoSliceMut]) -> Result<usize> {
+        (**self).read_vectored_at(pos, bufs)
+    }
+
+    #[inline]
+    fn read_buf_at(&mut self, pos: u64, buf: &mut ReadBuf) -> Result<()> {
+        (**self).read_buf_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_to_end_at(&mut self, pos: u64, buf: &mut Vec<u8>) -> Result<usize> {
+        (**self).read_to_end_at(pos, buf)
+    }
+
+    #[cfg(feature = "bytes")]
+    #[inline]
+    fn read_bytes_at(&mut self, pos: u64, len: usize) -> Result<Bytes> {
+        (**self).read_bytes_at(pos, len)
     }
 }
 
-impl ReadAt for Box<[u8]> {
+impl<W: WriteAt + ?Sized> WriteAt for Box<W> {
     #[inline]
-    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
-        (&self[..]).read_at(pos, buf)
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        (**self).write_at(pos, buf)
     }
 
     #[inline]
-    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
-        (&self[..]).read_exact_at(pos, buf)
+    fn write_all_at(&mut self, pos: u64, buf: &[u8]) -> Result<()> {
+        (**self).write_all_at(pos, buf)
+    }
+
+    #[inline]
+    fn write_at_with(&mut self, pos: u64, buf: &[u8], options: &IoOptions) -> Result<usize> {
+        (**self).write_at_with(pos, buf, options)
+    }
+
+    #[inline]
+    fn write_vectored_at(&mut self, pos: u64, bufs: &[IoSlice]) -> Result<usize> {
+        (**self).write_vectored_at(pos, bufs)
+    }
+
+    #[inline]
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        (**self).write_zeroes_at(pos, len)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        (**self).flush()
     }
 }
 
-impl ReadAt for File {
+impl<R: ReadAt + ?Sized> ReadAt for Mutex<R> {
     #[inline]
     fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
-        AssertThreadSafe(self).read_at(pos, buf)
+        self.get_mut().unwrap_or_else(|e| e.into_inner()).read_at(pos, buf)
     }
 
     #[inline]
     fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
-        AssertThreadSafe(self).read_exact_at(pos, buf)
+        self.get_mut().unwrap_or_else(|e| e.into_inner()).read_exact_at(pos, buf)
     }
-}
 
-impl ReadAt for Empty {
     #[inline]
-    fn read_at(&mut self, _pos: u64, _buf: &mut [u8]) -> Result<usize> {
-        Ok(0)
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        self.get_mut().unwrap_or_else(|e| e.into_inner()).read_at_with(pos, buf, options)
     }
-}
 
-impl ReadAt for Repeat {
     #[inline]
-    fn read_at(&mut self, _pos: u64, buf: &mut [u8]) -> Result<usize> {
-        self.read(buf)
+    fn read_vectored_at(&mut self, pos: u64, bufs: &mut [IoSliceMut]) -> Result<usize> {
+        self.get_mut().unwrap_or_else(|e| e.into_inner()).read_vectored_at(pos, bufs)
+    }
+
+    #[inline]
+    fn read_buf_at(&mut self, pos: u64, buf: &mut ReadBuf) -> Result<()> {
+        self.get_mut().unwrap_or_else(|e| e.into_inner()).read_buf_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_to_end_at(&mut self, pos: u64, buf: &mut Vec<u8>) -> Result<usize> {
+        self.get_mut().unwrap_or_else(|e| e.into_inner()).read_to_end_at(pos, buf)
     }
 }
 
-impl WriteAt for [u8] {
+impl<W: WriteAt + ?Sized> WriteAt for Mutex<W> {
+    #[inline]
     fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
-        if pos >= self.len() as u64 {
-            return Ok(0);
-        }
-        let i = pos as usize;
-        self[i..].copy_from_slice(buf);
-        Ok(cmp::min(self.len() - i, buf.len()))
+        self.get_mut().unwrap_or_else(|e| e.into_inner()).write_at(pos, buf)
     }
 
     #[inline]
-    fn flush(&mut self) -> Result<()> {
-        Ok(())
+    fn write_all_at(&mut self, pos: u64, buf: &[u8]) -> Result<()> {
+        self.get_mut().unwrap_or_else(|e| e.into_inner()).write_all_at(pos, buf)
     }
 
-    fn write_all_at(&mut self, pos: u64, buf: &[u8]) -> Result<()> {
-        if try!(self.write_at(pos, buf)) < buf.len() {
-            Err(Error::new(ErrorKind::UnexpectedEof, "failed to write whole buffer"))
-        } else {
-            Ok(())
-        }
+    #[inline]
+    fn write_at_with(&mut self, pos: u64, buf: &[u8], options: &IoOptions) -> Result<usize> {
+        self.get_mut().unwrap_or_else(|e| e.into_inner()).write_at_with(pos, buf, options)
     }
-}
 
-impl WriteAt for Vec<u8> {
-    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
-        if pos >= usize::max_value() as u64 {
-            return Ok(0);
-        }
-        let i = pos as usize;
-        if i >= self.len() {
-            let needed = self.len() - i;
-            self.reserve(needed);
-        }
-        self[i..].copy_from_slice(buf);
-        Ok(cmp::min(self.len() - i, buf.len()))
+    #[inline]
+    fn write_vectored_at(&mut self, pos: u64, bufs: &[IoSlice]) -> Result<usize> {
+        self.get_mut().unwrap_or_else(|e| e.into_inner()).write_vectored_at(pos, bufs)
     }
 
     #[inline]
-    fn flush(&mut self) -> Result<()> {
-        Ok(())
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        self.get_mut().unwrap_or_else(|e| e.into_inner()).write_zeroes_at(pos, len)
     }
 
-    fn write_all_at(&mut self, pos: u64, buf: &[u8]) -> Result<()> {
-        if try!(self.write_at(pos, buf)) < buf.len() {
-            Err(Error::new(ErrorKind::UnexpectedEof, "failed to write whole buffer"))
-        } else {
-            Ok(())
-        }
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        self.get_mut().unwrap_or_else(|e| e.into_inner()).flush()
     }
 }
 
-impl WriteAt for Box<[u8]> {
+/// Locks the mutex for the duration of each call, so the source can be
+/// shared between threads. A poisoned mutex is still used.
+impl<R: ReadAt + ?Sized> ReadAt for &Mutex<R> {
     #[inline]
-    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
-        (&mut self[..]).write_at(pos, buf)
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        lock(self).read_at(pos, buf)
     }
 
     #[inline]
-    fn write_all_at(&mut self, pos: u64, buf: &[u8]) -> Result<()> {
-        (&mut self[..]).write_all_at(pos, buf)
+    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
+        lock(self).read_exact_at(pos, buf)
     }
 
     #[inline]
-    fn flush(&mut self) -> Result<()> {
-        Ok(())
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        lock(self).read_at_with(pos, buf, options)
+    }
+
+    #[inline]
+    fn read_vectored_at(&mut self, pos: u64, bufs: &mut [IoSliceMut]) -> Result<usize> {
+        lock(self).read_vectored_at(pos, bufs)
+    }
+
+    #[inline]
+    fn read_buf_at(&mut self, pos: u64, buf: &mut ReadBuf) -> Result<()> {
+        lock(self).read_buf_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_to_end_at(&mut self, pos: u64, buf: &mut Vec<u8>) -> Result<usize> {
+        lock(self).read_to_end_at(pos, buf)
     }
 }
 
-impl WriteAt for File {
+/// Locks the mutex for the duration of each call, so the sink can be
+/// shared between threads. A poisoned mutex is still used.
+impl<W: WriteAt + ?Sized> WriteAt for &Mutex<W> {
     #[inline]
     fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
-        AssertThreadSafe(self).write_at(pos, buf)
+        lock(self).write_at(pos, buf)
     }
 
     #[inline]
-    fn flush(&mut self) -> Result<()> {
-        AssertThreadSafe(self).flush()
+    fn write_all_at(&mut self, pos: u64, buf: &[u8]) -> Result<()> {
+        lock(self).write_all_at(pos, buf)
     }
 
     #[inline]
-    fn write_all_at(&mut self, pos: u64, buf: &[u8]) -> Result<()> {
-        AssertThreadSafe(self).write_all_at(pos, buf)
+    fn write_at_with(&mut self, pos: u64, buf: &[u8], options: &IoOptions) -> Result<usize> {
+        lock(self).write_at_with(pos, buf, options)
     }
-}
 
-impl WriteAt for Sink {
     #[inline]
-    fn write_at(&mut self, _pos: u64, buf: &[u8]) -> Result<usize> {
-        Ok(buf.len())
+    fn write_vectored_at(&mut self, pos: u64, bufs: &[IoSlice]) -> Result<usize> {
+        lock(self).write_vectored_at(pos, bufs)
+    }
+
+    #[inline]
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        lock(self).write_zeroes_at(pos, len)
     }
 
     #[inline]
     fn flush(&mut self) -> Result<()> {
-        Ok(())
+        lock(self).flush()
     }
 }
 
-/// A struct for wrapping thread-safe readers or writers.
-///
-/// Using this struct asserts, that the contained `Read + Seek` or
-/// `Write + Seek` value can be used for [`ReadAt`](trait.ReadAt.html)
-/// and [`WriteAt`](trait.WriteAt.html), without risking a seek from
-/// another thread in parallel.
-///
-/// The traits are implemented by first seeking to the offset, and then
-/// calling the appropriate method on the wrapped value.
-#[derive(Clone, Debug)]
-pub struct AssertThreadSafe<T>(pub T);
-
-impl<T> ReadAt for AssertThreadSafe<T>
-    where T: Read + Seek
-{
+impl<R: ReadAt + ?Sized> ReadAt for RwLock<R> {
     #[inline]
     fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
-        try!(self.0.seek(SeekFrom::Start(pos)));
-        self.0.read(buf)
+        self.get_mut().unwrap_or_else(|e| e.into_inner()).read_at(pos, buf)
     }
 
     #[inline]
     fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
-        try!(self.0.seek(SeekFrom::Start(pos)));
-        self.0.read_exact(buf)
+        self.get_mut().unwrap_or_else(|e| e.into_inner()).read_exact_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        self.get_mut().unwrap_or_else(|e| e.into_inner()).read_at_with(pos, buf, options)
+    }
+
+    #[inline]
+    fn read_vectored_at(&mut self, pos: u64, bufs: &mut [IoSliceMut]) -> Result<usize> {
+        self.get_mut().unwrap_or_else(|e| e.into_inner()).read_vectored_at(pos, bufs)
+    }
+
+    #[inline]
+    fn read_buf_at(&mut self, pos: u64, buf: &mut ReadBuf) -> Result<()> {
+        self.get_mut().unwrap_or_else(|e| e.into_inner()).read_buf_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_to_end_at(&mut self, pos: u64, buf: &mut Vec<u8>) -> Result<usize> {
+        self.get_mut().unwrap_or_else(|e| e.into_inner()).read_to_end_at(pos, buf)
     }
 }
 
-impl<T> WriteAt for AssertThreadSafe<T>
-    where T: Write + Seek
-{
+impl<W: WriteAt + ?Sized> WriteAt for RwLock<W> {
     #[inline]
     fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
-        try!(self.0.seek(SeekFrom::Start(pos)));
-        self.0.write(buf)
+        self.get_mut().unwrap_or_else(|e| e.into_inner()).write_at(pos, buf)
     }
 
     #[inline]
-    fn flush(&mut self) -> Result<()> {
-        self.0.flush()
+    fn write_all_at(&mut self, pos: u64, buf: &[u8]) -> Result<()> {
+        self.get_mut().unwrap_or_else(|e| e.into_inner()).write_all_at(pos, buf)
+    }
+
+    #[inline]
+    fn write_at_with(&mut self, pos: u64, buf: &[u8], options: &IoOptions) -> Result<usize> {
+        self.get_mut().unwrap_or_else(|e| e.into_inner()).write_at_with(pos, buf, options)
+    }
+
+    #[inline]
+    fn write_vectored_at(&mut self, pos: u64, bufs: &[IoSlice]) -> Result<usize> {
+        self.get_mut().unwrap_or_else(|e| e.into_inner()).write_vectored_at(pos, bufs)
+    }
+
+    #[inline]
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        self.get_mut().unwrap_or_else(|e| e.into_inner()).write_zeroes_at(pos, len)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        self.get_mut().unwrap_or_else(|e| e.into_inner()).flush()
+    }
+}
+
+/// Takes the write lock for the duration of each call, since reads need
+/// mutable access to the source. A poisoned lock is still used.
+impl<R: ReadAt + ?Sized> ReadAt for &RwLock<R> {
+    #[inline]
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        write_lock(self).read_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
+        write_lock(self).read_exact_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        write_lock(self).read_at_with(pos, buf, options)
+    }
+
+    #[inline]
+    fn read_vectored_at(&mut self, pos: u64, bufs: &mut [IoSliceMut]) -> Result<usize> {
+        write_lock(self).read_vectored_at(pos, bufs)
+    }
+
+    #[inline]
+    fn read_buf_at(&mut self, pos: u64, buf: &mut ReadBuf) -> Result<()> {
+        write_lock(self).read_buf_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_to_end_at(&mut self, pos: u64, buf: &mut Vec<u8>) -> Result<usize> {
+        write_lock(self).read_to_end_at(pos, buf)
+    }
+}
+
+/// Takes the write lock for the duration of each call, so the sink can
+/// be shared between threads. A poisoned lock is still used.
+impl<W: WriteAt + ?Sized> WriteAt for &RwLock<W> {
+    #[inline]
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        write_lock(self).write_at(pos, buf)
+    }
+
+    #[inline]
+    fn write_all_at(&mut self, pos: u64, buf: &[u8]) -> Result<()> {
+        write_lock(self).write_all_at(pos, buf)
+    }
+
+    #[inline]
+    fn write_at_with(&mut self, pos: u64, buf: &[u8], options: &IoOptions) -> Result<usize> {
+        write_lock(self).write_at_with(pos, buf, options)
+    }
+
+    #[inline]
+    fn write_vectored_at(&mut self, pos: u64, bufs: &[IoSlice]) -> Result<usize> {
+        write_lock(self).write_vectored_at(pos, bufs)
+    }
+
+    #[inline]
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        write_lock(self).write_zeroes_at(pos, len)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        write_lock(self).flush()
+    }
+}
+
+/// Implements `read_to_end_at`, reserving `remaining` bytes up front if
+/// the source knows how many are left.
+fn read_to_end<R: ReadAt + ?Sized>(src: &mut R, pos: u64, buf: &mut Vec<u8>, remaining: Option<u64>) -> Result<usize> {
+    let start = buf.len();
+    if let Some(n) = remaining.and_then(|n| usize::try_from(n).ok()) {
+        // One byte more, so the read finding the end does not grow it. A
+        // length that cannot be reserved is only a hint, so it is ignored.
+        let _ = buf.try_reserve_exact(n.saturating_add(1));
+    }
+    // The vector is kept at its full capacity while reading, so each byte
+    // of it is zeroed only once, and cut back to the data at the end.
+    let mut filled = start;
+    let result = loop {
+        if filled == buf.len() {
+            if buf.len() == buf.capacity() {
+                buf.reserve(cmp::max(filled - start, 32));
+            }
+            let capacity = buf.capacity();
+            buf.resize(capacity, 0);
+        }
+        let at = match pos.checked_add((filled - start) as u64) {
+            Some(at) => at,
+            None => break Err(io::Error::new(ErrorKind::InvalidInput, "read past the maximum offset")),
+        };
+        match src.read_at(at, &mut buf[filled..]) {
+            Ok(0) => break Ok(filled - start),
+            Ok(n) => filled += n,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => break Err(e),
+        }
+    };
+    buf.truncate(filled);
+    result
+}
+
+/// Reads into `buf` at `pos`, with `options` if there are any.
+fn read_opt<R: ReadAt + ?Sized>(src: &mut R, pos: u64, buf: &mut [u8], options: Option<&IoOptions>) -> Result<usize> {
+    match options {
+        Some(options) => src.read_at_with(pos, buf, options),
+        None => src.read_at(pos, buf),
+    }
+}
+
+/// Like `write_all_at`, passing `options` along to each write.
+fn write_all_with<W: WriteAt + ?Sized>(dst: &mut W, mut pos: u64, mut buf: &[u8], options: &IoOptions) -> Result<()> {
+    while !buf.is_empty() {
+        match dst.write_at_with(pos, buf, options) {
+            Ok(0) => return Err(io::Error::new(ErrorKind::WriteZero, "failed to write whole buffer")),
+            Ok(n) => {
+                buf = &buf[n..];
+                pos += n as u64;
+            }
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+fn lock<T: ?Sized>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn write_lock<T: ?Sized>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(|e| e.into_inner())
+}
+
+// For now this only supports `[u8]`, `Vec<u8>` and `Box<[u8]>`, and
+// cursors over them, which is the same as `Cursor` supports for
+// `Write`, plus `VecDeque<u8>` and the shared `Arc<[u8]>`, `Rc<[u8]>`
+// and `Cow<[u8]>` for reading. Any other `AsRef<[u8]>` can be wrapped in
+// `SliceAt`. A blanket directly on `AsRef<[u8]>` is not possible, since
+// that would conflict with the concrete implementations.
+
+impl ReadAt for &[u8] {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        if pos >= self.len() as u64 {
+            return Ok(0);
+        }
+        let i = pos as usize;
+        let n = cmp::min(self.len() - i, buf.len());
+        buf[..n].copy_from_slice(&self[i..i + n]);
+        Ok(n)
+    }
+
+    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
+        if self.read_at(pos, buf)? < buf.len() {
+            Err(io::Error::new(ErrorKind::UnexpectedEof, "failed to fill whole buffer"))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn read_to_end_at(&mut self, pos: u64, buf: &mut Vec<u8>) -> Result<usize> {
+        if pos >= self.len() as u64 {
+            return Ok(0);
+        }
+        let data = &self[pos as usize..];
+        buf.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn read_buf_at(&mut self, pos: u64, buf: &mut ReadBuf) -> Result<()> {
+        if pos >= self.len() as u64 {
+            return Ok(());
+        }
+        let i = pos as usize;
+        let n = cmp::min(self.len() - i, buf.remaining());
+        unsafe {
+            for (dst, &src) in buf.unfilled_mut().iter_mut().zip(&self[i..i + n]) {
+                *dst = MaybeUninit::new(src);
+            }
+            buf.assume_filled(n);
+        }
+        Ok(())
+    }
+}
+
+impl ReadAt for Vec<u8> {
+    #[inline]
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        (&self[..]).read_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
+        (&self[..]).read_exact_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_to_end_at(&mut self, pos: u64, buf: &mut Vec<u8>) -> Result<usize> {
+        (&self[..]).read_to_end_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_buf_at(&mut self, pos: u64, buf: &mut ReadBuf) -> Result<()> {
+        (&self[..]).read_buf_at(pos, buf)
+    }
+}
+
+impl ReadAt for Arc<[u8]> {
+    #[inline]
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        (&self[..]).read_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
+        (&self[..]).read_exact_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_to_end_at(&mut self, pos: u64, buf: &mut Vec<u8>) -> Result<usize> {
+        (&self[..]).read_to_end_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_buf_at(&mut self, pos: u64, buf: &mut ReadBuf) -> Result<()> {
+        (&self[..]).read_buf_at(pos, buf)
+    }
+}
+
+impl ReadAt for Rc<[u8]> {
+    #[inline]
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        (&self[..]).read_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
+        (&self[..]).read_exact_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_to_end_at(&mut self, pos: u64, buf: &mut Vec<u8>) -> Result<usize> {
+        (&self[..]).read_to_end_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_buf_at(&mut self, pos: u64, buf: &mut ReadBuf) -> Result<()> {
+        (&self[..]).read_buf_at(pos, buf)
+    }
+}
+
+impl ReadAt for Cow<'_, [u8]> {
+    #[inline]
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        (&self[..]).read_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
+        (&self[..]).read_exact_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_to_end_at(&mut self, pos: u64, buf: &mut Vec<u8>) -> Result<usize> {
+        (&self[..]).read_to_end_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_buf_at(&mut self, pos: u64, buf: &mut ReadBuf) -> Result<()> {
+        (&self[..]).read_buf_at(pos, buf)
+    }
+}
+
+/// Reads are served from both halves of the ring buffer, so reads
+/// crossing its wrap-around point are not cut short.
+impl ReadAt for VecDeque<u8> {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        if pos >= self.len() as u64 {
+            return Ok(0);
+        }
+        let i = pos as usize;
+        let n = cmp::min(self.len() - i, buf.len());
+        let (front, back) = self.as_slices();
+        if i < front.len() {
+            let m = cmp::min(front.len() - i, n);
+            buf[..m].copy_from_slice(&front[i..i + m]);
+            buf[m..n].copy_from_slice(&back[..n - m]);
+        } else {
+            let j = i - front.len();
+            buf[..n].copy_from_slice(&back[j..j + n]);
+        }
+        Ok(n)
+    }
+
+    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
+        if self.read_at(pos, buf)? < buf.len() {
+            Err(io::Error::new(ErrorKind::UnexpectedEof, "failed to fill whole buffer"))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn read_to_end_at(&mut self, pos: u64, buf: &mut Vec<u8>) -> Result<usize> {
+        if pos >= self.len() as u64 {
+            return Ok(0);
+        }
+        let n = self.len() - pos as usize;
+        buf.extend(self.range(pos as usize..));
+        Ok(n)
+    }
+}
+
+impl ReadAt for Box<[u8]> {
+    #[inline]
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        (&self[..]).read_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
+        (&self[..]).read_exact_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_to_end_at(&mut self, pos: u64, buf: &mut Vec<u8>) -> Result<usize> {
+        (&self[..]).read_to_end_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_buf_at(&mut self, pos: u64, buf: &mut ReadBuf) -> Result<()> {
+        (&self[..]).read_buf_at(pos, buf)
+    }
+}
+
+/// Reads ignore and keep the position of the cursor.
+impl ReadAt for Cursor<Vec<u8>> {
+    #[inline]
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        (&self.get_ref()[..]).read_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
+        (&self.get_ref()[..]).read_exact_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_to_end_at(&mut self, pos: u64, buf: &mut Vec<u8>) -> Result<usize> {
+        (&self.get_ref()[..]).read_to_end_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_buf_at(&mut self, pos: u64, buf: &mut ReadBuf) -> Result<()> {
+        (&self.get_ref()[..]).read_buf_at(pos, buf)
+    }
+}
+
+/// Reads ignore and keep the position of the cursor.
+impl ReadAt for Cursor<&[u8]> {
+    #[inline]
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        (&self.get_ref()[..]).read_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
+        (&self.get_ref()[..]).read_exact_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_to_end_at(&mut self, pos: u64, buf: &mut Vec<u8>) -> Result<usize> {
+        (&self.get_ref()[..]).read_to_end_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_buf_at(&mut self, pos: u64, buf: &mut ReadBuf) -> Result<()> {
+        (&self.get_ref()[..]).read_buf_at(pos, buf)
+    }
+}
+
+/// Reads ignore and keep the position of the cursor.
+impl ReadAt for Cursor<Box<[u8]>> {
+    #[inline]
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        (&self.get_ref()[..]).read_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
+        (&self.get_ref()[..]).read_exact_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_to_end_at(&mut self, pos: u64, buf: &mut Vec<u8>) -> Result<usize> {
+        (&self.get_ref()[..]).read_to_end_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_buf_at(&mut self, pos: u64, buf: &mut ReadBuf) -> Result<()> {
+        (&self.get_ref()[..]).read_buf_at(pos, buf)
+    }
+}
+
+/// Uses `pread` on Unix, which leaves the file position alone, and
+/// `seek_read` on Windows, which leaves it undefined. Elsewhere the file
+/// is seeked to `pos` first.
+impl ReadAt for File {
+    #[cfg(any(unix, windows))]
+    #[inline]
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        sys::read_at(self, pos, buf)
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    #[inline]
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        AssertThreadSafe(self).read_at(pos, buf)
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    #[inline]
+    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
+        AssertThreadSafe(self).read_exact_at(pos, buf)
+    }
+
+    fn read_to_end_at(&mut self, pos: u64, buf: &mut Vec<u8>) -> Result<usize> {
+        let remaining = self.remaining_at(pos).ok();
+        read_to_end(self, pos, buf, remaining)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        sys::read_at_with(self, pos, buf, options)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn read_vectored_at(&mut self, pos: u64, bufs: &mut [IoSliceMut]) -> Result<usize> {
+        sys::read_vectored_at(self, pos, bufs)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn read_buf_at(&mut self, pos: u64, buf: &mut ReadBuf) -> Result<()> {
+        sys::read_buf_at(self, pos, buf)
+    }
+}
+
+/// Uses `pread` on Unix and `seek_read` on Windows, which leaves the
+/// file position undefined.
+#[cfg(any(unix, windows))]
+impl ReadAt for &File {
+    #[inline]
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        sys::read_at(self, pos, buf)
+    }
+
+    fn read_to_end_at(&mut self, pos: u64, buf: &mut Vec<u8>) -> Result<usize> {
+        let remaining = self.remaining_at(pos).ok();
+        read_to_end(self, pos, buf, remaining)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        sys::read_at_with(self, pos, buf, options)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn read_vectored_at(&mut self, pos: u64, bufs: &mut [IoSliceMut]) -> Result<usize> {
+        sys::read_vectored_at(self, pos, bufs)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn read_buf_at(&mut self, pos: u64, buf: &mut ReadBuf) -> Result<()> {
+        sys::read_buf_at(self, pos, buf)
+    }
+}
+
+impl ReadAt for Empty {
+    #[inline]
+    fn read_at(&mut self, _pos: u64, _buf: &mut [u8]) -> Result<usize> {
+        Ok(0)
+    }
+}
+
+impl ReadAt for Repeat {
+    #[inline]
+    fn read_at(&mut self, _pos: u64, buf: &mut [u8]) -> Result<usize> {
+        self.read(buf)
+    }
+}
+
+impl WriteAt for [u8] {
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        if pos >= self.len() as u64 {
+            return Ok(0);
+        }
+        let i = pos as usize;
+        let n = cmp::min(self.len() - i, buf.len());
+        self[i..i + n].copy_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_all_at(&mut self, pos: u64, buf: &[u8]) -> Result<()> {
+        if self.write_at(pos, buf)? < buf.len() {
+            Err(io::Error::new(ErrorKind::WriteZero, "failed to write whole buffer"))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        match pos.checked_add(len) {
+            Some(end) if end <= self.len() as u64 => {
+                self[pos as usize..end as usize].fill(0);
+                Ok(())
+            }
+            _ => Err(io::Error::new(ErrorKind::WriteZero, "failed to write whole buffer")),
+        }
+    }
+}
+
+impl WriteAt for Vec<u8> {
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        if pos >= usize::MAX as u64 {
+            return Ok(0);
+        }
+        let i = pos as usize;
+        let n = cmp::min(usize::MAX - i, buf.len());
+        if i + n > self.len() {
+            // A huge `pos` must not abort the process on allocation failure.
+            self.try_reserve(i + n - self.len()).map_err(|_| Error::OutOfBounds { offset: pos, len: n as u64 })?;
+            self.resize(i + n, 0);
+        }
+        self[i..i + n].copy_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_all_at(&mut self, pos: u64, buf: &[u8]) -> Result<()> {
+        if self.write_at(pos, buf)? < buf.len() {
+            Err(io::Error::new(ErrorKind::WriteZero, "failed to write whole buffer"))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        let end = match pos.checked_add(len) {
+            Some(end) if end <= usize::MAX as u64 => end as usize,
+            _ => return Err(Error::OutOfBounds { offset: pos, len }.into()),
+        };
+        if end > self.len() {
+            self.try_reserve(end - self.len()).map_err(|_| Error::OutOfBounds { offset: pos, len })?;
+            self.resize(end, 0);
+        }
+        self[pos as usize..end].fill(0);
+        Ok(())
+    }
+}
+
+impl WriteAt for VecDeque<u8> {
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        if pos >= usize::MAX as u64 {
+            return Ok(0);
+        }
+        let i = pos as usize;
+        let n = cmp::min(usize::MAX - i, buf.len());
+        if i + n > self.len() {
+            self.try_reserve(i + n - self.len()).map_err(|_| Error::OutOfBounds { offset: pos, len: n as u64 })?;
+            self.resize(i + n, 0);
+        }
+        let (front, back) = self.as_mut_slices();
+        if i < front.len() {
+            let m = cmp::min(front.len() - i, n);
+            front[i..i + m].copy_from_slice(&buf[..m]);
+            back[..n - m].copy_from_slice(&buf[m..n]);
+        } else {
+            let j = i - front.len();
+            back[j..j + n].copy_from_slice(&buf[..n]);
+        }
+        Ok(n)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_all_at(&mut self, pos: u64, buf: &[u8]) -> Result<()> {
+        if self.write_at(pos, buf)? < buf.len() {
+            Err(io::Error::new(ErrorKind::WriteZero, "failed to write whole buffer"))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        let end = match pos.checked_add(len) {
+            Some(end) if end <= usize::MAX as u64 => end as usize,
+            _ => return Err(Error::OutOfBounds { offset: pos, len }.into()),
+        };
+        if end > self.len() {
+            self.try_reserve(end - self.len()).map_err(|_| Error::OutOfBounds { offset: pos, len })?;
+            self.resize(end, 0);
+        }
+        self.range_mut(pos as usize..end).for_each(|b| *b = 0);
+        Ok(())
+    }
+}
+
+/// Writes ignore and keep the position of the cursor.
+impl WriteAt for Cursor<Vec<u8>> {
+    #[inline]
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        self.get_mut().write_at(pos, buf)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    #[inline]
+    fn write_all_at(&mut self, pos: u64, buf: &[u8]) -> Result<()> {
+        self.get_mut().write_all_at(pos, buf)
+    }
+
+    #[inline]
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        self.get_mut().write_zeroes_at(pos, len)
+    }
+}
+
+/// Writes ignore and keep the position of the cursor.
+impl WriteAt for Cursor<&mut [u8]> {
+    #[inline]
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        self.get_mut().write_at(pos, buf)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    #[inline]
+    fn write_all_at(&mut self, pos: u64, buf: &[u8]) -> Result<()> {
+        self.get_mut().write_all_at(pos, buf)
+    }
+
+    #[inline]
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        self.get_mut().write_zeroes_at(pos, len)
+    }
+}
+
+/// Writes ignore and keep the position of the cursor.
+impl WriteAt for Cursor<Box<[u8]>> {
+    #[inline]
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        self.get_mut().write_at(pos, buf)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    #[inline]
+    fn write_all_at(&mut self, pos: u64, buf: &[u8]) -> Result<()> {
+        self.get_mut().write_all_at(pos, buf)
+    }
+
+    #[inline]
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        self.get_mut().write_zeroes_at(pos, len)
+    }
+}
+
+/// Uses `pwrite` on Unix, which leaves the file position alone, and
+/// `seek_write` on Windows, which leaves it undefined. Elsewhere the file
+/// is seeked to `pos` first.
+impl WriteAt for File {
+    #[cfg(any(unix, windows))]
+    #[inline]
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        sys::write_at(self, pos, buf)
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    #[inline]
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        AssertThreadSafe(self).write_at(pos, buf)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        AssertThreadSafe(self).flush()
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    #[inline]
+    fn write_all_at(&mut self, pos: u64, buf: &[u8]) -> Result<()> {
+        AssertThreadSafe(self).write_all_at(pos, buf)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn write_at_with(&mut self, pos: u64, buf: &[u8], options: &IoOptions) -> Result<usize> {
+        sys::write_at_with(self, pos, buf, options)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn write_vectored_at(&mut self, pos: u64, bufs: &[IoSlice]) -> Result<usize> {
+        sys::write_vectored_at(self, pos, bufs)
+    }
+
+    /// Uses `fallocate` with `FALLOC_FL_ZERO_RANGE`, which lets the file
+    /// system zero the range without writing it, and falls back to writing
+    /// zeros if the file system does not support it.
+    #[cfg(target_os = "linux")]
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        if len > 0 && sys::zero_range(self, pos, len)? {
+            return Ok(());
+        }
+        write_zeroes(self, pos, len)
+    }
+}
+
+/// Uses `pwrite` on Unix and `seek_write` on Windows, which leaves the
+/// file position undefined.
+#[cfg(any(unix, windows))]
+impl WriteAt for &File {
+    #[inline]
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        sys::write_at(self, pos, buf)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn write_at_with(&mut self, pos: u64, buf: &[u8], options: &IoOptions) -> Result<usize> {
+        sys::write_at_with(self, pos, buf, options)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn write_vectored_at(&mut self, pos: u64, bufs: &[IoSlice]) -> Result<usize> {
+        sys::write_vectored_at(self, pos, bufs)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        if len > 0 && sys::zero_range(self, pos, len)? {
+            return Ok(());
+        }
+        write_zeroes(self, pos, len)
+    }
+}
+
+impl WriteAt for Sink {
+    #[inline]
+    fn write_at(&mut self, _pos: u64, buf: &[u8]) -> Result<usize> {
+        Ok(buf.len())
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    #[inline]
+    fn write_zeroes_at(&mut self, _pos: u64, _len: u64) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A struct for wrapping thread-safe readers or writers.
+///
+/// Using this struct asserts, that the contained `Read + Seek` or
+/// `Write + Seek` value can be used for [`ReadAt`](trait.ReadAt.html)
+/// and [`WriteAt`](trait.WriteAt.html), without risking a seek from
+/// another thread in parallel.
+///
+/// The traits are implemented by first seeking to the offset, and then
+/// calling the appropriate method on the wrapped value.
+#[derive(Clone, Debug)]
+pub struct AssertThreadSafe<T>(pub T);
+
+impl<T> ReadAt for AssertThreadSafe<T>
+    where T: Read + Seek
+{
+    #[inline]
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        self.0.seek(SeekFrom::Start(pos))?;
+        self.0.read(buf)
+    }
+
+    #[inline]
+    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
+        self.0.seek(SeekFrom::Start(pos))?;
+        self.0.read_exact(buf)
+    }
+
+    #[inline]
+    fn read_to_end_at(&mut self, pos: u64, buf: &mut Vec<u8>) -> Result<usize> {
+        self.0.seek(SeekFrom::Start(pos))?;
+        self.0.read_to_end(buf)
+    }
+}
+
+impl<T> WriteAt for AssertThreadSafe<T>
+    where T: Write + Seek
+{
+    #[inline]
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        self.0.seek(SeekFrom::Start(pos))?;
+        self.0.write(buf)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        self.0.flush()
     }
 
     #[inline]
     fn write_all_at(&mut self, pos: u64, buf: &[u8]) -> Result<()> {
-        try!(self.0.seek(SeekFrom::Start(pos)));
+        self.0.seek(SeekFrom::Start(pos))?;
         self.0.write_all(buf)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A source of `len` bytes of `7` at any offset, returning at most
+    /// `chunk` bytes per read.
+    struct Trickle {
+        start: u64,
+        len: u64,
+        chunk: usize,
+        reads: usize,
+    }
+
+    impl ReadAt for Trickle {
+        fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+            self.reads += 1;
+            let end = self.start.saturating_add(self.len);
+            let n = cmp::min(end.saturating_sub(pos), cmp::min(buf.len(), self.chunk) as u64) as usize;
+            buf[..n].fill(7);
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn read_to_end_at_short_reads() {
+        let mut src = Trickle { start: 0, len: 100_000, chunk: 3, reads: 0 };
+        let mut buf = b"head".to_vec();
+        assert_eq!(src.read_to_end_at(10, &mut buf).unwrap(), 99_990);
+        assert_eq!(buf.len(), 4 + 99_990);
+        assert!(buf[..4] == *b"head" && buf[4..].iter().all(|&b| b == 7));
+        assert!(src.reads < 99_990 / 3 + 30);
+    }
+
+    #[test]
+    fn read_to_end_at_offset_overflow() {
+        let mut buf = Vec::new();
+        let err = io::repeat(7).read_to_end_at(u64::MAX - 10, &mut buf).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert!(buf.len() > 10 && buf.iter().all(|&b| b == 7));
+    }
+
+    #[test]
+    fn read_to_end_at_reserves_file_length() {
+        let path = std::env::temp_dir().join(format!("ioat-read-to-end-{}", std::process::id()));
+        std::fs::write(&path, vec![5; 100_000]).unwrap();
+        let mut file = File::open(&path).unwrap();
+        let mut buf = Vec::new();
+        let n = file.read_to_end_at(1000, &mut buf);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(n.unwrap(), 99_000);
+        assert_eq!(buf.capacity(), 99_001);
+    }
+}
@@ -0,0 +1,199 @@
+use std::fs::File;
+use std::io::Result;
+
+/// The kind of a byte-range lock.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LockKind {
+    /// A lock that can be held by several owners at once, such as
+    /// readers.
+    Shared,
+    /// A lock held by a single owner, such as a writer.
+    Exclusive,
+}
+
+/// The `LockAt` trait allows for locking byte ranges of a target, so that
+/// processes sharing it can coordinate which of them owns which region.
+///
+/// Locks are advisory on Unix and mandatory on Windows, where reads and
+/// writes overlapping a range locked through another handle fail. A `len`
+/// of zero locks from `pos` to the end of the target, however far it
+/// grows. Portable code should only unlock ranges exactly as they were
+/// locked, and not lock overlapping ranges through the same handle.
+pub trait LockAt {
+    /// Locks `len` bytes at `pos`, waiting until no conflicting lock is
+    /// held.
+    ///
+    /// # Errors
+    ///
+    /// This method can return any I/O error, such as one of kind
+    /// `Deadlock` if waiting would deadlock.
+    fn lock_range(&mut self, pos: u64, len: u64, kind: LockKind) -> Result<()>;
+
+    /// Locks `len` bytes at `pos` if no conflicting lock is held, and
+    /// returns whether the lock was taken.
+    ///
+    /// # Errors
+    ///
+    /// This method can return any I/O error.
+    fn try_lock_range(&mut self, pos: u64, len: u64, kind: LockKind) -> Result<bool>;
+
+    /// Releases the lock on `len` bytes at `pos`.
+    ///
+    /// # Errors
+    ///
+    /// This method can return any I/O error.
+    fn unlock_range(&mut self, pos: u64, len: u64) -> Result<()>;
+}
+
+impl<L: LockAt + ?Sized> LockAt for &mut L {
+    #[inline]
+    fn lock_range(&mut self, pos: u64, len: u64, kind: LockKind) -> Result<()> {
+        (**self).lock_range(pos, len, kind)
+    }
+
+    #[inline]
+    fn try_lock_range(&mut self, pos: u64, len: u64, kind: LockKind) -> Result<bool> {
+        (**self).try_lock_range(pos, len, kind)
+    }
+
+    #[inline]
+    fn unlock_range(&mut self, pos: u64, len: u64) -> Result<()> {
+        (**self).unlock_range(pos, len)
+    }
+}
+
+impl<L: LockAt + ?Sized> LockAt for Box<L> {
+    #[inline]
+    fn lock_range(&mut self, pos: u64, len: u64, kind: LockKind) -> Result<()> {
+        (**self).lock_range(pos, len, kind)
+    }
+
+    #[inline]
+    fn try_lock_range(&mut self, pos: u64, len: u64, kind: LockKind) -> Result<bool> {
+        (**self).try_lock_range(pos, len, kind)
+    }
+
+    #[inline]
+    fn unlock_range(&mut self, pos: u64, len: u64) -> Result<()> {
+        (**self).unlock_range(pos, len)
+    }
+}
+
+/// Uses open file description locks through `fcntl` on Linux, which
+/// belong to the `File` and are released when it is closed. Other Unix
+/// platforms use classic `fcntl` locks, which belong to the process: they
+/// never conflict within one process, and closing any handle to the file
+/// releases all of them. Windows uses `LockFileEx`.
+impl LockAt for File {
+    fn lock_range(&mut self, pos: u64, len: u64, kind: LockKind) -> Result<()> {
+        imp::lock(self, pos, len, Some(kind), true).map(drop)
+    }
+
+    fn try_lock_range(&mut self, pos: u64, len: u64, kind: LockKind) -> Result<bool> {
+        imp::lock(self, pos, len, Some(kind), false)
+    }
+
+    fn unlock_range(&mut self, pos: u64, len: u64) -> Result<()> {
+        imp::lock(self, pos, len, None, false).map(drop)
+    }
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::fs::File;
+    use std::io::{Error, Result};
+    use std::mem;
+    use std::os::unix::io::AsRawFd;
+
+    use super::LockKind;
+    use sys;
+
+    #[cfg(target_os = "linux")]
+    const SETLK: (libc::c_int, libc::c_int) = (libc::F_OFD_SETLK, libc::F_OFD_SETLKW);
+    #[cfg(not(target_os = "linux"))]
+    const SETLK: (libc::c_int, libc::c_int) = (libc::F_SETLK, libc::F_SETLKW);
+
+    /// Takes or, if `kind` is `None`, releases a lock, and returns whether
+    /// it was taken.
+    pub fn lock(file: &File, pos: u64, len: u64, kind: Option<LockKind>, wait: bool) -> Result<bool> {
+        let mut lock: libc::flock = unsafe { mem::zeroed() };
+        lock.l_type = match kind {
+            Some(LockKind::Shared) => libc::F_RDLCK,
+            Some(LockKind::Exclusive) => libc::F_WRLCK,
+            None => libc::F_UNLCK,
+        } as _;
+        lock.l_whence = libc::SEEK_SET as _;
+        lock.l_start = sys::off_t(pos)?;
+        lock.l_len = sys::off_t(len)?;
+        let cmd = if wait { SETLK.1 } else { SETLK.0 };
+        loop {
+            if unsafe { libc::fcntl(file.as_raw_fd(), cmd, &lock) } != -1 {
+                return Ok(true);
+            }
+            let err = Error::last_os_error();
+            match err.raw_os_error() {
+                Some(libc::EINTR) => {}
+                Some(libc::EAGAIN) | Some(libc::EACCES) if !wait => return Ok(false),
+                _ => return Err(err),
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::fs::File;
+    use std::io::{Error, Result};
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::Foundation::ERROR_LOCK_VIOLATION;
+    use windows_sys::Win32::Storage::FileSystem::{LOCKFILE_EXCLUSIVE_LOCK, LOCKFILE_FAIL_IMMEDIATELY, LockFileEx,
+                                                  UnlockFileEx};
+    use windows_sys::Win32::System::IO::OVERLAPPED;
+
+    use super::LockKind;
+
+    /// Takes or, if `kind` is `None`, releases a lock, and returns whether
+    /// it was taken.
+    pub fn lock(file: &File, pos: u64, len: u64, kind: Option<LockKind>, wait: bool) -> Result<bool> {
+        let mut overlapped = OVERLAPPED::default();
+        overlapped.Anonymous.Anonymous.Offset = pos as u32;
+        overlapped.Anonymous.Anonymous.OffsetHigh = (pos >> 32) as u32;
+        // A length of zero would lock nothing, so it is taken to reach
+        // past any possible end of the file instead.
+        let len = if len == 0 { u64::MAX - pos } else { len };
+        let handle = file.as_raw_handle();
+        let ok = match kind {
+            Some(kind) => {
+                let mut flags = 0;
+                if kind == LockKind::Exclusive {
+                    flags |= LOCKFILE_EXCLUSIVE_LOCK;
+                }
+                if !wait {
+                    flags |= LOCKFILE_FAIL_IMMEDIATELY;
+                }
+                unsafe { LockFileEx(handle, flags, 0, len as u32, (len >> 32) as u32, &mut overlapped) }
+            }
+            None => unsafe { UnlockFileEx(handle, 0, len as u32, (len >> 32) as u32, &mut overlapped) },
+        };
+        if ok != 0 {
+            return Ok(true);
+        }
+        let err = Error::last_os_error();
+        if !wait && kind.is_some() && err.raw_os_error() == Some(ERROR_LOCK_VIOLATION as i32) {
+            return Ok(false);
+        }
+        Err(err)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod imp {
+    use std::fs::File;
+    use std::io::{Error, ErrorKind, Result};
+
+    use super::LockKind;
+
+    pub fn lock(_file: &File, _pos: u64, _len: u64, _kind: Option<LockKind>, _wait: bool) -> Result<bool> {
+        Err(Error::new(ErrorKind::Unsupported, "byte-range locks are not supported on this platform"))
+    }
+}
@@ -0,0 +1,216 @@
+const MIN_MATCH: usize = 4;
+const LAST_LITERALS: usize = 5;
+const MF_LIMIT: usize = 12;
+const MAX_DISTANCE: usize = 65535;
+const HASH_LOG: u32 = 12;
+
+fn read_u32(src: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([src[pos], src[pos + 1], src[pos + 2], src[pos + 3]])
+}
+
+fn hash(seq: u32) -> usize {
+    (seq.wrapping_mul(2_654_435_761) >> (32 - HASH_LOG)) as usize
+}
+
+fn push_len(out: &mut Vec<u8>, mut n: usize) {
+    while n >= 255 {
+        out.push(255);
+        n -= 255;
+    }
+    out.push(n as u8);
+}
+
+fn push_sequence(out: &mut Vec<u8>, literals: &[u8], matched: Option<(usize, usize)>) {
+    let lit_len = literals.len();
+    let match_len = matched.map_or(0, |(_, len)| len - MIN_MATCH);
+    out.push(((lit_len.min(15) as u8) << 4) | match_len.min(15) as u8);
+    if lit_len >= 15 {
+        push_len(out, lit_len - 15);
+    }
+    out.extend_from_slice(literals);
+    if let Some((distance, _)) = matched {
+        out.extend_from_slice(&(distance as u16).to_le_bytes());
+        if match_len >= 15 {
+            push_len(out, match_len - 15);
+        }
+    }
+}
+
+/// Compresses `src` into a single LZ4 block, readable by any LZ4 block
+/// decoder.
+///
+/// Matches are found greedily in a single pass, as in the fast mode of the
+/// reference implementation.
+pub fn compress(src: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(src.len() / 2 + 16);
+    let mut anchor = 0;
+    if src.len() > MF_LIMIT {
+        let mut table = vec![0usize; 1 << HASH_LOG];
+        let match_limit = src.len() - LAST_LITERALS;
+        let mut pos = 0;
+        while pos < src.len() - MF_LIMIT {
+            let seq = read_u32(src, pos);
+            let slot = &mut table[hash(seq)];
+            let candidate = *slot;
+            *slot = pos;
+            if candidate < pos && pos - candidate <= MAX_DISTANCE && read_u32(src, candidate) == seq {
+                let mut len = MIN_MATCH;
+                while pos + len < match_limit && src[candidate + len] == src[pos + len] {
+                    len += 1;
+                }
+                push_sequence(&mut out, &src[anchor..pos], Some((pos - candidate, len)));
+                pos += len;
+                anchor = pos;
+            } else {
+                pos += 1;
+            }
+        }
+    }
+    push_sequence(&mut out, &src[anchor..], None);
+    out
+}
+
+fn read_len(src: &[u8], i: &mut usize) -> Option<usize> {
+    let mut n = 0usize;
+    loop {
+        let b = *src.get(*i)?;
+        *i += 1;
+        n = n.checked_add(b as usize)?;
+        if b != 255 {
+            return Some(n);
+        }
+    }
+}
+
+/// Decompresses the LZ4 block `src` into `out`, which is cleared first.
+///
+/// Returns `None` if the block is malformed or decompresses to more than
+/// `max_len` bytes.
+pub fn decompress(src: &[u8], out: &mut Vec<u8>, max_len: usize) -> Option<()> {
+    out.clear();
+    let mut i = 0;
+    loop {
+        let token = *src.get(i)?;
+        i += 1;
+        let mut lit_len = (token >> 4) as usize;
+        if lit_len == 15 {
+            lit_len = lit_len.checked_add(read_len(src, &mut i)?)?;
+        }
+        let literals = src.get(i..i.checked_add(lit_len)?)?;
+        if out.len() + lit_len > max_len {
+            return None;
+        }
+        out.extend_from_slice(literals);
+        i += lit_len;
+        if i == src.len() {
+            return Some(());
+        }
+
+        let distance = u16::from_le_bytes([*src.get(i)?, *src.get(i + 1)?]) as usize;
+        i += 2;
+        let mut match_len = (token & 15) as usize;
+        if match_len == 15 {
+            match_len = match_len.checked_add(read_len(src, &mut i)?)?;
+        }
+        match_len += MIN_MATCH;
+        if distance == 0 || distance > out.len() || out.len() + match_len > max_len {
+            return None;
+        }
+        let start = out.len() - distance;
+        if distance >= match_len {
+            out.extend_from_within(start..start + match_len);
+        } else {
+            // Overlapping matches repeat the last `distance` bytes.
+            for k in start..start + match_len {
+                let b = out[k];
+                out.push(b);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(data: &[u8]) {
+        let compressed = compress(data);
+        let mut out = Vec::new();
+        assert_eq!(decompress(&compressed, &mut out, data.len()), Some(()), "len {}", data.len());
+        assert_eq!(out, data);
+    }
+
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491_u32;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn short_lengths() {
+        let text = b"abcdabcdabcdabcdefghefghefghefghabcdabcdabcdabcd";
+        let noise = noise(64);
+        for len in 0..=text.len() {
+            round_trip(&text[..len]);
+            round_trip(&noise[..len]);
+            round_trip(&vec![7; len]);
+        }
+    }
+
+    #[test]
+    fn overlapping_matches() {
+        let data = vec![b'a'; 1000];
+        let compressed = compress(&data);
+        assert!(compressed.len() < 20);
+        round_trip(&data);
+
+        let mut data = Vec::new();
+        for i in 0..300 {
+            data.extend_from_slice(&[b'x', b'y', b'z'][..1 + i % 3]);
+        }
+        round_trip(&data);
+    }
+
+    #[test]
+    fn long_literals_and_matches() {
+        let mut data = noise(1000);
+        data.extend_from_slice(&noise(700));
+        data.extend(vec![0; 600]);
+        data.extend_from_slice(&noise(300));
+        round_trip(&data);
+        assert!(compress(&data).len() < data.len() - 1000);
+    }
+
+    #[test]
+    fn decompress_known_block() {
+        // "a", then a match of 19 at distance 1, then "bbbbb".
+        let block = [0x1f, b'a', 1, 0, 0, 0x50, b'b', b'b', b'b', b'b', b'b'];
+        let mut out = Vec::new();
+        decompress(&block, &mut out, 25).unwrap();
+        let mut expected = vec![b'a'; 20];
+        expected.extend_from_slice(b"bbbbb");
+        assert_eq!(out, expected);
+        assert_eq!(decompress(&block, &mut out, 24), None);
+    }
+
+    #[test]
+    fn malformed_blocks() {
+        let mut out = Vec::new();
+        assert_eq!(decompress(&[], &mut out, 100), None);
+        // Literals past the end.
+        assert_eq!(decompress(&[0x30, b'a'], &mut out, 100), None);
+        // A zero distance, and one reaching before the start.
+        assert_eq!(decompress(&[0x10, b'a', 0, 0, 0x00], &mut out, 100), None);
+        assert_eq!(decompress(&[0x10, b'a', 2, 0, 0x00], &mut out, 100), None);
+        // A truncated match.
+        assert_eq!(decompress(&[0x10, b'a', 1], &mut out, 100), None);
+        // A length continuation past the end.
+        assert_eq!(decompress(&[0xf0, 255], &mut out, 1000), None);
+    }
+}
@@ -0,0 +1,136 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Debug)]
+struct Shared {
+    limit: u64,
+    used: AtomicU64,
+    pressure: AtomicU64,
+}
+
+/// A limit on the memory used by buffers and caches, shared by every
+/// consumer drawing from it.
+///
+/// Clones refer to the same budget, so one budget can be handed to every
+/// [`SpillBuf`](struct.SpillBuf.html) and [`Gzip`](struct.Gzip.html) of
+/// an application to cap their total memory use. Consumers hold a
+/// [`MemoryReservation`](struct.MemoryReservation.html) for the memory
+/// they use. When a reservation cannot grow, the budget is under
+/// pressure: the consumer that failed falls back to a path that needs no
+/// more memory, such as spilling to a file, and consumers holding caches
+/// drop them on their next access.
+#[derive(Clone, Debug)]
+pub struct MemoryBudget {
+    shared: Arc<Shared>,
+}
+
+impl MemoryBudget {
+    /// Creates a budget of `limit` bytes.
+    pub fn new(limit: u64) -> MemoryBudget {
+        MemoryBudget {
+            shared: Arc::new(Shared {
+                limit,
+                used: AtomicU64::new(0),
+                pressure: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Returns the limit of the budget in bytes.
+    pub fn limit(&self) -> u64 {
+        self.shared.limit
+    }
+
+    /// Returns the number of bytes reserved from the budget.
+    pub fn used(&self) -> u64 {
+        self.shared.used.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of bytes that can still be reserved.
+    pub fn available(&self) -> u64 {
+        self.limit().saturating_sub(self.used())
+    }
+
+    /// Creates an empty reservation against the budget.
+    pub fn reservation(&self) -> MemoryReservation {
+        MemoryReservation {
+            budget: self.clone(),
+            len: 0,
+            pressure: self.shared.pressure.load(Ordering::Relaxed),
+        }
+    }
+
+    fn try_reserve(&self, n: u64) -> bool {
+        let limit = self.shared.limit;
+        let reserved = self.shared.used.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+            used.checked_add(n).filter(|&total| total <= limit)
+        });
+        if reserved.is_err() {
+            self.shared.pressure.fetch_add(1, Ordering::Relaxed);
+        }
+        reserved.is_ok()
+    }
+
+    fn release(&self, n: u64) {
+        self.shared.used.fetch_sub(n, Ordering::Relaxed);
+    }
+}
+
+/// A number of bytes reserved from a [`MemoryBudget`](struct.MemoryBudget.html),
+/// returned to it when the reservation is dropped.
+#[derive(Debug)]
+pub struct MemoryReservation {
+    budget: MemoryBudget,
+    len: u64,
+    pressure: u64,
+}
+
+impl MemoryReservation {
+    /// Returns the number of bytes reserved.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns whether no bytes are reserved.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the budget the reservation draws from.
+    pub fn budget(&self) -> &MemoryBudget {
+        &self.budget
+    }
+
+    /// Changes the reservation to `len` bytes, and returns whether it
+    /// succeeded.
+    ///
+    /// Shrinking always succeeds. Growing fails, leaving the reservation
+    /// unchanged and putting the budget under pressure, if the budget
+    /// does not have enough bytes available.
+    pub fn try_resize(&mut self, len: u64) -> bool {
+        if len > self.len {
+            if !self.budget.try_reserve(len - self.len) {
+                return false;
+            }
+        } else {
+            self.budget.release(self.len - len);
+        }
+        self.len = len;
+        true
+    }
+
+    /// Returns whether a reservation against the budget failed to grow
+    /// since the last call, signalling that caches should be dropped.
+    pub fn take_pressure(&mut self) -> bool {
+        let pressure = self.budget.shared.pressure.load(Ordering::Relaxed);
+        let changed = pressure != self.pressure;
+        self.pressure = pressure;
+        changed
+    }
+}
+
+impl Drop for MemoryReservation {
+    fn drop(&mut self) {
+        self.budget.release(self.len);
+    }
+}
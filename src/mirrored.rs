@@ -0,0 +1,449 @@
+use std::error;
+use std::fmt;
+use std::io::{self, Result};
+use std::sync::Arc;
+
+use {ErrorExt, IoOptions, KnownEof, ReadAt, ResizeAt, SizeAt, SyncAt, WriteAt, write_all_with};
+
+/// A policy choosing which replica of a [`Mirrored`](struct.Mirrored.html)
+/// backend serves a read.
+///
+/// Closures taking the same arguments as
+/// [`choose`](#tymethod.choose) can be used as policies.
+pub trait ReadPolicy {
+    /// Returns the replica to read `len` bytes at `pos` from, or `None` to
+    /// fail the read.
+    ///
+    /// `usable[i]` tells whether replica `i` is healthy and has not already
+    /// failed this read. Returning an index that is not usable is treated
+    /// like returning `None`.
+    fn choose(&mut self, pos: u64, len: usize, usable: &[bool]) -> Option<usize>;
+}
+
+impl<F: FnMut(u64, usize, &[bool]) -> Option<usize>> ReadPolicy for F {
+    #[inline]
+    fn choose(&mut self, pos: u64, len: usize, usable: &[bool]) -> Option<usize> {
+        self(pos, len, usable)
+    }
+}
+
+/// The default [`ReadPolicy`](trait.ReadPolicy.html), reading from the
+/// first usable replica.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FirstHealthy;
+
+impl ReadPolicy for FirstHealthy {
+    fn choose(&mut self, _pos: u64, _len: usize, usable: &[bool]) -> Option<usize> {
+        usable.iter().position(|&u| u)
+    }
+}
+
+/// A [`ReadPolicy`](trait.ReadPolicy.html) taking turns between the
+/// usable replicas, to spread reads across them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct RoundRobin {
+    next: usize,
+}
+
+impl ReadPolicy for RoundRobin {
+    fn choose(&mut self, _pos: u64, _len: usize, usable: &[bool]) -> Option<usize> {
+        let n = usable.len();
+        let i = (0..n).map(|k| (self.next + k) % n).find(|&i| usable[i])?;
+        self.next = (i + 1) % n;
+        Some(i)
+    }
+}
+
+/// A backend keeping identical copies of its data on several replicas,
+/// like RAID 1.
+///
+/// Writes, resizes, flushes and syncs go to every healthy replica, and
+/// succeed as long as one of them does. A replica failing one of them is
+/// marked as failed, since it no longer holds the same data, and its
+/// error is kept for [`error`](#method.error). Reads are served by one
+/// healthy replica chosen by a [`ReadPolicy`](trait.ReadPolicy.html). If it
+/// fails, the read is retried on the other healthy replicas. Replicas
+/// failing a read with a transient error stay healthy; any other error
+/// marks them as failed. Failed replicas are left alone until
+/// [`restore`](#method.restore) is called, typically after copying the
+/// data over from a healthy replica.
+///
+/// When every replica fails, the error of the last one is returned. If it
+/// marked the replica as failed, the returned error names the replica and
+/// has the kept error as its source.
+#[derive(Debug)]
+pub struct Mirrored<T, P = FirstHealthy> {
+    replicas: Vec<T>,
+    errors: Vec<Option<Arc<io::Error>>>,
+    policy: P,
+}
+
+impl<T> Mirrored<T> {
+    /// Creates a backend mirroring across `replicas`, reading from the
+    /// first healthy one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `replicas` is empty.
+    pub fn new(replicas: Vec<T>) -> Mirrored<T> {
+        Mirrored::with_policy(replicas, FirstHealthy)
+    }
+}
+
+impl<T, P> Mirrored<T, P> {
+    /// Creates a backend mirroring across `replicas`, with reads balanced
+    /// by `policy`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `replicas` is empty.
+    pub fn with_policy(replicas: Vec<T>, policy: P) -> Mirrored<T, P> {
+        assert!(!replicas.is_empty(), "at least one replica is required");
+        Mirrored {
+            errors: replicas.iter().map(|_| None).collect(),
+            replicas,
+            policy,
+        }
+    }
+
+    /// Returns the number of replicas, healthy or not.
+    pub fn replica_count(&self) -> usize {
+        self.replicas.len()
+    }
+
+    /// Returns the number of healthy replicas.
+    pub fn healthy_count(&self) -> usize {
+        self.errors.iter().filter(|e| e.is_none()).count()
+    }
+
+    /// Returns whether replica `index` is healthy.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of range.
+    pub fn is_healthy(&self, index: usize) -> bool {
+        self.errors[index].is_none()
+    }
+
+    /// Returns the error that marked replica `index` as failed, or `None`
+    /// if it is healthy.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of range.
+    pub fn error(&self, index: usize) -> Option<&io::Error> {
+        self.errors[index].as_deref()
+    }
+
+    /// Marks replica `index` as healthy again, returning the error that
+    /// marked it as failed.
+    ///
+    /// The replica must hold the same data as the healthy ones again, or
+    /// reads from it return stale data. While an error returned by a
+    /// failed operation still shares the original error as its source,
+    /// the one returned here does the same.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of range.
+    pub fn restore(&mut self, index: usize) -> Option<io::Error> {
+        self.errors[index].take().map(|e| Arc::try_unwrap(e).unwrap_or_else(|e| ReplicaError::wrap(index, &e)))
+    }
+
+    /// Returns the replicas.
+    pub fn replicas(&self) -> &[T] {
+        &self.replicas
+    }
+
+    /// Returns a mutable reference to the replicas.
+    pub fn replicas_mut(&mut self) -> &mut [T] {
+        &mut self.replicas
+    }
+
+    /// Returns a reference to the read policy.
+    pub fn policy(&self) -> &P {
+        &self.policy
+    }
+
+    /// Consumes the backend, returning the replicas.
+    pub fn into_inner(self) -> Vec<T> {
+        self.replicas
+    }
+
+    /// Runs `f` on every healthy replica, marking those failing as failed,
+    /// and returns the result of the first one succeeding.
+    fn each<R, F>(&mut self, mut f: F) -> Result<R>
+        where F: FnMut(&mut T) -> Result<R>
+    {
+        let mut result = None;
+        let mut last = None;
+        for (i, (replica, error)) in self.replicas.iter_mut().zip(&mut self.errors).enumerate() {
+            if error.is_some() {
+                continue;
+            }
+            match f(replica) {
+                Ok(r) => {
+                    result.get_or_insert(r);
+                }
+                Err(e) => {
+                    let e = Arc::new(e);
+                    last = Some(ReplicaError::wrap(i, &e));
+                    *error = Some(e);
+                }
+            }
+        }
+        result.ok_or_else(|| last.unwrap_or_else(no_replica))
+    }
+
+    /// Returns the first healthy replica.
+    fn first(&self) -> Result<&T> {
+        self.errors.iter().position(Option::is_none).map(|i| &self.replicas[i]).ok_or_else(no_replica)
+    }
+}
+
+/// The error of a failed replica, shared with the one kept for
+/// [`Mirrored::error`](struct.Mirrored.html#method.error) and returned as
+/// its source.
+#[derive(Debug)]
+struct ReplicaError {
+    replica: usize,
+    error: Arc<io::Error>,
+}
+
+impl ReplicaError {
+    fn wrap(replica: usize, error: &Arc<io::Error>) -> io::Error {
+        io::Error::new(error.kind(), ReplicaError { replica, error: error.clone() })
+    }
+}
+
+impl fmt::Display for ReplicaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "replica {}: {}", self.replica, self.error)
+    }
+}
+
+impl error::Error for ReplicaError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&*self.error)
+    }
+}
+
+fn no_replica() -> io::Error {
+    io::Error::other("no healthy replica")
+}
+
+impl<T: ReadAt, P: ReadPolicy> Mirrored<T, P> {
+    /// Reads from the replicas chosen by the policy with `f`, until one
+    /// succeeds.
+    fn read_with<F>(&mut self, pos: u64, buf: &mut [u8], mut f: F) -> Result<usize>
+        where F: FnMut(&mut T, &mut [u8]) -> Result<usize>
+    {
+        let mut usable: Vec<bool> = self.errors.iter().map(Option::is_none).collect();
+        let mut last = None;
+        while let Some(i) = self.policy.choose(pos, buf.len(), &usable).filter(|&i| usable.get(i) == Some(&true)) {
+            match f(&mut self.replicas[i], buf) {
+                Ok(n) => return Ok(n),
+                Err(e) => {
+                    usable[i] = false;
+                    if e.is_transient() {
+                        last = Some(e);
+                    } else {
+                        let e = Arc::new(e);
+                        last = Some(ReplicaError::wrap(i, &e));
+                        self.errors[i] = Some(e);
+                    }
+                }
+            }
+        }
+        Err(last.unwrap_or_else(no_replica))
+    }
+}
+
+impl<T: ReadAt, P: ReadPolicy> ReadAt for Mirrored<T, P> {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        self.read_with(pos, buf, |replica, buf| replica.read_at(pos, buf))
+    }
+
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        self.read_with(pos, buf, |replica, buf| replica.read_at_with(pos, buf, options))
+    }
+}
+
+impl<T: WriteAt, P> WriteAt for Mirrored<T, P> {
+    /// Writes all of `buf` to every healthy replica, so that they stay
+    /// identical.
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        self.each(|replica| replica.write_all_at(pos, buf))?;
+        Ok(buf.len())
+    }
+
+    /// Writes all of `buf` to every healthy replica with `options`.
+    fn write_at_with(&mut self, pos: u64, buf: &[u8], options: &IoOptions) -> Result<usize> {
+        self.each(|replica| write_all_with(replica, pos, buf, options))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.each(|replica| replica.flush())
+    }
+
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        self.each(|replica| replica.write_zeroes_at(pos, len))
+    }
+}
+
+impl<T: ResizeAt, P> ResizeAt for Mirrored<T, P> {
+    fn set_len(&mut self, len: u64) -> Result<()> {
+        self.each(|replica| replica.set_len(len))
+    }
+}
+
+impl<T: SyncAt, P> SyncAt for Mirrored<T, P> {
+    fn sync_all(&mut self) -> Result<()> {
+        self.each(|replica| replica.sync_all())
+    }
+
+    fn sync_data(&mut self) -> Result<()> {
+        self.each(|replica| replica.sync_data())
+    }
+}
+
+/// Uses the size of the first healthy replica.
+impl<T: SizeAt, P> SizeAt for Mirrored<T, P> {
+    fn size(&self) -> Result<u64> {
+        self.first()?.size()
+    }
+}
+
+/// Uses the first healthy replica.
+impl<T: KnownEof, P> KnownEof for Mirrored<T, P> {
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        let i = self.errors.iter().position(Option::is_none).ok_or_else(no_replica)?;
+        self.replicas[i].remaining_at(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+
+    /// A replica that fails every operation with `fail`, if set.
+    #[derive(Debug, Default)]
+    struct Replica {
+        data: Vec<u8>,
+        fail: Option<ErrorKind>,
+        reads: usize,
+    }
+
+    impl Replica {
+        fn new(data: &[u8]) -> Replica {
+            Replica { data: data.to_vec(), ..Replica::default() }
+        }
+
+        fn failing(kind: ErrorKind) -> Replica {
+            Replica { fail: Some(kind), ..Replica::default() }
+        }
+
+        fn check(&self) -> Result<()> {
+            match self.fail {
+                Some(kind) => Err(io::Error::new(kind, "replica down")),
+                None => Ok(()),
+            }
+        }
+    }
+
+    impl ReadAt for Replica {
+        fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+            self.reads += 1;
+            self.check()?;
+            self.data.read_at(pos, buf)
+        }
+    }
+
+    impl WriteAt for Replica {
+        fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+            self.check()?;
+            self.data.write_at(pos, buf)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            self.check()
+        }
+    }
+
+    #[test]
+    fn read_failover() {
+        let replicas = vec![Replica::failing(ErrorKind::PermissionDenied), Replica::new(b"data")];
+        let mut mirrored = Mirrored::new(replicas);
+        let mut buf = [0; 4];
+        assert_eq!(mirrored.read_at(0, &mut buf).unwrap(), 4);
+        assert_eq!(&buf, b"data");
+        assert!(!mirrored.is_healthy(0) && mirrored.is_healthy(1));
+        assert_eq!(mirrored.error(0).unwrap().kind(), ErrorKind::PermissionDenied);
+        // The failed replica is not tried again.
+        mirrored.read_at(0, &mut buf).unwrap();
+        assert_eq!(mirrored.replicas()[0].reads, 1);
+    }
+
+    #[test]
+    fn transient_read_errors_keep_replicas_healthy() {
+        let replicas = vec![Replica::failing(ErrorKind::TimedOut), Replica::new(b"data")];
+        let mut mirrored = Mirrored::new(replicas);
+        let mut buf = [0; 4];
+        mirrored.read_at(0, &mut buf).unwrap();
+        assert_eq!(mirrored.healthy_count(), 2);
+    }
+
+    #[test]
+    fn round_robin() {
+        let replicas = vec![Replica::new(b"a"), Replica::new(b"a"), Replica::new(b"a")];
+        let mut mirrored = Mirrored::with_policy(replicas, RoundRobin::default());
+        let mut buf = [0; 1];
+        for _ in 0..6 {
+            mirrored.read_at(0, &mut buf).unwrap();
+        }
+        assert!(mirrored.replicas().iter().all(|r| r.reads == 2));
+
+        mirrored.replicas_mut()[1].fail = Some(ErrorKind::Other);
+        for _ in 0..8 {
+            mirrored.read_at(0, &mut buf).unwrap();
+        }
+        let reads: Vec<usize> = mirrored.replicas().iter().map(|r| r.reads).collect();
+        assert_eq!(reads, [6, 3, 6]);
+        assert!(!mirrored.is_healthy(1));
+    }
+
+    #[test]
+    fn writes_go_to_every_healthy_replica() {
+        let replicas = vec![Replica::new(b""), Replica::failing(ErrorKind::Other), Replica::new(b"")];
+        let mut mirrored = Mirrored::new(replicas);
+        mirrored.write_all_at(2, b"xy").unwrap();
+        assert_eq!(mirrored.healthy_count(), 2);
+        assert_eq!(mirrored.replicas()[0].data, b"\0\0xy");
+        assert_eq!(mirrored.replicas()[2].data, b"\0\0xy");
+    }
+
+    #[test]
+    fn all_replicas_failed() {
+        let replicas = vec![Replica::failing(ErrorKind::Other), Replica::failing(ErrorKind::PermissionDenied)];
+        let mut mirrored = Mirrored::new(replicas);
+        let err = mirrored.write_at(0, b"x").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+        assert_eq!(err.to_string(), "replica 1: replica down");
+        let source = err.get_ref().and_then(|e| e.source()).and_then(|e| e.downcast_ref::<io::Error>());
+        assert!(std::ptr::eq(source.unwrap(), mirrored.error(1).unwrap()));
+        assert_eq!(mirrored.healthy_count(), 0);
+
+        let mut buf = [0; 1];
+        assert_eq!(mirrored.read_at(0, &mut buf).unwrap_err().to_string(), "no healthy replica");
+        assert!(mirrored.flush().is_err());
+
+        // The kept error is handed back as it was, or shared while the
+        // returned one is still around.
+        assert_eq!(mirrored.restore(0).unwrap().to_string(), "replica down");
+        assert_eq!(mirrored.restore(1).unwrap().to_string(), "replica 1: replica down");
+        drop(err);
+        assert!(mirrored.restore(1).is_none());
+    }
+}
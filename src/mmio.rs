@@ -0,0 +1,252 @@
+use std::cmp;
+use std::fs::OpenOptions;
+use std::io::{self, ErrorKind, Result};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::ptr;
+
+use sys;
+use {Error, KnownEof, ReadAt, SizeAt, WriteAt};
+
+/// The width of the individual loads and stores used to access a
+/// [`MmioWindow`](struct.MmioWindow.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AccessWidth {
+    /// 8-bit accesses.
+    U8,
+    /// 16-bit accesses.
+    U16,
+    /// 32-bit accesses.
+    U32,
+    /// 64-bit accesses.
+    U64,
+}
+
+impl AccessWidth {
+    fn bytes(self) -> usize {
+        match self {
+            AccessWidth::U8 => 1,
+            AccessWidth::U16 => 2,
+            AccessWidth::U32 => 4,
+            AccessWidth::U64 => 8,
+        }
+    }
+}
+
+/// A memory-mapped window into a device, such as a range of `/dev/mem`
+/// or a PCI resource file in sysfs.
+///
+/// Offsets are relative to the start of the window. All accesses are
+/// volatile and made with the configured [`AccessWidth`](enum.AccessWidth.html),
+/// which defaults to 8 bits. Many device registers must be accessed
+/// with a specific width, and fail or misbehave otherwise.
+///
+/// With a width of more than 8 bits, the offset and length of every
+/// read and write must be multiples of the width; otherwise an error of
+/// kind `InvalidInput` is returned. A read or write extending past the
+/// end of the window is shortened to the whole accesses that fit, so if
+/// the length of the window is not a multiple of the width, the bytes
+/// after its last whole access cannot be reached.
+///
+/// This is only available on Unix with the `mmio` feature.
+#[derive(Debug)]
+pub struct MmioWindow {
+    map: *mut u8,
+    map_len: usize,
+    delta: usize,
+    len: usize,
+    writable: bool,
+    width: AccessWidth,
+}
+
+unsafe impl Send for MmioWindow {}
+
+impl MmioWindow {
+    /// Maps `len` bytes at `offset` into the file at `path`.
+    ///
+    /// The file is opened with `O_SYNC`, which makes `/dev/mem` map the
+    /// range uncached. If `writable` is `false`, the window is mapped
+    /// read-only and writes fail with an error of kind `PermissionDenied`.
+    ///
+    /// # Safety
+    ///
+    /// Accessing device memory can have arbitrary side effects, such as
+    /// clearing status registers on read or reconfiguring hardware on
+    /// write, and accessing a range not backed by a device may crash the
+    /// machine. The caller must ensure that the whole range is safe to
+    /// access with the configured access width, and, for regular files,
+    /// that the file is not truncated while mapped.
+    ///
+    /// # Errors
+    ///
+    /// Any error from opening or mapping the file is returned.
+    pub unsafe fn open<P: AsRef<Path>>(path: P, offset: u64, len: usize, writable: bool) -> Result<MmioWindow> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(writable)
+            .custom_flags(libc::O_SYNC)
+            .open(path)?;
+
+        let page = libc::sysconf(libc::_SC_PAGESIZE) as u64;
+        let delta = (offset % page) as usize;
+        let map_len = match len.checked_add(delta) {
+            Some(map_len) if len > 0 => map_len,
+            _ => return Err(io::Error::new(ErrorKind::InvalidInput, "invalid window length")),
+        };
+        let prot = if writable {
+            libc::PROT_READ | libc::PROT_WRITE
+        } else {
+            libc::PROT_READ
+        };
+        let map = libc::mmap(ptr::null_mut(),
+                             map_len,
+                             prot,
+                             libc::MAP_SHARED,
+                             file.as_raw_fd(),
+                             sys::off_t(offset - delta as u64)?);
+        if map == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(MmioWindow {
+            map: map as *mut u8,
+            map_len,
+            delta,
+            len,
+            writable,
+            width: AccessWidth::U8,
+        })
+    }
+
+    /// Returns the length of the window in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the window is empty, which is never the case.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Sets the width of the loads and stores used for reads and writes.
+    pub fn set_access_width(&mut self, width: AccessWidth) {
+        self.width = width;
+    }
+
+    /// Returns a pointer to the start of the range and its length in
+    /// bytes, after checking alignment and shortening it to the whole
+    /// accesses that fit in the window.
+    fn range(&self, pos: u64, len: usize) -> Result<Option<(*mut u8, usize)>> {
+        if pos >= self.len as u64 || len == 0 {
+            return Ok(None);
+        }
+        let pos = pos as usize;
+        let width = self.width.bytes();
+        if !(self.delta + pos).is_multiple_of(width) || !len.is_multiple_of(width) {
+            return Err(Error::Misaligned {
+                offset: pos as u64,
+                len: len as u64,
+                align: width as u64,
+            }.into());
+        }
+        let n = cmp::min(self.len - pos, len);
+        let n = n - n % width;
+        if n == 0 {
+            return Ok(None);
+        }
+        Ok(Some((unsafe { self.map.add(self.delta + pos) }, n)))
+    }
+}
+
+impl ReadAt for MmioWindow {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        let (src, n) = match self.range(pos, buf.len())? {
+            Some(range) => range,
+            None => return Ok(0),
+        };
+        let width = self.width.bytes();
+        for (i, chunk) in buf[..n].chunks_mut(width).enumerate() {
+            unsafe {
+                let src = src.add(i * width);
+                match self.width {
+                    AccessWidth::U8 => chunk[0] = ptr::read_volatile(src),
+                    AccessWidth::U16 => {
+                        chunk.copy_from_slice(&ptr::read_volatile(src as *const u16).to_ne_bytes())
+                    }
+                    AccessWidth::U32 => {
+                        chunk.copy_from_slice(&ptr::read_volatile(src as *const u32).to_ne_bytes())
+                    }
+                    AccessWidth::U64 => {
+                        chunk.copy_from_slice(&ptr::read_volatile(src as *const u64).to_ne_bytes())
+                    }
+                }
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl KnownEof for MmioWindow {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        Ok((self.len as u64).saturating_sub(pos))
+    }
+}
+
+impl SizeAt for MmioWindow {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        Ok(self.len as u64)
+    }
+}
+
+impl WriteAt for MmioWindow {
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        if !self.writable {
+            return Err(io::Error::new(ErrorKind::PermissionDenied, "window is mapped read-only"));
+        }
+        let (dst, n) = match self.range(pos, buf.len())? {
+            Some(range) => range,
+            None => return Ok(0),
+        };
+        let width = self.width.bytes();
+        for (i, chunk) in buf[..n].chunks(width).enumerate() {
+            unsafe {
+                let dst = dst.add(i * width);
+                match self.width {
+                    AccessWidth::U8 => ptr::write_volatile(dst, chunk[0]),
+                    AccessWidth::U16 => {
+                        ptr::write_volatile(dst as *mut u16, u16::from_ne_bytes([chunk[0], chunk[1]]))
+                    }
+                    AccessWidth::U32 => {
+                        let mut word = [0; 4];
+                        word.copy_from_slice(chunk);
+                        ptr::write_volatile(dst as *mut u32, u32::from_ne_bytes(word))
+                    }
+                    AccessWidth::U64 => {
+                        let mut word = [0; 8];
+                        word.copy_from_slice(chunk);
+                        ptr::write_volatile(dst as *mut u64, u64::from_ne_bytes(word))
+                    }
+                }
+            }
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.writable {
+            let ret = unsafe { libc::msync(self.map as *mut libc::c_void, self.map_len, libc::MS_SYNC) };
+            sys::cvt(ret)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for MmioWindow {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.map as *mut libc::c_void, self.map_len);
+        }
+    }
+}
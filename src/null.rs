@@ -0,0 +1,148 @@
+use std::io::Result;
+
+use {Allocate, Error, Extent, Extents, KnownEof, PunchHole, ReadAt, ResizeAt, SizeAt, SyncAt, WriteAt};
+
+/// A device that reads as zeros and discards everything written to it,
+/// while keeping track of its length.
+///
+/// Unlike `Empty` and `Sink`, it behaves like a file: writes past the end
+/// extend it, and reads return zeros up to the end. This makes it a
+/// measuring stick for benchmarks, and a safe stand-in for the target of
+/// a dry run, which reports how much would have been written.
+#[derive(Clone, Debug, Default)]
+pub struct Null {
+    len: u64,
+    high_water: u64,
+    written: u64,
+}
+
+impl Null {
+    /// Creates a device of `len` bytes.
+    pub fn new(len: u64) -> Null {
+        Null {
+            len,
+            high_water: len,
+            written: 0,
+        }
+    }
+
+    /// Returns the length of the device in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns whether the device is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the largest length the device has had.
+    pub fn high_water(&self) -> u64 {
+        self.high_water
+    }
+
+    /// Returns the number of bytes written to the device, including
+    /// zeros.
+    pub fn bytes_written(&self) -> u64 {
+        self.written
+    }
+
+    fn extend(&mut self, end: u64) {
+        self.len = self.len.max(end);
+        self.high_water = self.high_water.max(end);
+    }
+}
+
+impl ReadAt for Null {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        if pos >= self.len {
+            return Ok(0);
+        }
+        let n = (self.len - pos).min(buf.len() as u64) as usize;
+        buf[..n].fill(0);
+        Ok(n)
+    }
+}
+
+impl KnownEof for Null {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        Ok(self.len.saturating_sub(pos))
+    }
+}
+
+impl SizeAt for Null {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        Ok(self.len)
+    }
+}
+
+impl WriteAt for Null {
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        let n = (u64::MAX - pos).min(buf.len() as u64);
+        if n > 0 {
+            self.extend(pos + n);
+            self.written += n;
+        }
+        Ok(n as usize)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        let end = end(pos, len)?;
+        if len > 0 {
+            self.extend(end);
+            self.written += len;
+        }
+        Ok(())
+    }
+}
+
+impl ResizeAt for Null {
+    fn set_len(&mut self, len: u64) -> Result<()> {
+        self.len = len;
+        self.high_water = self.high_water.max(len);
+        Ok(())
+    }
+}
+
+impl SyncAt for Null {
+    #[inline]
+    fn sync_all(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl PunchHole for Null {
+    #[inline]
+    fn punch_hole(&mut self, _pos: u64, _len: u64) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl Allocate for Null {
+    fn allocate(&mut self, pos: u64, len: u64) -> Result<()> {
+        let end = end(pos, len)?;
+        self.extend(end);
+        Ok(())
+    }
+}
+
+/// The whole device is reported as a hole.
+impl Extents for Null {
+    fn extent_at(&mut self, pos: u64) -> Result<Option<Extent>> {
+        if pos >= self.len {
+            return Ok(None);
+        }
+        Ok(Some(Extent { offset: pos, len: self.len - pos, is_hole: true }))
+    }
+}
+
+fn end(pos: u64, len: u64) -> Result<u64> {
+    pos.checked_add(len).ok_or_else(|| Error::OutOfBounds { offset: pos, len }.into())
+}
@@ -0,0 +1,211 @@
+use std::fmt;
+use std::io::{Error, ErrorKind, Result};
+
+/// A position in a backend, in bytes from its start.
+///
+/// `Offset` and [`Length`](struct.Length.html) are optional wrappers
+/// around `u64` that keep positions and sizes apart and make overflow
+/// explicit. Both convert into `u64`, so they can be passed to any
+/// method taking a raw position or length with `.into()`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Offset(pub u64);
+
+/// A size of a range, in bytes.
+///
+/// See [`Offset`](struct.Offset.html).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Length(pub u64);
+
+impl Offset {
+    /// Returns the raw position.
+    pub fn get(self) -> u64 {
+        self.0
+    }
+
+    /// Returns the offset `len` bytes further, or `None` on overflow.
+    pub fn checked_add(self, len: Length) -> Option<Offset> {
+        self.0.checked_add(len.0).map(Offset)
+    }
+
+    /// Returns the offset `len` bytes earlier, or `None` if it would be
+    /// negative.
+    pub fn checked_sub(self, len: Length) -> Option<Offset> {
+        self.0.checked_sub(len.0).map(Offset)
+    }
+
+    /// Returns the distance from `start` to this offset, or `None` if
+    /// `start` is past it.
+    pub fn checked_distance_from(self, start: Offset) -> Option<Length> {
+        self.0.checked_sub(start.0).map(Length)
+    }
+
+    /// Returns the end of the range of `len` bytes starting here.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if the end does not fit
+    /// in a `u64`.
+    pub fn end(self, len: Length) -> Result<Offset> {
+        self.checked_add(len).ok_or_else(overflow)
+    }
+
+    /// Returns whether the offset is a multiple of `align`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `align` is not a power of two.
+    pub fn is_aligned(self, align: u64) -> bool {
+        self.0 & mask(align) == 0
+    }
+
+    /// Rounds the offset down to a multiple of `align`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `align` is not a power of two.
+    pub fn align_down(self, align: u64) -> Offset {
+        Offset(self.0 & !mask(align))
+    }
+
+    /// Rounds the offset up to a multiple of `align`, or returns `None`
+    /// on overflow.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `align` is not a power of two.
+    pub fn checked_align_up(self, align: u64) -> Option<Offset> {
+        let mask = mask(align);
+        self.0.checked_add(mask).map(|n| Offset(n & !mask))
+    }
+
+    /// Converts the offset into a `usize`, for indexing into memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if the offset does not
+    /// fit in a `usize`.
+    pub fn to_usize(self) -> Result<usize> {
+        to_usize(self.0)
+    }
+}
+
+impl Length {
+    /// Returns the raw length.
+    pub fn get(self) -> u64 {
+        self.0
+    }
+
+    /// Returns whether the length is zero.
+    pub fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns the sum of both lengths, or `None` on overflow.
+    pub fn checked_add(self, other: Length) -> Option<Length> {
+        self.0.checked_add(other.0).map(Length)
+    }
+
+    /// Returns the difference of both lengths, or `None` if `other` is
+    /// larger.
+    pub fn checked_sub(self, other: Length) -> Option<Length> {
+        self.0.checked_sub(other.0).map(Length)
+    }
+
+    /// Returns whether the length is a multiple of `align`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `align` is not a power of two.
+    pub fn is_aligned(self, align: u64) -> bool {
+        self.0 & mask(align) == 0
+    }
+
+    /// Rounds the length down to a multiple of `align`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `align` is not a power of two.
+    pub fn align_down(self, align: u64) -> Length {
+        Length(self.0 & !mask(align))
+    }
+
+    /// Rounds the length up to a multiple of `align`, or returns `None`
+    /// on overflow.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `align` is not a power of two.
+    pub fn checked_align_up(self, align: u64) -> Option<Length> {
+        let mask = mask(align);
+        self.0.checked_add(mask).map(|n| Length(n & !mask))
+    }
+
+    /// Converts the length into a `usize`, for sizing buffers.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if the length does not
+    /// fit in a `usize`.
+    pub fn to_usize(self) -> Result<usize> {
+        to_usize(self.0)
+    }
+}
+
+impl From<u64> for Offset {
+    fn from(pos: u64) -> Offset {
+        Offset(pos)
+    }
+}
+
+impl From<Offset> for u64 {
+    fn from(pos: Offset) -> u64 {
+        pos.0
+    }
+}
+
+impl From<u64> for Length {
+    fn from(len: u64) -> Length {
+        Length(len)
+    }
+}
+
+impl From<usize> for Length {
+    fn from(len: usize) -> Length {
+        Length(len as u64)
+    }
+}
+
+impl From<Length> for u64 {
+    fn from(len: Length) -> u64 {
+        len.0
+    }
+}
+
+impl fmt::Display for Offset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Display for Length {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+fn mask(align: u64) -> u64 {
+    assert!(align.is_power_of_two(), "alignment must be a power of two");
+    align - 1
+}
+
+fn overflow() -> Error {
+    Error::new(ErrorKind::InvalidInput, "offset overflows u64")
+}
+
+fn to_usize(n: u64) -> Result<usize> {
+    if n > usize::MAX as u64 {
+        Err(Error::new(ErrorKind::InvalidInput, "value does not fit in usize"))
+    } else {
+        Ok(n as usize)
+    }
+}
@@ -0,0 +1,243 @@
+use std::fs::{self, File};
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+use advise::{self, Advice};
+use allocate;
+
+/// The host page cache behavior requested for a file.
+///
+/// These map to the platform flags described on
+/// [`OpenOptions::direct`](struct.OpenOptions.html#method.direct) and
+/// [`OpenOptions::dsync`](struct.OpenOptions.html#method.dsync).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum CacheControl {
+    /// Writes go through the page cache and reach the device later. This
+    /// is the default.
+    #[default]
+    Writeback,
+    /// Writes go through the page cache, and each write is synchronized to
+    /// the device before returning.
+    Writethrough,
+    /// Reads and writes bypass the page cache. This does not by itself
+    /// make writes durable.
+    NoCache,
+}
+
+/// Options and flags for opening a file as a positional I/O handle.
+///
+/// This wraps `std::fs::OpenOptions` and adds knobs that otherwise
+/// require platform-specific flags or calls after opening the file.
+/// The returned `File` implements both [`ReadAt`](trait.ReadAt.html)
+/// and [`WriteAt`](trait.WriteAt.html).
+#[derive(Clone, Debug, Default)]
+pub struct OpenOptions {
+    read: bool,
+    write: bool,
+    create: bool,
+    create_new: bool,
+    truncate: bool,
+    read_only: bool,
+    direct: bool,
+    dsync: bool,
+    preallocate: Option<u64>,
+    advice: Option<Advice>,
+}
+
+impl OpenOptions {
+    /// Creates a blank set of options, with every option set to `false`.
+    pub fn new() -> OpenOptions {
+        OpenOptions::default()
+    }
+
+    /// Sets the option for read access.
+    pub fn read(&mut self, read: bool) -> &mut OpenOptions {
+        self.read = read;
+        self
+    }
+
+    /// Sets the option for write access.
+    pub fn write(&mut self, write: bool) -> &mut OpenOptions {
+        self.write = write;
+        self
+    }
+
+    /// Sets the option to create the file if it does not exist.
+    pub fn create(&mut self, create: bool) -> &mut OpenOptions {
+        self.create = create;
+        self
+    }
+
+    /// Sets the option to always create a new file, failing if it exists.
+    pub fn create_new(&mut self, create_new: bool) -> &mut OpenOptions {
+        self.create_new = create_new;
+        self
+    }
+
+    /// Sets the option to truncate the file to zero length when opened.
+    pub fn truncate(&mut self, truncate: bool) -> &mut OpenOptions {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Guards against accidentally opening the file for writing.
+    ///
+    /// If set, the file is opened for reading only, and `open` fails if
+    /// write access, creation or truncation has also been requested.
+    pub fn read_only(&mut self, read_only: bool) -> &mut OpenOptions {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Sets the option to bypass the page cache.
+    ///
+    /// This uses `O_DIRECT` on Linux, Android and FreeBSD,
+    /// `F_NOCACHE` on macOS and iOS, and `FILE_FLAG_NO_BUFFERING` on
+    /// Windows. Note that with `O_DIRECT` and `FILE_FLAG_NO_BUFFERING`
+    /// offsets, lengths and buffer addresses must usually be aligned to
+    /// the logical block size of the device.
+    pub fn direct(&mut self, direct: bool) -> &mut OpenOptions {
+        self.direct = direct;
+        self
+    }
+
+    /// Sets the option for every write to be synchronized to the device
+    /// before returning.
+    ///
+    /// This uses `O_DSYNC` on Unix and `FILE_FLAG_WRITE_THROUGH` on Windows.
+    pub fn dsync(&mut self, dsync: bool) -> &mut OpenOptions {
+        self.dsync = dsync;
+        self
+    }
+
+    /// Sets the page cache behavior, replacing the `direct` and `dsync`
+    /// options.
+    pub fn cache_control(&mut self, cache: CacheControl) -> &mut OpenOptions {
+        self.direct = cache == CacheControl::NoCache;
+        self.dsync = cache == CacheControl::Writethrough;
+        self
+    }
+
+    /// Sets a size to preallocate for the file after opening it.
+    ///
+    /// Space is reserved as with [`Allocate`](trait.Allocate.html), so the
+    /// file is extended to `len` bytes if it is shorter, and never shrunk.
+    pub fn preallocate(&mut self, len: u64) -> &mut OpenOptions {
+        self.preallocate = Some(len);
+        self
+    }
+
+    /// Sets the access pattern advice to give for the whole file after
+    /// opening it.
+    pub fn advise(&mut self, advice: Advice) -> &mut OpenOptions {
+        self.advice = Some(advice);
+        self
+    }
+
+    /// Opens the file at `path` with the options specified by `self`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if `read_only` is set
+    /// along with an option requiring write access, and an error of kind
+    /// `Unsupported` if direct I/O is requested on a platform that does
+    /// not support it. Any error from opening the file or applying the
+    /// options is returned.
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> Result<File> {
+        if self.read_only && (self.write || self.create || self.create_new || self.truncate) {
+            return Err(Error::new(ErrorKind::InvalidInput, "read-only file cannot be opened for writing"));
+        }
+        let mut options = fs::OpenOptions::new();
+        options.read(self.read || self.read_only)
+            .write(self.write && !self.read_only)
+            .create(self.create)
+            .create_new(self.create_new)
+            .truncate(self.truncate);
+        self.set_flags(&mut options)?;
+
+        let file = options.open(path)?;
+        self.configure(&file)?;
+        if let Some(len) = self.preallocate {
+            allocate::allocate_file(&file, 0, len)?;
+        }
+        if let Some(advice) = self.advice {
+            advise::advise_file(&file, 0, 0, advice)?;
+        }
+        Ok(file)
+    }
+
+    #[cfg(unix)]
+    fn set_flags(&self, options: &mut fs::OpenOptions) -> Result<()> {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let mut flags = 0;
+        if self.dsync {
+            flags |= libc::O_DSYNC;
+        }
+        if self.direct {
+            flags |= direct_flag()?;
+        }
+        options.custom_flags(flags);
+        Ok(())
+    }
+
+    #[cfg(windows)]
+    fn set_flags(&self, options: &mut fs::OpenOptions) -> Result<()> {
+        use std::os::windows::fs::OpenOptionsExt;
+
+        const FILE_FLAG_NO_BUFFERING: u32 = 0x2000_0000;
+        const FILE_FLAG_WRITE_THROUGH: u32 = 0x8000_0000;
+
+        let mut flags = 0;
+        if self.dsync {
+            flags |= FILE_FLAG_WRITE_THROUGH;
+        }
+        if self.direct {
+            flags |= FILE_FLAG_NO_BUFFERING;
+        }
+        options.custom_flags(flags);
+        Ok(())
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    fn set_flags(&self, _options: &mut fs::OpenOptions) -> Result<()> {
+        if self.direct || self.dsync {
+            Err(Error::new(ErrorKind::Unsupported, "direct and synchronous I/O are not supported"))
+        } else {
+            Ok(())
+        }
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    fn configure(&self, file: &File) -> Result<()> {
+        use std::os::unix::io::AsRawFd;
+        use sys;
+
+        if self.direct {
+            sys::cvt(unsafe { libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1) })?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+    fn configure(&self, _file: &File) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+fn direct_flag() -> Result<libc::c_int> {
+    Ok(libc::O_DIRECT)
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+fn direct_flag() -> Result<libc::c_int> {
+    // Set with `fcntl` after opening instead.
+    Ok(0)
+}
+
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android", target_os = "freebsd",
+                        target_os = "macos", target_os = "ios"))))]
+fn direct_flag() -> Result<libc::c_int> {
+    Err(Error::new(ErrorKind::Unsupported, "direct I/O is not supported on this platform"))
+}
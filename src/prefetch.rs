@@ -0,0 +1,110 @@
+use std::fs::File;
+use std::io::Result;
+
+/// The `Prefetch` trait allows for asking the operating system to read a
+/// range into its cache ahead of time.
+///
+/// Unlike issuing a throwaway read, prefetching does not occupy the
+/// calling thread while the data is read, and the data does not need to
+/// be copied into user space.
+pub trait Prefetch {
+    /// Starts reading `len` bytes at `pos` into the operating system's
+    /// cache, without waiting for the read to complete.
+    ///
+    /// This is only a hint, and implementations may do nothing at all.
+    ///
+    /// # Errors
+    ///
+    /// This method can return any I/O error.
+    fn kernel_prefetch(&mut self, pos: u64, len: u64) -> Result<()>;
+}
+
+impl<P: Prefetch + ?Sized> Prefetch for &mut P {
+    #[inline]
+    fn kernel_prefetch(&mut self, pos: u64, len: u64) -> Result<()> {
+        (**self).kernel_prefetch(pos, len)
+    }
+}
+
+impl<P: Prefetch + ?Sized> Prefetch for Box<P> {
+    #[inline]
+    fn kernel_prefetch(&mut self, pos: u64, len: u64) -> Result<()> {
+        (**self).kernel_prefetch(pos, len)
+    }
+}
+
+/// Uses `readahead` on Linux and Android, `F_RDADVISE` on macOS and iOS,
+/// and `POSIX_FADV_WILLNEED` on FreeBSD. Does nothing elsewhere.
+impl Prefetch for File {
+    fn kernel_prefetch(&mut self, pos: u64, len: u64) -> Result<()> {
+        imp::prefetch(self, pos, len)
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod imp {
+    use std::cmp;
+    use std::fs::File;
+    use std::io::{Error, ErrorKind, Result};
+    use std::os::unix::io::AsRawFd;
+
+    pub fn prefetch(file: &File, pos: u64, len: u64) -> Result<()> {
+        if pos > i64::MAX as u64 {
+            return Err(Error::new(ErrorKind::InvalidInput, "offset out of range"));
+        }
+        let len = cmp::min(len, usize::MAX as u64) as usize;
+        let ret = unsafe { libc::readahead(file.as_raw_fd(), pos as _, len) };
+        if ret == -1 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+mod imp {
+    use std::cmp;
+    use std::fs::File;
+    use std::io::Result;
+    use std::os::unix::io::AsRawFd;
+
+    use sys;
+
+    pub fn prefetch(file: &File, mut pos: u64, mut len: u64) -> Result<()> {
+        while len > 0 {
+            let count = cmp::min(len, libc::c_int::MAX as u64);
+            let mut advisory = libc::radvisory {
+                ra_offset: sys::off_t(pos)?,
+                ra_count: count as libc::c_int,
+            };
+            sys::cvt(unsafe { libc::fcntl(file.as_raw_fd(), libc::F_RDADVISE, &mut advisory) })?;
+            pos += count;
+            len -= count;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "freebsd")]
+mod imp {
+    use std::fs::File;
+    use std::io::Result;
+
+    use advise::{self, Advice};
+
+    pub fn prefetch(file: &File, pos: u64, len: u64) -> Result<()> {
+        advise::advise_file(file, pos, len, Advice::WillNeed)
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios",
+              target_os = "freebsd")))]
+mod imp {
+    use std::fs::File;
+    use std::io::Result;
+
+    pub fn prefetch(_file: &File, _pos: u64, _len: u64) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+use std::io::{Error, ErrorKind, Result};
+
+use registry::BoxReadAt;
+use {Gzip, ReadAt};
+
+/// An image or container format recognized by [`probe`](fn.probe.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum FormatKind {
+    /// No known signature was found, so the source is assumed to be a raw image.
+    Raw,
+    /// A QEMU copy-on-write image, version 2 or 3.
+    Qcow2,
+    /// A dynamic or differencing Virtual Hard Disk image.
+    ///
+    /// Fixed VHD images only carry their footer at the end of the image
+    /// and are detected as `Raw`.
+    Vhd,
+    /// A Hyper-V VHDX image.
+    Vhdx,
+    /// A gzip stream.
+    Gzip,
+    /// An xz stream.
+    Xz,
+    /// A Zstandard frame. This includes seekable Zstandard archives,
+    /// which begin with a regular frame.
+    Zstd,
+}
+
+const SIGNATURES: &[(&[u8], FormatKind)] = &[
+    (b"QFI\xfb", FormatKind::Qcow2),
+    (b"conectix", FormatKind::Vhd),
+    (b"vhdxfile", FormatKind::Vhdx),
+    (b"\x1f\x8b", FormatKind::Gzip),
+    (b"\xfd7zXZ\x00", FormatKind::Xz),
+    (b"\x28\xb5\x2f\xfd", FormatKind::Zstd),
+];
+
+/// Detects the format of `src` from the magic bytes at its start.
+///
+/// Sources too short to hold a signature are detected as `Raw`.
+///
+/// # Errors
+///
+/// Any I/O error from reading `src` is returned.
+pub fn probe<R: ReadAt + ?Sized>(src: &mut R) -> Result<FormatKind> {
+    let mut magic = [0; 8];
+    let mut len = 0;
+    while len < magic.len() {
+        match src.read_at(len as u64, &mut magic[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    let magic = &magic[..len];
+    Ok(SIGNATURES.iter()
+        .find(|&&(signature, _)| magic.starts_with(signature))
+        .map_or(FormatKind::Raw, |&(_, kind)| kind))
+}
+
+/// Probes the format of `src` and opens it as a reader over the image
+/// contents.
+///
+/// Raw images are returned as is, and gzip images are indexed with a
+/// full decode and read through [`Gzip`](struct.Gzip.html).
+///
+/// # Errors
+///
+/// Returns an error of kind `Unsupported` if `src` is in a format other
+/// than `Raw` or `Gzip`. Any I/O error from probing `src` and any error
+/// from [`Gzip::new`](struct.Gzip.html#method.new) is returned.
+pub fn open_image<R: ReadAt + Send + 'static>(mut src: R) -> Result<BoxReadAt> {
+    match probe(&mut src)? {
+        FormatKind::Raw => Ok(Box::new(src)),
+        FormatKind::Gzip => Ok(Box::new(Gzip::new(src)?)),
+        kind => Err(Error::new(ErrorKind::Unsupported, format!("{:?} images are not supported", kind))),
+    }
+}
@@ -0,0 +1,180 @@
+use std::io::{Error, ErrorKind, Result};
+
+use {ReadAt, WriteAt};
+
+/// The address space of another process.
+///
+/// Offsets are virtual addresses in the target process. Reads and
+/// writes of unmapped memory fail, or return a short count if only the
+/// start of the range is mapped.
+///
+/// This uses `process_vm_readv` and `process_vm_writev` on Linux, which
+/// require the same permissions as attaching to the process with
+/// `ptrace`, and `ReadProcessMemory` and `WriteProcessMemory` on
+/// Windows. It is only available with the `process` feature.
+#[derive(Debug)]
+pub struct ProcessMemory {
+    #[cfg(target_os = "linux")]
+    pid: libc::pid_t,
+    #[cfg(windows)]
+    handle: windows_sys::Win32::Foundation::HANDLE,
+}
+
+#[cfg(target_os = "linux")]
+impl ProcessMemory {
+    /// Opens the address space of the process with the ID `pid`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if `pid` is not a valid
+    /// process ID. Permissions are only checked on access.
+    pub fn open(pid: u32) -> Result<ProcessMemory> {
+        if pid == 0 || pid > libc::pid_t::MAX as u32 {
+            return Err(Error::new(ErrorKind::InvalidInput, "invalid process ID"));
+        }
+        Ok(ProcessMemory { pid: pid as libc::pid_t })
+    }
+
+    fn transfer(&self, pos: u64, local: libc::iovec, write: bool) -> Result<usize> {
+        if local.iov_len == 0 {
+            return Ok(0);
+        }
+        let remote = libc::iovec {
+            iov_base: address(pos)? as *mut libc::c_void,
+            iov_len: local.iov_len,
+        };
+        let ret = unsafe {
+            if write {
+                libc::process_vm_writev(self.pid, &local, 1, &remote, 1, 0)
+            } else {
+                libc::process_vm_readv(self.pid, &local, 1, &remote, 1, 0)
+            }
+        };
+        if ret == -1 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(ret as usize)
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl ReadAt for ProcessMemory {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        let local = libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+        self.transfer(pos, local, false)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl WriteAt for ProcessMemory {
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        let local = libc::iovec {
+            iov_base: buf.as_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+        self.transfer(pos, local, true)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+unsafe impl Send for ProcessMemory {}
+
+#[cfg(windows)]
+unsafe impl Sync for ProcessMemory {}
+
+#[cfg(windows)]
+impl ProcessMemory {
+    /// Opens the address space of the process with the ID `pid`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the process cannot be opened with the access
+    /// rights required for reading and writing its memory.
+    pub fn open(pid: u32) -> Result<ProcessMemory> {
+        use windows_sys::Win32::System::Threading::{OpenProcess, PROCESS_VM_OPERATION, PROCESS_VM_READ,
+                                                    PROCESS_VM_WRITE};
+
+        let access = PROCESS_VM_READ | PROCESS_VM_WRITE | PROCESS_VM_OPERATION;
+        let handle = unsafe { OpenProcess(access, 0, pid) };
+        if handle.is_null() {
+            Err(Error::last_os_error())
+        } else {
+            Ok(ProcessMemory { handle })
+        }
+    }
+}
+
+#[cfg(windows)]
+impl ReadAt for ProcessMemory {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        use windows_sys::Win32::System::Diagnostics::Debug::ReadProcessMemory;
+
+        let address = address(pos)?;
+        let mut n = 0;
+        let ok = unsafe {
+            ReadProcessMemory(self.handle,
+                              address as *const _,
+                              buf.as_mut_ptr() as *mut _,
+                              buf.len(),
+                              &mut n)
+        };
+        if ok == 0 && n == 0 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(n)
+        }
+    }
+}
+
+#[cfg(windows)]
+impl WriteAt for ProcessMemory {
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        use windows_sys::Win32::System::Diagnostics::Debug::WriteProcessMemory;
+
+        let address = address(pos)?;
+        let mut n = 0;
+        let ok = unsafe {
+            WriteProcessMemory(self.handle,
+                               address as *const _,
+                               buf.as_ptr() as *const _,
+                               buf.len(),
+                               &mut n)
+        };
+        if ok == 0 && n == 0 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(n)
+        }
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+impl Drop for ProcessMemory {
+    fn drop(&mut self) {
+        unsafe {
+            windows_sys::Win32::Foundation::CloseHandle(self.handle);
+        }
+    }
+}
+
+fn address(pos: u64) -> Result<usize> {
+    if pos > usize::MAX as u64 {
+        Err(Error::new(ErrorKind::InvalidInput, "address out of range"))
+    } else {
+        Ok(pos as usize)
+    }
+}
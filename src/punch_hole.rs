@@ -0,0 +1,72 @@
+use std::cmp;
+use std::fs::File;
+use std::io::Result;
+
+use {SizeAt, WriteAt};
+
+/// The `PunchHole` trait allows for releasing the storage behind a range
+/// of a target, such as the blocks of a deleted file in a thin-provisioned
+/// image.
+pub trait PunchHole {
+    /// Deallocates `len` bytes at `pos`, which read as zeros afterwards.
+    ///
+    /// The length of the target does not change, and the part of the range
+    /// past its end is ignored. Targets that cannot release storage write
+    /// zeros instead.
+    ///
+    /// # Errors
+    ///
+    /// This method can return any I/O error.
+    fn punch_hole(&mut self, pos: u64, len: u64) -> Result<()>;
+}
+
+impl<P: PunchHole + ?Sized> PunchHole for &mut P {
+    #[inline]
+    fn punch_hole(&mut self, pos: u64, len: u64) -> Result<()> {
+        (**self).punch_hole(pos, len)
+    }
+}
+
+impl<P: PunchHole + ?Sized> PunchHole for Box<P> {
+    #[inline]
+    fn punch_hole(&mut self, pos: u64, len: u64) -> Result<()> {
+        (**self).punch_hole(pos, len)
+    }
+}
+
+impl PunchHole for [u8] {
+    fn punch_hole(&mut self, pos: u64, len: u64) -> Result<()> {
+        let end = cmp::min(pos.saturating_add(len), self.len() as u64);
+        if pos < end {
+            self[pos as usize..end as usize].fill(0);
+        }
+        Ok(())
+    }
+}
+
+impl PunchHole for Vec<u8> {
+    #[inline]
+    fn punch_hole(&mut self, pos: u64, len: u64) -> Result<()> {
+        self[..].punch_hole(pos, len)
+    }
+}
+
+/// Uses `fallocate` with `FALLOC_FL_PUNCH_HOLE` on Linux, and marks the
+/// file as sparse and uses `FSCTL_SET_ZERO_DATA` on Windows. Zeros are
+/// written on other platforms, and on file systems without support for
+/// holes.
+impl PunchHole for File {
+    fn punch_hole(&mut self, pos: u64, len: u64) -> Result<()> {
+        let end = cmp::min(pos.saturating_add(len), self.size()?);
+        if pos >= end {
+            return Ok(());
+        }
+        #[cfg(any(target_os = "linux", windows))]
+        {
+            if ::sys::punch_hole(self, pos, end - pos)? {
+                return Ok(());
+            }
+        }
+        self.write_zeroes_at(pos, end - pos)
+    }
+}
@@ -0,0 +1,114 @@
+use std::cmp;
+use std::ops::Range;
+use std::slice;
+
+/// A set of byte offsets, stored as sorted, non-overlapping and
+/// non-adjacent ranges.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct RangeSet {
+    ranges: Vec<Range<u64>>,
+}
+
+impl RangeSet {
+    /// Creates an empty set.
+    pub fn new() -> RangeSet {
+        RangeSet { ranges: Vec::new() }
+    }
+
+    /// Returns whether the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Returns the number of offsets in the set.
+    pub fn len(&self) -> u64 {
+        self.ranges.iter().map(|r| r.end - r.start).sum()
+    }
+
+    /// Returns whether `pos` is in the set.
+    pub fn contains(&self, pos: u64) -> bool {
+        match self.ranges.binary_search_by(|r| r.start.cmp(&pos)) {
+            Ok(_) => true,
+            Err(0) => false,
+            Err(i) => pos < self.ranges[i - 1].end,
+        }
+    }
+
+    /// Returns whether every offset in `range` is in the set.
+    pub fn contains_range(&self, range: Range<u64>) -> bool {
+        if range.start >= range.end {
+            return true;
+        }
+        self.ranges.iter().any(|r| r.start <= range.start && range.end <= r.end)
+    }
+
+    /// Adds the offsets in `range` to the set.
+    pub fn insert(&mut self, range: Range<u64>) {
+        if range.start >= range.end {
+            return;
+        }
+        let first = self.ranges.partition_point(|r| r.end < range.start);
+        let last = self.ranges.partition_point(|r| r.start <= range.end);
+        let mut merged = range;
+        if first < last {
+            merged.start = cmp::min(merged.start, self.ranges[first].start);
+            merged.end = cmp::max(merged.end, self.ranges[last - 1].end);
+        }
+        self.ranges.splice(first..last, Some(merged));
+    }
+
+    /// Removes the offsets in `range` from the set.
+    pub fn remove(&mut self, range: Range<u64>) {
+        if range.start >= range.end {
+            return;
+        }
+        let first = self.ranges.partition_point(|r| r.end <= range.start);
+        let last = self.ranges.partition_point(|r| r.start < range.end);
+        if first >= last {
+            return;
+        }
+        let mut kept = Vec::with_capacity(2);
+        if self.ranges[first].start < range.start {
+            kept.push(self.ranges[first].start..range.start);
+        }
+        if range.end < self.ranges[last - 1].end {
+            kept.push(range.end..self.ranges[last - 1].end);
+        }
+        self.ranges.splice(first..last, kept);
+    }
+
+    /// Returns the ranges of offsets in `range` that are not in the set,
+    /// in ascending order.
+    pub fn gaps(&self, range: Range<u64>) -> Vec<Range<u64>> {
+        let mut gaps = Vec::new();
+        let mut pos = range.start;
+        let first = self.ranges.partition_point(|r| r.end <= range.start);
+        for r in &self.ranges[first..] {
+            if r.start >= range.end {
+                break;
+            }
+            if r.start > pos {
+                gaps.push(pos..r.start);
+            }
+            pos = cmp::max(pos, r.end);
+        }
+        if pos < range.end {
+            gaps.push(pos..range.end);
+        }
+        gaps
+    }
+
+    /// Returns an iterator over the ranges of the set, in ascending order.
+    pub fn iter(&self) -> slice::Iter<'_, Range<u64>> {
+        self.ranges.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a RangeSet {
+    type Item = &'a Range<u64>;
+    type IntoIter = slice::Iter<'a, Range<u64>>;
+
+    fn into_iter(self) -> slice::Iter<'a, Range<u64>> {
+        self.ranges.iter()
+    }
+}
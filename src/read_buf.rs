@@ -0,0 +1,115 @@
+use std::mem::MaybeUninit;
+use std::slice;
+
+/// A buffer that may start out uninitialized, filled by
+/// [`read_buf_at`](trait.ReadAt.html#method.read_buf_at).
+///
+/// The buffer is split into three parts: the filled part at the start,
+/// holding the bytes read so far, followed by a part that has been
+/// initialized but not filled, and the remaining uninitialized part.
+/// Sources that can write into uninitialized memory do so directly, and
+/// others only have the buffer zeroed once, however many reads it is
+/// filled by.
+#[derive(Debug)]
+pub struct ReadBuf<'a> {
+    buf: &'a mut [MaybeUninit<u8>],
+    filled: usize,
+    init: usize,
+}
+
+impl<'a> ReadBuf<'a> {
+    /// Creates an empty buffer over initialized memory.
+    pub fn new(buf: &'a mut [u8]) -> ReadBuf<'a> {
+        let init = buf.len();
+        // SAFETY: `MaybeUninit<u8>` has the same layout as `u8`, and no
+        // uninitialized value is ever written through the slice.
+        let buf = unsafe { slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut MaybeUninit<u8>, init) };
+        ReadBuf { buf, filled: 0, init }
+    }
+
+    /// Creates an empty buffer over uninitialized memory.
+    pub fn uninit(buf: &'a mut [MaybeUninit<u8>]) -> ReadBuf<'a> {
+        ReadBuf { buf, filled: 0, init: 0 }
+    }
+
+    /// Returns the total size of the buffer.
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Returns the number of bytes that can still be filled.
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.filled
+    }
+
+    /// Returns the filled part of the buffer.
+    pub fn filled(&self) -> &[u8] {
+        // SAFETY: The filled part is always initialized.
+        unsafe { slice::from_raw_parts(self.buf.as_ptr() as *const u8, self.filled) }
+    }
+
+    /// Returns the filled part of the buffer mutably.
+    pub fn filled_mut(&mut self) -> &mut [u8] {
+        // SAFETY: The filled part is always initialized.
+        unsafe { slice::from_raw_parts_mut(self.buf.as_mut_ptr() as *mut u8, self.filled) }
+    }
+
+    /// Empties the buffer, keeping track of its initialized part.
+    pub fn clear(&mut self) {
+        self.filled = 0;
+    }
+
+    /// Zeros the uninitialized part of the buffer, and returns the
+    /// whole unfilled part.
+    ///
+    /// Only bytes that have never been initialized are zeroed, so calling
+    /// this repeatedly is cheap.
+    pub fn initialize_unfilled(&mut self) -> &mut [u8] {
+        for b in &mut self.buf[self.init..] {
+            *b = MaybeUninit::new(0);
+        }
+        self.init = self.buf.len();
+        let unfilled = &mut self.buf[self.filled..];
+        // SAFETY: The whole buffer was just initialized.
+        unsafe { slice::from_raw_parts_mut(unfilled.as_mut_ptr() as *mut u8, unfilled.len()) }
+    }
+
+    /// Marks the next `n` bytes of the unfilled part as filled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if those bytes have not been initialized.
+    pub fn add_filled(&mut self, n: usize) {
+        assert!(n <= self.init - self.filled, "filled bytes must be initialized");
+        self.filled += n;
+    }
+
+    /// Returns the unfilled part of the buffer, which may be
+    /// uninitialized.
+    ///
+    /// # Safety
+    ///
+    /// The caller must not write uninitialized values into the returned
+    /// slice, since parts of it may already be known to be initialized.
+    pub unsafe fn unfilled_mut(&mut self) -> &mut [MaybeUninit<u8>] {
+        &mut self.buf[self.filled..]
+    }
+
+    /// Marks the next `n` bytes of the unfilled part as filled, after they
+    /// have been written through [`unfilled_mut`](#method.unfilled_mut).
+    ///
+    /// # Safety
+    ///
+    /// The caller must have initialized those `n` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is greater than the number of unfilled bytes.
+    pub unsafe fn assume_filled(&mut self, n: usize) {
+        assert!(n <= self.remaining(), "filled past the end of the buffer");
+        self.filled += n;
+        if self.init < self.filled {
+            self.init = self.filled;
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use std::cmp;
+use std::io::{ErrorKind, Result};
+
+use {Error, IoOptions, KnownEof, ReadAt, ResizeAt, SizeAt, SyncAt, WriteAt, read_opt, write_all_with};
+
+/// A byte order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Endian {
+    /// Least significant byte first.
+    Little,
+    /// Most significant byte first.
+    Big,
+}
+
+impl Endian {
+    /// Returns the byte order of the target.
+    pub fn native() -> Endian {
+        if cfg!(target_endian = "big") {
+            Endian::Big
+        } else {
+            Endian::Little
+        }
+    }
+}
+
+/// The layout of a fixed-size record, listing its integer fields.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RecordSchema {
+    size: usize,
+    swapped: Vec<(usize, usize)>,
+}
+
+impl RecordSchema {
+    /// Creates a schema for records of `size` bytes without fields.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero.
+    pub fn new(size: usize) -> RecordSchema {
+        assert!(size > 0, "record size must be non-zero");
+        RecordSchema {
+            size,
+            swapped: Vec::new(),
+        }
+    }
+
+    /// Adds an integer field of `width` bytes at `offset` from the start
+    /// of the record, stored in `endian` byte order.
+    ///
+    /// Bytes not covered by a field are passed through unchanged.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `width` is not 1, 2, 4, 8 or 16, or if the field does
+    /// not fit in the record.
+    pub fn field(&mut self, offset: usize, width: usize, endian: Endian) -> &mut RecordSchema {
+        assert!(width.is_power_of_two() && width <= 16, "invalid field width");
+        assert!(offset.checked_add(width).is_some_and(|end| end <= self.size),
+                "field does not fit in the record");
+        if endian != Endian::native() && width > 1 {
+            self.swapped.push((offset, width));
+        }
+        self
+    }
+
+    /// Returns the size of a record in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Converts whole records between stored and native byte order.
+    fn swap(&self, records: &mut [u8]) {
+        for record in records.chunks_mut(self.size) {
+            for &(offset, width) in &self.swapped {
+                if let Some(field) = record.get_mut(offset..offset + width) {
+                    field.reverse();
+                }
+            }
+        }
+    }
+
+    /// Converts every field starting before `end` in `records`, which
+    /// must hold whole records.
+    fn swap_before(&self, records: &mut [u8], end: usize) {
+        for (i, record) in records.chunks_mut(self.size).enumerate() {
+            for &(offset, width) in &self.swapped {
+                if i * self.size + offset < end {
+                    record[offset..offset + width].reverse();
+                }
+            }
+        }
+    }
+
+    /// Returns `pos`, or the end of the swapped field it falls inside.
+    fn field_end(&self, pos: usize) -> usize {
+        let start = pos - pos % self.size;
+        self.swapped
+            .iter()
+            .filter(|&&(offset, width)| start + offset < pos && pos < start + offset + width)
+            .map(|&(offset, width)| start + offset + width)
+            .fold(pos, cmp::max)
+    }
+}
+
+/// A view of a sequence of fixed-size records, with the integer fields
+/// of a [`RecordSchema`](struct.RecordSchema.html) in native byte order.
+///
+/// Fields stored in the other byte order are swapped on the fly, in both
+/// directions, so a big-endian on-disk format can be read and written
+/// with native-endian integer accessors on a little-endian host.
+///
+/// Reads and writes may start and end anywhere. The inner value is
+/// accessed in whole records, so writing part of a record reads the
+/// rest of it first. A trailing partial record is read as is, with only
+/// the fields it fully contains swapped. Writes reaching into a field
+/// past the end of the inner value extend it with zeros to the end of
+/// that field, so that swapped fields are always stored whole.
+#[derive(Clone, Debug)]
+pub struct RecordView<T> {
+    inner: T,
+    schema: RecordSchema,
+}
+
+impl<T> RecordView<T> {
+    /// Creates a view of `inner` laid out according to `schema`.
+    pub fn new(inner: T, schema: RecordSchema) -> RecordView<T> {
+        RecordView { inner, schema }
+    }
+
+    /// Returns the schema of the records.
+    pub fn schema(&self) -> &RecordSchema {
+        &self.schema
+    }
+
+    /// Returns a reference to the inner value.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner value.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Consumes the view, returning the inner value.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Returns the record-aligned range covering `len` bytes at `pos`,
+    /// and the offset of `pos` within it.
+    fn span(&self, pos: u64, len: usize) -> Result<(u64, usize, usize)> {
+        let size = self.schema.size as u64;
+        let start = pos - pos % size;
+        let offset = (pos - start) as usize;
+        let span = (offset + len).div_ceil(self.schema.size).checked_mul(self.schema.size);
+        match span {
+            Some(span) if start.checked_add(span as u64).is_some() => Ok((start, offset, span)),
+            _ => Err(Error::OutOfBounds { offset: pos, len: len as u64 }.into()),
+        }
+    }
+}
+
+/// Reads as much of `buf` as possible, stopping early only at the end of
+/// `inner`.
+fn fill<T: ReadAt>(inner: &mut T, pos: u64, buf: &mut [u8], options: Option<&IoOptions>) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match read_opt(inner, pos + filled as u64, &mut buf[filled..], options) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+impl<T: ReadAt> RecordView<T> {
+    /// Reads at `pos`, with `options` if there are any.
+    fn read_with(&mut self, pos: u64, buf: &mut [u8], options: Option<&IoOptions>) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let (start, offset, span) = self.span(pos, buf.len())?;
+        let mut records = vec![0; span];
+        let n = fill(&mut self.inner, start, &mut records, options)?;
+        self.schema.swap(&mut records[..n]);
+        if n <= offset {
+            return Ok(0);
+        }
+        let m = cmp::min(n - offset, buf.len());
+        buf[..m].copy_from_slice(&records[offset..offset + m]);
+        Ok(m)
+    }
+}
+
+impl<T: ReadAt + WriteAt> RecordView<T> {
+    /// Writes at `pos`, with `options` if there are any.
+    fn write_with(&mut self, pos: u64, buf: &[u8], options: Option<&IoOptions>) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let (start, offset, span) = self.span(pos, buf.len())?;
+        let mut records = vec![0; span];
+        let n = if offset == 0 && buf.len() == span {
+            0
+        } else {
+            fill(&mut self.inner, start, &mut records, options)?
+        };
+        // A field cut off at the end of the inner value still holds its
+        // first bytes in stored order, so it is swapped as a whole, with
+        // the missing bytes read as zeros.
+        self.schema.swap_before(&mut records, n);
+        records[offset..offset + buf.len()].copy_from_slice(buf);
+        let end = self.schema.field_end(cmp::max(n, offset + buf.len()));
+        self.schema.swap_before(&mut records, end);
+        match options {
+            Some(options) => write_all_with(&mut self.inner, start, &records[..end], options)?,
+            None => self.inner.write_all_at(start, &records[..end])?,
+        }
+        Ok(buf.len())
+    }
+}
+
+impl<T: ReadAt> ReadAt for RecordView<T> {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        self.read_with(pos, buf, None)
+    }
+
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        self.read_with(pos, buf, Some(options))
+    }
+}
+
+impl<T: ReadAt + WriteAt> WriteAt for RecordView<T> {
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        self.write_with(pos, buf, None)
+    }
+
+    /// The options are also used to read the rest of partially written
+    /// records.
+    fn write_at_with(&mut self, pos: u64, buf: &[u8], options: &IoOptions) -> Result<usize> {
+        self.write_with(pos, buf, Some(options))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<T: KnownEof> KnownEof for RecordView<T> {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        self.inner.remaining_at(pos)
+    }
+
+    #[inline]
+    fn is_eof_at(&mut self, pos: u64) -> Result<bool> {
+        self.inner.is_eof_at(pos)
+    }
+}
+
+impl<T: ResizeAt> ResizeAt for RecordView<T> {
+    #[inline]
+    fn set_len(&mut self, len: u64) -> Result<()> {
+        self.inner.set_len(len)
+    }
+}
+
+impl<T: SizeAt> SizeAt for RecordView<T> {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        self.inner.size()
+    }
+}
+
+impl<T: SyncAt> SyncAt for RecordView<T> {
+    #[inline]
+    fn sync_all(&mut self) -> Result<()> {
+        self.inner.sync_all()
+    }
+
+    #[inline]
+    fn sync_data(&mut self) -> Result<()> {
+        self.inner.sync_data()
+    }
+}
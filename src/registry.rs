@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{Error, ErrorKind, Result};
+use std::sync::{OnceLock, RwLock};
+
+use {ReadAt, WriteAt};
+
+/// A boxed, readable backend as returned by [`open`](fn.open.html).
+pub type BoxReadAt = Box<dyn ReadAt + Send>;
+
+/// A boxed, writable backend as returned by [`open_write`](fn.open_write.html).
+pub type BoxWriteAt = Box<dyn WriteAt + Send>;
+
+type ReadOpener = Box<dyn Fn(&str) -> Result<BoxReadAt> + Send + Sync>;
+type WriteOpener = Box<dyn Fn(&str) -> Result<BoxWriteAt> + Send + Sync>;
+
+/// A table of URI schemes and the functions opening backends for them.
+///
+/// Openers are passed the part of the URI following `scheme:`. The
+/// table returned by [`Registry::with_defaults`](#method.with_defaults)
+/// knows the following schemes:
+///
+/// - `file:` opens a file. The path may be given as `file:path` or
+///   `file:///absolute/path`. Percent-encoding is not decoded. Writable
+///   files are created if missing, but never truncated.
+/// - `mem:` creates an empty in-memory buffer, or a zero-filled one if
+///   a length is given, as in `mem:4096`. Lengths that cannot be
+///   allocated fail with an error of kind `OutOfMemory`.
+///
+/// Schemes for network backends, such as `http:`, `s3:` or `nbd:`, are
+/// not built in and can be registered by downstream crates.
+///
+/// A URI without a scheme, or with a single-letter scheme such as a
+/// Windows drive letter, is treated as a file path.
+#[derive(Default)]
+pub struct Registry {
+    readers: HashMap<String, ReadOpener>,
+    writers: HashMap<String, WriteOpener>,
+}
+
+impl Registry {
+    /// Creates an empty registry.
+    pub fn new() -> Registry {
+        Registry::default()
+    }
+
+    /// Creates a registry with the built-in `file:` and `mem:` schemes.
+    pub fn with_defaults() -> Registry {
+        let mut registry = Registry::new();
+        registry.register_reader("file", |path| Ok(Box::new(File::open(file_path(path)?)?) as BoxReadAt));
+        registry.register_writer("file", |path| {
+            let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(file_path(path)?)?;
+            Ok(Box::new(file) as BoxWriteAt)
+        });
+        registry.register_reader("mem", |len| Ok(Box::new(mem_buffer(len)?) as BoxReadAt));
+        registry.register_writer("mem", |len| Ok(Box::new(mem_buffer(len)?) as BoxWriteAt));
+        registry
+    }
+
+    /// Registers a function opening readable backends for `scheme`,
+    /// replacing any previously registered function.
+    ///
+    /// Schemes are matched case-insensitively.
+    pub fn register_reader<F>(&mut self, scheme: &str, opener: F)
+        where F: Fn(&str) -> Result<BoxReadAt> + Send + Sync + 'static
+    {
+        self.readers.insert(scheme.to_ascii_lowercase(), Box::new(opener));
+    }
+
+    /// Registers a function opening writable backends for `scheme`,
+    /// replacing any previously registered function.
+    ///
+    /// Schemes are matched case-insensitively.
+    pub fn register_writer<F>(&mut self, scheme: &str, opener: F)
+        where F: Fn(&str) -> Result<BoxWriteAt> + Send + Sync + 'static
+    {
+        self.writers.insert(scheme.to_ascii_lowercase(), Box::new(opener));
+    }
+
+    /// Opens a readable backend for `uri`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `Unsupported` if no reader is registered
+    /// for the scheme of `uri`. Otherwise any error from the opener is
+    /// returned.
+    pub fn open(&self, uri: &str) -> Result<BoxReadAt> {
+        let (scheme, rest) = split_uri(uri);
+        match self.readers.get(&scheme) {
+            Some(opener) => opener(rest),
+            None => Err(unsupported(&scheme)),
+        }
+    }
+
+    /// Opens a writable backend for `uri`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `Unsupported` if no writer is registered
+    /// for the scheme of `uri`. Otherwise any error from the opener is
+    /// returned.
+    pub fn open_write(&self, uri: &str) -> Result<BoxWriteAt> {
+        let (scheme, rest) = split_uri(uri);
+        match self.writers.get(&scheme) {
+            Some(opener) => opener(rest),
+            None => Err(unsupported(&scheme)),
+        }
+    }
+}
+
+impl fmt::Debug for Registry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Registry")
+            .field("readers", &self.readers.keys().collect::<Vec<_>>())
+            .field("writers", &self.writers.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+fn global() -> &'static RwLock<Registry> {
+    static GLOBAL: OnceLock<RwLock<Registry>> = OnceLock::new();
+    GLOBAL.get_or_init(|| RwLock::new(Registry::with_defaults()))
+}
+
+/// Opens a readable backend for `uri` using the global registry.
+///
+/// See [`Registry`](struct.Registry.html) for the built-in schemes.
+///
+/// # Errors
+///
+/// Returns an error of kind `Unsupported` if no reader is registered
+/// for the scheme of `uri`. Otherwise any error from the opener is
+/// returned.
+pub fn open(uri: &str) -> Result<BoxReadAt> {
+    global().read().unwrap_or_else(|e| e.into_inner()).open(uri)
+}
+
+/// Opens a writable backend for `uri` using the global registry.
+///
+/// # Errors
+///
+/// Returns an error of kind `Unsupported` if no writer is registered
+/// for the scheme of `uri`. Otherwise any error from the opener is
+/// returned.
+pub fn open_write(uri: &str) -> Result<BoxWriteAt> {
+    global().read().unwrap_or_else(|e| e.into_inner()).open_write(uri)
+}
+
+/// Registers a reader for `scheme` in the global registry.
+pub fn register_reader<F>(scheme: &str, opener: F)
+    where F: Fn(&str) -> Result<BoxReadAt> + Send + Sync + 'static
+{
+    global().write().unwrap_or_else(|e| e.into_inner()).register_reader(scheme, opener)
+}
+
+/// Registers a writer for `scheme` in the global registry.
+pub fn register_writer<F>(scheme: &str, opener: F)
+    where F: Fn(&str) -> Result<BoxWriteAt> + Send + Sync + 'static
+{
+    global().write().unwrap_or_else(|e| e.into_inner()).register_writer(scheme, opener)
+}
+
+fn split_uri(uri: &str) -> (String, &str) {
+    if let Some(i) = uri.find(':') {
+        let scheme = &uri[..i];
+        let valid = scheme.len() > 1 && scheme.starts_with(|c: char| c.is_ascii_alphabetic()) &&
+                    scheme.chars().all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.');
+        if valid {
+            return (scheme.to_ascii_lowercase(), &uri[i + 1..]);
+        }
+    }
+    ("file".to_owned(), uri)
+}
+
+fn file_path(path: &str) -> Result<&str> {
+    match path.strip_prefix("//") {
+        Some(path) if path.starts_with('/') => Ok(path),
+        Some(_) => Err(Error::new(ErrorKind::InvalidInput, "file: URIs with a host are not supported")),
+        None => Ok(path),
+    }
+}
+
+fn mem_buffer(len: &str) -> Result<Vec<u8>> {
+    if len.is_empty() {
+        return Ok(Vec::new());
+    }
+    let len = len.parse()
+        .map_err(|_| Error::new(ErrorKind::InvalidInput, format!("invalid mem: length `{}`", len)))?;
+    // The length comes from the URI, so a failed allocation is reported
+    // instead of aborting.
+    let mut buf = Vec::new();
+    buf.try_reserve_exact(len)
+        .map_err(|_| Error::new(ErrorKind::OutOfMemory, format!("cannot allocate a mem: buffer of {} bytes", len)))?;
+    buf.resize(len, 0);
+    Ok(buf)
+}
+
+fn unsupported(scheme: &str) -> Error {
+    Error::new(ErrorKind::Unsupported, format!("unsupported URI scheme `{}`", scheme))
+}
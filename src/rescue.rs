@@ -0,0 +1,547 @@
+use std::cmp;
+use std::fmt;
+use std::io::{Error, ErrorKind, Result};
+use std::str::FromStr;
+
+use {ErrorPolicy, ReadAt, Salvage, WriteAt};
+
+const DEFAULT_CLUSTER_SIZE: u64 = 64 * 1024;
+
+/// The state of a block in a [`RescueMap`](struct.RescueMap.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BlockStatus {
+    /// Not read yet, written as `?`.
+    NonTried,
+    /// Failed to be read in a large request, written as `*`.
+    NonTrimmed,
+    /// Partly read sector by sector, written as `/`.
+    NonScraped,
+    /// Failed to be read sector by sector, written as `-`.
+    BadSector,
+    /// Read and copied, written as `+`.
+    Finished,
+}
+
+/// The phase of a rescue, as recorded in a
+/// [`RescueMap`](struct.RescueMap.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RescuePhase {
+    /// Reading untried areas in large requests, written as `?`.
+    Copying,
+    /// Reading the edges of failed areas, written as `*`.
+    Trimming,
+    /// Reading failed areas sector by sector, written as `/`.
+    Scraping,
+    /// Retrying bad sectors, written as `-`.
+    Retrying,
+    /// Filling areas of the destination, written as `F`.
+    Filling,
+    /// Generating a map from an existing copy, written as `G`.
+    Generating,
+    /// Done, written as `+`.
+    Finished,
+}
+
+/// A block of a [`RescueMap`](struct.RescueMap.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MapBlock {
+    /// The offset of the block.
+    pub pos: u64,
+    /// The length of the block in bytes.
+    pub size: u64,
+    /// The state of the block.
+    pub status: BlockStatus,
+}
+
+/// The progress of a rescue, in the map file format of GNU ddrescue.
+///
+/// The map divides the rescued range into contiguous blocks, each with a
+/// [`BlockStatus`](enum.BlockStatus.html). It can be parsed from and
+/// formatted to text, so a rescue can be saved and resumed later, by
+/// this crate or by ddrescue itself.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RescueMap {
+    current_pos: u64,
+    phase: RescuePhase,
+    pass: u32,
+    blocks: Vec<MapBlock>,
+}
+
+/// A copy engine reading everything it can from failing media.
+///
+/// Like GNU ddrescue, [`run`](#method.run) makes several passes of
+/// shrinking granularity, recording its progress in a
+/// [`RescueMap`](struct.RescueMap.html):
+///
+/// 1. Untried areas are read in clusters. Clusters that fail to be read
+///    are marked as non-trimmed and skipped, so the bulk of the data is
+///    copied before any time is spent on bad areas.
+/// 2. Non-trimmed and non-scraped areas are read sector by sector with
+///    [`Salvage::read_at_best_effort`](struct.Salvage.html#method.read_at_best_effort),
+///    and sectors that fail are marked as bad.
+/// 3. Bad sectors are retried for the configured number of passes.
+///
+/// Only data that was read is written to the destination; bad areas are
+/// left untouched. Starting from a map saved by an interrupted run
+/// resumes the rescue where it stopped.
+#[derive(Clone, Debug)]
+pub struct Rescue {
+    map: RescueMap,
+    cluster_size: u64,
+    retry_passes: u32,
+}
+
+impl BlockStatus {
+    fn to_char(self) -> char {
+        match self {
+            BlockStatus::NonTried => '?',
+            BlockStatus::NonTrimmed => '*',
+            BlockStatus::NonScraped => '/',
+            BlockStatus::BadSector => '-',
+            BlockStatus::Finished => '+',
+        }
+    }
+
+    fn parse(s: &str) -> Option<BlockStatus> {
+        match s {
+            "?" => Some(BlockStatus::NonTried),
+            "*" => Some(BlockStatus::NonTrimmed),
+            "/" => Some(BlockStatus::NonScraped),
+            "-" => Some(BlockStatus::BadSector),
+            "+" => Some(BlockStatus::Finished),
+            _ => None,
+        }
+    }
+}
+
+impl RescuePhase {
+    fn to_char(self) -> char {
+        match self {
+            RescuePhase::Copying => '?',
+            RescuePhase::Trimming => '*',
+            RescuePhase::Scraping => '/',
+            RescuePhase::Retrying => '-',
+            RescuePhase::Filling => 'F',
+            RescuePhase::Generating => 'G',
+            RescuePhase::Finished => '+',
+        }
+    }
+
+    fn parse(s: &str) -> Option<RescuePhase> {
+        match s {
+            "?" => Some(RescuePhase::Copying),
+            "*" => Some(RescuePhase::Trimming),
+            "/" => Some(RescuePhase::Scraping),
+            "-" => Some(RescuePhase::Retrying),
+            "F" => Some(RescuePhase::Filling),
+            "G" => Some(RescuePhase::Generating),
+            "+" => Some(RescuePhase::Finished),
+            _ => None,
+        }
+    }
+}
+
+impl RescueMap {
+    /// Creates a map of `size` bytes that have not been tried yet.
+    pub fn new(size: u64) -> RescueMap {
+        let mut blocks = Vec::new();
+        if size > 0 {
+            blocks.push(MapBlock {
+                pos: 0,
+                size,
+                status: BlockStatus::NonTried,
+            });
+        }
+        RescueMap {
+            current_pos: 0,
+            phase: RescuePhase::Copying,
+            pass: 1,
+            blocks,
+        }
+    }
+
+    /// Returns the total size of the map in bytes.
+    pub fn size(&self) -> u64 {
+        self.blocks.last().map_or(0, |b| b.pos + b.size)
+    }
+
+    /// Returns the blocks of the map, in ascending order.
+    pub fn blocks(&self) -> &[MapBlock] {
+        &self.blocks
+    }
+
+    /// Returns the number of bytes with the given status.
+    pub fn count(&self, status: BlockStatus) -> u64 {
+        self.blocks.iter().filter(|b| b.status == status).map(|b| b.size).sum()
+    }
+
+    /// Returns the offset the rescue was last working at.
+    pub fn current_pos(&self) -> u64 {
+        self.current_pos
+    }
+
+    /// Returns the phase the rescue was last in.
+    pub fn phase(&self) -> RescuePhase {
+        self.phase
+    }
+
+    /// Returns the pass number within the current phase.
+    pub fn pass(&self) -> u32 {
+        self.pass
+    }
+
+    /// Sets the status of every byte in `pos..pos + size`.
+    ///
+    /// Bytes beyond the end of the map are ignored.
+    pub fn set_status(&mut self, pos: u64, size: u64, status: BlockStatus) {
+        let end = cmp::min(pos.saturating_add(size), self.size());
+        if pos >= end {
+            return;
+        }
+        let mut blocks = Vec::with_capacity(self.blocks.len() + 2);
+        for b in &self.blocks {
+            let b_end = b.pos + b.size;
+            if b_end <= pos || b.pos >= end {
+                push_block(&mut blocks, *b);
+                continue;
+            }
+            if b.pos < pos {
+                push_block(&mut blocks, MapBlock { size: pos - b.pos, ..*b });
+            }
+            let start = cmp::max(b.pos, pos);
+            push_block(&mut blocks, MapBlock {
+                pos: start,
+                size: cmp::min(b_end, end) - start,
+                status,
+            });
+            if b_end > end {
+                push_block(&mut blocks, MapBlock {
+                    pos: end,
+                    size: b_end - end,
+                    status: b.status,
+                });
+            }
+        }
+        self.blocks = blocks;
+    }
+
+    /// Returns the blocks with any of the given statuses, split into
+    /// pieces of at most `max` bytes.
+    fn pieces(&self, statuses: &[BlockStatus], max: u64) -> Vec<(u64, u64)> {
+        let mut pieces = Vec::new();
+        for b in self.blocks.iter().filter(|b| statuses.contains(&b.status)) {
+            let mut pos = b.pos;
+            while pos < b.pos + b.size {
+                let size = cmp::min(max, b.pos + b.size - pos);
+                pieces.push((pos, size));
+                pos += size;
+            }
+        }
+        pieces
+    }
+}
+
+fn push_block(blocks: &mut Vec<MapBlock>, block: MapBlock) {
+    if let Some(last) = blocks.last_mut() {
+        if last.status == block.status {
+            last.size += block.size;
+            return;
+        }
+    }
+    blocks.push(block);
+}
+
+fn parse_number(s: &str) -> Option<u64> {
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16).ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
+fn invalid_map(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Parses a map in the format of GNU ddrescue.
+///
+/// Blocks must be sorted, contiguous and start at offset zero.
+impl FromStr for RescueMap {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<RescueMap> {
+        let mut lines = s.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#'));
+        let status = lines.next().ok_or_else(|| invalid_map("missing status line"))?;
+        let fields: Vec<&str> = status.split_whitespace().collect();
+        if fields.len() < 2 || fields.len() > 3 {
+            return Err(invalid_map("malformed status line"));
+        }
+        let mut map = RescueMap {
+            current_pos: parse_number(fields[0]).ok_or_else(|| invalid_map("invalid current position"))?,
+            phase: RescuePhase::parse(fields[1]).ok_or_else(|| invalid_map("invalid current status"))?,
+            pass: match fields.get(2) {
+                Some(pass) => pass.parse().map_err(|_| invalid_map("invalid current pass"))?,
+                None => 1,
+            },
+            blocks: Vec::new(),
+        };
+        for line in lines {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 3 {
+                return Err(invalid_map("malformed block line"));
+            }
+            let block = MapBlock {
+                pos: parse_number(fields[0]).ok_or_else(|| invalid_map("invalid block position"))?,
+                size: parse_number(fields[1]).ok_or_else(|| invalid_map("invalid block size"))?,
+                status: BlockStatus::parse(fields[2]).ok_or_else(|| invalid_map("invalid block status"))?,
+            };
+            if block.pos != map.size() || block.pos.checked_add(block.size).is_none() {
+                return Err(invalid_map("blocks are not contiguous"));
+            }
+            if block.size > 0 {
+                push_block(&mut map.blocks, block);
+            }
+        }
+        Ok(map)
+    }
+}
+
+/// Formats the map in the format of GNU ddrescue.
+impl fmt::Display for RescueMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "# Rescue map file")?;
+        writeln!(f, "# current_pos  current_status  current_pass")?;
+        writeln!(f, "0x{:08X}     {}               {}", self.current_pos, self.phase.to_char(), self.pass)?;
+        writeln!(f, "#      pos        size  status")?;
+        for b in &self.blocks {
+            writeln!(f, "0x{:08X}  0x{:08X}  {}", b.pos, b.size, b.status.to_char())?;
+        }
+        Ok(())
+    }
+}
+
+impl Rescue {
+    /// Creates an engine continuing the rescue recorded in `map`.
+    pub fn new(map: RescueMap) -> Rescue {
+        Rescue {
+            map,
+            cluster_size: DEFAULT_CLUSTER_SIZE,
+            retry_passes: 0,
+        }
+    }
+
+    /// Sets the size of the requests used to read untried areas. The
+    /// default is 64 KiB.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cluster_size` is zero.
+    pub fn set_cluster_size(&mut self, cluster_size: u64) {
+        assert!(cluster_size > 0, "cluster size must be non-zero");
+        self.cluster_size = cluster_size;
+    }
+
+    /// Sets how many times bad sectors are retried. The default is zero.
+    pub fn set_retry_passes(&mut self, retry_passes: u32) {
+        self.retry_passes = retry_passes;
+    }
+
+    /// Returns the current map.
+    pub fn map(&self) -> &RescueMap {
+        &self.map
+    }
+
+    /// Consumes the engine, returning the current map.
+    pub fn into_map(self) -> RescueMap {
+        self.map
+    }
+
+    /// Copies everything that can be read from `src` to `dst`.
+    ///
+    /// `checkpoint` is called with the map after every request, and can
+    /// be used to save it or to report progress. If it returns an error,
+    /// the rescue stops and returns that error; it can be resumed later
+    /// from the last saved map.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `UnexpectedEof` if an untried area extends
+    /// past the end of `src`, and any error from writing to `dst` or from
+    /// `checkpoint`. Read errors are recorded in the map instead.
+    pub fn run<R, P, W, F>(&mut self, src: &mut Salvage<R, P>, dst: &mut W, mut checkpoint: F) -> Result<()>
+        where R: ReadAt,
+              P: ErrorPolicy,
+              W: WriteAt + ?Sized,
+              F: FnMut(&RescueMap) -> Result<()>
+    {
+        let mut buf = vec![0; self.cluster_size as usize];
+
+        self.enter(RescuePhase::Copying, 1);
+        for (pos, size) in self.map.pieces(&[BlockStatus::NonTried], self.cluster_size) {
+            self.map.current_pos = pos;
+            let buf = &mut buf[..size as usize];
+            let status = match src.read_exact_at(pos, buf) {
+                Ok(()) => {
+                    dst.write_all_at(pos, buf)?;
+                    BlockStatus::Finished
+                }
+                Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => {
+                    return Err(Error::new(ErrorKind::UnexpectedEof, "source ended before the end of the map"));
+                }
+                Err(_) => BlockStatus::NonTrimmed,
+            };
+            self.map.set_status(pos, size, status);
+            checkpoint(&self.map)?;
+        }
+
+        self.enter(RescuePhase::Scraping, 1);
+        self.scrape(src, dst, &mut buf, &[BlockStatus::NonTrimmed, BlockStatus::NonScraped], &mut checkpoint)?;
+
+        for pass in 1..=self.retry_passes {
+            if self.map.count(BlockStatus::BadSector) == 0 {
+                break;
+            }
+            self.enter(RescuePhase::Retrying, pass);
+            self.scrape(src, dst, &mut buf, &[BlockStatus::BadSector], &mut checkpoint)?;
+        }
+
+        self.map.phase = RescuePhase::Finished;
+        self.map.current_pos = self.map.size();
+        checkpoint(&self.map)
+    }
+
+    fn enter(&mut self, phase: RescuePhase, pass: u32) {
+        self.map.phase = phase;
+        self.map.pass = pass;
+    }
+
+    fn scrape<R, P, W, F>(&mut self,
+                          src: &mut Salvage<R, P>,
+                          dst: &mut W,
+                          buf: &mut [u8],
+                          statuses: &[BlockStatus],
+                          checkpoint: &mut F)
+                          -> Result<()>
+        where R: ReadAt,
+              P: ErrorPolicy,
+              W: WriteAt + ?Sized,
+              F: FnMut(&RescueMap) -> Result<()>
+    {
+        for (pos, size) in self.map.pieces(statuses, self.cluster_size) {
+            self.map.current_pos = pos;
+            let buf = &mut buf[..size as usize];
+            let valid = src.read_at_best_effort(pos, buf)?;
+            self.map.set_status(pos, size, BlockStatus::BadSector);
+            for range in &valid {
+                let offset = (range.start - pos) as usize;
+                dst.write_all_at(range.start, &buf[offset..offset + (range.end - range.start) as usize])?;
+                self.map.set_status(range.start, range.end - range.start, BlockStatus::Finished);
+            }
+            checkpoint(&self.map)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A map as written by GNU ddrescue 1.27.
+    const DDRESCUE_MAP: &str = "\
+# Mapfile. Created by GNU ddrescue version 1.27
+# Command line: ddrescue -d /dev/sdb disk.img disk.map
+# Start time:   2023-03-01 10:00:00
+# Current time: 2023-03-01 10:05:00
+# Finished
+# current_pos  current_status  current_pass
+0x00120000     +               1
+#      pos        size  status
+0x00000000  0x00100000  +
+0x00100000  0x00000200  -
+0x00100200  0x0000FE00  +
+0x00110000  0x00010000  *
+0x00120000  0x00008000  /
+0x00128000  0x000D8000  ?
+";
+
+    fn block(pos: u64, size: u64, status: BlockStatus) -> MapBlock {
+        MapBlock { pos, size, status }
+    }
+
+    #[test]
+    fn parse_ddrescue_map() {
+        let map: RescueMap = DDRESCUE_MAP.parse().unwrap();
+        assert_eq!(map.current_pos(), 0x120000);
+        assert_eq!(map.phase(), RescuePhase::Finished);
+        assert_eq!(map.pass(), 1);
+        assert_eq!(map.size(), 0x200000);
+        assert_eq!(map.blocks(), &[
+            block(0, 0x100000, BlockStatus::Finished),
+            block(0x100000, 0x200, BlockStatus::BadSector),
+            block(0x100200, 0xfe00, BlockStatus::Finished),
+            block(0x110000, 0x10000, BlockStatus::NonTrimmed),
+            block(0x120000, 0x8000, BlockStatus::NonScraped),
+            block(0x128000, 0xd8000, BlockStatus::NonTried),
+        ]);
+        assert_eq!(map.count(BlockStatus::BadSector), 0x200);
+    }
+
+    #[test]
+    fn round_trip() {
+        let map: RescueMap = DDRESCUE_MAP.parse().unwrap();
+        let text = map.to_string();
+        assert!(text.contains("0x00100000  0x00000200  -\n"));
+        assert_eq!(text.parse::<RescueMap>().unwrap(), map);
+
+        let mut map = RescueMap::new(1 << 40);
+        map.set_status(4096, 512, BlockStatus::BadSector);
+        assert_eq!(map.to_string().parse::<RescueMap>().unwrap(), map);
+        assert_eq!(RescueMap::new(0).to_string().parse::<RescueMap>().unwrap(), RescueMap::new(0));
+    }
+
+    #[test]
+    fn parse_variants() {
+        // Decimal numbers, no pass, and adjacent blocks of one status.
+        let map: RescueMap = "0 ?\n0 100 +\n100 0 -\n100 50 +\n150 0x10 ?\n".parse().unwrap();
+        assert_eq!(map.pass(), 1);
+        assert_eq!(map.blocks(), &[block(0, 150, BlockStatus::Finished), block(150, 16, BlockStatus::NonTried)]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        for text in [
+            "",
+            "# only comments\n",
+            "0x0\n",
+            "0x0 ? 1 extra\n",
+            "0x0 X\n",
+            "0x0 ? 1\n0x0 0x10\n",
+            "0x0 ? 1\n0x0 0x10 X\n",
+            "0x0 ? 1\n0x10 0x10 +\n",
+            "0x0 ? 1\n0x0 0x10 +\n0x20 0x10 +\n",
+            "0x0 ? 1\n0x0 0xFFFFFFFFFFFFFFFF +\n0xFFFFFFFFFFFFFFFF 0x1 +\n",
+            "zz ? 1\n",
+        ] {
+            let err = text.parse::<RescueMap>().unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn set_status() {
+        let mut map = RescueMap::new(100);
+        map.set_status(10, 20, BlockStatus::Finished);
+        map.set_status(30, 10, BlockStatus::Finished);
+        map.set_status(90, 50, BlockStatus::BadSector);
+        assert_eq!(map.blocks(), &[
+            block(0, 10, BlockStatus::NonTried),
+            block(10, 30, BlockStatus::Finished),
+            block(40, 50, BlockStatus::NonTried),
+            block(90, 10, BlockStatus::BadSector),
+        ]);
+        map.set_status(0, 100, BlockStatus::Finished);
+        assert_eq!(map.blocks(), &[block(0, 100, BlockStatus::Finished)]);
+        assert_eq!(map.size(), 100);
+    }
+}
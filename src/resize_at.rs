@@ -0,0 +1,63 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::Result;
+
+use Error;
+
+/// The `ResizeAt` trait allows for changing the length of a writable
+/// target.
+///
+/// This lets fixed layouts be preallocated up front, or shrunk when data
+/// is dropped, through the same abstraction used for writing.
+pub trait ResizeAt {
+    /// Truncates or extends the target to `len` bytes.
+    ///
+    /// Bytes added by extending the target read as zeros.
+    ///
+    /// # Errors
+    ///
+    /// This method can return any I/O error. An error of kind
+    /// `InvalidInput` is returned if the target cannot hold `len` bytes.
+    fn set_len(&mut self, len: u64) -> Result<()>;
+}
+
+impl<R: ResizeAt + ?Sized> ResizeAt for &mut R {
+    #[inline]
+    fn set_len(&mut self, len: u64) -> Result<()> {
+        (**self).set_len(len)
+    }
+}
+
+impl<R: ResizeAt + ?Sized> ResizeAt for Box<R> {
+    #[inline]
+    fn set_len(&mut self, len: u64) -> Result<()> {
+        (**self).set_len(len)
+    }
+}
+
+impl ResizeAt for Vec<u8> {
+    fn set_len(&mut self, len: u64) -> Result<()> {
+        if len > usize::MAX as u64 {
+            return Err(Error::OutOfBounds { offset: 0, len }.into());
+        }
+        self.resize(len as usize, 0);
+        Ok(())
+    }
+}
+
+impl ResizeAt for VecDeque<u8> {
+    fn set_len(&mut self, len: u64) -> Result<()> {
+        if len > usize::MAX as u64 {
+            return Err(Error::OutOfBounds { offset: 0, len }.into());
+        }
+        self.resize(len as usize, 0);
+        Ok(())
+    }
+}
+
+impl ResizeAt for File {
+    #[inline]
+    fn set_len(&mut self, len: u64) -> Result<()> {
+        File::set_len(self, len)
+    }
+}
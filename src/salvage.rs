@@ -0,0 +1,207 @@
+use std::cmp;
+use std::io::{ErrorKind, Result};
+
+use {DefaultErrorPolicy, Error, ErrorClass, ErrorPolicy, IoOptions, KnownEof, RangeSet, ReadAt, SizeAt};
+
+/// A wrapper for reading from failing media, such as a damaged disk being
+/// imaged for recovery or forensics.
+///
+/// [`read_at_best_effort`](#method.read_at_best_effort) reads as much of
+/// a range as possible instead of failing on the first error. The plain
+/// [`ReadAt`](trait.ReadAt.html) methods are passed through unchanged.
+///
+/// An [`ErrorPolicy`](trait.ErrorPolicy.html) decides which failures are
+/// worth retrying. Sectors failing with a permanent error are given up on
+/// right away.
+#[derive(Clone, Debug)]
+pub struct Salvage<T, P = DefaultErrorPolicy> {
+    inner: T,
+    sector_size: u64,
+    retries: u32,
+    policy: P,
+}
+
+impl<T> Salvage<T> {
+    /// Wraps `inner`, retrying failed reads in units of `sector_size`
+    /// bytes, with the default error policy.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `sector_size` is zero.
+    pub fn new(inner: T, sector_size: u64) -> Salvage<T> {
+        Salvage::with_policy(inner, sector_size, DefaultErrorPolicy)
+    }
+}
+
+impl<T, P> Salvage<T, P> {
+    /// Wraps `inner`, retrying failed reads in units of `sector_size`
+    /// bytes, with errors classified by `policy`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `sector_size` is zero.
+    pub fn with_policy(inner: T, sector_size: u64, policy: P) -> Salvage<T, P> {
+        assert!(sector_size > 0, "sector size must be non-zero");
+        Salvage {
+            inner,
+            sector_size,
+            retries: 0,
+            policy,
+        }
+    }
+
+    /// Returns the sector size in bytes.
+    pub fn sector_size(&self) -> u64 {
+        self.sector_size
+    }
+
+    /// Sets how many more times a failing sector is read before it is
+    /// given up on. The default is zero.
+    pub fn set_retries(&mut self, retries: u32) {
+        self.retries = retries;
+    }
+
+    /// Returns a reference to the inner value.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner value.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Consumes the wrapper, returning the inner value.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: ReadAt, P: ErrorPolicy> Salvage<T, P> {
+    /// Reads `buf.len()` bytes at `pos`, skipping over unreadable sectors.
+    ///
+    /// The range is first read in large requests. Once a request fails,
+    /// the rest of the range is read one sector at a time, retrying each
+    /// failing sector up to the configured number of times unless the
+    /// error is permanent. Sectors that cannot be read, and any bytes past
+    /// the end of the inner source, are filled with zeros.
+    ///
+    /// Returns the absolute offsets of the bytes that were actually read.
+    /// This takes the place of a validity bitmap: it stays small however
+    /// large the range is, the unreadable parts are its
+    /// [`gaps`](struct.RangeSet.html#method.gaps), and
+    /// [`contains_range`](struct.RangeSet.html#method.contains_range)
+    /// tells whether a given sector is valid.
+    ///
+    /// # Errors
+    ///
+    /// Read errors are not returned. An error of kind `InvalidInput` is
+    /// returned if the range does not fit in a `u64`.
+    pub fn read_at_best_effort(&mut self, pos: u64, buf: &mut [u8]) -> Result<RangeSet> {
+        let end = pos.checked_add(buf.len() as u64)
+            .ok_or(Error::OutOfBounds { offset: pos, len: buf.len() as u64 })?;
+        let mut valid = RangeSet::new();
+
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.inner.read_at(pos + filled as u64, &mut buf[filled..]) {
+                Ok(0) => {
+                    zero(&mut buf[filled..]);
+                    valid.insert(pos..pos + filled as u64);
+                    return Ok(valid);
+                }
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(_) => break,
+            }
+        }
+        let mut cur = pos + filled as u64;
+        valid.insert(pos..cur);
+
+        while cur < end {
+            let next = cmp::min((cur / self.sector_size + 1).saturating_mul(self.sector_size), end);
+            let sector = &mut buf[(cur - pos) as usize..(next - pos) as usize];
+            match read_sector(&mut self.inner, &self.policy, cur, sector, self.retries) {
+                Some(n) if n < sector.len() => {
+                    zero(&mut buf[(cur - pos) as usize + n..]);
+                    valid.insert(cur..cur + n as u64);
+                    break;
+                }
+                Some(_) => valid.insert(cur..next),
+                None => zero(sector),
+            }
+            cur = next;
+        }
+        Ok(valid)
+    }
+}
+
+/// Reads a whole sector, returning the number of bytes read before the
+/// end of the source, or `None` if the sector could not be read.
+fn read_sector<T, P>(inner: &mut T, policy: &P, pos: u64, buf: &mut [u8], retries: u32) -> Option<usize>
+    where T: ReadAt,
+          P: ErrorPolicy
+{
+    let mut attempts = 0;
+    loop {
+        let mut filled = 0;
+        let error = loop {
+            if filled == buf.len() {
+                break None;
+            }
+            match inner.read_at(pos + filled as u64, &mut buf[filled..]) {
+                Ok(0) => break None,
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => break Some(e),
+            }
+        };
+        let error = match error {
+            Some(error) => error,
+            None => return Some(filled),
+        };
+        if attempts == retries || policy.classify(&error) == ErrorClass::Permanent {
+            return None;
+        }
+        attempts += 1;
+    }
+}
+
+fn zero(buf: &mut [u8]) {
+    for b in buf {
+        *b = 0;
+    }
+}
+
+impl<T: ReadAt, P> ReadAt for Salvage<T, P> {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        self.inner.read_at(pos, buf)
+    }
+
+    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
+        self.inner.read_exact_at(pos, buf)
+    }
+
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        self.inner.read_at_with(pos, buf, options)
+    }
+}
+
+impl<T: KnownEof, P> KnownEof for Salvage<T, P> {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        self.inner.remaining_at(pos)
+    }
+
+    #[inline]
+    fn is_eof_at(&mut self, pos: u64) -> Result<bool> {
+        self.inner.is_eof_at(pos)
+    }
+}
+
+impl<T: SizeAt, P> SizeAt for Salvage<T, P> {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        self.inner.size()
+    }
+}
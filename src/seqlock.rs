@@ -0,0 +1,244 @@
+use std::cmp;
+use std::hint;
+use std::io::Result;
+use std::sync::atomic::{self, AtomicU8, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use {KnownEof, ReadAt, SizeAt, WriteAt};
+
+#[derive(Debug)]
+struct Shared {
+    seq: AtomicUsize,
+    data: Box<[AtomicU8]>,
+}
+
+/// A fixed-size in-memory backend with a single writer and any number of
+/// concurrent readers, which never take a lock.
+///
+/// The backend itself is the only writer. Readers are obtained with
+/// [`reader`](#method.reader), and can be cloned and sent to other
+/// threads. Each write bumps a sequence counter before and after
+/// changing the data, and a read is retried until it completes without
+/// a write in between, so every read returns data from a single point
+/// in time. Writes never wait for readers.
+///
+/// This suits small, frequently read structures such as telemetry
+/// counters or status blocks. Readers of large ranges may be delayed
+/// for a long time by a busy writer.
+///
+/// Like a slice, the backend does not grow: reads and writes past the
+/// end are shortened.
+#[derive(Debug)]
+pub struct SeqLockMem {
+    shared: Arc<Shared>,
+}
+
+/// A reader of a [`SeqLockMem`](struct.SeqLockMem.html).
+#[derive(Clone, Debug)]
+pub struct SeqLockReader {
+    shared: Arc<Shared>,
+}
+
+impl SeqLockMem {
+    /// Creates a backend of `len` zero bytes.
+    pub fn new(len: usize) -> SeqLockMem {
+        SeqLockMem {
+            shared: Arc::new(Shared {
+                seq: AtomicUsize::new(0),
+                data: (0..len).map(|_| AtomicU8::new(0)).collect(),
+            }),
+        }
+    }
+
+    /// Returns the length of the backend in bytes.
+    pub fn len(&self) -> usize {
+        self.shared.data.len()
+    }
+
+    /// Returns whether the backend is empty.
+    pub fn is_empty(&self) -> bool {
+        self.shared.data.is_empty()
+    }
+
+    /// Returns a new reader of the backend.
+    pub fn reader(&self) -> SeqLockReader {
+        SeqLockReader { shared: self.shared.clone() }
+    }
+}
+
+impl From<Vec<u8>> for SeqLockMem {
+    fn from(data: Vec<u8>) -> SeqLockMem {
+        SeqLockMem {
+            shared: Arc::new(Shared {
+                seq: AtomicUsize::new(0),
+                data: data.into_iter().map(AtomicU8::new).collect(),
+            }),
+        }
+    }
+}
+
+impl SeqLockReader {
+    /// Returns the length of the backend in bytes.
+    pub fn len(&self) -> usize {
+        self.shared.data.len()
+    }
+
+    /// Returns whether the backend is empty.
+    pub fn is_empty(&self) -> bool {
+        self.shared.data.is_empty()
+    }
+}
+
+impl Shared {
+    fn read(&self, pos: u64, buf: &mut [u8]) -> usize {
+        if pos >= self.data.len() as u64 {
+            return 0;
+        }
+        let i = pos as usize;
+        let n = cmp::min(self.data.len() - i, buf.len());
+        loop {
+            let before = self.seq.load(Ordering::Acquire);
+            if before % 2 == 1 {
+                hint::spin_loop();
+                continue;
+            }
+            for (b, src) in buf[..n].iter_mut().zip(&self.data[i..i + n]) {
+                *b = src.load(Ordering::Relaxed);
+            }
+            atomic::fence(Ordering::Acquire);
+            if self.seq.load(Ordering::Relaxed) == before {
+                return n;
+            }
+        }
+    }
+}
+
+impl ReadAt for SeqLockMem {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        Ok(self.shared.read(pos, buf))
+    }
+}
+
+impl ReadAt for SeqLockReader {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        Ok(self.shared.read(pos, buf))
+    }
+}
+
+impl ReadAt for &SeqLockReader {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        Ok(self.shared.read(pos, buf))
+    }
+}
+
+impl KnownEof for SeqLockMem {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        Ok((self.len() as u64).saturating_sub(pos))
+    }
+}
+
+impl KnownEof for SeqLockReader {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        Ok((self.len() as u64).saturating_sub(pos))
+    }
+}
+
+impl SizeAt for SeqLockMem {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        Ok(self.len() as u64)
+    }
+}
+
+impl SizeAt for SeqLockReader {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        Ok(self.len() as u64)
+    }
+}
+
+impl WriteAt for SeqLockMem {
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        let data = &self.shared.data;
+        if pos >= data.len() as u64 || buf.is_empty() {
+            return Ok(0);
+        }
+        let i = pos as usize;
+        let n = cmp::min(data.len() - i, buf.len());
+        let seq = &self.shared.seq;
+        let before = seq.load(Ordering::Relaxed);
+        seq.store(before.wrapping_add(1), Ordering::Relaxed);
+        atomic::fence(Ordering::Release);
+        for (dst, b) in data[i..i + n].iter().zip(&buf[..n]) {
+            dst.store(*b, Ordering::Relaxed);
+        }
+        seq.store(before.wrapping_add(2), Ordering::Release);
+        Ok(n)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn shortened_at_end() {
+        let mut mem = SeqLockMem::new(4);
+        assert_eq!(mem.write_at(2, b"abc").unwrap(), 2);
+        assert_eq!(mem.write_at(4, b"d").unwrap(), 0);
+        let mut reader = mem.reader();
+        let mut buf = [9; 4];
+        assert_eq!(reader.read_at(1, &mut buf).unwrap(), 3);
+        assert_eq!(&buf, b"\0ab\x09");
+        assert_eq!(reader.remaining_at(1).unwrap(), 3);
+    }
+
+    #[test]
+    fn readers_see_whole_writes() {
+        const WRITES: u8 = 200;
+        const ROUNDS: usize = 20;
+        let mut mem = SeqLockMem::new(1024);
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let mut reader = mem.reader();
+                thread::spawn(move || {
+                    let mut buf = vec![0; 1024];
+                    let mut last = (0, 0);
+                    let mut reads = 0u64;
+                    loop {
+                        reader.read_exact_at(0, &mut buf).unwrap();
+                        let (round, value) = (buf[0], buf[1]);
+                        assert!(buf[1..].iter().all(|&b| b == value), "torn read");
+                        assert!((round, value) >= last, "read went back in time");
+                        last = (round, value);
+                        reads += 1;
+                        if last == (ROUNDS as u8, WRITES) {
+                            return reads;
+                        }
+                    }
+                })
+            })
+            .collect();
+        let mut buf = vec![0; 1024];
+        for round in 1..=ROUNDS {
+            for value in 1..=WRITES {
+                buf[0] = round as u8;
+                for b in &mut buf[1..] {
+                    *b = value;
+                }
+                mem.write_all_at(0, &buf).unwrap();
+            }
+        }
+        for reader in readers {
+            assert!(reader.join().unwrap() > 0);
+        }
+    }
+}
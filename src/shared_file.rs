@@ -0,0 +1,210 @@
+use std::fs::File;
+use std::io::{IoSlice, IoSliceMut, Result};
+use std::sync::Arc;
+
+use {IoOptions, KnownEof, ReadAt, ReadBuf, ResizeAt, SizeAt, SyncAt, WriteAt};
+
+/// A handle to an open file that can be cloned and used from several
+/// threads at once.
+///
+/// Reads and writes go through the positional I/O of `&File`, so clones
+/// need neither a lock nor a duplicated operating system handle. The file
+/// is closed when the last clone is dropped.
+#[derive(Clone, Debug)]
+pub struct SharedFile {
+    inner: Arc<File>,
+}
+
+impl SharedFile {
+    /// Creates a shared handle to `file`.
+    pub fn new(file: File) -> SharedFile {
+        SharedFile { inner: Arc::new(file) }
+    }
+
+    /// Returns a reference to the file.
+    pub fn get_ref(&self) -> &File {
+        &self.inner
+    }
+
+    /// Returns the file if this is the only clone of the handle, or the
+    /// handle otherwise.
+    pub fn try_into_inner(self) -> ::std::result::Result<File, SharedFile> {
+        Arc::try_unwrap(self.inner).map_err(|inner| SharedFile { inner })
+    }
+}
+
+impl From<File> for SharedFile {
+    fn from(file: File) -> SharedFile {
+        SharedFile::new(file)
+    }
+}
+
+impl From<Arc<File>> for SharedFile {
+    fn from(inner: Arc<File>) -> SharedFile {
+        SharedFile { inner }
+    }
+}
+
+impl ReadAt for Arc<File> {
+    #[inline]
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        (&**self).read_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        (&**self).read_at_with(pos, buf, options)
+    }
+
+    #[inline]
+    fn read_vectored_at(&mut self, pos: u64, bufs: &mut [IoSliceMut]) -> Result<usize> {
+        (&**self).read_vectored_at(pos, bufs)
+    }
+
+    #[inline]
+    fn read_buf_at(&mut self, pos: u64, buf: &mut ReadBuf) -> Result<()> {
+        (&**self).read_buf_at(pos, buf)
+    }
+}
+
+impl WriteAt for Arc<File> {
+    #[inline]
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        (&**self).write_at(pos, buf)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    #[inline]
+    fn write_at_with(&mut self, pos: u64, buf: &[u8], options: &IoOptions) -> Result<usize> {
+        (&**self).write_at_with(pos, buf, options)
+    }
+
+    #[inline]
+    fn write_vectored_at(&mut self, pos: u64, bufs: &[IoSlice]) -> Result<usize> {
+        (&**self).write_vectored_at(pos, bufs)
+    }
+
+    #[inline]
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        (&**self).write_zeroes_at(pos, len)
+    }
+}
+
+impl KnownEof for Arc<File> {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        (&**self).remaining_at(pos)
+    }
+}
+
+impl SizeAt for Arc<File> {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        (**self).size()
+    }
+}
+
+impl ResizeAt for Arc<File> {
+    #[inline]
+    fn set_len(&mut self, len: u64) -> Result<()> {
+        File::set_len(self, len)
+    }
+}
+
+impl SyncAt for Arc<File> {
+    #[inline]
+    fn sync_all(&mut self) -> Result<()> {
+        File::sync_all(self)
+    }
+
+    #[inline]
+    fn sync_data(&mut self) -> Result<()> {
+        File::sync_data(self)
+    }
+}
+
+impl ReadAt for SharedFile {
+    #[inline]
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        self.inner.read_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        self.inner.read_at_with(pos, buf, options)
+    }
+
+    #[inline]
+    fn read_vectored_at(&mut self, pos: u64, bufs: &mut [IoSliceMut]) -> Result<usize> {
+        self.inner.read_vectored_at(pos, bufs)
+    }
+
+    #[inline]
+    fn read_buf_at(&mut self, pos: u64, buf: &mut ReadBuf) -> Result<()> {
+        self.inner.read_buf_at(pos, buf)
+    }
+}
+
+impl WriteAt for SharedFile {
+    #[inline]
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        self.inner.write_at(pos, buf)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    #[inline]
+    fn write_at_with(&mut self, pos: u64, buf: &[u8], options: &IoOptions) -> Result<usize> {
+        self.inner.write_at_with(pos, buf, options)
+    }
+
+    #[inline]
+    fn write_vectored_at(&mut self, pos: u64, bufs: &[IoSlice]) -> Result<usize> {
+        self.inner.write_vectored_at(pos, bufs)
+    }
+
+    #[inline]
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        self.inner.write_zeroes_at(pos, len)
+    }
+}
+
+impl KnownEof for SharedFile {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        self.inner.remaining_at(pos)
+    }
+}
+
+impl SizeAt for SharedFile {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        self.inner.size()
+    }
+}
+
+impl ResizeAt for SharedFile {
+    #[inline]
+    fn set_len(&mut self, len: u64) -> Result<()> {
+        self.inner.set_len(len)
+    }
+}
+
+impl SyncAt for SharedFile {
+    #[inline]
+    fn sync_all(&mut self) -> Result<()> {
+        self.inner.sync_all()
+    }
+
+    #[inline]
+    fn sync_data(&mut self) -> Result<()> {
+        self.inner.sync_data()
+    }
+}
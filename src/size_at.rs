@@ -0,0 +1,106 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{Empty, Result};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use SliceAt;
+
+/// The `SizeAt` trait allows for querying the total length of a source.
+///
+/// Algorithms such as a binary search in an on-disk index, or reading
+/// a footer at the end of a file, need to know where the source ends.
+pub trait SizeAt {
+    /// Returns the length of the source in bytes.
+    ///
+    /// # Errors
+    ///
+    /// This method can return any I/O error.
+    fn size(&self) -> Result<u64>;
+}
+
+impl<S: SizeAt + ?Sized> SizeAt for &S {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        (**self).size()
+    }
+}
+
+impl<S: SizeAt + ?Sized> SizeAt for &mut S {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        (**self).size()
+    }
+}
+
+impl<S: SizeAt + ?Sized> SizeAt for Box<S> {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        (**self).size()
+    }
+}
+
+impl SizeAt for [u8] {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        Ok(self.len() as u64)
+    }
+}
+
+impl SizeAt for Vec<u8> {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        Ok(self.len() as u64)
+    }
+}
+
+impl SizeAt for VecDeque<u8> {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        Ok(self.len() as u64)
+    }
+}
+
+impl SizeAt for Arc<[u8]> {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        Ok(self.len() as u64)
+    }
+}
+
+impl SizeAt for Rc<[u8]> {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        Ok(self.len() as u64)
+    }
+}
+
+impl SizeAt for Cow<'_, [u8]> {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        Ok(self.len() as u64)
+    }
+}
+
+/// Uses the current length of the file from its metadata.
+impl SizeAt for File {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+}
+
+impl<T: AsRef<[u8]>> SizeAt for SliceAt<T> {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        Ok(self.0.as_ref().len() as u64)
+    }
+}
+
+impl SizeAt for Empty {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        Ok(0)
+    }
+}
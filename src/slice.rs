@@ -0,0 +1,152 @@
+use std::io::{IoSlice, Result};
+
+use {Error, IoOptions, KnownEof, PunchHole, ReadAt, SizeAt, SyncAt, TakeAt, WriteAt};
+
+/// A read-only window of a source, such as a partition inside a disk
+/// image.
+///
+/// This is the same adapter as [`TakeAt`](struct.TakeAt.html); see
+/// [`SliceMut`](struct.SliceMut.html) for a window that can be written.
+pub type Slice<R> = TakeAt<R>;
+
+/// An adapter which exposes a window of a target for reading and writing,
+/// such as a partition inside a disk image.
+///
+/// Positions are relative to the start of the window. Reads are clamped
+/// at its end like for [`Slice`](type.Slice.html), while writes that do
+/// not fit in the window fail as a whole with
+/// [`Error::OutOfBounds`](enum.Error.html), so the rest of the target
+/// cannot be reached through it.
+#[derive(Clone, Debug)]
+pub struct SliceMut<T> {
+    window: TakeAt<T>,
+}
+
+impl<T> SliceMut<T> {
+    /// Creates a window of `len` bytes at `offset` of `inner`.
+    ///
+    /// A window reaching past the largest offset is shortened to end
+    /// there.
+    pub fn new(inner: T, offset: u64, len: u64) -> SliceMut<T> {
+        SliceMut { window: TakeAt::new(inner, offset, len) }
+    }
+
+    /// Returns the offset of the window in the target.
+    pub fn offset(&self) -> u64 {
+        self.window.offset()
+    }
+
+    /// Returns the length of the window in bytes.
+    pub fn len(&self) -> u64 {
+        self.window.len()
+    }
+
+    /// Returns whether the window is empty.
+    pub fn is_empty(&self) -> bool {
+        self.window.is_empty()
+    }
+
+    /// Returns a reference to the target.
+    pub fn get_ref(&self) -> &T {
+        self.window.get_ref()
+    }
+
+    /// Returns a mutable reference to the target.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.window.get_mut()
+    }
+
+    /// Consumes the adapter, returning the target.
+    pub fn into_inner(self) -> T {
+        self.window.into_inner()
+    }
+
+    /// Returns the position in the target of `len` bytes at `pos`, if they
+    /// fit in the window.
+    fn check(&self, pos: u64, len: u64) -> Result<u64> {
+        match pos.checked_add(len) {
+            Some(end) if end <= self.len() => Ok(self.offset() + pos),
+            _ => Err(Error::OutOfBounds { offset: pos, len }.into()),
+        }
+    }
+}
+
+/// Reads are served by the read-only window, so they behave exactly like
+/// those of [`Slice`](type.Slice.html).
+impl<T: ReadAt> ReadAt for SliceMut<T> {
+    #[inline]
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        self.window.read_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        self.window.read_at_with(pos, buf, options)
+    }
+}
+
+impl<T: KnownEof> KnownEof for SliceMut<T> {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        self.window.remaining_at(pos)
+    }
+}
+
+impl<T: SizeAt> SizeAt for SliceMut<T> {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        self.window.size()
+    }
+}
+
+impl<T: WriteAt> WriteAt for SliceMut<T> {
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        let pos = self.check(pos, buf.len() as u64)?;
+        self.get_mut().write_at(pos, buf)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        self.get_mut().flush()
+    }
+
+    fn write_all_at(&mut self, pos: u64, buf: &[u8]) -> Result<()> {
+        let pos = self.check(pos, buf.len() as u64)?;
+        self.get_mut().write_all_at(pos, buf)
+    }
+
+    fn write_at_with(&mut self, pos: u64, buf: &[u8], options: &IoOptions) -> Result<usize> {
+        let pos = self.check(pos, buf.len() as u64)?;
+        self.get_mut().write_at_with(pos, buf, options)
+    }
+
+    fn write_vectored_at(&mut self, pos: u64, bufs: &[IoSlice]) -> Result<usize> {
+        let len = bufs.iter().map(|b| b.len() as u64).sum();
+        let pos = self.check(pos, len)?;
+        self.get_mut().write_vectored_at(pos, bufs)
+    }
+
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        let pos = self.check(pos, len)?;
+        self.get_mut().write_zeroes_at(pos, len)
+    }
+}
+
+impl<T: PunchHole> PunchHole for SliceMut<T> {
+    fn punch_hole(&mut self, pos: u64, len: u64) -> Result<()> {
+        let pos = self.check(pos, len)?;
+        self.get_mut().punch_hole(pos, len)
+    }
+}
+
+impl<T: SyncAt> SyncAt for SliceMut<T> {
+    #[inline]
+    fn sync_all(&mut self) -> Result<()> {
+        self.get_mut().sync_all()
+    }
+
+    #[inline]
+    fn sync_data(&mut self) -> Result<()> {
+        self.get_mut().sync_data()
+    }
+}
@@ -0,0 +1,57 @@
+use std::io::Result;
+
+use {ReadAt, ReadBuf, WriteAt};
+
+/// A struct for reading from and writing to any byte container.
+///
+/// `ReadAt` is implemented for wrapped `AsRef<[u8]>` values and
+/// `WriteAt` for wrapped `AsMut<[u8]>` values, so containers such as
+/// arrays, memory-mapped regions and aligned buffers can be used without
+/// implementations of their own. Writes cannot grow the container, and
+/// fail with an error of kind `WriteZero` past its end, like for `[u8]`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SliceAt<T>(pub T);
+
+impl<T: AsRef<[u8]>> ReadAt for SliceAt<T> {
+    #[inline]
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        self.0.as_ref().read_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
+        self.0.as_ref().read_exact_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_to_end_at(&mut self, pos: u64, buf: &mut Vec<u8>) -> Result<usize> {
+        self.0.as_ref().read_to_end_at(pos, buf)
+    }
+
+    #[inline]
+    fn read_buf_at(&mut self, pos: u64, buf: &mut ReadBuf) -> Result<()> {
+        self.0.as_ref().read_buf_at(pos, buf)
+    }
+}
+
+impl<T: AsMut<[u8]>> WriteAt for SliceAt<T> {
+    #[inline]
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        self.0.as_mut().write_at(pos, buf)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    #[inline]
+    fn write_all_at(&mut self, pos: u64, buf: &[u8]) -> Result<()> {
+        self.0.as_mut().write_all_at(pos, buf)
+    }
+
+    #[inline]
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        self.0.as_mut().write_zeroes_at(pos, len)
+    }
+}
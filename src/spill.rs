@@ -0,0 +1,264 @@
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Result};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use {Advice, Advise, KnownEof, MemoryBudget, MemoryReservation, ReadAt, ResizeAt, SizeAt, SyncAt, WriteAt};
+
+#[derive(Debug)]
+enum Storage {
+    Memory(Vec<u8>),
+    File(File, Option<PathBuf>),
+}
+
+/// A growable buffer that is kept in memory up to a threshold, and moved
+/// to a temporary file once it grows beyond it, or beyond what its
+/// [`MemoryBudget`](struct.MemoryBudget.html) has available.
+///
+/// Reads past the end of the buffer return `Ok(0)`, and writes past the
+/// end extend it, filling any gap with zeros. The temporary file is
+/// removed when the buffer is dropped; on Unix it is unlinked right away,
+/// so it is also cleaned up if the process terminates abnormally.
+#[derive(Debug)]
+pub struct SpillBuf {
+    threshold: u64,
+    dir: Option<PathBuf>,
+    len: u64,
+    storage: Storage,
+    reservation: Option<MemoryReservation>,
+}
+
+impl SpillBuf {
+    /// Creates an empty buffer, which spills to a file in the system's
+    /// temporary directory once it grows beyond `threshold` bytes.
+    pub fn new(threshold: u64) -> SpillBuf {
+        SpillBuf {
+            threshold,
+            dir: None,
+            len: 0,
+            storage: Storage::Memory(Vec::new()),
+            reservation: None,
+        }
+    }
+
+    /// Creates an empty buffer, which spills to a file in `dir` once it
+    /// grows beyond `threshold` bytes.
+    pub fn with_dir<P: Into<PathBuf>>(threshold: u64, dir: P) -> SpillBuf {
+        SpillBuf {
+            threshold,
+            dir: Some(dir.into()),
+            len: 0,
+            storage: Storage::Memory(Vec::new()),
+            reservation: None,
+        }
+    }
+
+    /// Returns the length of the buffer in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns whether the buffer is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns whether the buffer has been moved to a temporary file.
+    pub fn is_spilled(&self) -> bool {
+        match self.storage {
+            Storage::Memory(_) => false,
+            Storage::File(..) => true,
+        }
+    }
+
+    /// Draws the memory used by the buffer from `budget`.
+    ///
+    /// The buffer is also moved to a temporary file before it grows
+    /// beyond what `budget` has available, even if it is below the
+    /// threshold.
+    ///
+    /// # Errors
+    ///
+    /// If the buffer does not fit in `budget`, it is moved to a temporary
+    /// file right away, and any error from creating the file is returned.
+    pub fn set_budget(&mut self, budget: &MemoryBudget) -> Result<()> {
+        self.reservation = Some(budget.reservation());
+        if !self.is_spilled() && !self.reserve(self.len) {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    fn reserve(&mut self, len: u64) -> bool {
+        match self.reservation {
+            Some(ref mut reservation) => len <= reservation.len() || reservation.try_resize(len),
+            None => true,
+        }
+    }
+
+    /// Moves the buffer to a temporary file unless it can grow to `end`
+    /// bytes in memory.
+    fn make_room(&mut self, end: u64) -> Result<()> {
+        if self.is_spilled() || end <= self.threshold && self.reserve(end) {
+            return Ok(());
+        }
+        self.spill()
+    }
+
+    fn spill(&mut self) -> Result<()> {
+        let dir = self.dir.clone().unwrap_or_else(env::temp_dir);
+        let (mut file, path) = temp_file(dir)?;
+        if let Storage::Memory(ref data) = self.storage {
+            file.write_all_at(0, data)?;
+        }
+        self.storage = Storage::File(file, path);
+        if let Some(ref mut reservation) = self.reservation {
+            reservation.try_resize(0);
+        }
+        Ok(())
+    }
+}
+
+impl ReadAt for SpillBuf {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        if pos >= self.len {
+            return Ok(0);
+        }
+        let n = (self.len - pos).min(buf.len() as u64) as usize;
+        match self.storage {
+            Storage::Memory(ref data) => (&data[..]).read_at(pos, &mut buf[..n]),
+            Storage::File(ref mut file, _) => file.read_at(pos, &mut buf[..n]),
+        }
+    }
+}
+
+impl KnownEof for SpillBuf {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        Ok(self.len.saturating_sub(pos))
+    }
+}
+
+impl SizeAt for SpillBuf {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        Ok(self.len)
+    }
+}
+
+impl WriteAt for SpillBuf {
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let end = pos.saturating_add(buf.len() as u64);
+        self.make_room(end)?;
+        let n = match self.storage {
+            Storage::Memory(ref mut data) => data.write_at(pos, buf)?,
+            Storage::File(ref mut file, _) => file.write_at(pos, buf)?,
+        };
+        self.len = self.len.max(pos + n as u64);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match self.storage {
+            Storage::Memory(_) => Ok(()),
+            Storage::File(ref mut file, _) => WriteAt::flush(file),
+        }
+    }
+
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        if len == 0 {
+            return Ok(());
+        }
+        let end = pos.saturating_add(len);
+        self.make_room(end)?;
+        match self.storage {
+            Storage::Memory(ref mut data) => data.write_zeroes_at(pos, len)?,
+            Storage::File(ref mut file, _) => file.write_zeroes_at(pos, len)?,
+        }
+        self.len = self.len.max(end);
+        Ok(())
+    }
+}
+
+/// Extending the buffer beyond the threshold moves it to a temporary
+/// file. Shrinking it does not move it back to memory.
+impl ResizeAt for SpillBuf {
+    fn set_len(&mut self, len: u64) -> Result<()> {
+        self.make_room(len)?;
+        match self.storage {
+            Storage::Memory(ref mut data) => ResizeAt::set_len(data, len)?,
+            Storage::File(ref mut file, _) => file.set_len(len)?,
+        }
+        self.len = len;
+        Ok(())
+    }
+}
+
+/// Syncs the temporary file once the buffer has spilled.
+impl SyncAt for SpillBuf {
+    fn sync_all(&mut self) -> Result<()> {
+        match self.storage {
+            Storage::Memory(_) => Ok(()),
+            Storage::File(ref mut file, _) => file.sync_all(),
+        }
+    }
+
+    fn sync_data(&mut self) -> Result<()> {
+        match self.storage {
+            Storage::Memory(_) => Ok(()),
+            Storage::File(ref mut file, _) => file.sync_data(),
+        }
+    }
+}
+
+/// Passes the advice on to the temporary file once the buffer has
+/// spilled.
+impl Advise for SpillBuf {
+    fn advise(&mut self, pos: u64, len: u64, advice: Advice) -> Result<()> {
+        match self.storage {
+            Storage::Memory(_) => Ok(()),
+            Storage::File(ref mut file, _) => file.advise(pos, len, advice),
+        }
+    }
+}
+
+impl Drop for SpillBuf {
+    fn drop(&mut self) {
+        if let Storage::File(_, Some(ref path)) = self.storage {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Creates a new file with a unique name in `dir`.
+///
+/// On Unix the file is unlinked immediately, and no path is returned.
+fn temp_file(dir: PathBuf) -> Result<(File, Option<PathBuf>)> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    loop {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+        let name = format!(".ioat-spill-{}-{}-{}",
+                           process::id(),
+                           COUNTER.fetch_add(1, Ordering::Relaxed),
+                           nanos);
+        let path = dir.join(name);
+        match OpenOptions::new().read(true).write(true).create_new(true).open(&path) {
+            Ok(file) => {
+                if cfg!(unix) {
+                    fs::remove_file(&path)?;
+                    return Ok((file, None));
+                }
+                return Ok((file, Some(path)));
+            }
+            Err(ref e) if e.kind() == ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use std::cmp;
+use std::io::Result;
+
+use {IoOptions, KnownEof, ReadAt, ResizeAt, SizeAt, SyncAt, WriteAt};
+
+/// A wrapper splitting reads and writes into requests the inner value
+/// accepts.
+///
+/// Requests are cut to at most a maximum size, and optionally so that
+/// none crosses a multiple of a boundary. This suits backends with hard
+/// per-request limits, such as NBD servers, some USB storage bridges and
+/// remote protocols.
+///
+/// A short or failed piece ends the request early, as described for
+/// [split requests](trait.ReadAt.html#split-requests).
+#[derive(Clone, Debug)]
+pub struct Split<T> {
+    inner: T,
+    max_size: usize,
+    boundary: Option<u64>,
+}
+
+impl<T> Split<T> {
+    /// Wraps `inner`, issuing requests of at most `max_size` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_size` is zero.
+    pub fn new(inner: T, max_size: usize) -> Split<T> {
+        assert!(max_size > 0, "maximum request size must be non-zero");
+        Split {
+            inner,
+            max_size,
+            boundary: None,
+        }
+    }
+
+    /// Also splits requests so that none crosses a multiple of
+    /// `boundary` bytes, or stops doing so if `None`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `boundary` is zero.
+    pub fn set_boundary(&mut self, boundary: Option<u64>) {
+        assert!(boundary != Some(0), "boundary must be non-zero");
+        self.boundary = boundary;
+    }
+
+    /// Returns a reference to the inner value.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner value.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Consumes the wrapper, returning the inner value.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Returns the length of the piece starting at `pos`, for a request
+    /// with `len` bytes left.
+    fn piece(&self, pos: u64, len: usize) -> usize {
+        let mut n = cmp::min(len, self.max_size);
+        if let Some(boundary) = self.boundary {
+            let left = boundary - pos % boundary;
+            n = cmp::min(n as u64, left) as usize;
+        }
+        n
+    }
+
+    fn each<F>(&mut self, pos: u64, len: usize, mut f: F) -> Result<usize>
+        where F: FnMut(&mut T, u64, usize, usize) -> Result<usize>
+    {
+        let mut done = 0;
+        while done < len {
+            let cur = pos + done as u64;
+            let n = self.piece(cur, len - done);
+            match f(&mut self.inner, cur, done, n) {
+                Ok(m) => {
+                    done += m;
+                    if m < n {
+                        break;
+                    }
+                }
+                Err(_) if done > 0 => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(done)
+    }
+}
+
+impl<T: ReadAt> ReadAt for Split<T> {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        self.each(pos, buf.len(), |inner, pos, i, n| inner.read_at(pos, &mut buf[i..i + n]))
+    }
+
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        self.each(pos, buf.len(), |inner, pos, i, n| inner.read_at_with(pos, &mut buf[i..i + n], options))
+    }
+}
+
+impl<T: WriteAt> WriteAt for Split<T> {
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        self.each(pos, buf.len(), |inner, pos, i, n| inner.write_at(pos, &buf[i..i + n]))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    fn write_at_with(&mut self, pos: u64, buf: &[u8], options: &IoOptions) -> Result<usize> {
+        self.each(pos, buf.len(), |inner, pos, i, n| inner.write_at_with(pos, &buf[i..i + n], options))
+    }
+}
+
+impl<T: KnownEof> KnownEof for Split<T> {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        self.inner.remaining_at(pos)
+    }
+
+    #[inline]
+    fn is_eof_at(&mut self, pos: u64) -> Result<bool> {
+        self.inner.is_eof_at(pos)
+    }
+}
+
+impl<T: ResizeAt> ResizeAt for Split<T> {
+    #[inline]
+    fn set_len(&mut self, len: u64) -> Result<()> {
+        self.inner.set_len(len)
+    }
+}
+
+impl<T: SizeAt> SizeAt for Split<T> {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        self.inner.size()
+    }
+}
+
+impl<T: SyncAt> SyncAt for Split<T> {
+    #[inline]
+    fn sync_all(&mut self) -> Result<()> {
+        self.inner.sync_all()
+    }
+
+    #[inline]
+    fn sync_data(&mut self) -> Result<()> {
+        self.inner.sync_data()
+    }
+}
@@ -0,0 +1,105 @@
+use std::io::{ErrorKind, Read, Result};
+
+use {KnownEof, ReadAt, SpillBuf, WriteAt};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A positional source over a sequential reader, such as a pipe or a
+/// network stream.
+///
+/// Data is read from the reader only as far as it is needed, and spooled
+/// into a [`SpillBuf`](struct.SpillBuf.html), so it can be read again at
+/// any offset. Reads past the spooled data block on the reader until
+/// enough has arrived, or until it ends. Once the reader has ended, reads
+/// past the end return `Ok(0)`.
+#[derive(Debug)]
+pub struct Spooled<R> {
+    reader: R,
+    spool: SpillBuf,
+    complete: bool,
+    buf: Vec<u8>,
+}
+
+impl<R: Read> Spooled<R> {
+    /// Creates a source spooling `reader` in memory up to `threshold`
+    /// bytes, and to a temporary file beyond that.
+    pub fn new(reader: R, threshold: u64) -> Spooled<R> {
+        Spooled::with_spool(reader, SpillBuf::new(threshold))
+    }
+
+    /// Creates a source spooling `reader` into `spool`.
+    ///
+    /// Any data already in `spool` is taken to have been read from the
+    /// reader, and is returned before what the reader yields.
+    pub fn with_spool(reader: R, spool: SpillBuf) -> Spooled<R> {
+        Spooled {
+            reader,
+            spool,
+            complete: false,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Returns the number of bytes spooled so far.
+    pub fn spooled(&self) -> u64 {
+        self.spool.len()
+    }
+
+    /// Returns whether the reader has ended, so every byte has been
+    /// spooled.
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// Reads from the reader until at least `end` bytes are spooled, or it
+    /// ends, and returns the number of bytes spooled.
+    pub fn spool_to(&mut self, end: u64) -> Result<u64> {
+        if self.buf.is_empty() && !self.complete && self.spool.len() < end {
+            self.buf = vec![0; CHUNK_SIZE];
+        }
+        while !self.complete && self.spool.len() < end {
+            match self.reader.read(&mut self.buf) {
+                Ok(0) => self.complete = true,
+                Ok(n) => {
+                    let len = self.spool.len();
+                    self.spool.write_all_at(len, &self.buf[..n])?;
+                }
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(self.spool.len())
+    }
+
+    /// Reads the reader to its end, and returns the total number of bytes.
+    pub fn spool_to_end(&mut self) -> Result<u64> {
+        self.spool_to(u64::MAX)
+    }
+
+    /// Returns the spooled data.
+    pub fn spool(&self) -> &SpillBuf {
+        &self.spool
+    }
+
+    /// Consumes the source, returning the reader and the spooled data.
+    pub fn into_parts(self) -> (R, SpillBuf) {
+        (self.reader, self.spool)
+    }
+}
+
+impl<R: Read> ReadAt for Spooled<R> {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.spool_to(pos.saturating_add(buf.len() as u64))?;
+        self.spool.read_at(pos, buf)
+    }
+}
+
+/// Finding the remaining length reads the reader to its end.
+impl<R: Read> KnownEof for Spooled<R> {
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        Ok(self.spool_to_end()?.saturating_sub(pos))
+    }
+}
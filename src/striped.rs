@@ -0,0 +1,170 @@
+use std::cmp;
+use std::io::Result;
+
+use {Error, IoOptions, KnownEof, ReadAt, SizeAt, SyncAt, WriteAt};
+
+/// A backend spreading fixed-size stripes round-robin across several
+/// inner backends, like RAID 0.
+///
+/// Stripe `i` is stored in backend `i % n`, at stripe `i / n` of that
+/// backend. A request is cut at every stripe boundary it crosses and
+/// visits the backends in turn, so large sequential requests draw on the
+/// bandwidth of all of them, and one backend failing partway through
+/// ends the [split request](trait.ReadAt.html#split-requests) there.
+/// Losing any backend loses the data of all of them.
+#[derive(Clone, Debug)]
+pub struct Striped<T> {
+    backends: Vec<T>,
+    stripe_size: u64,
+}
+
+impl<T> Striped<T> {
+    /// Creates a backend striping across `backends` in stripes of
+    /// `stripe_size` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `backends` is empty or `stripe_size` is zero.
+    pub fn new(backends: Vec<T>, stripe_size: u64) -> Striped<T> {
+        assert!(!backends.is_empty(), "at least one backend is required");
+        assert!(stripe_size > 0, "stripe size must be non-zero");
+        Striped { backends, stripe_size }
+    }
+
+    /// Returns the stripe size in bytes.
+    pub fn stripe_size(&self) -> u64 {
+        self.stripe_size
+    }
+
+    /// Returns the inner backends.
+    pub fn backends(&self) -> &[T] {
+        &self.backends
+    }
+
+    /// Returns a mutable reference to the inner backends.
+    pub fn backends_mut(&mut self) -> &mut [T] {
+        &mut self.backends
+    }
+
+    /// Consumes the wrapper, returning the inner backends.
+    pub fn into_inner(self) -> Vec<T> {
+        self.backends
+    }
+
+    /// Returns the backend holding `pos`, the position in it, and the
+    /// number of bytes left in the stripe.
+    fn locate(&self, pos: u64) -> (usize, u64, u64) {
+        let n = self.backends.len() as u64;
+        let stripe = pos / self.stripe_size;
+        let offset = pos % self.stripe_size;
+        let inner = (stripe / n) * self.stripe_size + offset;
+        ((stripe % n) as usize, inner, self.stripe_size - offset)
+    }
+
+    fn each<F>(&mut self, pos: u64, len: usize, mut f: F) -> Result<usize>
+        where F: FnMut(&mut T, u64, usize, usize) -> Result<usize>
+    {
+        let len = cmp::min(len as u64, u64::MAX - pos) as usize;
+        let mut done = 0;
+        while done < len {
+            let (i, inner, left) = self.locate(pos + done as u64);
+            let n = cmp::min((len - done) as u64, left) as usize;
+            match f(&mut self.backends[i], inner, done, n) {
+                Ok(m) => {
+                    done += m;
+                    if m < n {
+                        break;
+                    }
+                }
+                Err(_) if done > 0 => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(done)
+    }
+
+    /// Returns the position just past the last byte a backend of `size`
+    /// bytes at `index` holds.
+    fn end_of(&self, index: usize, size: u64) -> u64 {
+        if size == 0 {
+            return 0;
+        }
+        let n = self.backends.len() as u64;
+        let last = (size - 1) / self.stripe_size;
+        let offset = (size - 1) % self.stripe_size;
+        (last * n + index as u64) * self.stripe_size + offset + 1
+    }
+}
+
+impl<T: ReadAt> ReadAt for Striped<T> {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        self.each(pos, buf.len(), |backend, pos, i, n| backend.read_at(pos, &mut buf[i..i + n]))
+    }
+
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        self.each(pos, buf.len(), |backend, pos, i, n| backend.read_at_with(pos, &mut buf[i..i + n], options))
+    }
+}
+
+impl<T: WriteAt> WriteAt for Striped<T> {
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        self.each(pos, buf.len(), |backend, pos, i, n| backend.write_at(pos, &buf[i..i + n]))
+    }
+
+    fn write_at_with(&mut self, pos: u64, buf: &[u8], options: &IoOptions) -> Result<usize> {
+        self.each(pos, buf.len(), |backend, pos, i, n| backend.write_at_with(pos, &buf[i..i + n], options))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        for backend in &mut self.backends {
+            backend.flush()?;
+        }
+        Ok(())
+    }
+
+    fn write_zeroes_at(&mut self, pos: u64, len: u64) -> Result<()> {
+        pos.checked_add(len).ok_or(Error::OutOfBounds { offset: pos, len })?;
+        let mut done = 0;
+        while done < len {
+            let (i, inner, left) = self.locate(pos + done);
+            let n = cmp::min(len - done, left);
+            self.backends[i].write_zeroes_at(inner, n)?;
+            done += n;
+        }
+        Ok(())
+    }
+}
+
+/// The size is the end of the last byte held by any backend. Stripes
+/// missing from a shorter backend before that point read short.
+impl<T: SizeAt> SizeAt for Striped<T> {
+    fn size(&self) -> Result<u64> {
+        let mut size = 0;
+        for (i, backend) in self.backends.iter().enumerate() {
+            size = cmp::max(size, self.end_of(i, backend.size()?));
+        }
+        Ok(size)
+    }
+}
+
+impl<T: SizeAt> KnownEof for Striped<T> {
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        Ok(self.size()?.saturating_sub(pos))
+    }
+}
+
+impl<T: SyncAt> SyncAt for Striped<T> {
+    fn sync_all(&mut self) -> Result<()> {
+        for backend in &mut self.backends {
+            backend.sync_all()?;
+        }
+        Ok(())
+    }
+
+    fn sync_data(&mut self) -> Result<()> {
+        for backend in &mut self.backends {
+            backend.sync_data()?;
+        }
+        Ok(())
+    }
+}
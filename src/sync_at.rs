@@ -0,0 +1,92 @@
+use std::fs::File;
+use std::io::{Result, Sink};
+
+/// The `SyncAt` trait allows for requesting that written data reaches
+/// durable storage.
+///
+/// [`flush`](trait.WriteAt.html#tymethod.flush) only empties buffers held
+/// in user space, and leaves data in the operating system's cache, where
+/// it is lost on a crash. Layers that need crash safety, such as journals
+/// and commit protocols, call these methods through the same abstraction
+/// their writes go through.
+pub trait SyncAt {
+    /// Waits until all written data and metadata have reached durable
+    /// storage.
+    ///
+    /// # Errors
+    ///
+    /// This method can return any I/O error.
+    fn sync_all(&mut self) -> Result<()>;
+
+    /// Waits until all written data has reached durable storage, along
+    /// with the metadata needed to read it back, such as the length.
+    ///
+    /// By default this calls `sync_all`.
+    ///
+    /// # Errors
+    ///
+    /// This method can return any I/O error.
+    fn sync_data(&mut self) -> Result<()> {
+        self.sync_all()
+    }
+}
+
+impl<S: SyncAt + ?Sized> SyncAt for &mut S {
+    #[inline]
+    fn sync_all(&mut self) -> Result<()> {
+        (**self).sync_all()
+    }
+
+    #[inline]
+    fn sync_data(&mut self) -> Result<()> {
+        (**self).sync_data()
+    }
+}
+
+impl<S: SyncAt + ?Sized> SyncAt for Box<S> {
+    #[inline]
+    fn sync_all(&mut self) -> Result<()> {
+        (**self).sync_all()
+    }
+
+    #[inline]
+    fn sync_data(&mut self) -> Result<()> {
+        (**self).sync_data()
+    }
+}
+
+/// In-memory buffers have nothing to sync.
+impl SyncAt for [u8] {
+    #[inline]
+    fn sync_all(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// In-memory buffers have nothing to sync.
+impl SyncAt for Vec<u8> {
+    #[inline]
+    fn sync_all(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Uses `fsync` and `fdatasync` on Unix, and `FlushFileBuffers` on Windows.
+impl SyncAt for File {
+    #[inline]
+    fn sync_all(&mut self) -> Result<()> {
+        File::sync_all(self)
+    }
+
+    #[inline]
+    fn sync_data(&mut self) -> Result<()> {
+        File::sync_data(self)
+    }
+}
+
+impl SyncAt for Sink {
+    #[inline]
+    fn sync_all(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
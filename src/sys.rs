@@ -0,0 +1,243 @@
+//! Helpers shared by the platform-specific code paths.
+
+#[cfg(target_os = "linux")]
+use std::cmp;
+#[cfg(any(unix, windows))]
+use std::fs::File;
+#[cfg(any(unix, windows))]
+use std::io::{Error, ErrorKind, Result};
+#[cfg(target_os = "linux")]
+use std::io::{IoSlice, IoSliceMut};
+#[cfg(target_os = "linux")]
+use std::os::unix::io::AsRawFd;
+
+#[cfg(target_os = "linux")]
+use {IoOptions, ReadBuf};
+
+/// Converts an offset or length to `off_t`, failing if it does not fit.
+#[cfg(unix)]
+#[allow(dead_code)]
+pub fn off_t(n: u64) -> Result<libc::off_t> {
+    if n > libc::off_t::MAX as u64 {
+        Err(Error::new(ErrorKind::InvalidInput, "offset does not fit in off_t"))
+    } else {
+        Ok(n as libc::off_t)
+    }
+}
+
+/// Converts the return value of a libc function that sets `errno` on failure.
+#[cfg(unix)]
+#[allow(dead_code)]
+pub fn cvt(ret: libc::c_int) -> Result<libc::c_int> {
+    if ret == -1 {
+        Err(Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
+
+// Positional reads and writes do not use the file position, so they are
+// safe to issue from several threads at once. On Windows they still move
+// the file position, which is left undefined.
+
+#[cfg(unix)]
+pub fn read_at(file: &File, pos: u64, buf: &mut [u8]) -> Result<usize> {
+    use std::os::unix::fs::FileExt;
+    file.read_at(buf, pos)
+}
+
+#[cfg(unix)]
+pub fn write_at(file: &File, pos: u64, buf: &[u8]) -> Result<usize> {
+    use std::os::unix::fs::FileExt;
+    file.write_at(buf, pos)
+}
+
+#[cfg(windows)]
+pub fn read_at(file: &File, pos: u64, buf: &mut [u8]) -> Result<usize> {
+    use std::os::windows::fs::FileExt;
+    file.seek_read(buf, pos)
+}
+
+#[cfg(windows)]
+pub fn write_at(file: &File, pos: u64, buf: &[u8]) -> Result<usize> {
+    use std::os::windows::fs::FileExt;
+    file.seek_write(buf, pos)
+}
+
+#[cfg(target_os = "linux")]
+fn rw_flags(options: &IoOptions, write: bool) -> libc::c_int {
+    let mut flags = 0;
+    if options.nowait {
+        flags |= libc::RWF_NOWAIT;
+    }
+    if options.dsync && write {
+        flags |= libc::RWF_DSYNC;
+    }
+    if options.no_cache {
+        flags |= libc::RWF_DONTCACHE;
+    }
+    flags
+}
+
+/// Calls `f` with the `preadv2`/`pwritev2` flags for `options`, retrying
+/// without `RWF_DONTCACHE` on kernels that do not support it, since it is
+/// only a hint.
+#[cfg(target_os = "linux")]
+fn with_rw_flags<F>(options: &IoOptions, write: bool, mut f: F) -> Result<usize>
+    where F: FnMut(libc::c_int) -> libc::ssize_t
+{
+    let flags = rw_flags(options, write);
+    let mut ret = f(flags);
+    if ret == -1 && flags & libc::RWF_DONTCACHE != 0 &&
+       Error::last_os_error().raw_os_error() == Some(libc::EOPNOTSUPP) {
+        ret = f(flags & !libc::RWF_DONTCACHE);
+    }
+    if ret == -1 {
+        Err(Error::last_os_error())
+    } else {
+        Ok(ret as usize)
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn read_at_with(file: &File, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+    let pos = off_t(pos)?;
+    let iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    with_rw_flags(options, false, |flags| unsafe { libc::preadv2(file.as_raw_fd(), &iov, 1, pos, flags) })
+}
+
+#[cfg(target_os = "linux")]
+pub fn write_at_with(file: &File, pos: u64, buf: &[u8], options: &IoOptions) -> Result<usize> {
+    let pos = off_t(pos)?;
+    let iov = libc::iovec {
+        iov_base: buf.as_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    with_rw_flags(options, true, |flags| unsafe { libc::pwritev2(file.as_raw_fd(), &iov, 1, pos, flags) })
+}
+
+/// Zeroes `len` bytes at `pos` with `fallocate`, returning `Ok(false)` if
+/// the file system or file type does not support it.
+#[cfg(target_os = "linux")]
+pub fn zero_range(file: &File, pos: u64, len: u64) -> Result<bool> {
+    let ret = unsafe { libc::fallocate(file.as_raw_fd(), libc::FALLOC_FL_ZERO_RANGE, off_t(pos)?, off_t(len)?) };
+    if ret == -1 {
+        let err = Error::last_os_error();
+        return match err.raw_os_error() {
+            Some(libc::EOPNOTSUPP) | Some(libc::ENOSYS) | Some(libc::ENODEV) => Ok(false),
+            _ => Err(err),
+        };
+    }
+    Ok(true)
+}
+
+/// Deallocates `len` bytes at `pos` with `fallocate`, keeping the length
+/// of the file, and returns `Ok(false)` if the file system or file type
+/// does not support it.
+#[cfg(target_os = "linux")]
+pub fn punch_hole(file: &File, pos: u64, len: u64) -> Result<bool> {
+    let mode = libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE;
+    let ret = unsafe { libc::fallocate(file.as_raw_fd(), mode, off_t(pos)?, off_t(len)?) };
+    if ret == -1 {
+        let err = Error::last_os_error();
+        return match err.raw_os_error() {
+            Some(libc::EOPNOTSUPP) | Some(libc::ENOSYS) | Some(libc::ENODEV) => Ok(false),
+            _ => Err(err),
+        };
+    }
+    Ok(true)
+}
+
+/// The maximum number of buffers accepted by `preadv` and `pwritev`.
+#[cfg(target_os = "linux")]
+const IOV_MAX: usize = 1024;
+
+// `IoSlice` and `IoSliceMut` are guaranteed to be ABI compatible with
+// `iovec` on Unix. Buffers beyond `IOV_MAX` are left for the next call,
+// as allowed for a short read or write.
+
+#[cfg(target_os = "linux")]
+pub fn read_vectored_at(file: &File, pos: u64, bufs: &mut [IoSliceMut]) -> Result<usize> {
+    let pos = off_t(pos)?;
+    let count = cmp::min(bufs.len(), IOV_MAX) as libc::c_int;
+    let ret = unsafe { libc::preadv(file.as_raw_fd(), bufs.as_ptr() as *const libc::iovec, count, pos) };
+    if ret == -1 {
+        Err(Error::last_os_error())
+    } else {
+        Ok(ret as usize)
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn write_vectored_at(file: &File, pos: u64, bufs: &[IoSlice]) -> Result<usize> {
+    let pos = off_t(pos)?;
+    let count = cmp::min(bufs.len(), IOV_MAX) as libc::c_int;
+    let ret = unsafe { libc::pwritev(file.as_raw_fd(), bufs.as_ptr() as *const libc::iovec, count, pos) };
+    if ret == -1 {
+        Err(Error::last_os_error())
+    } else {
+        Ok(ret as usize)
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn read_buf_at(file: &File, pos: u64, buf: &mut ReadBuf) -> Result<()> {
+    let pos = off_t(pos)?;
+    unsafe {
+        let unfilled = buf.unfilled_mut();
+        let ret = libc::pread(file.as_raw_fd(), unfilled.as_mut_ptr() as *mut libc::c_void, unfilled.len(), pos);
+        if ret == -1 {
+            return Err(Error::last_os_error());
+        }
+        buf.assume_filled(ret as usize);
+    }
+    Ok(())
+}
+
+/// Marks the file as sparse and deallocates `len` bytes at `pos` with
+/// `FSCTL_SET_ZERO_DATA`, and returns `Ok(false)` if the file system does
+/// not support sparse files.
+#[cfg(windows)]
+pub fn punch_hole(file: &File, pos: u64, len: u64) -> Result<bool> {
+    use std::os::windows::io::AsRawHandle;
+    use std::{mem, ptr};
+    use windows_sys::Win32::Foundation::ERROR_INVALID_FUNCTION;
+    use windows_sys::Win32::System::IO::DeviceIoControl;
+    use windows_sys::Win32::System::Ioctl::{FILE_ZERO_DATA_INFORMATION, FSCTL_SET_SPARSE, FSCTL_SET_ZERO_DATA};
+
+    let end = pos.checked_add(len).filter(|&end| end <= i64::MAX as u64)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "range does not fit in a file offset"))?;
+    let handle = file.as_raw_handle();
+    let mut returned = 0;
+    let ok = unsafe {
+        DeviceIoControl(handle, FSCTL_SET_SPARSE, ptr::null(), 0, ptr::null_mut(), 0, &mut returned, ptr::null_mut())
+    };
+    if ok == 0 {
+        let err = Error::last_os_error();
+        if err.raw_os_error() == Some(ERROR_INVALID_FUNCTION as i32) {
+            return Ok(false);
+        }
+        return Err(err);
+    }
+    let info = FILE_ZERO_DATA_INFORMATION {
+        FileOffset: pos as i64,
+        BeyondFinalZero: end as i64,
+    };
+    let ok = unsafe {
+        DeviceIoControl(handle,
+                        FSCTL_SET_ZERO_DATA,
+                        &info as *const FILE_ZERO_DATA_INFORMATION as *const _,
+                        mem::size_of::<FILE_ZERO_DATA_INFORMATION>() as u32,
+                        ptr::null_mut(),
+                        0,
+                        &mut returned,
+                        ptr::null_mut())
+    };
+    if ok == 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(true)
+}
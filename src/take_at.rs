@@ -0,0 +1,107 @@
+use std::cmp;
+use std::io::Result;
+
+use {IoOptions, KnownEof, ReadAt, SizeAt};
+
+/// An adapter which exposes a window of a source, such as a partition
+/// inside a disk image.
+///
+/// Positions are relative to the start of the window, and reads are
+/// clamped at its end, so the rest of the source cannot be reached
+/// through it.
+///
+/// This struct is created by [`ReadAt::take_at`](trait.ReadAt.html#method.take_at).
+#[derive(Clone, Debug)]
+pub struct TakeAt<R> {
+    inner: R,
+    offset: u64,
+    len: u64,
+}
+
+impl<R> TakeAt<R> {
+    /// Creates a window of `len` bytes at `offset` of `inner`.
+    ///
+    /// A window reaching past the largest offset is shortened to end
+    /// there.
+    pub fn new(inner: R, offset: u64, len: u64) -> TakeAt<R> {
+        TakeAt {
+            inner,
+            offset,
+            len: cmp::min(len, u64::MAX - offset),
+        }
+    }
+
+    /// Returns the offset of the window in the source.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the length of the window in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns whether the window is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns a reference to the source.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the source.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Consumes the adapter, returning the source.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Returns how many of `len` bytes at `pos` lie in the window, or
+    /// `None` if `pos` is at or past its end.
+    fn clamp(&self, pos: u64, len: usize) -> Option<usize> {
+        if pos >= self.len {
+            return None;
+        }
+        Some(cmp::min(len as u64, self.len - pos) as usize)
+    }
+}
+
+impl<R: ReadAt> ReadAt for TakeAt<R> {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        match self.clamp(pos, buf.len()) {
+            Some(n) => self.inner.read_at(self.offset + pos, &mut buf[..n]),
+            None => Ok(0),
+        }
+    }
+
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        match self.clamp(pos, buf.len()) {
+            Some(n) => self.inner.read_at_with(self.offset + pos, &mut buf[..n], options),
+            None => Ok(0),
+        }
+    }
+}
+
+/// The window ends at its length or at the end of the source, whichever
+/// comes first.
+impl<R: KnownEof> KnownEof for TakeAt<R> {
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        if pos >= self.len {
+            return Ok(0);
+        }
+        Ok(cmp::min(self.len - pos, self.inner.remaining_at(self.offset + pos)?))
+    }
+}
+
+/// The window ends at its length or at the end of the source, whichever
+/// comes first.
+impl<R: SizeAt> SizeAt for TakeAt<R> {
+    fn size(&self) -> Result<u64> {
+        Ok(cmp::min(self.len, self.inner.size()?.saturating_sub(self.offset)))
+    }
+}
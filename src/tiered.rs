@@ -0,0 +1,780 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::io::{self, ErrorKind, Result};
+use std::sync::{Arc, Condvar, Mutex, TryLockError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use sha256;
+use {Error, IoOptions, ReadAt, SyncAt, WriteAt};
+
+const PLACEMENT_MAGIC: &[u8; 8] = b"IOATTIER";
+const INDEX_MAGIC: &[u8; 8] = b"IOATTIX1";
+const INDEX_HEADER_LEN: usize = 40;
+const SLOT_ENTRY_LEN: usize = 49;
+const HEAT_ENTRY_LEN: usize = 12;
+const HEAT_BATCH: u64 = 4096;
+const LOCK_RETRY: Duration = Duration::from_millis(1);
+
+#[derive(Clone, Copy, Debug)]
+struct Slot {
+    block: u64,
+    dirty: bool,
+}
+
+/// A composite of a small, fast backend and a large, slow backend.
+///
+/// The logical address space is that of the slow tier. It is divided
+/// into blocks of `block_size` bytes, and up to `fast_blocks` of these
+/// can be held by the fast tier at a time. Reads and writes are served
+/// by whichever tier currently holds the block.
+///
+/// Every access to a block increases its heat. Once a block on the
+/// slow tier has been accessed `promote_after` times, it is copied to
+/// the fast tier, evicting a colder block if the fast tier is full.
+/// Writes to a block on the fast tier are not written through, so the
+/// fast tier holds the only up-to-date copy until the block is demoted.
+///
+/// Demotion of cold blocks happens in [`rebalance`](#method.rebalance),
+/// which must be called periodically. Nothing calls it on its own: either
+/// call it from an existing maintenance loop, or share the value behind a
+/// mutex and let [`spawn_rebalancer`](#method.spawn_rebalancer) call it
+/// from a background thread. Since the fast tier may hold data not yet on
+/// the slow tier, the placement map must be persisted with
+/// [`save_placement`](#method.save_placement) and restored with
+/// [`load_placement`](#method.load_placement) if the fast tier is itself
+/// persistent. [`save_index`](#method.save_index) and
+/// [`load_index`](#method.load_index) also keep the heat of every block,
+/// so a restarted process does not start out with a cold cache.
+#[derive(Debug)]
+pub struct Tiered<F, S> {
+    fast: F,
+    slow: S,
+    block_size: u64,
+    promote_after: u32,
+    slots: Vec<Option<Slot>>,
+    placement: HashMap<u64, usize>,
+    heat: HashMap<u64, u32>,
+}
+
+impl<F, S> Tiered<F, S>
+    where F: ReadAt + WriteAt,
+          S: ReadAt + WriteAt
+{
+    /// Creates a new tiered composite with an empty fast tier.
+    ///
+    /// The fast tier must be able to hold `fast_blocks * block_size`
+    /// bytes. Blocks are promoted after `promote_after` accesses.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block_size` is zero.
+    pub fn new(fast: F, slow: S, block_size: u64, fast_blocks: usize, promote_after: u32) -> Tiered<F, S> {
+        assert!(block_size > 0, "block size must be non-zero");
+        Tiered {
+            fast,
+            slow,
+            block_size,
+            promote_after: cmp::max(promote_after, 1),
+            slots: vec![None; fast_blocks],
+            placement: HashMap::new(),
+            heat: HashMap::new(),
+        }
+    }
+
+    /// Returns whether the block containing `pos` is held by the fast tier.
+    pub fn is_fast(&self, pos: u64) -> bool {
+        self.placement.contains_key(&(pos / self.block_size))
+    }
+
+    /// Halves the heat of every block, then demotes all blocks on the fast
+    /// tier whose heat has decayed to zero.
+    ///
+    /// Dirty blocks are written back to the slow tier before their slot
+    /// is freed.
+    ///
+    /// # Errors
+    ///
+    /// If writing back a dirty block fails, the error is returned and
+    /// the block remains on the fast tier.
+    pub fn rebalance(&mut self) -> Result<()> {
+        self.heat.retain(|_, heat| {
+            *heat /= 2;
+            *heat > 0
+        });
+        for i in 0..self.slots.len() {
+            if let Some(slot) = self.slots[i] {
+                if !self.heat.contains_key(&slot.block) {
+                    self.demote(i)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes every dirty block on the fast tier back to the slow tier,
+    /// leaving the blocks in place.
+    ///
+    /// # Errors
+    ///
+    /// This method can return any I/O error from either tier.
+    pub fn write_back(&mut self) -> Result<()> {
+        for i in 0..self.slots.len() {
+            if self.slots[i].is_some_and(|s| s.dirty) {
+                self.copy_slot_to_slow(i)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the placement map to `dst` at `pos`.
+    ///
+    /// # Errors
+    ///
+    /// This method can return any I/O error from `dst`.
+    pub fn save_placement<W: WriteAt>(&self, dst: &mut W, pos: u64) -> Result<()> {
+        let entries: Vec<(usize, Slot)> = self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(i, s)| s.map(|s| (i, s)))
+            .collect();
+        let mut buf = Vec::with_capacity(24 + entries.len() * 17);
+        buf.extend_from_slice(PLACEMENT_MAGIC);
+        buf.extend_from_slice(&self.block_size.to_le_bytes());
+        buf.extend_from_slice(&(entries.len() as u64).to_le_bytes());
+        for (i, slot) in entries {
+            buf.extend_from_slice(&slot.block.to_le_bytes());
+            buf.extend_from_slice(&(i as u64).to_le_bytes());
+            buf.push(slot.dirty as u8);
+        }
+        dst.write_all_at(pos, &buf)
+    }
+
+    /// Replaces the placement map with one read from `src` at `pos`.
+    ///
+    /// Heat is not persisted, so all restored blocks start out cold.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidData` if the placement map is
+    /// malformed, was saved with a different block size, or refers to
+    /// slots beyond the capacity of the fast tier. Any I/O error from
+    /// `src` is propagated.
+    pub fn load_placement<R: ReadAt>(&mut self, src: &mut R, pos: u64) -> Result<()> {
+        let mut header = [0; 24];
+        src.read_exact_at(pos, &mut header)?;
+        if &header[..8] != PLACEMENT_MAGIC {
+            return Err(invalid("invalid placement map magic"));
+        }
+        if le_u64(&header[8..16]) != self.block_size {
+            return Err(invalid("placement map block size mismatch"));
+        }
+        let count = le_u64(&header[16..24]);
+        if count > self.slots.len() as u64 {
+            return Err(invalid("placement map exceeds fast tier capacity"));
+        }
+        let mut entries = vec![0; count as usize * 17];
+        src.read_exact_at(pos + 24, &mut entries)?;
+
+        let mut slots = vec![None; self.slots.len()];
+        let mut placement = HashMap::new();
+        for entry in entries.chunks(17) {
+            let block = le_u64(&entry[..8]);
+            let i = le_u64(&entry[8..16]);
+            if i >= slots.len() as u64 || slots[i as usize].is_some() || placement.contains_key(&block) {
+                return Err(invalid("invalid placement map entry"));
+            }
+            slots[i as usize] = Some(Slot { block, dirty: entry[16] != 0 });
+            placement.insert(block, i as usize);
+        }
+        self.slots = slots;
+        self.placement = placement;
+        self.heat.clear();
+        Ok(())
+    }
+
+    /// Writes the placement map and the heat of every block to `dst` at
+    /// `pos`, along with integrity checks for
+    /// [`load_index`](#method.load_index).
+    ///
+    /// `version` identifies the contents of the slow tier, such as the
+    /// ETag or modification time of a remote image. Each block on the
+    /// fast tier is read back to store its digest, and the index as a
+    /// whole is checksummed.
+    ///
+    /// # Errors
+    ///
+    /// This method can return any I/O error from the fast tier or `dst`.
+    pub fn save_index<W: WriteAt>(&mut self, dst: &mut W, pos: u64, version: &[u8]) -> Result<()> {
+        let mut slots = Vec::new();
+        let mut block = vec![0; self.block_size as usize];
+        for (i, slot) in self.slots.iter().enumerate() {
+            if let Some(slot) = *slot {
+                self.fast.read_exact_at(i as u64 * self.block_size, &mut block)?;
+                slots.push((i, slot, sha256::digest(&block)));
+            }
+        }
+        let mut heat: Vec<(u64, u32)> = self.heat.iter().map(|(&block, &heat)| (block, heat)).collect();
+        heat.sort_unstable();
+
+        let mut buf = Vec::with_capacity(INDEX_HEADER_LEN + version.len() + slots.len() * SLOT_ENTRY_LEN +
+                                         heat.len() * HEAT_ENTRY_LEN + 32);
+        buf.extend_from_slice(INDEX_MAGIC);
+        buf.extend_from_slice(&self.block_size.to_le_bytes());
+        buf.extend_from_slice(&(version.len() as u64).to_le_bytes());
+        buf.extend_from_slice(&(slots.len() as u64).to_le_bytes());
+        buf.extend_from_slice(&(heat.len() as u64).to_le_bytes());
+        buf.extend_from_slice(version);
+        for (i, slot, digest) in slots {
+            buf.extend_from_slice(&slot.block.to_le_bytes());
+            buf.extend_from_slice(&(i as u64).to_le_bytes());
+            buf.push(slot.dirty as u8);
+            buf.extend_from_slice(&digest);
+        }
+        for (block, heat) in heat {
+            buf.extend_from_slice(&block.to_le_bytes());
+            buf.extend_from_slice(&heat.to_le_bytes());
+        }
+        let digest = sha256::digest(&buf);
+        buf.extend_from_slice(&digest);
+        dst.write_all_at(pos, &buf)
+    }
+
+    /// Replaces the placement map and the heat of every block with an
+    /// index read from `src` at `pos`, as written by
+    /// [`save_index`](#method.save_index).
+    ///
+    /// The index is only used if it was saved for the same `version` of
+    /// the slow tier. Each block on the fast tier is read back and
+    /// compared with its stored digest. Clean blocks that no longer match
+    /// are dropped from the fast tier and served from the slow tier
+    /// again, so an index that is out of date with the fast tier cannot
+    /// serve wrong data.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ChecksumMismatch`](enum.Error.html) if the
+    /// index is corrupt or a dirty block does not match its digest, and
+    /// an error of kind `InvalidData` if the index is malformed or
+    /// truncated, was saved with a different block size or `version`, or
+    /// refers to slots beyond the capacity of the fast tier. Any other
+    /// I/O error from `src` or the fast tier is propagated. On error, the
+    /// current placement map is left unchanged.
+    pub fn load_index<R: ReadAt>(&mut self, src: &mut R, pos: u64, version: &[u8]) -> Result<()> {
+        let mut header = [0; INDEX_HEADER_LEN];
+        src.read_exact_at(pos, &mut header)?;
+        if &header[..8] != INDEX_MAGIC {
+            return Err(invalid("invalid cache index magic"));
+        }
+        if le_u64(&header[8..16]) != self.block_size {
+            return Err(invalid("cache index block size mismatch"));
+        }
+        if le_u64(&header[16..24]) != version.len() as u64 {
+            return Err(invalid("cache index was saved for a different version of the slow tier"));
+        }
+        let slot_count = le_u64(&header[24..32]);
+        if slot_count > self.slots.len() as u64 {
+            return Err(invalid("cache index exceeds fast tier capacity"));
+        }
+        let heat_count = le_u64(&header[32..40]);
+        // The heat entries are read in batches, so that the buffer only
+        // grows as far as the index actually reaches.
+        let mut buf = header.to_vec();
+        buf.resize(INDEX_HEADER_LEN + version.len() + slot_count as usize * SLOT_ENTRY_LEN, 0);
+        read_index(src, pos + INDEX_HEADER_LEN as u64, &mut buf[INDEX_HEADER_LEN..])?;
+        let mut left = heat_count;
+        while left > 0 {
+            let n = cmp::min(left, HEAT_BATCH) as usize;
+            let start = buf.len();
+            buf.resize(start + n * HEAT_ENTRY_LEN, 0);
+            read_index(src, pos + start as u64, &mut buf[start..])?;
+            left -= n as u64;
+        }
+        let len = buf.len() + 32;
+        buf.resize(len, 0);
+        read_index(src, pos + (len - 32) as u64, &mut buf[len - 32..])?;
+        let (body, digest) = buf.split_at(len - 32);
+        if sha256::digest(body)[..] != digest[..] {
+            return Err(Error::ChecksumMismatch { offset: pos }.into());
+        }
+        let (stored_version, entries) = body[INDEX_HEADER_LEN..].split_at(version.len());
+        if stored_version != version {
+            return Err(invalid("cache index was saved for a different version of the slow tier"));
+        }
+        let (slot_entries, heat_entries) = entries.split_at(slot_count as usize * SLOT_ENTRY_LEN);
+
+        let mut slots = vec![None; self.slots.len()];
+        let mut placement = HashMap::new();
+        let mut block = vec![0; self.block_size as usize];
+        for entry in slot_entries.chunks(SLOT_ENTRY_LEN) {
+            let index = le_u64(&entry[..8]);
+            let i = le_u64(&entry[8..16]);
+            if i >= slots.len() as u64 || slots[i as usize].is_some() || placement.contains_key(&index) {
+                return Err(invalid("invalid cache index entry"));
+            }
+            let dirty = entry[16] != 0;
+            self.fast.read_exact_at(i * self.block_size, &mut block)?;
+            if sha256::digest(&block)[..] != entry[17..] {
+                if dirty {
+                    return Err(Error::ChecksumMismatch { offset: i * self.block_size }.into());
+                }
+                continue;
+            }
+            slots[i as usize] = Some(Slot { block: index, dirty });
+            placement.insert(index, i as usize);
+        }
+        let heat = heat_entries.chunks(HEAT_ENTRY_LEN)
+            .map(|entry| (le_u64(&entry[..8]), u32::from_le_bytes([entry[8], entry[9], entry[10], entry[11]])))
+            .collect();
+        self.slots = slots;
+        self.placement = placement;
+        self.heat = heat;
+        Ok(())
+    }
+
+    /// Returns references to the fast and slow tiers.
+    pub fn get_ref(&self) -> (&F, &S) {
+        (&self.fast, &self.slow)
+    }
+
+    /// Consumes the composite, returning the fast and slow tiers.
+    ///
+    /// Dirty blocks are not written back; call
+    /// [`write_back`](#method.write_back) first if required.
+    pub fn into_inner(self) -> (F, S) {
+        (self.fast, self.slow)
+    }
+
+    fn touch(&mut self, block: u64) -> Result<()> {
+        let heat = {
+            let heat = self.heat.entry(block).or_insert(0);
+            *heat = heat.saturating_add(1);
+            *heat
+        };
+        if heat >= self.promote_after && !self.placement.contains_key(&block) {
+            self.promote(block, heat)?;
+        }
+        Ok(())
+    }
+
+    fn promote(&mut self, block: u64, heat: u32) -> Result<()> {
+        let i = match self.slots.iter().position(|s| s.is_none()) {
+            Some(i) => i,
+            None => {
+                let coldest = self.slots
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, s)| s.map(|s| (i, self.heat.get(&s.block).cloned().unwrap_or(0))))
+                    .min_by_key(|&(_, heat)| heat);
+                match coldest {
+                    Some((i, cold)) if cold < heat => {
+                        self.demote(i)?;
+                        i
+                    }
+                    _ => return Ok(()),
+                }
+            }
+        };
+        let mut buf = vec![0; self.block_size as usize];
+        match self.slow.read_exact_at(block * self.block_size, &mut buf) {
+            Ok(()) => {}
+            // Partial blocks at the end of the slow tier are never promoted.
+            Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        }
+        self.fast.write_all_at(i as u64 * self.block_size, &buf)?;
+        self.slots[i] = Some(Slot { block, dirty: false });
+        self.placement.insert(block, i);
+        Ok(())
+    }
+
+    fn demote(&mut self, i: usize) -> Result<()> {
+        if let Some(slot) = self.slots[i] {
+            if slot.dirty {
+                self.copy_slot_to_slow(i)?;
+            }
+            self.placement.remove(&slot.block);
+            self.slots[i] = None;
+        }
+        Ok(())
+    }
+
+    fn copy_slot_to_slow(&mut self, i: usize) -> Result<()> {
+        if let Some(slot) = self.slots[i] {
+            let mut buf = vec![0; self.block_size as usize];
+            self.fast.read_exact_at(i as u64 * self.block_size, &mut buf)?;
+            self.slow.write_all_at(slot.block * self.block_size, &buf)?;
+            self.slots[i] = Some(Slot { block: slot.block, dirty: false });
+        }
+        Ok(())
+    }
+
+    /// Returns the block index, the offset into the block and the number
+    /// of bytes of the block, which an operation of `len` bytes at `pos`
+    /// covers.
+    fn locate(&self, pos: u64, len: usize) -> (u64, u64, usize) {
+        let block = pos / self.block_size;
+        let offset = pos % self.block_size;
+        let n = cmp::min(len as u64, self.block_size - offset) as usize;
+        (block, offset, n)
+    }
+
+    /// Reads at `pos` from the tier holding its block, with `options` if
+    /// there are any.
+    fn read_with(&mut self, pos: u64, buf: &mut [u8], options: Option<&IoOptions>) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let (block, offset, n) = self.locate(pos, buf.len());
+        self.touch(block)?;
+        let (tier, pos): (&mut dyn ReadAt, u64) = match self.placement.get(&block) {
+            Some(&i) => (&mut self.fast, i as u64 * self.block_size + offset),
+            None => (&mut self.slow, pos),
+        };
+        match options {
+            Some(options) => tier.read_at_with(pos, &mut buf[..n], options),
+            None => tier.read_at(pos, &mut buf[..n]),
+        }
+    }
+
+    /// Writes at `pos` to the tier holding its block, with `options` if
+    /// there are any.
+    fn write_with(&mut self, pos: u64, buf: &[u8], options: Option<&IoOptions>) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let (block, offset, n) = self.locate(pos, buf.len());
+        self.touch(block)?;
+        let slot = self.placement.get(&block).cloned();
+        let (tier, pos): (&mut dyn WriteAt, u64) = match slot {
+            Some(i) => (&mut self.fast, i as u64 * self.block_size + offset),
+            None => (&mut self.slow, pos),
+        };
+        let written = match options {
+            Some(options) => tier.write_at_with(pos, &buf[..n], options)?,
+            None => tier.write_at(pos, &buf[..n])?,
+        };
+        if let Some(i) = slot {
+            if written > 0 {
+                self.slots[i] = Some(Slot { block, dirty: true });
+            }
+        }
+        Ok(written)
+    }
+}
+
+impl<F, S> Tiered<F, S>
+    where F: ReadAt + WriteAt + Send + 'static,
+          S: ReadAt + WriteAt + Send + 'static
+{
+    /// Starts a background thread calling
+    /// [`rebalance`](#method.rebalance) on `tiered` every `interval`,
+    /// holding the lock while it runs.
+    ///
+    /// The thread runs until the returned
+    /// [`Rebalancer`](struct.Rebalancer.html) is stopped or dropped, or
+    /// until `rebalance` fails. It never blocks on the lock, so the
+    /// rebalancer can be stopped or dropped while holding it; a call due
+    /// while the lock is held is retried shortly after.
+    pub fn spawn_rebalancer(tiered: Arc<Mutex<Tiered<F, S>>>, interval: Duration) -> Rebalancer {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let handle = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let (ref stopped, ref wake) = *stop;
+                let mut guard = stopped.lock().unwrap_or_else(|e| e.into_inner());
+                let mut next = Instant::now() + interval;
+                loop {
+                    let now = Instant::now();
+                    if *guard {
+                        return Ok(());
+                    } else if now < next {
+                        // Wake-ups can be spurious, so wait out the rest of
+                        // the interval, since every call decays the heat.
+                        guard = wake.wait_timeout(guard, next - now).unwrap_or_else(|e| e.into_inner()).0;
+                        continue;
+                    }
+                    // The flag is released while rebalancing, so stopping
+                    // does not wait for a whole call.
+                    drop(guard);
+                    let result = match tiered.try_lock() {
+                        Ok(mut tiered) => Some(tiered.rebalance()),
+                        Err(TryLockError::Poisoned(e)) => Some(e.into_inner().rebalance()),
+                        Err(TryLockError::WouldBlock) => None,
+                    };
+                    guard = stopped.lock().unwrap_or_else(|e| e.into_inner());
+                    next = match result {
+                        Some(result) => {
+                            result?;
+                            Instant::now() + interval
+                        }
+                        None => Instant::now() + LOCK_RETRY,
+                    };
+                }
+            })
+        };
+        Rebalancer { stop, handle: Some(handle) }
+    }
+}
+
+/// A handle to the background thread started by
+/// [`Tiered::spawn_rebalancer`](struct.Tiered.html#method.spawn_rebalancer).
+///
+/// Dropping the handle stops the thread and waits for it, discarding any
+/// error; use [`stop`](#method.stop) to see it.
+#[derive(Debug)]
+pub struct Rebalancer {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    handle: Option<JoinHandle<Result<()>>>,
+}
+
+impl Rebalancer {
+    /// Returns whether the thread has stopped, which happens early only
+    /// if `rebalance` failed.
+    pub fn is_finished(&self) -> bool {
+        self.handle.as_ref().is_none_or(JoinHandle::is_finished)
+    }
+
+    /// Stops the thread and waits for it.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from `rebalance` that stopped the thread early,
+    /// if any.
+    pub fn stop(mut self) -> Result<()> {
+        self.finish()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let handle = match self.handle.take() {
+            Some(handle) => handle,
+            None => return Ok(()),
+        };
+        let (ref stopped, ref wake) = *self.stop;
+        *stopped.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        wake.notify_one();
+        handle.join().unwrap_or_else(|_| Err(io::Error::other("rebalancer thread panicked")))
+    }
+}
+
+impl Drop for Rebalancer {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+impl<F, S> ReadAt for Tiered<F, S>
+    where F: ReadAt + WriteAt,
+          S: ReadAt + WriteAt
+{
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        self.read_with(pos, buf, None)
+    }
+
+    /// The options are passed to the tier holding the block. Promoting the
+    /// block to the fast tier does not use them.
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        self.read_with(pos, buf, Some(options))
+    }
+}
+
+impl<F, S> WriteAt for Tiered<F, S>
+    where F: ReadAt + WriteAt,
+          S: ReadAt + WriteAt
+{
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        self.write_with(pos, buf, None)
+    }
+
+    /// The options are passed to the tier holding the block. Promoting the
+    /// block to the fast tier does not use them.
+    fn write_at_with(&mut self, pos: u64, buf: &[u8], options: &IoOptions) -> Result<usize> {
+        self.write_with(pos, buf, Some(options))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.fast.flush()?;
+        self.slow.flush()
+    }
+}
+
+/// Syncs the fast tier, then the slow tier. Dirty blocks are not written
+/// back first; call [`write_back`](struct.Tiered.html#method.write_back)
+/// for the slow tier to hold every write.
+impl<F: SyncAt, S: SyncAt> SyncAt for Tiered<F, S> {
+    fn sync_all(&mut self) -> Result<()> {
+        self.fast.sync_all()?;
+        self.slow.sync_all()
+    }
+
+    fn sync_data(&mut self) -> Result<()> {
+        self.fast.sync_data()?;
+        self.slow.sync_data()
+    }
+}
+
+fn le_u64(bytes: &[u8]) -> u64 {
+    let mut buf = [0; 8];
+    buf.copy_from_slice(bytes);
+    u64::from_le_bytes(buf)
+}
+
+/// Reads part of a cache index, reporting a truncated index as malformed.
+fn read_index<R: ReadAt>(src: &mut R, pos: u64, buf: &mut [u8]) -> Result<()> {
+    match src.read_exact_at(pos, buf) {
+        Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => Err(invalid("cache index is truncated")),
+        result => result,
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCK: u64 = 16;
+
+    /// A composite with four fast slots over eight slow blocks, in which
+    /// block 1 was promoted and block 3 promoted and written to.
+    fn warmed() -> Tiered<Vec<u8>, Vec<u8>> {
+        let slow: Vec<u8> = (0..8 * BLOCK as u8).collect();
+        let mut tiered = Tiered::new(vec![0; 4 * BLOCK as usize], slow, BLOCK, 4, 2);
+        let mut buf = [0; 4];
+        for _ in 0..3 {
+            tiered.read_exact_at(BLOCK, &mut buf).unwrap();
+        }
+        tiered.read_exact_at(3 * BLOCK, &mut buf).unwrap();
+        tiered.write_all_at(3 * BLOCK + 2, b"new").unwrap();
+        tiered.read_exact_at(5 * BLOCK, &mut buf).unwrap();
+        assert!(tiered.is_fast(BLOCK) && tiered.is_fast(3 * BLOCK) && !tiered.is_fast(5 * BLOCK));
+        tiered
+    }
+
+    fn saved(tiered: &mut Tiered<Vec<u8>, Vec<u8>>) -> Vec<u8> {
+        let mut index = Vec::new();
+        tiered.save_index(&mut index, 0, b"etag-1").unwrap();
+        index
+    }
+
+    /// Another composite over copies of the tiers of `tiered`.
+    fn reopened(tiered: &Tiered<Vec<u8>, Vec<u8>>) -> Tiered<Vec<u8>, Vec<u8>> {
+        let (fast, slow) = tiered.get_ref();
+        Tiered::new(fast.clone(), slow.clone(), BLOCK, 4, 2)
+    }
+
+    #[test]
+    fn index_round_trip() {
+        let mut tiered = warmed();
+        let index = saved(&mut tiered);
+        let mut loaded = reopened(&tiered);
+        loaded.load_index(&mut &index[..], 0, b"etag-1").unwrap();
+        assert_eq!(loaded.heat, tiered.heat);
+        assert_eq!(loaded.placement, tiered.placement);
+        assert!(loaded.slots[loaded.placement[&3]].unwrap().dirty);
+
+        let mut buf = [0; 5];
+        loaded.read_exact_at(3 * BLOCK + 1, &mut buf).unwrap();
+        assert_eq!(&buf, b"\x31new\x35");
+        // Block 5 had one access saved, so this one promotes it.
+        loaded.read_exact_at(5 * BLOCK, &mut buf).unwrap();
+        assert!(loaded.is_fast(5 * BLOCK));
+
+        loaded.write_back().unwrap();
+        assert_eq!(&loaded.get_ref().1[3 * BLOCK as usize + 2..][..3], b"new");
+    }
+
+    #[test]
+    fn stale_clean_block() {
+        let mut tiered = warmed();
+        let index = saved(&mut tiered);
+        let mut loaded = reopened(&tiered);
+        let slot = tiered.placement[&1] as u64;
+        loaded.fast[(slot * BLOCK) as usize] ^= 1;
+        loaded.load_index(&mut &index[..], 0, b"etag-1").unwrap();
+        assert!(!loaded.is_fast(BLOCK) && loaded.is_fast(3 * BLOCK));
+        let mut buf = [0; 1];
+        loaded.read_exact_at(BLOCK, &mut buf).unwrap();
+        assert_eq!(buf[0], BLOCK as u8);
+    }
+
+    #[test]
+    fn rejected_indexes() {
+        let mut tiered = warmed();
+        let index = saved(&mut tiered);
+        let checksum = |err: &io::Error| matches!(Error::downcast_ref(err), Some(&Error::ChecksumMismatch { .. }));
+
+        // A corrupt heat entry.
+        let mut corrupt = index.clone();
+        let heat = index.len() - 32 - HEAT_ENTRY_LEN + 8;
+        corrupt[heat] ^= 1;
+        let mut loaded = reopened(&tiered);
+        assert!(checksum(&loaded.load_index(&mut &corrupt[..], 0, b"etag-1").unwrap_err()));
+
+        // A dirty block that does not match its digest.
+        let mut loaded = reopened(&tiered);
+        let slot = tiered.placement[&3] as u64;
+        loaded.fast[(slot * BLOCK) as usize] ^= 1;
+        assert!(checksum(&loaded.load_index(&mut &index[..], 0, b"etag-1").unwrap_err()));
+
+        let mut loaded = reopened(&tiered);
+        for (index, version) in [
+            (&index[..], &b"etag-2"[..]),
+            (&index[..], &b"etag"[..]),
+            (&index[..index.len() - 1], &b"etag-1"[..]),
+            (&index[..60], &b"etag-1"[..]),
+        ] {
+            let err = loaded.load_index(&mut &index[..], 0, version).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+            assert!(!checksum(&err));
+        }
+
+        // A header claiming far more heat entries than the index holds.
+        let mut huge = index.clone();
+        huge[32..40].copy_from_slice(&(u64::MAX / 16).to_le_bytes());
+        let err = loaded.load_index(&mut &huge[..], 0, b"etag-1").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        let mut other = Tiered::new(vec![0; 128], vec![0; 256], 2 * BLOCK, 4, 2);
+        let err = other.load_index(&mut &index[..], 0, b"etag-1").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        let mut small = Tiered::new(vec![0; 16], vec![0; 256], BLOCK, 1, 2);
+        let err = small.load_index(&mut &index[..], 0, b"etag-1").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        assert!(loaded.placement.is_empty() && loaded.heat.is_empty());
+    }
+
+    #[test]
+    fn rebalancer_demotes_cold_blocks() {
+        let tiered = Arc::new(Mutex::new(warmed()));
+        let rebalancer = Tiered::spawn_rebalancer(tiered.clone(), Duration::from_millis(1));
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while tiered.lock().unwrap().is_fast(BLOCK) {
+            assert!(Instant::now() < deadline, "block 1 was never demoted");
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(!rebalancer.is_finished());
+        rebalancer.stop().unwrap();
+        // The dirty block was written back on demotion.
+        let tiered = tiered.lock().unwrap();
+        assert!(!tiered.is_fast(3 * BLOCK));
+        assert_eq!(&tiered.get_ref().1[3 * BLOCK as usize + 2..][..3], b"new");
+    }
+
+    #[test]
+    fn stop_rebalancer_while_locked() {
+        let tiered = Arc::new(Mutex::new(warmed()));
+        let rebalancer = Tiered::spawn_rebalancer(tiered.clone(), Duration::from_millis(1));
+        let guard = tiered.lock().unwrap();
+        // Give the thread time to find the lock held.
+        thread::sleep(Duration::from_millis(20));
+        drop(rebalancer);
+        let rebalancer = Tiered::spawn_rebalancer(tiered.clone(), Duration::from_millis(1));
+        thread::sleep(Duration::from_millis(20));
+        rebalancer.stop().unwrap();
+        assert!(guard.is_fast(BLOCK));
+    }
+}
@@ -0,0 +1,176 @@
+use std::io::{ErrorKind, IoSlice, IoSliceMut, Result};
+
+use {IoOptions, KnownEof, RangeSet, ReadAt, ReadBuf, SizeAt, SyncAt, WriteAt};
+
+/// A wrapper recording the previous contents of every range it
+/// overwrites, so that the inner value can be rolled back.
+///
+/// Before a range is written for the first time, its current contents
+/// are read and kept in memory. [`revert`](#method.revert) writes them
+/// back, restoring the inner value to its state when the wrapper was
+/// created or last committed. Ranges are only captured once, so the log
+/// grows with the amount of data touched, not with the number of writes.
+///
+/// Writes past the end of the inner value extend it, and reverting does
+/// not shrink it back.
+#[derive(Clone, Debug)]
+pub struct UndoLog<T> {
+    inner: T,
+    captured: RangeSet,
+    log: Vec<(u64, Vec<u8>)>,
+}
+
+impl<T> UndoLog<T> {
+    /// Wraps `inner`, recording the previous contents of written ranges.
+    pub fn new(inner: T) -> UndoLog<T> {
+        UndoLog {
+            inner,
+            captured: RangeSet::new(),
+            log: Vec::new(),
+        }
+    }
+
+    /// Returns the number of bytes of previous contents in the log.
+    pub fn log_len(&self) -> usize {
+        self.log.iter().map(|(_, data)| data.len()).sum()
+    }
+
+    /// Discards the log, keeping all writes made so far.
+    pub fn commit(&mut self) {
+        self.captured = RangeSet::new();
+        self.log.clear();
+    }
+
+    /// Returns a reference to the inner value.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Consumes the wrapper, returning the inner value with all writes
+    /// made so far.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: ReadAt + WriteAt> UndoLog<T> {
+    /// Writes the recorded previous contents back to the inner value,
+    /// and clears the log.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from writing to the inner value. The log is only
+    /// cleared once every range has been restored, so a failed revert can
+    /// be retried.
+    pub fn revert(&mut self) -> Result<()> {
+        for &(pos, ref data) in self.log.iter().rev() {
+            self.inner.write_all_at(pos, data)?;
+        }
+        self.inner.flush()?;
+        self.commit();
+        Ok(())
+    }
+
+    /// Records the contents of the parts of `len` bytes at `pos` that have
+    /// not been recorded yet.
+    fn capture(&mut self, pos: u64, len: usize) -> Result<()> {
+        let end = pos.saturating_add(len as u64);
+        for gap in self.captured.gaps(pos..end) {
+            let mut data = vec![0; (gap.end - gap.start) as usize];
+            let mut filled = 0;
+            while filled < data.len() {
+                match self.inner.read_at(gap.start + filled as u64, &mut data[filled..]) {
+                    Ok(0) => break,
+                    Ok(n) => filled += n,
+                    Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            data.truncate(filled);
+            if !data.is_empty() {
+                self.log.push((gap.start, data));
+            }
+            self.captured.insert(gap);
+        }
+        Ok(())
+    }
+}
+
+impl<T: ReadAt> ReadAt for UndoLog<T> {
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize> {
+        self.inner.read_at(pos, buf)
+    }
+
+    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
+        self.inner.read_exact_at(pos, buf)
+    }
+
+    fn read_at_with(&mut self, pos: u64, buf: &mut [u8], options: &IoOptions) -> Result<usize> {
+        self.inner.read_at_with(pos, buf, options)
+    }
+
+    fn read_vectored_at(&mut self, pos: u64, bufs: &mut [IoSliceMut]) -> Result<usize> {
+        self.inner.read_vectored_at(pos, bufs)
+    }
+
+    fn read_buf_at(&mut self, pos: u64, buf: &mut ReadBuf) -> Result<()> {
+        self.inner.read_buf_at(pos, buf)
+    }
+}
+
+impl<T: ReadAt + WriteAt> WriteAt for UndoLog<T> {
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<usize> {
+        self.capture(pos, buf.len())?;
+        self.inner.write_at(pos, buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    fn write_all_at(&mut self, pos: u64, buf: &[u8]) -> Result<()> {
+        self.capture(pos, buf.len())?;
+        self.inner.write_all_at(pos, buf)
+    }
+
+    fn write_at_with(&mut self, pos: u64, buf: &[u8], options: &IoOptions) -> Result<usize> {
+        self.capture(pos, buf.len())?;
+        self.inner.write_at_with(pos, buf, options)
+    }
+
+    fn write_vectored_at(&mut self, pos: u64, bufs: &[IoSlice]) -> Result<usize> {
+        self.capture(pos, bufs.iter().map(|b| b.len()).sum())?;
+        self.inner.write_vectored_at(pos, bufs)
+    }
+}
+
+impl<T: KnownEof> KnownEof for UndoLog<T> {
+    #[inline]
+    fn remaining_at(&mut self, pos: u64) -> Result<u64> {
+        self.inner.remaining_at(pos)
+    }
+
+    #[inline]
+    fn is_eof_at(&mut self, pos: u64) -> Result<bool> {
+        self.inner.is_eof_at(pos)
+    }
+}
+
+impl<T: SizeAt> SizeAt for UndoLog<T> {
+    #[inline]
+    fn size(&self) -> Result<u64> {
+        self.inner.size()
+    }
+}
+
+impl<T: SyncAt> SyncAt for UndoLog<T> {
+    #[inline]
+    fn sync_all(&mut self) -> Result<()> {
+        self.inner.sync_all()
+    }
+
+    #[inline]
+    fn sync_data(&mut self) -> Result<()> {
+        self.inner.sync_data()
+    }
+}
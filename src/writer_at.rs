@@ -0,0 +1,85 @@
+use std::io::{self, ErrorKind, IoSlice, Result, Seek, SeekFrom, Write};
+
+use {SizeAt, WriteAt};
+
+/// An adapter which writes to a sink as a stream, starting at a chosen
+/// offset.
+///
+/// It implements `Write` by keeping track of a position of its own, so
+/// encoders and serializers can emit into the sink. Writes are passed on
+/// unbuffered. `Seek` is implemented for sinks that know their size.
+#[derive(Clone, Debug)]
+pub struct WriterAt<T> {
+    inner: T,
+    pos: u64,
+}
+
+impl<T> WriterAt<T> {
+    /// Creates a writer at `pos` of `inner`.
+    pub fn new(inner: T, pos: u64) -> WriterAt<T> {
+        WriterAt { inner, pos }
+    }
+
+    /// Returns the position of the writer in the sink.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Returns a reference to the sink.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the sink.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Consumes the writer, returning the sink.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: WriteAt> Write for WriterAt<T> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let n = self.inner.write_at(self.pos, buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice]) -> Result<usize> {
+        let n = self.inner.write_vectored_at(self.pos, bufs)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<T: SizeAt> Seek for WriterAt<T> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(pos) => {
+                self.pos = pos;
+                return Ok(pos);
+            }
+            SeekFrom::Current(offset) => (self.pos, offset),
+            SeekFrom::End(offset) => (self.inner.size()?, offset),
+        };
+        match base.checked_add_signed(offset) {
+            Some(pos) => {
+                self.pos = pos;
+                Ok(pos)
+            }
+            None => Err(io::Error::new(ErrorKind::InvalidInput, "invalid seek to a negative or overflowing position")),
+        }
+    }
+
+    fn stream_position(&mut self) -> Result<u64> {
+        Ok(self.pos)
+    }
+}